@@ -1,4 +1,4 @@
-use agents::{Agent};
+use agents::{Agent, PreGame};
 use board::{Board, RuleSet, PlayerRank, Stone, Point, Action};
 use convnet_new::{
   //build_12layer384_19x19x37_arch_nodir,
@@ -6,6 +6,8 @@ use convnet_new::{
   //build_13layer384_19x19x32_arch_nodir,
   build_13layer384multi3_19x19x32_arch_nodir,
 };
+use gtp_board::{UndoResult};
+use search::decision::{DecisionConfig, DecisionPolicy};
 use txnstate::{TxnStateConfig, TxnState};
 use txnstate::extras::{
   TxnStateNodeData,
@@ -31,8 +33,8 @@ use std::sync::{Arc};
 pub struct ConvnetAgent {
   komi:     f32,
   player:   Option<Stone>,
+  decision: DecisionPolicy,
 
-  history:  Vec<(TxnState<TxnStateNodeData>, Action)>,
   state:    TxnState<TxnStateNodeData>,
 
   context:  DeviceContext,
@@ -71,9 +73,9 @@ impl ConvnetAgent {
     ConvnetAgent{
       komi:     0.0,
       player:   None,
-      history:  vec![],
+      decision: DecisionPolicy::new(DecisionConfig::default()),
       state:    TxnState::new(
-          TxnStateConfig::default(),
+          TxnStateConfig{history: true, .. TxnStateConfig::default()},
           //TxnStateAlphaFeatsV2Data::new(),
           TxnStateNodeData::new(),
       ),
@@ -83,12 +85,37 @@ impl ConvnetAgent {
   }
 }
 
+/// The points ranked by the policy network for `turn`, best first.
+fn rank_points(state: &TxnState<TxnStateNodeData>, context: &DeviceContext, arch: &mut PipelineArchWorker<()>, turn: Stone) -> Vec<Point> {
+  let ctx = (*context).as_ref();
+  state.get_data().features.extract_relative_features(turn, arch.input_layer().expose_host_frame_buf(0));
+  arch.input_layer().load_frames(1, &ctx);
+  arch.forward(1, Phase::Inference, &ctx);
+  arch.loss_layer().store_probs(1, &ctx);
+  let pred_probs = arch.loss_layer().get_probs(1);
+
+  // FIXME(20160131): for 3-lookahead, but should use 1-lookahead for inference.
+  let mut ranked_probs: Vec<_> = pred_probs.as_slice().iter().enumerate()
+    .filter_map(|(k, &x)| {
+      if k < 361 {
+        Some((F32SupNan(-x), k))
+      } else {
+        None
+      }
+    })
+    .collect()
+  ;
+  ranked_probs.sort();
+  assert_eq!(Board::SIZE, ranked_probs.len());
+  ranked_probs.iter().map(|&(_, k)| Point(k as i16)).collect()
+}
+
 impl Agent for ConvnetAgent {
   fn reset(&mut self) {
-    self.komi = 7.5;
+    self.komi = self.state.config.komi;
     self.player = None;
+    self.decision.reset();
 
-    self.history.clear();
     self.state.reset();
   }
 
@@ -98,75 +125,65 @@ impl Agent for ConvnetAgent {
 
   fn komi(&mut self, komi: f32) {
     self.komi = komi;
+    self.state.config.komi = komi;
   }
 
   fn player(&mut self, stone: Stone) {
-    self.player = Some(Stone::Black);
+    self.player = Some(stone);
+  }
+
+  fn place_handicap(&mut self, points: &[Point]) -> Result<(), ()> {
+    self.state.place_handicap_stones(points)
+  }
+
+  fn choose_free_handicap(&mut self, num_stones: usize) -> Vec<Point> {
+    let &mut ConvnetAgent{
+      ref state, ref context, ref mut arch, .. } = self;
+    let mut scratch_state = state.clone();
+    PreGame.choose_free_handicap(num_stones, |placed| {
+      scratch_state.reset();
+      let stones: Vec<_> = placed.iter().map(|&pt| (Stone::Black, pt)).collect();
+      scratch_state.place_setup_stones(&stones).unwrap();
+      rank_points(&scratch_state, context, arch, Stone::Black).into_iter()
+        .filter(|&pt| scratch_state.current_stone(pt) == Stone::Empty)
+        .collect()
+    })
   }
 
   fn apply_action(&mut self, turn: Stone, action: Action) {
-    self.history.push((self.state.clone(), action));
     match self.state.try_action(turn, action) {
       Ok(_)   => { self.state.commit(); }
       Err(_)  => { panic!("agent tried to apply an illegal action!"); }
     }
   }
 
-  fn undo(&mut self) {
-    // FIXME(20151108): track ply; limit to how far we can undo.
-    if let Some((prev_state, _)) = self.history.pop() {
-      self.state.clone_from(&prev_state);
-    } else {
-      self.state.reset();
+  fn undo(&mut self) -> UndoResult {
+    match self.state.undo_plies(1) {
+      Ok(_)   => UndoResult::Okay,
+      Err(_)  => UndoResult::CannotUndo,
     }
   }
 
   fn act(&mut self, turn: Stone) -> Action {
-    if self.history.len() > 0 {
-      match self.history[self.history.len() - 1].1 {
-        Action::Resign | Action::Pass => {
-          return Action::Pass;
-        }
-        _ => {}
-      }
-    }
+    let action = {
+      let &mut ConvnetAgent{
+        ref mut state, ref context, ref mut arch, .. } = self;
+      best_legal_action(state, context, arch, turn)
+    };
+    self.decision.decide_unsearched(&self.state, turn, action)
+  }
+}
 
-    let &mut ConvnetAgent{
-      ref mut state, ref context, ref mut arch, .. } = self;
-    let ctx = (*context).as_ref();
-    state.get_data().features.extract_relative_features(turn, arch.input_layer().expose_host_frame_buf(0));
-    arch.input_layer().load_frames(1, &ctx);
-    arch.forward(1, Phase::Inference, &ctx);
-    arch.loss_layer().store_probs(1, &ctx);
-    let pred_probs = arch.loss_layer().get_probs(1);
-
-    // FIXME(20160131): for 3-lookahead, but should use 1-lookahead for inference.
-    let mut ranked_probs: Vec<_> = pred_probs.as_slice().iter().enumerate()
-      .filter_map(|(k, &x)| {
-        if k < 361 {
-          Some((F32SupNan(-x), k))
-        } else {
-          None
-        }
-      })
-      .collect()
-    ;
-    ranked_probs.sort();
-    assert_eq!(Board::SIZE, ranked_probs.len());
-
-    let mut action = Action::Pass;
-    for k in 0 .. Board::SIZE {
-      let place_point = Point(ranked_probs[k].1 as i16);
-      let res = state.try_place(turn, place_point);
-      state.undo();
-      match res {
-        Ok(_) => {
-          action = Action::Place{point: place_point};
-          break;
-        }
-        Err(_) => {}
-      }
+/// The legal placement ranked best by the policy network, or a pass if there
+/// is none.
+fn best_legal_action(state: &mut TxnState<TxnStateNodeData>, context: &DeviceContext, arch: &mut PipelineArchWorker<()>, turn: Stone) -> Action {
+  let ranked_points = rank_points(state, context, arch, turn);
+  for &place_point in ranked_points.iter() {
+    let res = state.try_place(turn, place_point);
+    state.undo();
+    if res.is_ok() {
+      return Action::Place{point: place_point};
     }
-    action
   }
+  Action::Pass
 }
@@ -1,5 +1,6 @@
 use agents::{Agent};
 use board::{Board, RuleSet, PlayerRank, Coord, Stone, Point, Action};
+use gtp_board::{UndoResult};
 use txnstate::{TxnStateConfig, TxnState};
 
 use std::io::{stdin};
@@ -8,7 +9,6 @@ pub struct ManualAgent {
   komi:     f32,
   player:   Option<Stone>,
 
-  history:  Vec<(TxnState, Action)>,
   state:    TxnState,
 }
 
@@ -17,9 +17,8 @@ impl ManualAgent {
     ManualAgent{
       komi:     0.0,
       player:   None,
-      history:  vec![],
       state:    TxnState::new(
-          TxnStateConfig::default(),
+          TxnStateConfig{history: true, .. TxnStateConfig::default()},
           (),
       ),
     }
@@ -28,10 +27,9 @@ impl ManualAgent {
 
 impl Agent for ManualAgent {
   fn reset(&mut self) {
-    self.komi = 7.5;
+    self.komi = self.state.config.komi;
     self.player = None;
 
-    self.history.clear();
     self.state.reset();
   }
 
@@ -41,26 +39,28 @@ impl Agent for ManualAgent {
 
   fn komi(&mut self, komi: f32) {
     self.komi = komi;
+    self.state.config.komi = komi;
   }
 
   fn player(&mut self, stone: Stone) {
-    self.player = Some(Stone::Black);
+    self.player = Some(stone);
+  }
+
+  fn place_handicap(&mut self, points: &[Point]) -> Result<(), ()> {
+    self.state.place_handicap_stones(points)
   }
 
   fn apply_action(&mut self, turn: Stone, action: Action) {
-    self.history.push((self.state.clone(), action));
     match self.state.try_action(turn, action) {
       Ok(_)   => { self.state.commit(); }
       Err(_)  => { panic!("agent tried to apply an illegal action!"); }
     }
   }
 
-  fn undo(&mut self) {
-    // FIXME(20151108): track ply; limit to how far we can undo.
-    if let Some((prev_state, _)) = self.history.pop() {
-      self.state.clone_from(&prev_state);
-    } else {
-      self.state.reset();
+  fn undo(&mut self) -> UndoResult {
+    match self.state.undo_plies(1) {
+      Ok(_)   => UndoResult::Okay,
+      Err(_)  => UndoResult::CannotUndo,
     }
   }
 
@@ -1,4 +1,5 @@
-use board::{Coord, Stone, Point, Action};
+use board::{Board, Coord, Stone, Point, Action};
+use gtp_board::{UndoResult};
 
 //pub mod convnet;
 pub mod convnet_new;
@@ -12,9 +13,24 @@ pub trait Agent {
   fn komi(&mut self, komi: f32);
   fn player(&mut self, stone: Stone);
 
+  /// Places black handicap stones on the empty board without using up any
+  /// turns; white moves next.
+  fn place_handicap(&mut self, points: &[Point]) -> Result<(), ()>;
+
+  /// Chooses where black places `num_stones` free handicap stones.
+  fn choose_free_handicap(&mut self, num_stones: usize) -> Vec<Point> {
+    PreGame.fixed_handicap_positions(num_stones)
+  }
+
   fn apply_action(&mut self, turn: Stone, action: Action);
-  fn undo(&mut self);
+  fn undo(&mut self) -> UndoResult;
   fn act(&mut self, turn: Stone) -> Action;
+
+  /// Acts in the cleanup phase after a dispute over dead stones, in which the
+  /// dead stones should be captured before passing.
+  fn act_cleanup(&mut self, turn: Stone) -> Action {
+    self.act(turn)
+  }
 }
 
 // XXX: See <http://www.lysator.liu.se/~gunnar/gtp/gtp2-spec-draft2/gtp2-spec.html#sec:fixed-handicap-placement>.
@@ -46,7 +62,42 @@ impl PreGame {
     ps
   }
 
-  pub fn prefer_handicap_positions(&self, num_stones: usize) -> Vec<Point> {
-    self.fixed_handicap_positions(num_stones)
+  /// Whether `point` is a reasonable free handicap stone next to `placed`:
+  /// not on the first two lines, and not within two lines of another
+  /// handicap stone.
+  pub fn is_free_handicap_candidate(&self, placed: &[Point], point: Point) -> bool {
+    let coord = point.to_coord();
+    let upper = Board::DIM as u8 - 3;
+    if coord.x < 2 || coord.x > upper || coord.y < 2 || coord.y > upper {
+      return false;
+    }
+    for &other in placed.iter() {
+      let other = other.to_coord();
+      let dx = (coord.x as i32 - other.x as i32).abs();
+      let dy = (coord.y as i32 - other.y as i32).abs();
+      if dx <= 2 && dy <= 2 {
+        return false;
+      }
+    }
+    true
+  }
+
+  /// Picks free handicap stones one at a time from candidate points ranked
+  /// best first after each placement by `rank`, falling back to the fixed
+  /// positions if no candidate is left.
+  pub fn choose_free_handicap<F>(&self, num_stones: usize, mut rank: F) -> Vec<Point>
+  where F: FnMut(&[Point]) -> Vec<Point> {
+    let mut placed = vec![];
+    while placed.len() < num_stones {
+      let ranked = rank(&placed);
+      match ranked.into_iter().find(|&pt| self.is_free_handicap_candidate(&placed, pt)) {
+        Some(point) => placed.push(point),
+        None => {
+          println!("WARNING: free handicap: no candidates left, using fixed positions");
+          return self.fixed_handicap_positions(num_stones);
+        }
+      }
+    }
+    placed
   }
 }
@@ -1,20 +1,32 @@
-use agents::{Agent};
+use agents::{Agent, PreGame};
 use board::{Board, RuleSet, PlayerRank, Stone, Point, Action};
+use book::{OpeningBook};
+use config::{EngineConfig, EngineBookConfig};
+use gtp_board::{UndoResult};
 use search::{SearchResult};
+use search::decision::{DecisionPolicy};
+use search::komi::{DynamicKomi};
 use search::parallel_policies::convnet::{
   ConvnetPolicyWorkerBuilder, ConvnetPolicyWorker,
 };
 use search::parallel_tree::{
   MonteCarloSearchConfig,
+  CleanupPriorConfig,
+  ShapePriorConfig,
   TreePolicyConfig,
   SharedTree,
+  TreeMemoryConfig,
   SearchWorkerConfig,
   SearchWorkerBatchConfig,
+  SearchParallelism,
   MonteCarloSearchResult,
+  MonteCarloSearchStats,
   ParallelMonteCarloSearchServer,
   ParallelMonteCarloSearch,
+  RootParallelMonteCarloSearch,
 };
-use txnstate::{TxnStateConfig, TxnState};
+use search::report::{SearchReport, TreeExportConfig, save_search_report};
+use txnstate::{TxnState, BensonScratch};
 use txnstate::extras::{TxnStateNodeData};
 
 use async_cuda::context::{DeviceContext};
@@ -22,8 +34,25 @@ use cuda::runtime::{CudaDevice};
 use rng::xorshift::{Xorshiftplus128Rng};
 
 use rand::{Rng, thread_rng};
-use std::cmp::{min};
+use std::cmp::{max, min};
+use std::fs::{create_dir_all};
 use std::path::{PathBuf};
+use std::sync::{Arc};
+
+/// Half of a search budget, but at least one batch, millisecond, or node.
+fn half_batch_cfg(batch_cfg: SearchWorkerBatchConfig) -> SearchWorkerBatchConfig {
+  match batch_cfg {
+    SearchWorkerBatchConfig::Fixed{num_batches} => {
+      SearchWorkerBatchConfig::Fixed{num_batches: max(1, num_batches / 2)}
+    }
+    SearchWorkerBatchConfig::TimeLimit{budget_ms, tol_ms} => {
+      SearchWorkerBatchConfig::TimeLimit{budget_ms: max(1, budget_ms / 2), tol_ms: tol_ms}
+    }
+    SearchWorkerBatchConfig::NodeLimit{max_nodes} => {
+      SearchWorkerBatchConfig::NodeLimit{max_nodes: max(1, max_nodes / 2)}
+    }
+  }
+}
 
 /*pub struct MonteCarloConfig {
   pub num_rollouts: usize,
@@ -32,65 +61,340 @@ use std::path::{PathBuf};
 
 pub struct ParallelMonteCarloSearchAgent {
   config:       MonteCarloSearchConfig,
+  batch_cfg:    SearchWorkerBatchConfig,
+  early_stop:   bool,
+  parallelism:  SearchParallelism,
   tree_cfg:     TreePolicyConfig,
+  memory_cfg:   Option<TreeMemoryConfig>,
   komi:         f32,
+  dyn_komi:     DynamicKomi,
+  decision:     DecisionPolicy,
+  cleanup_weight:   f32,
   player:       Option<Stone>,
 
-  history:      Vec<(TxnState<TxnStateNodeData>, Action, Option<MonteCarloSearchResult>)>,
-  ply:          usize,
+  // Search results before each committed action; the actions themselves are
+  // in the state history.
+  prev_results: Vec<Option<MonteCarloSearchResult>>,
   state:        TxnState<TxnStateNodeData>,
   result:       Option<MonteCarloSearchResult>,
-  tree:         Option<SharedTree>,
+  // One tree, or one per group of workers with root parallelism; empty if
+  // there is no tree to reuse.
+  trees:        Vec<SharedTree>,
+  // The komi the tree values were backed up with.
+  tree_komi:    f32,
+
+  book:         Option<OpeningBook>,
+  book_cfg:     EngineBookConfig,
+  shape_prior:  Option<ShapePriorConfig>,
+
+  // Where to write a search report and tree export after each search.
+  report_dir:   Option<PathBuf>,
+  report_pv_depth:  usize,
+  export_cfg:   TreeExportConfig,
 
   rng:          Xorshiftplus128Rng,
   server:       ParallelMonteCarloSearchServer<ConvnetPolicyWorker>,
 }
 
 impl ParallelMonteCarloSearchAgent {
-  pub fn new(config: MonteCarloSearchConfig, tree_cfg: TreePolicyConfig, num_workers: Option<usize>) -> ParallelMonteCarloSearchAgent {
+  pub fn new(cfg: &EngineConfig) -> ParallelMonteCarloSearchAgent {
+    let config = cfg.search_cfg();
+    let batch_cfg = cfg.search_batch_cfg();
+    let tree_cfg = cfg.tree_policy_cfg();
     println!("DEBUG: parallel search agent: search config: {:?}", config);
+    println!("DEBUG: parallel search agent: search limit: {:?} early stop: {}", batch_cfg, cfg.search.early_stop);
     println!("DEBUG: parallel search agent: tree policy config: {:?}", tree_cfg);
-    let state_cfg = TxnStateConfig::default();
+    let state_cfg = cfg.state_cfg();
     let num_devices = CudaDevice::count().unwrap();
-    let num_workers = num_workers.unwrap_or(num_devices);
-    let num_workers = min(num_workers, num_devices);
+    let num_workers = min(cfg.num_workers(num_devices), num_devices);
+    let parallelism = match cfg.search_parallelism() {
+      SearchParallelism::Root{num_trees} if num_trees > num_workers => {
+        println!("WARNING: parallel search agent: {} root trees for {} workers, using {}",
+            num_trees, num_workers, num_workers);
+        SearchParallelism::Root{num_trees: num_workers}
+      }
+      parallelism => parallelism,
+    };
+    println!("DEBUG: parallel search agent: parallelism: {:?}", parallelism);
     //let batch_capacity = 256;
     //let worker_batch_capacity = batch_capacity / num_workers;
     //let worker_batch_capacity = 576;
-    let worker_batch_capacity = 256;
+    let worker_batch_capacity = cfg.search.worker_batch_capacity;
+    let book = match cfg.open_book() {
+      Ok(book) => book,
+      Err(e) => panic!("FATAL: parallel search agent: {}", e),
+    };
+    if let Some(ref book) = book {
+      println!("DEBUG: parallel search agent: opening book: {} positions, {} moves",
+          book.entries.len(), book.num_moves());
+    }
+    let shape_prior = match cfg.shape_prior_cfg() {
+      Ok(shape_prior) => shape_prior,
+      Err(e) => panic!("FATAL: parallel search agent: {}", e),
+    };
+    if let Some(ref shape_cfg) = shape_prior {
+      println!("DEBUG: parallel search agent: shape dictionary: {} shapes", shape_cfg.dict.entries.len());
+    }
+    let node_data = match shape_prior {
+      Some(_) => TxnStateNodeData::with_shapes(),
+      None => TxnStateNodeData::new(),
+    };
     ParallelMonteCarloSearchAgent{
       config:   config,
+      batch_cfg:    batch_cfg,
+      early_stop:   cfg.search.early_stop,
+      parallelism:  parallelism,
       tree_cfg: tree_cfg,
-      komi:     0.0,
+      memory_cfg:   cfg.tree_memory_cfg(),
+      komi:     state_cfg.komi,
+      dyn_komi: DynamicKomi::new(cfg.dynamic_komi_cfg()),
+      decision: DecisionPolicy::new(cfg.decision_cfg()),
+      cleanup_weight:   cfg.decision.cleanup_weight,
       player:   None,
-      history:  vec![],
-      ply:      0,
+      prev_results: vec![],
       state:    TxnState::new(
           state_cfg,
-          TxnStateNodeData::new(),
+          node_data,
       ),
       result:   None,
-      tree:     None,
+      trees:    vec![],
+      tree_komi:    state_cfg.komi,
+      book:     book,
+      book_cfg: cfg.book.clone(),
+      shape_prior:  shape_prior,
+      report_dir:   cfg.report_dir(),
+      report_pv_depth:  cfg.report.pv_depth,
+      export_cfg:   cfg.tree_export_cfg(),
       rng:      Xorshiftplus128Rng::new(&mut thread_rng()),
       server:   ParallelMonteCarloSearchServer::new(
           state_cfg,
           num_workers, 1, worker_batch_capacity,
-          ConvnetPolicyWorkerBuilder::new(tree_cfg, num_workers, 1, worker_batch_capacity),
+          ConvnetPolicyWorkerBuilder::new(tree_cfg, num_workers, 1, worker_batch_capacity)
+            .with_save_paths(
+                PathBuf::from(&cfg.network.prior_model),
+                PathBuf::from(&cfg.network.rollout_model),
+            ),
       ),
     }
   }
 }
 
+impl ParallelMonteCarloSearchAgent {
+  /// Sets the budget of each subsequent search: a fixed number of batches, a
+  /// wall-clock budget, or a number of new tree nodes.
+  pub fn set_search_limit(&mut self, batch_cfg: SearchWorkerBatchConfig, early_stop: bool) {
+    self.batch_cfg = batch_cfg;
+    self.early_stop = early_stop;
+  }
+
+  fn new_tree(&self) -> SharedTree {
+    let tree = match self.shape_prior {
+      Some(ref shape_cfg) => SharedTree::with_shape_prior(self.tree_cfg, shape_cfg.clone()),
+      None => SharedTree::new(self.tree_cfg),
+    };
+    if let Some(memory_cfg) = self.memory_cfg {
+      tree.set_memory_cfg(memory_cfg);
+    }
+    tree
+  }
+
+  fn book_action(&mut self, turn: Stone) -> Option<Action> {
+    if self.state.current_ply() >= self.book_cfg.max_ply {
+      return None;
+    }
+    let book_move = match self.book {
+      Some(ref book) => book.choose(&self.state, turn, self.book_cfg.min_count, self.book_cfg.temperature, &mut self.rng),
+      None => None,
+    };
+    let book_move = match book_move {
+      Some(book_move) => book_move,
+      None => return None,
+    };
+    let action = Action::Place{point: book_move.point};
+    let legal = self.state.try_action(turn, action).is_ok();
+    self.state.undo();
+    if !legal {
+      println!("WARNING: parallel search agent: illegal book move: {:?}", book_move.point.to_coord());
+      return None;
+    }
+    println!("DEBUG: search result: book move: {:?} count: {} win rate: {:.3}",
+        book_move.point.to_coord(), book_move.count, book_move.win_rate());
+    Some(action)
+  }
+
+  /// One tree, or one per group of workers with root parallelism.
+  fn new_trees(&self) -> Vec<SharedTree> {
+    let num_trees = match self.parallelism {
+      SearchParallelism::Tree => 1,
+      SearchParallelism::Root{num_trees} => num_trees,
+    };
+    (0 .. num_trees).map(|_| self.new_tree()).collect()
+  }
+
+  fn search_trees(&mut self, batch_cfg: SearchWorkerBatchConfig, search_state: &TxnState<TxnStateNodeData>, trees: &[SharedTree]) -> (MonteCarloSearchResult, MonteCarloSearchStats) {
+    let worker_cfg = SearchWorkerConfig{
+      batch_cfg:    batch_cfg,
+      tree_batch_size:      None,
+      rollout_batch_size:   self.config.batch_size,
+      early_stop:   self.early_stop,
+    };
+    match self.parallelism {
+      SearchParallelism::Tree => {
+        let mut search = ParallelMonteCarloSearch::new();
+        search.join(
+            worker_cfg,
+            &mut self.server,
+            self.player.unwrap(),
+            search_state,
+            trees[0].clone(),
+            //self.result.as_ref(),
+            &mut self.rng)
+      }
+      SearchParallelism::Root{..} => {
+        let search = RootParallelMonteCarloSearch::new();
+        search.join(
+            worker_cfg,
+            &mut self.server,
+            self.player.unwrap(),
+            search_state,
+            trees,
+            &mut self.rng)
+      }
+    }
+  }
+
+  /// The win rate of the root rollouts of the last search rescored with the
+  /// game komi, over all of the trees.
+  fn game_komi_value(&self, turn: Stone, search_res: &MonteCarloSearchResult) -> f32 {
+    let (num_wins, num_rollouts) = self.trees.iter()
+      .map(|tree| tree.root_wins_with_komi(turn, self.komi))
+      .fold((0, 0), |(w, n), (tree_w, tree_n)| (w + tree_w, n + tree_n));
+    if num_rollouts == 0 {
+      return search_res.expected_value;
+    }
+    let value = num_wins as f32 / num_rollouts as f32;
+    println!("DEBUG: search result: game komi: {:.1} value: {:.3} rollouts: {}",
+        self.komi, value, num_rollouts);
+    value
+  }
+
+  /// Searches with `batch_cfg` as the budget. The dynamic komi is not
+  /// updated here but once per move, with the result that is played.
+  fn search(&mut self, turn: Stone, batch_cfg: SearchWorkerBatchConfig, cleanup_prior: Option<CleanupPriorConfig>) -> (MonteCarloSearchResult, MonteCarloSearchStats) {
+    // FIXME(20160114): read remaining time and apply a time management policy.
+
+    // XXX(20160422): Rollouts are scored with the effective komi through the
+    // search state config; a tree searched with another komi is discarded, so
+    // all of its values are on the same terms. The komi only changes in whole
+    // steps (see `DynamicKomi`), so most trees are kept.
+    let search_komi = self.dyn_komi.effective_komi(&self.state, turn, self.komi);
+    if search_komi != self.tree_komi || cleanup_prior.is_some() {
+      self.trees.clear();
+    }
+    let mut search_state = self.state.clone();
+    search_state.config.komi = search_komi;
+
+    if self.trees.is_empty() {
+      self.trees = self.new_trees();
+      if let Some(ref cleanup_cfg) = cleanup_prior {
+        for tree in self.trees.iter() {
+          tree.set_cleanup_prior(cleanup_cfg.clone());
+        }
+      }
+      self.tree_komi = search_komi;
+    }
+    let trees = self.trees.clone();
+    let (search_res, search_stats) = self.search_trees(batch_cfg, &search_state, &trees);
+    println!("DEBUG: search result: {:?}", search_res);
+    println!("DEBUG: search stats:  {:?}", search_stats);
+    if search_stats.stopped_early {
+      println!("DEBUG: search stats:  stopped early: rollouts: {} saved rollouts: {} saved ms: {}",
+          search_stats.num_rollouts, search_stats.saved_rollouts, search_stats.saved_ms);
+    }
+    self.save_reports(&search_stats);
+    (search_res, search_stats)
+  }
+
+  fn update_dyn_komi(&mut self, search_res: &MonteCarloSearchResult) {
+    self.dyn_komi.update(search_res.expected_value);
+    println!("DEBUG: search result: komi: game: {:.1} search: {:.1} mode: {} next shift: {:.1}",
+        self.komi, search_res.komi, self.dyn_komi.mode().to_str(), self.dyn_komi.shift());
+  }
+
+  /// The part of the move's budget left after a search with `stats`, or None
+  /// if it is used up.
+  fn remaining_batch_cfg(&self, stats: &MonteCarloSearchStats) -> Option<SearchWorkerBatchConfig> {
+    let remaining = match self.batch_cfg {
+      SearchWorkerBatchConfig::Fixed{num_batches} => {
+        let used_batches = stats.num_rollouts / self.config.batch_size;
+        SearchWorkerBatchConfig::Fixed{num_batches: num_batches.saturating_sub(used_batches)}
+      }
+      SearchWorkerBatchConfig::TimeLimit{budget_ms, tol_ms} => {
+        SearchWorkerBatchConfig::TimeLimit{budget_ms: budget_ms.saturating_sub(stats.elapsed_ms), tol_ms: tol_ms}
+      }
+      SearchWorkerBatchConfig::NodeLimit{max_nodes} => {
+        SearchWorkerBatchConfig::NodeLimit{max_nodes: max_nodes.saturating_sub(stats.num_expanded)}
+      }
+    };
+    match remaining {
+      SearchWorkerBatchConfig::Fixed{num_batches: 0} |
+      SearchWorkerBatchConfig::TimeLimit{budget_ms: 0, ..} |
+      SearchWorkerBatchConfig::NodeLimit{max_nodes: 0} => None,
+      remaining => Some(remaining),
+    }
+  }
+
+  /// Writes `move-<ply>.{json,dot,sgf}` for each tree to the report
+  /// directory, if any. With root parallelism the stats are those of the
+  /// merged search.
+  fn save_reports(&self, stats: &MonteCarloSearchStats) {
+    let report_dir = match self.report_dir {
+      Some(ref report_dir) => report_dir,
+      None => return,
+    };
+    if let Err(e) = create_dir_all(report_dir) {
+      println!("WARNING: parallel search agent: failed to create report dir {:?}: {:?}", report_dir, e);
+      return;
+    }
+    let ply = self.state.current_ply();
+    for (k, tree) in self.trees.iter().enumerate() {
+      let name = if self.trees.len() > 1 {
+        format!("move-{:03}-tree{}", ply, k)
+      } else {
+        format!("move-{:03}", ply)
+      };
+      let report = SearchReport::new(tree, stats, self.report_pv_depth);
+      if let Err(e) = save_search_report(&report_dir.join(&name), &report, tree, self.export_cfg) {
+        println!("WARNING: parallel search agent: failed to save search report {}: {:?}", name, e);
+      }
+    }
+  }
+
+  /// The stones of the opponent of `turn` which the search result considers
+  /// dead and which are still on the board, excluding those which are
+  /// unconditionally alive.
+  fn dead_opponent_stones(&self, turn: Stone, result: &MonteCarloSearchResult) -> Vec<Point> {
+    let opp_turn = turn.opponent();
+    let mut scratch = BensonScratch::new();
+    let alive = self.state.unconditionally_alive_stones(opp_turn, &mut scratch);
+    result.dead_stones[opp_turn.offset()].iter()
+      .filter(|&&pt| self.state.current_stone(pt) == opp_turn && alive.binary_search(&pt).is_err())
+      .map(|&pt| pt)
+      .collect()
+  }
+}
+
 impl Agent for ParallelMonteCarloSearchAgent {
   fn reset(&mut self) {
-    self.komi = 7.5;
+    self.komi = self.state.config.komi;
+    self.dyn_komi.reset();
+    self.decision.reset();
     self.player = None;
 
-    self.history.clear();
-    self.ply = 0;
+    self.prev_results.clear();
     self.state.reset();
     self.result = None;
-    self.tree = None;
+    self.trees.clear();
   }
 
   fn board_dim(&mut self, board_dim: usize) {
@@ -99,97 +403,166 @@ impl Agent for ParallelMonteCarloSearchAgent {
 
   fn komi(&mut self, komi: f32) {
     self.komi = komi;
+    self.state.config.komi = komi;
+    self.trees.clear();
   }
 
   fn player(&mut self, stone: Stone) {
     // TODO(20151111)
   }
 
+  fn place_handicap(&mut self, points: &[Point]) -> Result<(), ()> {
+    try!(self.state.place_handicap_stones(points));
+    self.result = None;
+    self.trees.clear();
+    Ok(())
+  }
+
+  fn choose_free_handicap(&mut self, num_stones: usize) -> Vec<Point> {
+    let batch_cfg = self.batch_cfg;
+    let batch_size = self.config.batch_size;
+    let mut scratch_state = self.state.clone();
+    let tree_cfg = self.tree_cfg;
+    let &mut ParallelMonteCarloSearchAgent{
+      ref mut server, ref mut rng, .. } = self;
+    PreGame.choose_free_handicap(num_stones, |placed| {
+      // XXX(20160422): Each stone is the best black move in a one-off search
+      // of the position with the stones placed so far.
+      scratch_state.reset();
+      let stones: Vec<_> = placed.iter().map(|&pt| (Stone::Black, pt)).collect();
+      scratch_state.place_setup_stones(&stones).unwrap();
+      let worker_cfg = SearchWorkerConfig{
+        batch_cfg:    batch_cfg,
+        tree_batch_size:      None,
+        rollout_batch_size:   batch_size,
+        // The whole ranking is used, not just the best point.
+        early_stop:   false,
+      };
+      let mut search = ParallelMonteCarloSearch::new();
+      let (search_res, _) = search.join(
+          worker_cfg,
+          server,
+          Stone::Black,
+          &scratch_state,
+          SharedTree::new(tree_cfg),
+          rng);
+      let mut root_visits = search_res.root_visits;
+      root_visits.sort_by(|a, b| b.1.cmp(&a.1));
+      root_visits.into_iter().map(|(pt, _)| pt).collect()
+    })
+  }
+
   fn apply_action(&mut self, turn: Stone, action: Action) {
     // FIXME(20160114): the search result may be stale here since we do not yet
     // support pondering.
-    self.history.push((self.state.clone(), action, self.result.clone()));
-    self.ply += 1;
-    assert_eq!(self.history.len(), self.ply);
     match self.state.try_action(turn, action) {
       Ok(_)   => { self.state.commit(); }
       Err(_)  => { panic!("agent tried to apply an illegal action!"); }
     }
+    self.prev_results.push(self.result.clone());
+    assert_eq!(self.prev_results.len(), self.state.current_ply());
 
     // XXX(20160210): Step forward the tree, if possible.
-    let mut advance_success = false;
-    if self.tree.is_none() {
+    if self.trees.is_empty() {
       println!("DEBUG: ParallelSearchAgent: no tree to advance");
     }
-    if let Some(ref tree) = self.tree {
-      if tree.try_advance(turn, action) {
-        advance_success = true;
+    let mut advance_success = true;
+    for tree in self.trees.iter() {
+      if !tree.try_advance(turn, action) {
+        advance_success = false;
       }
     }
     if !advance_success {
-      self.tree = None;
+      self.trees.clear();
     }
   }
 
-  fn undo(&mut self) {
-    // FIXME(20151108): track ply; limit to how far we can undo.
-    if let Some((prev_state, _, prev_result)) = self.history.pop() {
-      self.ply -= 1;
-      self.state.clone_from(&prev_state);
-      self.tree = None;
-      self.result = prev_result;
-    } else {
-      self.ply = 0;
-      self.state.reset();
-      self.tree = None;
-      self.result = None;
+  fn undo(&mut self) -> UndoResult {
+    if self.state.undo_plies(1).is_err() {
+      return UndoResult::CannotUndo;
     }
+    self.trees.clear();
+    self.result = self.prev_results.pop().unwrap();
+    UndoResult::Okay
   }
 
   fn act(&mut self, turn: Stone) -> Action {
-    if self.history.len() > 0 {
-      match self.history[self.history.len() - 1].1 {
-        Action::Resign | Action::Pass => {
-          return Action::Pass;
-        }
-        _ => {}
-      }
-    }
     if self.player.is_none() {
       self.player = Some(turn);
       self.state.unsafe_set_current_turn(turn);
     }
     assert_eq!(turn, self.state.current_turn());
 
-    // FIXME(20160114): read remaining time and apply a time management policy.
-
-    let num_rollouts = self.config.num_rollouts;
-    let batch_size = self.config.batch_size;
+    if let Some(action) = self.book_action(turn) {
+      self.result = None;
+      return action;
+    }
 
-    let shared_tree = if self.tree.is_none() {
-      let shared_tree = SharedTree::new(self.tree_cfg);
-      self.tree = Some(shared_tree.clone());
-      shared_tree
+    let batch_cfg = self.batch_cfg;
+    let (search_res, _) = self.search(turn, batch_cfg, None);
+    self.update_dyn_komi(&search_res);
+    // XXX(20160422): The decision is made with the game komi in `self.state`,
+    // not the search komi. A low win rate under a dynamic komi only counts
+    // toward resigning if the same rollouts rescored under the game komi
+    // agree; a dynamic komi in the engine's favor can only delay resigning.
+    let action = if search_res.komi != self.komi && self.decision.is_low_value(&self.state, search_res.expected_value) {
+      let mut game_res = search_res.clone();
+      game_res.expected_value = self.game_komi_value(turn, &search_res);
+      self.decision.decide(&self.state, turn, &game_res)
     } else {
-      self.tree.as_ref().unwrap().clone()
+      self.decision.decide(&self.state, turn, &search_res)
     };
-    let worker_cfg = SearchWorkerConfig{
-      batch_cfg:    SearchWorkerBatchConfig::Fixed{num_batches: num_rollouts / batch_size},
-      tree_batch_size:      None,
-      rollout_batch_size:   batch_size,
+    if action != search_res.action {
+      println!("DEBUG: search result: decision: {:?} (low value moves: {})",
+          action, self.decision.num_low());
+    }
+    self.result = Some(search_res);
+    action
+  }
+
+  fn act_cleanup(&mut self, turn: Stone) -> Action {
+    if self.player.is_none() {
+      self.player = Some(turn);
+      self.state.unsafe_set_current_turn(turn);
+    }
+    assert_eq!(turn, self.state.current_turn());
+
+    // XXX(20160422): A first search without bias, in half of the move's
+    // budget, finds the dead stones of the current position. The rest of the
+    // budget goes to a second search biased toward capturing them, whose tree
+    // is then discarded so that later moves are searched without bias; if
+    // there are none, it continues the unbiased search instead.
+    let half_batch_cfg = half_batch_cfg(self.batch_cfg);
+    let (unbiased_res, unbiased_stats) = self.search(turn, half_batch_cfg, None);
+    let unbiased_dead_stones = self.dead_opponent_stones(turn, &unbiased_res);
+    let search_res = match self.remaining_batch_cfg(&unbiased_stats) {
+      None => unbiased_res,
+      Some(rest_batch_cfg) => {
+        if unbiased_dead_stones.is_empty() {
+          self.search(turn, rest_batch_cfg, None).0
+        } else {
+          let cleanup_cfg = CleanupPriorConfig{
+            turn:         turn,
+            dead_stones:  Arc::new(unbiased_dead_stones),
+            weight:       self.cleanup_weight,
+          };
+          let (search_res, _) = self.search(turn, rest_batch_cfg, Some(cleanup_cfg));
+          self.trees.clear();
+          search_res
+        }
+      }
     };
-    let mut search = ParallelMonteCarloSearch::new();
-    let (search_res, search_stats) = search.join(
-        worker_cfg,
-        &mut self.server,
-        self.player.unwrap(),
-        &self.state,
-        shared_tree,
-        //self.result.as_ref(),
-        &mut self.rng);
-    let action = search_res.action;
-    println!("DEBUG: search result: {:?}", search_res);
-    println!("DEBUG: search stats:  {:?}", search_stats);
+    self.update_dyn_komi(&search_res);
+
+    // Never resign while cleaning up, and only pass once no dead stones are
+    // left and passing does not lose.
+    let dead_stones = self.dead_opponent_stones(turn, &search_res);
+    let action = match search_res.action {
+      Action::Place{..} if dead_stones.is_empty() && self.decision.should_pass(&self.state, turn, &search_res) => Action::Pass,
+      action => action,
+    };
+    println!("DEBUG: search result: cleanup: dead stones left: {} action: {:?}",
+        dead_stones.len(), action);
     self.result = Some(search_res);
     action
   }
@@ -1,5 +1,6 @@
 use agents::{Agent};
 use board::{Board, RuleSet, PlayerRank, Stone, Point, Action};
+use hyper::{HyperparamConfig};
 use search::{SearchResult};
 use search::tree::{Tree, Trajectory, Search};
 use search::policies::convnet::{
@@ -26,6 +27,7 @@ pub struct SearchAgent {
   result:   Option<SearchResult>,
 
   //ctx:      DeviceContext,
+  hyper:        HyperparamConfig,
   prior_policy: ConvnetPriorPolicy,
   //tree_policy:  UctRaveTreePolicy,
   tree_policy:  ThompsonRaveTreePolicy,
@@ -35,11 +37,11 @@ pub struct SearchAgent {
 }
 
 impl SearchAgent {
-  pub fn new() -> SearchAgent {
+  pub fn new(hyper: HyperparamConfig) -> SearchAgent {
     //let ctx = DeviceContext::new(0);
     let mut prior_policy = ConvnetPriorPolicy::new();
-    //let mut tree_policy = UctRaveTreePolicy::new();
-    let mut tree_policy = ThompsonRaveTreePolicy::new();
+    //let mut tree_policy = UctRaveTreePolicy::new(&hyper);
+    let mut tree_policy = ThompsonRaveTreePolicy::new(&hyper);
     let batch_size = 256;
     //let mut roll_policy = QuasiUniformRolloutPolicy;
     let mut roll_policy = BatchConvnetRolloutPolicy::new(batch_size);
@@ -57,6 +59,7 @@ impl SearchAgent {
       ),
       result:   None,
       //ctx:      ctx,
+      hyper:        hyper,
       prior_policy: prior_policy,
       tree_policy:  tree_policy,
       roll_policy:  roll_policy,
@@ -119,7 +122,7 @@ impl Agent for SearchAgent {
     }
     assert_eq!(turn, self.state.current_turn());
     let mut search = Search::new(5120);
-    let mut tree = Tree::new(self.state.clone(), &mut self.prior_policy, &mut self.tree_policy);
+    let mut tree = Tree::new(self.state.clone(), &self.hyper, &mut self.prior_policy, &mut self.tree_policy);
     let result = search.join(
         &mut tree,
         &mut self.prior_policy,
@@ -95,6 +95,18 @@ impl RuleSet {
       },
     }
   }
+
+  /// Parses an SGF `RU` property value, e.g. "Japanese", "Chinese", "AGA",
+  /// "NZ". Ing and GOE rules are scored by area, so they map to Chinese.
+  pub fn parse_sgf_str(rules_str: &str) -> Option<RuleSet> {
+    match &rules_str.trim().to_lowercase() as &str {
+      "japanese" | "jp" | "korean" => Some(RuleSet::KgsJapanese),
+      "chinese" | "cn" | "ing" | "goe" => Some(RuleSet::KgsChinese),
+      "aga" => Some(RuleSet::KgsAga),
+      "nz" | "new zealand" | "new_zealand" => Some(RuleSet::KgsNewZealand),
+      _ => None,
+    }
+  }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, RustcDecodable, RustcEncodable, Debug)]
@@ -104,6 +116,42 @@ pub enum PlayerRank {
   Dan(u8),
 }
 
+impl PlayerRank {
+  /// Parses a rank as written by go servers and in SGF `BR`/`WR` properties,
+  /// e.g. "12k", "3d", "9p", "1k*", "2d?". Amateur dan ranks ("d") map to
+  /// `Ama` and professional ranks ("p") map to `Dan`.
+  pub fn parse_str(rank_str: &str) -> Option<PlayerRank> {
+    let s = rank_str.trim().trim_right_matches(|c: char| c == '*' || c == '?').trim();
+    let split = match s.find(|c: char| !c.is_digit(10)) {
+      Some(split) => split,
+      None => return None,
+    };
+    let level: u8 = match s[ .. split].parse() {
+      Ok(level) => level,
+      Err(_) => return None,
+    };
+    if level == 0 {
+      return None;
+    }
+    match s[split .. ].trim() {
+      "k" | "K" | "kyu" => if level <= 30 { Some(PlayerRank::Kyu(level)) } else { None },
+      "d" | "D" | "dan" => if level <= 9 { Some(PlayerRank::Ama(level)) } else { None },
+      "p" | "P" | "pro" => if level <= 9 { Some(PlayerRank::Dan(level)) } else { None },
+      _ => None,
+    }
+  }
+
+  /// A total ordering of ranks: 30k is 1, 1k is 30, 1d is 31, 9d is 39, and
+  /// professional ranks start above amateur 9d.
+  pub fn strength(&self) -> i32 {
+    match *self {
+      PlayerRank::Kyu(k) => 31 - k as i32,
+      PlayerRank::Ama(d) => 30 + d as i32,
+      PlayerRank::Dan(p) => 39 + p as i32,
+    }
+  }
+}
+
 #[derive(Clone, Copy, Eq, PartialEq, RustcDecodable, RustcEncodable, Debug)]
 pub enum Stone {
   Black = 0,
@@ -1,128 +1,340 @@
-use fastboard::{PosExt, Pos, Stone, FastBoard};
+use board::{Board, Stone, Point, Action};
 use gtp_board::{Coord};
-use table::{TransTable};
+use selfplay::{SelfPlayEpisode};
+use sgf::{Sgf, SgfRankStyle};
+use txnstate::{TxnStateConfig, TxnStateData, TxnState, zobrist_key};
 
-use bit_set::{BitSet};
+use byteorder::{ReadBytesExt, WriteBytesExt, LittleEndian};
 use rand::{Rng};
+use std::cmp::{Ordering};
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{File};
-use std::io::{BufRead, BufReader};
+use std::io::{Read, Write, BufReader, BufWriter};
 use std::path::{Path};
 
+pub const BOOK_MAGIC: &'static [u8; 4] = b"HBK\0";
+pub const BOOK_FORMAT_VERSION: u32 = 1;
+
+/// Distinguishes identical stone configurations with different turns.
+const WHITE_TO_MOVE_KEY: u64 = 0x3c6ef372fe94f82b;
+
+fn rotate_point(point: Point, rot: u8) -> Point {
+  Point::from_coord(point.to_coord().rotate(rot))
+}
+
+/// Hashes the stones on the board and the turn under each of the 8 board
+/// symmetries.
+fn symmetric_position_keys<Data>(state: &TxnState<Data>, turn: Stone) -> [u64; 8]
+where Data: TxnStateData + Clone {
+  let mut stones = vec![];
+  for p in 0 .. Board::SIZE {
+    let point = Point::from_idx(p);
+    let stone = state.current_stone(point);
+    if stone != Stone::Empty {
+      stones.push((stone, point));
+    }
+  }
+  let mut keys = [0; 8];
+  for rot in 0 .. 8 {
+    let mut key = match turn {
+      Stone::White => WHITE_TO_MOVE_KEY,
+      _ => 0,
+    };
+    for &(stone, point) in stones.iter() {
+      key ^= zobrist_key(stone, rotate_point(point, rot as u8));
+    }
+    keys[rot] = key;
+  }
+  keys
+}
+
+/// Returns the least hash of the position over the 8 board symmetries, and a
+/// symmetry which attains it. The ko point is not hashed; a book move may be
+/// an illegal ko recapture, so callers must still check legality.
+pub fn canonical_position_key<Data>(state: &TxnState<Data>, turn: Stone) -> (u64, u8)
+where Data: TxnStateData + Clone {
+  let keys = symmetric_position_keys(state, turn);
+  let mut best = (keys[0], 0);
+  for rot in 1 .. 8 {
+    if keys[rot] < best.0 {
+      best = (keys[rot], rot as u8);
+    }
+  }
+  best
+}
+
+/// Returns the canonical key of the position and the canonical point of a
+/// move in it. A symmetric position attains its least hash under several
+/// symmetries, which map equivalent moves to different points; the least
+/// such point is taken, so that equivalent moves are counted together.
+pub fn canonical_move_key<Data>(state: &TxnState<Data>, turn: Stone, point: Point) -> (u64, Point)
+where Data: TxnStateData + Clone {
+  let keys = symmetric_position_keys(state, turn);
+  let min_key = keys.iter().fold(keys[0], |acc, &key| if key < acc { key } else { acc });
+  let mut best_point = None;
+  for rot in 0 .. 8 {
+    if keys[rot] != min_key {
+      continue;
+    }
+    let rot_point = rotate_point(point, rot as u8);
+    match best_point {
+      Some(best) if best <= rot_point => {}
+      _ => best_point = Some(rot_point),
+    }
+  }
+  (min_key, best_point.unwrap())
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BookMove {
+  pub point:    Point,
+  pub count:    u32,
+  /// Games won by the player making the move.
+  pub wins:     u32,
+}
+
+impl BookMove {
+  pub fn win_rate(&self) -> f32 {
+    if self.count == 0 {
+      0.0
+    } else {
+      self.wins as f32 / self.count as f32
+    }
+  }
+}
+
 #[derive(Clone, Debug)]
 pub struct BookEntry {
-  turn:   Stone,
-  digest: BitSet,
-  plays:  Vec<Pos>,
+  pub key:      u64,
+  /// Moves in the canonical orientation, most frequent first.
+  pub moves:    Vec<BookMove>,
 }
 
-#[derive(Clone, Debug)]
-pub struct OpeningBook {
-  table:    TransTable,
-  entries:  Vec<BookEntry>,
+/// Accumulates move frequencies and win rates per canonical position from
+/// game records or self-play, for the first `max_ply` moves of each game.
+pub struct OpeningBookBuilder {
+  max_ply:      usize,
+  num_games:    usize,
+  // Keyed by canonical position key, then by canonical point.
+  stats:        HashMap<u64, BTreeMap<Point, (u32, u32)>>,
 }
 
-impl OpeningBook {
-  pub fn load_fuego<R>(path: &Path, rng: &mut R) -> OpeningBook where R: Rng {
-    // Fuego opening books are text files, where each line follows the syntax:
-    //
-    //    <board_dim> [match_sequence] | [play_sequence]
-    //
-    // The current board is matched w/ symmetry on the book's set of configurations,
-    // and one of the corresponding plays are selected.
-    let file = File::open(path)
-      .ok().expect("failed to open opening book file!");
-    let reader = BufReader::new(file);
-    let mut line_count = 0;
-    for _ in reader.lines() {
-      line_count += 1;
-    }
-    let mut table = TransTable::new(line_count * 16, rng);
-    let mut entries: Vec<BookEntry> = Vec::new();
-    let file = File::open(path)
-      .ok().expect("failed to open opening book file!");
-    let reader = BufReader::new(file);
-    for line in reader.lines() {
-      let line = line.unwrap();
-      let toks: Vec<&str> = line.split_whitespace().collect();
-      if toks.len() == 0 {
-        continue;
-      }
-      let board_dim: Option<usize> = toks[0].parse().ok();
-      match board_dim {
-        Some(19) => {}
-        _ => continue,
+impl OpeningBookBuilder {
+  pub fn new(max_ply: usize) -> OpeningBookBuilder {
+    OpeningBookBuilder{
+      max_ply:      max_ply,
+      num_games:    0,
+      stats:        HashMap::new(),
+    }
+  }
+
+  pub fn num_games(&self) -> usize {
+    self.num_games
+  }
+
+  /// Adds the opening of one game. The game is only added if the setup stones
+  /// and the moves up to `max_ply` (or the first pass) are legal.
+  pub fn add_game(&mut self, state_cfg: TxnStateConfig, setup: &[(Stone, Point)], moves: &[(Stone, Action)], outcome: Option<Stone>) -> Result<(), ()> {
+    let mut state = TxnState::new(state_cfg, ());
+    state.reset();
+    try!(state.place_setup_stones(setup));
+    let mut samples = Vec::with_capacity(self.max_ply);
+    for &(turn, action) in moves.iter().take(self.max_ply) {
+      let point = match action {
+        Action::Place{point} => point,
+        _ => break,
+      };
+      let (key, canonical_point) = canonical_move_key(&state, turn, point);
+      if state.try_action(turn, action).is_err() {
+        state.undo();
+        return Err(());
       }
-      let mut matches = Vec::new();
-      let mut plays = Vec::new();
-      let mut turn = Stone::Black;
-      let mut i = 1;
-      while i < toks.len() {
-        if toks[i] == "|" {
-          i += 1;
-          break;
-        }
-        let coord = Coord::from_code(toks[i].as_bytes());
-        let pos = Pos::from_coord(coord);
-        matches.push((turn, pos));
-        turn = turn.opponent();
-        i += 1;
+      state.commit();
+      samples.push((key, canonical_point, Some(turn) == outcome));
+    }
+    for &(key, point, win) in samples.iter() {
+      let entry = self.stats.entry(key).or_insert_with(|| BTreeMap::new())
+        .entry(point).or_insert((0, 0));
+      entry.0 += 1;
+      if win {
+        entry.1 += 1;
       }
-      while i < toks.len() {
-        let coord = Coord::from_code(toks[i].as_bytes());
-        let pos = Pos::from_coord(coord);
-        plays.push(pos);
-        i += 1;
+    }
+    self.num_games += 1;
+    Ok(())
+  }
+
+  pub fn add_sgf(&mut self, sgf: &Sgf, rank_style: SgfRankStyle) -> Result<(), ()> {
+    if sgf.board_dim != Board::DIM as i64 {
+      return Err(());
+    }
+    let mut moves = Vec::with_capacity(sgf.moves.len());
+    for &(ref player, ref mov) in sgf.moves.iter() {
+      let action = match mov as &str {
+        "Pass"    => Action::Pass,
+        "Resign"  => Action::Resign,
+        x         => match Coord::parse_code_str(x) {
+          Some(coord) => Action::Place{point: Point::from_coord(coord)},
+          None => return Err(()),
+        },
+      };
+      moves.push((Stone::from_code_str(player), action));
+    }
+    self.add_game(sgf.infer_state_cfg(rank_style), &sgf.setup_stones(), &moves, sgf.outcome())
+  }
+
+  pub fn add_selfplay_episode(&mut self, episode: &SelfPlayEpisode) -> Result<(), ()> {
+    let moves: Vec<_> = episode.positions.iter()
+      .map(|pos| (pos.turn, pos.action))
+      .collect();
+    self.add_game(episode.state_cfg, &[], &moves, episode.outcome)
+  }
+
+  /// Keeps the moves played at least `min_count` times.
+  pub fn build(&self, min_count: u32) -> OpeningBook {
+    let mut entries = vec![];
+    for (&key, points) in self.stats.iter() {
+      let mut moves: Vec<_> = points.iter()
+        .filter(|&(_, &(count, _))| count >= min_count)
+        .map(|(&point, &(count, wins))| BookMove{point: point, count: count, wins: wins})
+        .collect();
+      if moves.is_empty() {
+        continue;
       }
-      for t in Pos::iter_trans() {
-        let mut hash: u64 = 0;
-        let mut digest = BitSet::with_capacity(FastBoard::BOARD_SIZE * 2);
-        for &(stone, pos) in matches.iter() {
-          let pos = pos.trans(t);
-          match stone {
-            Stone::Black => {
-              hash ^= table.key_stone(stone, pos);
-              digest.insert(pos.idx() * 2);
-            }
-            Stone::White => {
-              hash ^= table.key_stone(stone, pos);
-              digest.insert(pos.idx() * 2 + 1);
-            }
-            _ => unreachable!(),
-          }
-        }
-        let plays: Vec<_> = plays.iter()
-          .map(|&p| p.trans(t))
-          .collect();
-        let entry = BookEntry{turn: turn, digest: digest, plays: plays};
-        let new_id = entries.len();
-        if table.insert(hash, new_id, |cmp_id| entries[cmp_id].digest == entry.digest).is_none() {
-          entries.push(entry);
+      moves.sort_by(|a, b| match b.count.cmp(&a.count) {
+        Ordering::Equal => a.point.cmp(&b.point),
+        x => x,
+      });
+      entries.push(BookEntry{key: key, moves: moves});
+    }
+    entries.sort_by(|a, b| a.key.cmp(&b.key));
+    OpeningBook{
+      max_ply:  self.max_ply,
+      entries:  entries,
+    }
+  }
+}
+
+/// An opening book of canonical positions. On disk it is a little endian
+/// binary file: the magic, the format version, `max_ply`, the number of
+/// entries, then for each entry (in key order) the key, the number of moves,
+/// and for each move the point index, count, and wins.
+#[derive(Clone, Debug)]
+pub struct OpeningBook {
+  pub max_ply:  usize,
+  pub entries:  Vec<BookEntry>,
+}
+
+impl OpeningBook {
+  pub fn open(path: &Path) -> Result<OpeningBook, ()> {
+    let file = match File::open(path) {
+      Ok(file) => file,
+      Err(_) => return Err(()),
+    };
+    let mut reader = BufReader::new(file);
+    let mut magic = [0; 4];
+    if reader.read_exact(&mut magic).is_err() || &magic != BOOK_MAGIC {
+      println!("WARNING: book: not an opening book: {:?}", path);
+      return Err(());
+    }
+    let version = try!(reader.read_u32::<LittleEndian>().map_err(|_| ()));
+    if version != BOOK_FORMAT_VERSION {
+      println!("WARNING: book: format version mismatch: {} (expected {})",
+          version, BOOK_FORMAT_VERSION);
+      return Err(());
+    }
+    let max_ply = try!(reader.read_u32::<LittleEndian>().map_err(|_| ())) as usize;
+    let num_entries = try!(reader.read_u32::<LittleEndian>().map_err(|_| ())) as usize;
+    let mut entries = Vec::with_capacity(num_entries);
+    for _ in 0 .. num_entries {
+      let key = try!(reader.read_u64::<LittleEndian>().map_err(|_| ()));
+      let num_moves = try!(reader.read_u16::<LittleEndian>().map_err(|_| ())) as usize;
+      let mut moves = Vec::with_capacity(num_moves);
+      for _ in 0 .. num_moves {
+        let idx = try!(reader.read_u16::<LittleEndian>().map_err(|_| ())) as usize;
+        let count = try!(reader.read_u32::<LittleEndian>().map_err(|_| ()));
+        let wins = try!(reader.read_u32::<LittleEndian>().map_err(|_| ()));
+        if idx >= Board::SIZE {
+          return Err(());
         }
+        moves.push(BookMove{point: Point::from_idx(idx), count: count, wins: wins});
       }
+      entries.push(BookEntry{key: key, moves: moves});
     }
-    let book = OpeningBook{
-      table:    table,
+    Ok(OpeningBook{
+      max_ply:  max_ply,
       entries:  entries,
+    })
+  }
+
+  pub fn save(&self, path: &Path) {
+    let file = match File::create(path) {
+      Ok(file) => file,
+      Err(e) => panic!("failed to create opening book: {:?}", e),
     };
-    book
+    let mut writer = BufWriter::new(file);
+    writer.write_all(BOOK_MAGIC).unwrap();
+    writer.write_u32::<LittleEndian>(BOOK_FORMAT_VERSION).unwrap();
+    writer.write_u32::<LittleEndian>(self.max_ply as u32).unwrap();
+    writer.write_u32::<LittleEndian>(self.entries.len() as u32).unwrap();
+    for entry in self.entries.iter() {
+      writer.write_u64::<LittleEndian>(entry.key).unwrap();
+      writer.write_u16::<LittleEndian>(entry.moves.len() as u16).unwrap();
+      for mov in entry.moves.iter() {
+        writer.write_u16::<LittleEndian>(mov.point.idx() as u16).unwrap();
+        writer.write_u32::<LittleEndian>(mov.count).unwrap();
+        writer.write_u32::<LittleEndian>(mov.wins).unwrap();
+      }
+    }
   }
 
-  pub fn lookup(&self, turn: Stone, digest: &BitSet) -> Option<&[Pos]> {
-    let mut hash: u64 = 0;
-    for x in digest.iter() {
-      let p = x / 2;
-      let s = match x & 1 {
-        0 => Stone::Black,
-        1 => Stone::White,
-        _ => unreachable!(),
-      };
-      hash ^= self.table.key_stone(s, p as Pos);
+  pub fn num_moves(&self) -> usize {
+    self.entries.iter().fold(0, |acc, entry| acc + entry.moves.len())
+  }
+
+  /// The book moves for `turn` in the current position, in the orientation of
+  /// the board, most frequent first.
+  pub fn lookup<Data>(&self, state: &TxnState<Data>, turn: Stone) -> Vec<BookMove>
+  where Data: TxnStateData + Clone {
+    let (key, rot) = canonical_position_key(state, turn);
+    match self.entries.binary_search_by(|entry| entry.key.cmp(&key)) {
+      Ok(idx) => {
+        self.entries[idx].moves.iter()
+          .map(|mov| BookMove{
+            point:  Point::from_coord(mov.point.to_coord().unrotate(rot)),
+            .. *mov
+          })
+          .collect()
+      }
+      Err(_) => vec![],
+    }
+  }
+
+  /// Chooses a book move played at least `min_count` times. A zero
+  /// `temperature` chooses the most frequent move; otherwise moves are sampled
+  /// in proportion to `count^(1/temperature)`.
+  pub fn choose<Data, R>(&self, state: &TxnState<Data>, turn: Stone, min_count: u32, temperature: f32, rng: &mut R) -> Option<BookMove>
+  where Data: TxnStateData + Clone, R: Rng {
+    let moves: Vec<_> = self.lookup(state, turn).into_iter()
+      .filter(|mov| mov.count >= min_count)
+      .collect();
+    if moves.is_empty() {
+      return None;
+    }
+    if temperature <= 0.0 {
+      return Some(moves[0]);
     }
-    if let Some(query_id) = self.table.find(hash, |cmp_id| &self.entries[cmp_id].digest == digest) {
-      if self.entries[query_id].turn == turn {
-        return Some(&self.entries[query_id].plays);
+    let weights: Vec<f32> = moves.iter()
+      .map(|mov| (mov.count as f32).powf(1.0 / temperature))
+      .collect();
+    let total: f32 = weights.iter().fold(0.0, |acc, &w| acc + w);
+    let mut u = rng.gen::<f32>() * total;
+    for (mov, &w) in moves.iter().zip(weights.iter()) {
+      if u < w {
+        return Some(*mov);
       }
+      u -= w;
     }
-    None
+    moves.last().map(|&mov| mov)
   }
 }
@@ -35,7 +35,9 @@ pub enum AgentMsg {
     byoyomi_time_secs: i32,
   },
   CheckTime,
-  RecvTime,
+  RecvTime{
+    time_left_s:  i32,
+  },
   SubmitAction{
     turn:   Stone,
     action: Action,
@@ -54,10 +56,16 @@ pub enum AgentMsg {
     time_left_s:  Option<i32>,
   },
   FinishMatch,
+  // XXX(20160405): `FinishMatch` only ends the current game; the agent runloop
+  // keeps going (for consecutive games) until it receives `Quit`.
+  Quit,
 }
 
 pub trait AsyncAgent {
-  fn spawn_runloop(barrier: Arc<Barrier>, agent_in_rx: Receiver<AgentMsg>, agent_out_tx: Sender<AgentMsg>, load_save_path: Option<PathBuf>) -> JoinHandle<()>;
+  /// Passed to the agent runloop by the client, e.g. the engine config.
+  type Config: Send + 'static;
+
+  fn spawn_runloop(agent_cfg: Self::Config, barrier: Arc<Barrier>, agent_in_rx: Receiver<AgentMsg>, agent_out_tx: Sender<AgentMsg>, load_save_path: Option<PathBuf>) -> JoinHandle<()>;
 }
 
 /*pub struct HelloAsyncAgent;
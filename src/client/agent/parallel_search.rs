@@ -1,13 +1,16 @@
-use board::{RuleSet, PlayerRank, Stone, Point, Action};
+use board::{Stone, Point, Action};
+use book::{OpeningBook};
 use client::agent::{AgentMsg, AsyncAgent};
+use config::{EngineConfig, EngineBookConfig};
 use gtp_board::{Coord};
+use search::decision::{DecisionPolicy};
 use search::parallel_policies::convnet::{
   ConvnetPolicyWorkerBuilder, ConvnetPolicyWorker,
 };
 use search::parallel_tree::{
   MonteCarloSearchConfig,
+  ShapePriorConfig,
   TreePolicyConfig,
-  HorizonConfig,
   SharedTree,
   SearchWorkerConfig,
   SearchWorkerBatchConfig,
@@ -54,40 +57,32 @@ struct AgentImpl {
   komi:             f32,
   main_time_s:      i32,
   byoyomi_time_s:   i32,
+  min_remaining_ms: usize,
+  rtt_ms:           usize,
   //start_time:       Option<Timespec>,
 
   save_path:    PathBuf,
   save_file:    File,
-  history:  Vec<(TxnState<TxnStateNodeData>, Stone, Action)>,
   ply:      usize,
   state:    TxnState<TxnStateNodeData>,
   tree:     Option<SharedTree>,
+  decision: DecisionPolicy,
+
+  book:         Option<OpeningBook>,
+  book_cfg:     EngineBookConfig,
+  shape_prior:  Option<ShapePriorConfig>,
 
   rng:      Xorshiftplus128Rng,
   server:   ParallelMonteCarloSearchServer<ConvnetPolicyWorker>,
 }
 
 impl AgentImpl {
-  pub fn new() -> AgentImpl {
-    let state_cfg = TxnStateConfig{
-      rules:    RuleSet::KgsJapanese.rules(),
-      ranks:    [PlayerRank::Dan(9), PlayerRank::Dan(9)],
-      komi:     6.5,
-    };
-    let search_cfg = MonteCarloSearchConfig{
-      batch_size:   256,
-      // FIXME(20160310): Number of rollouts should be specified during search.
-      num_rollouts: 0,
-    };
-    let tree_cfg = TreePolicyConfig{
-      horizon_cfg:  HorizonConfig::Fixed{max_horizon: 30},
-      visit_thresh: 24,
-      mc_scale:     0.0625,
-      prior_equiv:  16.0,
-      rave:         false,
-      rave_equiv:   0.0,
-      virtual_loss: false,
-    };
+  pub fn new(cfg: &EngineConfig) -> AgentImpl {
+    let state_cfg = cfg.state_cfg();
+    // FIXME(20160310): Number of rollouts should be specified during search;
+    // the client always searches with a time limit.
+    let search_cfg = cfg.search_cfg();
+    let tree_cfg = cfg.tree_policy_cfg();
 
     // FIXME(20160316): give the save filename a unique part, e.g. timestamp.
     let save_path = PathBuf::from("save.tmp.txt");
@@ -95,31 +90,53 @@ impl AgentImpl {
     // FIXME(20160316): open save file with RW permissions.
     let save_file = File::create(&save_path).unwrap();
 
-    let num_workers = 2 * CudaDevice::count().unwrap();
+    let num_workers = cfg.num_workers(2 * CudaDevice::count().unwrap());
     let rounded_batch_size = (search_cfg.batch_size + num_workers - 1) / num_workers * num_workers;
     let worker_batch_capacity = rounded_batch_size / num_workers;
 
+    let book = match cfg.open_book() {
+      Ok(book) => book,
+      Err(e) => panic!("FATAL: agent: {}", e),
+    };
+    let shape_prior = match cfg.shape_prior_cfg() {
+      Ok(shape_prior) => shape_prior,
+      Err(e) => panic!("FATAL: agent: {}", e),
+    };
+    let node_data = match shape_prior {
+      Some(_) => TxnStateNodeData::with_shapes(),
+      None => TxnStateNodeData::new(),
+    };
+
     AgentImpl{
       state_cfg:    state_cfg,
       search_cfg:   search_cfg,
       tree_cfg:     tree_cfg,
       state_machine:    AgentStateMachine::Reset,
       our_stone:        None,
-      komi:             6.5,
-      main_time_s:      0,
-      byoyomi_time_s:   0,
+      komi:             state_cfg.komi,
+      main_time_s:      cfg.time.main_time_s as i32,
+      byoyomi_time_s:   cfg.time.byoyomi_time_s as i32,
+      min_remaining_ms: (1000.0 * cfg.time.min_remaining_s) as usize,
+      rtt_ms:           cfg.time.rtt_ms,
       //start_time:       None,
       save_path:    save_path,
       save_file:    save_file,
-      history:  vec![],
       ply:      0,
-      state:    TxnState::new(state_cfg, TxnStateNodeData::new()),
+      state:    TxnState::new(state_cfg, node_data),
       tree:     None,
+      decision: DecisionPolicy::new(cfg.decision_cfg()),
+      book:     book,
+      book_cfg: cfg.book.clone(),
+      shape_prior:  shape_prior,
       rng:      Xorshiftplus128Rng::new(&mut thread_rng()),
       server:   ParallelMonteCarloSearchServer::new(
           state_cfg,
           num_workers, 1, worker_batch_capacity,
-          ConvnetPolicyWorkerBuilder::new(tree_cfg, num_workers, 1, worker_batch_capacity),
+          ConvnetPolicyWorkerBuilder::new(tree_cfg, num_workers, 1, worker_batch_capacity)
+            .with_save_paths(
+                PathBuf::from(&cfg.network.prior_model),
+                PathBuf::from(&cfg.network.rollout_model),
+            ),
       ),
     }
   }
@@ -158,9 +175,37 @@ impl AgentImpl {
     self.server.wait_ready();
   }
 
+  fn new_tree(&self) -> SharedTree {
+    match self.shape_prior {
+      Some(ref shape_cfg) => SharedTree::with_shape_prior(self.tree_cfg, shape_cfg.clone()),
+      None => SharedTree::new(self.tree_cfg),
+    }
+  }
+
+  fn book_action(&mut self, turn: Stone) -> Option<Action> {
+    if self.state.current_ply() >= self.book_cfg.max_ply {
+      return None;
+    }
+    let book_move = match self.book {
+      Some(ref book) => book.choose(&self.state, turn, self.book_cfg.min_count, self.book_cfg.temperature, &mut self.rng),
+      None => None,
+    };
+    let action = match book_move {
+      Some(book_move) => Action::Place{point: book_move.point},
+      None => return None,
+    };
+    let legal = self.state.try_action(turn, action).is_ok();
+    self.state.undo();
+    if !legal {
+      return None;
+    }
+    println!("DEBUG: agent: book move: {:?}", action);
+    Some(action)
+  }
+
   pub fn ponder(&mut self) {
     let shared_tree = if self.tree.is_none() {
-      let shared_tree = SharedTree::new(self.tree_cfg);
+      let shared_tree = self.new_tree();
       self.tree = Some(shared_tree.clone());
       shared_tree
     } else {
@@ -171,6 +216,7 @@ impl AgentImpl {
       batch_cfg:    SearchWorkerBatchConfig::Fixed{num_batches: 1},
       tree_batch_size:      None,
       rollout_batch_size:   self.search_cfg.batch_size,
+      early_stop:   false,
     };
 
     let mut search = ParallelMonteCarloSearch::new();
@@ -187,12 +233,17 @@ impl AgentImpl {
   pub fn search(&mut self, remaining_time_ms: usize) -> (Action, Option<MonteCarloSearchResult>) {
     // XXX(20160318): Always pass if there is little remaining time left;
     // don't risk running out of time (assuming zero byoyomi).
-    if remaining_time_ms <= 15000 {
+    if remaining_time_ms <= self.min_remaining_ms {
       return (Action::Pass, None);
     }
 
+    let turn = self.our_stone.unwrap();
+    if let Some(action) = self.book_action(turn) {
+      return (action, None);
+    }
+
     let shared_tree = if self.tree.is_none() {
-      let shared_tree = SharedTree::new(self.tree_cfg);
+      let shared_tree = self.new_tree();
       self.tree = Some(shared_tree.clone());
       shared_tree
     } else {
@@ -208,12 +259,12 @@ impl AgentImpl {
 
     let worker_cfg = SearchWorkerConfig{
       batch_cfg:    SearchWorkerBatchConfig::TimeLimit{
-        // XXX(20160308): 200 ms corresponds to the expected RTT.
         budget_ms:  allocated_time_ms.floor() as usize,
-        tol_ms:     200,
+        tol_ms:     self.rtt_ms,
       },
       tree_batch_size:      None,
       rollout_batch_size:   self.search_cfg.batch_size,
+      early_stop:   true,
     };
 
     let mut search = ParallelMonteCarloSearch::new();
@@ -228,11 +279,15 @@ impl AgentImpl {
     println!("DEBUG: agent: search result:  {:?}", res);
     println!("DEBUG: agent: search stats:   {:?}", stats);
 
-    (res.action, Some(res))
+    let action = self.decision.decide(&self.state, self.our_stone.unwrap(), &res);
+    if action != res.action {
+      println!("DEBUG: agent: decision: {:?} (low value moves: {})",
+          action, self.decision.num_low());
+    }
+    (action, Some(res))
   }
 
   pub fn step(&mut self, turn: Stone, action: Action) -> Result<(), ()> {
-    let prev_state = self.state.clone();
     if self.state.try_action(turn, action).is_err() {
       self.state.undo();
       return Err(());
@@ -256,7 +311,6 @@ impl AgentImpl {
     };
     writeln!(self.save_file, "{},{},{}", self.ply, turn_str, action_str);
 
-    self.history.push((prev_state, turn, action));
     self.ply += 1;
 
     // Update the tree, if possible.
@@ -277,14 +331,17 @@ impl AgentImpl {
 pub struct ParallelSearchAsyncAgent;
 
 impl AsyncAgent for ParallelSearchAsyncAgent {
+  type Config = EngineConfig;
+
   fn spawn_runloop(
+      cfg: EngineConfig,
       barrier: Arc<Barrier>,
       agent_in_rx: Receiver<AgentMsg>,
       agent_out_tx: Sender<AgentMsg>,
       load_save_path: Option<PathBuf>,
   ) -> JoinHandle<()> {
     spawn(move || {
-      let mut agent = AgentImpl::new();
+      let mut agent = AgentImpl::new(&cfg);
       if let Some(load_save_path) = load_save_path {
         agent.load_save(load_save_path);
       }
@@ -313,10 +370,10 @@ impl AsyncAgent for ParallelSearchAsyncAgent {
             agent.main_time_s = main_time_secs;
             agent.byoyomi_time_s = byoyomi_time_secs;
 
-            agent.history.clear();
             agent.state.reset();
             agent.ply = 0;
             agent.tree = None;
+            agent.decision.reset();
 
             if Stone::Black == our_stone && !skip_as_black {
               agent.state_machine = AgentStateMachine::OurTurn;
@@ -355,8 +412,8 @@ impl AsyncAgent for ParallelSearchAsyncAgent {
             }
           }
 
-          Ok(AgentMsg::RecvTime) => {
-            println!("DEBUG: agent: recv time");
+          Ok(AgentMsg::RecvTime{time_left_s}) => {
+            println!("DEBUG: agent: recv time: {} s", time_left_s);
           }
 
           Ok(AgentMsg::RecvAction{turn, action, time_left_s, ..}) => {
@@ -410,6 +467,16 @@ impl AsyncAgent for ParallelSearchAsyncAgent {
 
           Ok(AgentMsg::FinishMatch) => {
             println!("DEBUG: agent: finish match");
+            agent.our_stone = None;
+            agent.state.reset();
+            agent.ply = 0;
+            agent.tree = None;
+            agent.decision.reset();
+            agent.state_machine = AgentStateMachine::Reset;
+          }
+
+          Ok(AgentMsg::Quit) => {
+            println!("DEBUG: agent: quit");
             break;
           }
 
@@ -438,9 +505,9 @@ impl AsyncAgent for ParallelSearchAsyncAgent {
             }
           }
 
-          Err(_) => {
-            println!("WARNING: agent: channel read error");
-            //break;
+          Err(TryRecvError::Disconnected) => {
+            println!("WARNING: agent: channel disconnected");
+            break;
           }
         }
       }
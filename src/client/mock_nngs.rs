@@ -0,0 +1,569 @@
+//! An in-process stand-in for an NNGS server, speaking just enough of the
+//! client-mode dialect (prompts, `9` info, `15` moves, `20` scores, `21`
+//! results) to challenge a client, play a scripted game, score it, and hang
+//! up. It is meant for integration tests of `client::nngs`.
+
+use board::{Stone, Action, Point};
+use client::agent::{AgentMsg, AsyncAgent};
+use gtp_board::{Coord};
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::{PathBuf};
+use std::sync::{Arc, Barrier};
+use std::sync::mpsc::{Sender, Receiver};
+use std::thread::{JoinHandle, spawn};
+
+#[derive(Clone, Debug)]
+pub struct MockNngsConfig {
+  /// The mock's own login, which is the client's opponent.
+  pub login:        String,
+  /// The rank reported for the mock by `stats`.
+  pub rating:       String,
+  pub mock_stone:   Stone,
+  /// Whether the mock challenges the client, or waits to be challenged.
+  pub challenge:    bool,
+  pub main_time_mins:     i32,
+  pub byoyomi_time_mins:  i32,
+  /// The mock's moves in order; once exhausted, the mock passes.
+  pub moves:        Vec<Action>,
+  /// The result reported at the end of the scoring phase.
+  pub score_result: String,
+  /// Number of games to offer the client before waiting for `quit`.
+  pub num_games:    usize,
+  /// Hang up (once) after this many moves have been played in a game, to
+  /// exercise reconnecting.
+  pub drop_after_moves: Option<usize>,
+}
+
+impl Default for MockNngsConfig {
+  fn default() -> MockNngsConfig {
+    MockNngsConfig{
+      login:        "mockbot".to_string(),
+      rating:       "5k".to_string(),
+      mock_stone:   Stone::White,
+      challenge:    true,
+      main_time_mins:     10,
+      byoyomi_time_mins:  0,
+      moves:        vec![],
+      score_result: "W 185.5 B 175.0".to_string(),
+      num_games:    1,
+      drop_after_moves: None,
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct MockNngsGame {
+  pub game_id:  i32,
+  pub client:   String,
+  pub client_stone: Stone,
+  pub moves:    Vec<(Stone, Action)>,
+  pub removed:  Vec<Point>,
+  pub result:   String,
+}
+
+/// Everything the mock saw, returned when the server thread exits.
+#[derive(Clone, Default, Debug)]
+pub struct MockNngsTranscript {
+  pub connections:  usize,
+  pub commands:     Vec<String>,
+  pub declined:     usize,
+  pub games:        Vec<MockNngsGame>,
+}
+
+pub struct MockNngsServer {
+  port:     u16,
+  handle:   JoinHandle<MockNngsTranscript>,
+}
+
+impl MockNngsServer {
+  pub fn spawn(cfg: MockNngsConfig) -> MockNngsServer {
+    let listener = match TcpListener::bind("127.0.0.1:0") {
+      Ok(listener) => listener,
+      Err(e) => panic!("failed to bind mock nngs server: {:?}", e),
+    };
+    let port = listener.local_addr().unwrap().port();
+    let handle = spawn(move || {
+      let mut server = MockServerImpl::new(cfg);
+      for stream in listener.incoming() {
+        let stream = match stream {
+          Ok(stream) => stream,
+          Err(_) => break,
+        };
+        server.transcript.connections += 1;
+        match server.serve(stream) {
+          Ok(true) => break,
+          Ok(false) | Err(_) => {
+            if server.is_finished() {
+              break;
+            }
+          }
+        }
+      }
+      server.transcript
+    });
+    MockNngsServer{
+      port:     port,
+      handle:   handle,
+    }
+  }
+
+  pub fn host(&self) -> String {
+    "127.0.0.1".to_string()
+  }
+
+  pub fn port(&self) -> u16 {
+    self.port
+  }
+
+  /// Waits for the client to quit (or hang up after the last game) and
+  /// returns the transcript.
+  pub fn join(self) -> MockNngsTranscript {
+    match self.handle.join() {
+      Ok(transcript) => transcript,
+      Err(_) => panic!("mock nngs server panicked"),
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MockPhase {
+  Idle,
+  Challenged,
+  Playing,
+  Scoring,
+}
+
+struct MockServerImpl {
+  cfg:          MockNngsConfig,
+  transcript:   MockNngsTranscript,
+  phase:        MockPhase,
+  client:       String,
+  game:         Option<MockNngsGame>,
+  mock_move_idx:    usize,
+  dropped:      bool,
+}
+
+fn stone_code(stone: Stone) -> &'static str {
+  match stone {
+    Stone::Black => "B",
+    Stone::White => "W",
+    _ => unreachable!(),
+  }
+}
+
+fn stone_name(stone: Stone) -> &'static str {
+  match stone {
+    Stone::Black => "Black",
+    Stone::White => "White",
+    _ => unreachable!(),
+  }
+}
+
+fn action_code(action: Action) -> String {
+  match action {
+    Action::Place{point} => point.to_coord().to_string(),
+    Action::Pass => "Pass".to_string(),
+    Action::Resign => "Resign".to_string(),
+  }
+}
+
+fn send(writer: &mut TcpStream, lines: &[String]) -> io::Result<()> {
+  for line in lines.iter() {
+    try!(writer.write_all(line.as_bytes()));
+    try!(writer.write_all(b"\r\n"));
+  }
+  writer.flush()
+}
+
+fn read_cmd(reader: &mut BufReader<TcpStream>) -> io::Result<Option<String>> {
+  let mut buf = String::new();
+  match try!(reader.read_line(&mut buf)) {
+    0 => Ok(None),
+    _ => Ok(Some(buf.trim().to_string())),
+  }
+}
+
+impl MockServerImpl {
+  fn new(cfg: MockNngsConfig) -> MockServerImpl {
+    MockServerImpl{
+      cfg:          cfg,
+      transcript:   Default::default(),
+      phase:        MockPhase::Idle,
+      client:       String::new(),
+      game:         None,
+      mock_move_idx:    0,
+      dropped:      false,
+    }
+  }
+
+  fn is_finished(&self) -> bool {
+    self.game.is_none() && self.transcript.games.len() + self.transcript.declined >= self.cfg.num_games
+  }
+
+  fn white_black(&self) -> (String, String) {
+    match self.cfg.mock_stone {
+      Stone::White => (self.cfg.login.clone(), self.client.clone()),
+      Stone::Black => (self.client.clone(), self.cfg.login.clone()),
+      _ => unreachable!(),
+    }
+  }
+
+  fn challenge_lines(&mut self) -> Vec<String> {
+    if !self.cfg.challenge || self.is_finished() {
+      return vec!["1 5".to_string()];
+    }
+    self.phase = MockPhase::Challenged;
+    vec![
+      format!("9 Match [19x19] in {} minutes requested with {} as {}.",
+          self.cfg.main_time_mins, self.cfg.login, stone_name(self.cfg.mock_stone)),
+      format!("9 Use <match {} {} 19 {} {}> or <decline {}> to respond.",
+          self.cfg.login, stone_code(self.cfg.mock_stone.opponent()),
+          self.cfg.main_time_mins, self.cfg.byoyomi_time_mins, self.cfg.login),
+      "1 5".to_string(),
+    ]
+  }
+
+  fn header_line(&self) -> String {
+    let (white, black) = self.white_black();
+    let time_s = 60 * self.cfg.main_time_mins;
+    format!("15 Game {} I: {} (0 {} -1) vs {} (0 {} -1)",
+        self.game.as_ref().unwrap().game_id, white, time_s, black, time_s)
+  }
+
+  fn move_lines(&self, move_number: usize) -> Vec<String> {
+    let (turn, action) = self.game.as_ref().unwrap().moves[move_number];
+    vec![
+      self.header_line(),
+      format!("15 {:3}({}): {}", move_number, stone_code(turn), action_code(action)),
+    ]
+  }
+
+  fn to_move(&self) -> Stone {
+    match self.game.as_ref().unwrap().moves.len() % 2 {
+      0 => Stone::Black,
+      _ => Stone::White,
+    }
+  }
+
+  fn is_double_pass(&self) -> bool {
+    let moves = &self.game.as_ref().unwrap().moves;
+    moves.len() >= 2
+        && moves[moves.len() - 1].1 == Action::Pass
+        && moves[moves.len() - 2].1 == Action::Pass
+  }
+
+  fn finish_game(&mut self, result: String) -> Vec<String> {
+    let mut game = self.game.take().unwrap();
+    let (white, black) = self.white_black();
+    let result_line = format!("{{Game {}: {} vs {} : {}}}", game.game_id, white, black, result);
+    game.result = result;
+    self.transcript.games.push(game);
+    self.phase = MockPhase::Idle;
+    let mut lines = vec![format!("21 {}", result_line)];
+    lines.extend(self.challenge_lines());
+    lines
+  }
+
+  /// Plays the mock's next scripted move, returning the lines to send.
+  fn play_mock_move(&mut self) -> Vec<String> {
+    let action = if self.mock_move_idx < self.cfg.moves.len() {
+      self.cfg.moves[self.mock_move_idx]
+    } else {
+      Action::Pass
+    };
+    self.mock_move_idx += 1;
+    if let Action::Resign = action {
+      let winner = stone_code(self.cfg.mock_stone.opponent());
+      let mut lines = vec![format!("9 {} has resigned the game.", self.cfg.login)];
+      lines.extend(self.finish_game(format!("{}+Resign", winner)));
+      return lines;
+    }
+    let mock_stone = self.cfg.mock_stone;
+    self.game.as_mut().unwrap().moves.push((mock_stone, action));
+    let move_number = self.game.as_ref().unwrap().moves.len() - 1;
+    let mut lines = self.move_lines(move_number);
+    lines.extend(self.after_move());
+    lines
+  }
+
+  fn after_move(&mut self) -> Vec<String> {
+    if self.is_double_pass() {
+      self.phase = MockPhase::Scoring;
+      vec![
+        "9 You can check your score with the score command, type 'done' when finished.".to_string(),
+        "1 7".to_string(),
+      ]
+    } else if self.to_move() == self.cfg.mock_stone {
+      self.play_mock_move()
+    } else {
+      vec!["1 6".to_string()]
+    }
+  }
+
+  fn start_game(&mut self, client_stone: Stone) -> Vec<String> {
+    self.phase = MockPhase::Playing;
+    self.mock_move_idx = 0;
+    self.game = Some(MockNngsGame{
+      game_id:  self.transcript.games.len() as i32 + 1,
+      client:   self.client.clone(),
+      client_stone: client_stone,
+      moves:    vec![],
+      removed:  vec![],
+      result:   String::new(),
+    });
+    let mut lines = vec![format!("9 Creating match [{}] with {}.",
+        self.game.as_ref().unwrap().game_id, self.client)];
+    lines.extend(self.after_move());
+    lines
+  }
+
+  fn resume_game(&mut self) -> Vec<String> {
+    let mut lines = vec![];
+    let num_moves = self.game.as_ref().unwrap().moves.len();
+    for move_number in 0 .. num_moves {
+      lines.extend(self.move_lines(move_number));
+    }
+    match self.phase {
+      MockPhase::Scoring => lines.push("1 7".to_string()),
+      _ => lines.extend(self.after_move()),
+    }
+    lines
+  }
+
+  fn handle_cmd(&mut self, cmd: &str) -> Vec<String> {
+    let toks: Vec<_> = cmd.split_whitespace().collect();
+    if toks.is_empty() {
+      return vec![];
+    }
+    match (self.phase, toks[0]) {
+      (_, "time") if self.game.is_some() => {
+        let (white, black) = self.white_black();
+        let time_s = 60 * self.cfg.main_time_mins;
+        vec![
+          format!("9 Game {}: {} ({}) vs {} ({})", self.game.as_ref().unwrap().game_id, white, white, black, black),
+          format!("9 White({}) : {}:{:02}", white, time_s / 60, time_s % 60),
+          format!("9 Black({}) : {}:{:02}", black, time_s / 60, time_s % 60),
+          "1 6".to_string(),
+        ]
+      }
+      (_, "stats") if toks.len() >= 2 => {
+        vec![
+          format!("9 Player:      {}", toks[1]),
+          format!("9 Rating:      {}", if toks[1] == self.cfg.login { &self.cfg.rating as &str } else { "NR" }),
+          "1 5".to_string(),
+        ]
+      }
+      (MockPhase::Idle, "match") | (MockPhase::Challenged, "match") if toks.len() >= 3 && toks[1] == self.cfg.login => {
+        let client_stone = match toks[2] {
+          "B" | "b" => Stone::Black,
+          _ => Stone::White,
+        };
+        let mut lines = vec![format!("9 Match [19x19] with {} in {} accepted.", self.cfg.login, self.cfg.main_time_mins)];
+        lines.extend(self.start_game(client_stone));
+        lines
+      }
+      (MockPhase::Challenged, "decline") => {
+        self.transcript.declined += 1;
+        self.phase = MockPhase::Idle;
+        let mut lines = vec![format!("9 {} declines your request for a match.", self.client)];
+        lines.extend(self.challenge_lines());
+        lines
+      }
+      (MockPhase::Playing, "resign") => {
+        let winner = stone_code(self.cfg.mock_stone);
+        let mut lines = vec![format!("9 {} has resigned the game.", self.client)];
+        lines.extend(self.finish_game(format!("{}+Resign", winner)));
+        lines
+      }
+      (MockPhase::Playing, code) => {
+        if self.to_move() == self.cfg.mock_stone {
+          return vec!["5 It is not your turn.".to_string(), "1 6".to_string()];
+        }
+        let action = match code {
+          "pass" | "Pass" | "PASS" => Action::Pass,
+          code => match Coord::parse_code_str(code) {
+            Some(coord) => Action::Place{point: Point::from_coord(coord)},
+            None => return vec!["5 Unknown command.".to_string(), "1 6".to_string()],
+          },
+        };
+        let client_stone = self.cfg.mock_stone.opponent();
+        self.game.as_mut().unwrap().moves.push((client_stone, action));
+        let move_number = self.game.as_ref().unwrap().moves.len() - 1;
+        let mut lines = self.move_lines(move_number);
+        lines.extend(self.after_move());
+        lines
+      }
+      (MockPhase::Scoring, "done") => {
+        let mut lines = vec![format!("20 {}", self.cfg.score_result)];
+        let result = self.cfg.score_result.clone();
+        lines.extend(self.finish_game(result));
+        lines
+      }
+      (MockPhase::Scoring, code) => {
+        match Coord::parse_code_str(code) {
+          Some(coord) => {
+            self.game.as_mut().unwrap().removed.push(Point::from_coord(coord));
+            vec![format!("9 Removing @ {}", coord.to_string()), "1 7".to_string()]
+          }
+          None => vec!["5 Unknown command.".to_string(), "1 7".to_string()],
+        }
+      }
+      _ => {
+        vec!["5 Unknown command.".to_string(), "1 5".to_string()]
+      }
+    }
+  }
+
+  /// Serves one connection. Returns `Ok(true)` once the client has quit.
+  fn serve(&mut self, stream: TcpStream) -> io::Result<bool> {
+    let mut reader = BufReader::new(try!(stream.try_clone()));
+    let mut writer = stream;
+
+    // Log in; everything up to "set verbose FALSE" is login chatter.
+    let login = match try!(read_cmd(&mut reader)) {
+      Some(login) => login,
+      None => return Ok(false),
+    };
+    self.client = login;
+    loop {
+      match try!(read_cmd(&mut reader)) {
+        Some(ref cmd) if cmd == "set verbose FALSE" => break,
+        Some(_) => {}
+        None => return Ok(false),
+      }
+    }
+
+    let lines = if self.game.is_some() {
+      self.resume_game()
+    } else {
+      self.challenge_lines()
+    };
+    try!(send(&mut writer, &lines));
+
+    loop {
+      let cmd = match try!(read_cmd(&mut reader)) {
+        Some(cmd) => cmd,
+        None => return Ok(false),
+      };
+      self.transcript.commands.push(cmd.clone());
+      if cmd == "quit" {
+        try!(send(&mut writer, &["9 Thank you for playing.".to_string()]));
+        let _ = writer.shutdown(Shutdown::Both);
+        return Ok(true);
+      }
+      let lines = self.handle_cmd(&cmd);
+      try!(send(&mut writer, &lines));
+      if !self.dropped && self.game.is_some() {
+        if let Some(n) = self.cfg.drop_after_moves {
+          if self.game.as_ref().unwrap().moves.len() >= n {
+            self.dropped = true;
+            let _ = writer.shutdown(Shutdown::Both);
+            return Ok(false);
+          }
+        }
+      }
+    }
+  }
+}
+
+/// A deterministic agent for tests: it accepts every match and plays its
+/// moves in order, passing once they run out (or when the opponent passes).
+pub struct ScriptedAsyncAgent;
+
+impl ScriptedAsyncAgent {
+  /// Reads the script as one move code per line ("D4", "pass", "resign").
+  fn load_script(load_save_path: Option<PathBuf>) -> Vec<Action> {
+    let mut script = vec![];
+    if let Some(path) = load_save_path {
+      let file = match ::std::fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) => panic!("failed to open agent script: {:?} {:?}", e, path),
+      };
+      for line in BufReader::new(file).lines() {
+        let line = line.unwrap();
+        let action = match line.trim() {
+          "" => continue,
+          "pass" => Action::Pass,
+          "resign" => Action::Resign,
+          code => Action::Place{point: Point::from_coord(Coord::parse_code_str(code).unwrap())},
+        };
+        script.push(action);
+      }
+    }
+    script
+  }
+}
+
+impl AsyncAgent for ScriptedAsyncAgent {
+  type Config = ();
+
+  fn spawn_runloop(
+      _agent_cfg: (),
+      barrier: Arc<Barrier>,
+      agent_in_rx: Receiver<AgentMsg>,
+      agent_out_tx: Sender<AgentMsg>,
+      load_save_path: Option<PathBuf>,
+  ) -> JoinHandle<()> {
+    spawn(move || {
+      let script = ScriptedAsyncAgent::load_script(load_save_path);
+      let mut idx = 0;
+      let mut our_stone = None;
+      let submit = |idx: &mut usize, our_stone: Stone, opponent_passed: bool| {
+        let action = if opponent_passed || *idx >= script.len() {
+          Action::Pass
+        } else {
+          *idx += 1;
+          script[*idx - 1]
+        };
+        let _ = agent_out_tx.send(AgentMsg::SubmitAction{
+          turn:     our_stone,
+          action:   action,
+          set_dead_stones:  false,
+          dead_stones:  vec![],
+          live_stones:  vec![],
+          territory:    vec![],
+          outcome:      None,
+        });
+      };
+      let _ = agent_out_tx.send(AgentMsg::Ready);
+      loop {
+        match agent_in_rx.recv() {
+          Ok(AgentMsg::RequestMatch{passive, opponent, our_stone, board_size, main_time_secs, byoyomi_time_secs}) => {
+            let _ = agent_out_tx.send(AgentMsg::AcceptMatch{
+              passive:      passive,
+              opponent:     opponent,
+              our_stone:    our_stone,
+              board_size:   board_size,
+              main_time_secs:       main_time_secs,
+              byoyomi_time_secs:    byoyomi_time_secs,
+            });
+          }
+          Ok(AgentMsg::StartMatch{skip_as_black, our_stone: stone, ..}) => {
+            idx = 0;
+            our_stone = Some(stone);
+            if stone == Stone::Black && !skip_as_black {
+              submit(&mut idx, stone, false);
+            }
+          }
+          Ok(AgentMsg::RecvAction{turn, action, ..}) => {
+            if let Some(stone) = our_stone {
+              if turn != stone {
+                submit(&mut idx, stone, action == Action::Pass);
+              }
+            }
+          }
+          Ok(AgentMsg::FinishMatch) => {
+            our_stone = None;
+          }
+          Ok(AgentMsg::Quit) | Err(_) => {
+            break;
+          }
+          Ok(_) => {}
+        }
+      }
+      barrier.wait();
+    })
+  }
+}
@@ -1,3 +1,4 @@
 pub mod agent;
+pub mod mock_nngs;
 //pub mod gtp;
 pub mod nngs;
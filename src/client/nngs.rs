@@ -1,15 +1,17 @@
-use board::{Stone, Action, Point};
+use board::{PlayerRank, Stone, Action, Point};
 use client::agent::{AsyncAgent, AgentMsg};
 use gtp_board::{Coord};
 
 use byteorder::{ReadBytesExt, WriteBytesExt};
 use toml;
 
+use std::cmp::{min};
 use std::fs::{File};
 use std::io::{Read, BufRead, Write, BufReader};
 use std::marker::{PhantomData};
+use std::mem::{replace};
 use std::path::{PathBuf};
-use std::net::{TcpStream};
+use std::net::{Shutdown, TcpStream};
 use std::str::{from_utf8};
 use std::sync::{Arc, Barrier};
 use std::sync::mpsc::{Sender, Receiver, channel};
@@ -106,6 +108,7 @@ enum LoProtocol {
   GameInfo{line: Vec<u8>},      // 22
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 struct GameOutcome {
   dead_stones:  Vec<Vec<Point>>,
   live_stones:  Vec<Vec<Point>>,
@@ -114,7 +117,7 @@ struct GameOutcome {
 }
 
 impl<A> NngsOneShotClient<A> where A: AsyncAgent {
-  pub fn new(server_cfg: NngsServerConfig, match_cfg: Option<NngsMatchConfig>) -> NngsOneShotClient<A> {
+  pub fn new(server_cfg: NngsServerConfig, match_cfg: Option<NngsMatchConfig>, agent_cfg: A::Config) -> NngsOneShotClient<A> {
     let stream = match TcpStream::connect((&server_cfg.host as &str, server_cfg.port)) {
       Ok(stream) => stream,
       Err(_) => panic!("failed to connect to nngs server"),
@@ -129,6 +132,7 @@ impl<A> NngsOneShotClient<A> where A: AsyncAgent {
     let (writer_tx, writer_rx) = channel();
 
     let agent_thr = A::spawn_runloop(
+        agent_cfg,
         barrier.clone(),
         agent_in_rx,
         agent_out_tx,
@@ -296,6 +300,7 @@ impl<A> NngsOneShotClient<A> where A: AsyncAgent {
 
               if is_resigned || recently_finished_match {
                 agent_in_tx.send(AgentMsg::FinishMatch).unwrap();
+                agent_in_tx.send(AgentMsg::Quit).unwrap();
                 writer_tx.send(InternalMsg::Quit).unwrap();
               }
             }
@@ -580,6 +585,1000 @@ impl<A> NngsOneShotClient<A> where A: AsyncAgent {
   }*/
 }
 
+#[derive(Clone, RustcDecodable, Debug)]
+pub struct NngsAutomatchConfig {
+  /// One of "blitz", "fast", "medium", or "slow"; requests with a longer main
+  /// time than the speed class allows are declined.
+  pub speed:    Option<String>,
+  /// Weakest opponent rank to accept, e.g. "10k".
+  pub rank:     Option<String>,
+}
+
+fn speed_max_main_time_mins(speed: &str) -> Result<Option<i32>, String> {
+  match speed {
+    "blitz"   => Ok(Some(5)),
+    "fast"    => Ok(Some(20)),
+    "medium"  => Ok(Some(45)),
+    "slow"    => Ok(None),
+    s => Err(format!("unknown automatch speed: \"{}\" (expected blitz, fast, medium, or slow)", s)),
+  }
+}
+
+impl NngsAutomatchConfig {
+  pub fn validate(&self) -> Result<(), String> {
+    if let Some(ref speed) = self.speed {
+      try!(speed_max_main_time_mins(speed));
+    }
+    if let Some(ref rank) = self.rank {
+      if PlayerRank::parse_str(rank).is_none() {
+        return Err(format!("unknown automatch rank: \"{}\"", rank));
+      }
+    }
+    Ok(())
+  }
+
+  // XXX(20160420): `validate` rejects unknown speeds and ranks at load time;
+  // here they are treated as no limit.
+  pub fn max_main_time_mins(&self) -> Option<i32> {
+    self.speed.as_ref().and_then(|s| speed_max_main_time_mins(s).unwrap_or(None))
+  }
+
+  pub fn min_rank(&self) -> Option<PlayerRank> {
+    self.rank.as_ref().and_then(|s| PlayerRank::parse_str(s))
+  }
+}
+
+#[derive(Clone, RustcDecodable, Debug)]
+pub struct NngsClientConfig {
+  pub reconnect:        bool,
+  pub min_backoff_ms:   u32,
+  pub max_backoff_ms:   u32,
+  /// Quit after this many consecutive games; play forever if unset.
+  pub max_games:        Option<usize>,
+  pub automatch:        Option<NngsAutomatchConfig>,
+}
+
+impl Default for NngsClientConfig {
+  fn default() -> NngsClientConfig {
+    NngsClientConfig{
+      reconnect:        true,
+      min_backoff_ms:   1000,
+      max_backoff_ms:   60000,
+      max_games:        None,
+      automatch:        None,
+    }
+  }
+}
+
+impl NngsClientConfig {
+  /// Reads and validates `nngs_client.config`; the defaults are used if it
+  /// does not exist.
+  pub fn open() -> Result<NngsClientConfig, String> {
+    let path = PathBuf::from("nngs_client.config");
+    if !path.exists() {
+      return Ok(NngsClientConfig::default());
+    }
+    let mut file = match File::open(&path) {
+      Ok(file) => file,
+      Err(e) => return Err(format!("failed to open {:?}: {}", path, e)),
+    };
+    let mut buf = String::new();
+    if let Err(e) = file.read_to_string(&mut buf) {
+      return Err(format!("failed to read {:?}: {}", path, e));
+    }
+    let cfg: NngsClientConfig = match toml::decode_str(&buf) {
+      Some(x) => x,
+      None => return Err(format!("failed to decode {:?}", path)),
+    };
+    try!(cfg.validate());
+    Ok(cfg)
+  }
+
+  pub fn validate(&self) -> Result<(), String> {
+    if self.min_backoff_ms > self.max_backoff_ms {
+      return Err("min_backoff_ms should be at most max_backoff_ms".to_string());
+    }
+    if let Some(ref automatch) = self.automatch {
+      try!(automatch.validate());
+    }
+    Ok(())
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NngsPrompt {
+  Server,   // 1 5
+  Game,     // 1 6
+  Scoring,  // 1 7
+  Other,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct NngsPlayerClock {
+  pub captures:       i32,
+  pub time_left_s:    i32,
+  pub byoyomi_stones: i32,
+}
+
+/// A single line of NNGS/IGS client-mode output.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum NngsMsg {
+  Prompt(NngsPrompt),
+  Error{text: String},
+  Info{text: String},
+  MatchRequest{
+    opponent:     String,
+    our_stone:    Stone,
+    board_size:   i32,
+    main_time_mins:     i32,
+    byoyomi_time_mins:  i32,
+  },
+  MatchAccepted{opponent: String},
+  MatchDeclined{opponent: String},
+  GameHeader{
+    game_id:  i32,
+    white:    String,
+    white_clock:  NngsPlayerClock,
+    black:    String,
+    black_clock:  NngsPlayerClock,
+  },
+  Move{move_number: i32, turn: Stone, action: Action},
+  Resigned{player: String},
+  ScoringStarted,
+  RemovedStone{point: Point},
+  GameResult{game_id: i32, white: String, black: String, result: String},
+  StatsPlayer{name: String},
+  StatsRating{rank: Option<PlayerRank>},
+  TimeLeft{player: String, time_left_s: i32},
+  Score{text: String},
+  Shout{text: String},
+  Other{code: i32, text: String},
+  Line{text: String},
+}
+
+fn parse_stone_code(s: &str) -> Option<Stone> {
+  match s.trim() {
+    "B" | "b" => Some(Stone::Black),
+    "W" | "w" => Some(Stone::White),
+    _ => None,
+  }
+}
+
+fn parse_action_code(s: &str) -> Option<Action> {
+  match s.trim() {
+    "PASS" | "Pass" | "pass" => Some(Action::Pass),
+    s => Coord::parse_code_str(s).map(|coord| Action::Place{point: Point::from_coord(coord)}),
+  }
+}
+
+/// Parses "M:SS" (or a bare number of seconds) into seconds.
+fn parse_clock_secs(s: &str) -> Option<i32> {
+  let toks: Vec<_> = s.trim().splitn(2, ':').collect();
+  if toks.len() == 2 {
+    match (toks[0].trim().parse::<i32>(), toks[1].trim().parse::<i32>()) {
+      (Ok(m), Ok(s)) => Some(60 * m + s),
+      _ => None,
+    }
+  } else {
+    toks[0].trim().parse().ok()
+  }
+}
+
+/// Parses a "{Game 3: white vs black : W+Resign}" style result line.
+fn parse_game_result(text: &str) -> Option<NngsMsg> {
+  let text = text.trim();
+  if !text.starts_with("{Game") {
+    return None;
+  }
+  let inner = text.trim_left_matches('{').trim_right_matches('}');
+  let toks: Vec<_> = inner.splitn(3, ':').collect();
+  if toks.len() < 3 {
+    return None;
+  }
+  let game_id: i32 = match toks[0].trim_left_matches("Game").trim().parse() {
+    Ok(x) => x,
+    Err(_) => return None,
+  };
+  let names: Vec<_> = toks[1].split(" vs ").collect();
+  if names.len() != 2 {
+    return None;
+  }
+  Some(NngsMsg::GameResult{
+    game_id:  game_id,
+    white:    names[0].trim().to_string(),
+    black:    names[1].trim().to_string(),
+    result:   toks[2].trim().to_string(),
+  })
+}
+
+fn parse_player_clock(s: &str) -> Option<(String, NngsPlayerClock)> {
+  // E.g. "guest3 ( 0 599 -1)".
+  let toks: Vec<_> = s.trim().splitn(2, '(').collect();
+  if toks.len() != 2 {
+    return None;
+  }
+  let name = toks[0].trim().to_string();
+  let nums: Vec<i32> = toks[1].trim_right_matches(')').split_whitespace()
+    .filter_map(|t| t.trim_right_matches(')').parse().ok())
+    .collect();
+  if nums.len() != 3 {
+    return None;
+  }
+  Some((name, NngsPlayerClock{
+    captures:       nums[0],
+    time_left_s:    nums[1],
+    byoyomi_stones: nums[2],
+  }))
+}
+
+fn parse_info(text: &str) -> NngsMsg {
+  let trimmed = text.trim();
+  if trimmed.starts_with("Use <match") {
+    let pre_toks: Vec<_> = trimmed.splitn(2, '<').collect();
+    let suf_toks: Vec<_> = pre_toks[1].splitn(2, '>').collect();
+    let match_toks: Vec<_> = suf_toks[0].split_whitespace().collect();
+    if match_toks.len() == 6 {
+      if let (Some(our_stone), Ok(board_size), Ok(main_time), Ok(byoyomi_time)) =
+          (parse_stone_code(match_toks[2]), match_toks[3].parse(), match_toks[4].parse(), match_toks[5].parse())
+      {
+        return NngsMsg::MatchRequest{
+          opponent:     match_toks[1].to_string(),
+          our_stone:    our_stone,
+          board_size:   board_size,
+          main_time_mins:     main_time,
+          byoyomi_time_mins:  byoyomi_time,
+        };
+      }
+    }
+  } else if trimmed.starts_with("Match [") && trimmed.ends_with("accepted.") {
+    // E.g. "Match [19x19] with guest3 in 10 accepted."
+    let toks: Vec<_> = trimmed.split_whitespace().collect();
+    if toks.len() >= 4 && toks[2] == "with" {
+      return NngsMsg::MatchAccepted{opponent: toks[3].to_string()};
+    }
+  } else if trimmed.contains("declines your request for a match") {
+    let opponent = trimmed.split_whitespace().next().unwrap_or("").to_string();
+    return NngsMsg::MatchDeclined{opponent: opponent};
+  } else if trimmed.ends_with("has resigned the game.") {
+    let player = trimmed.split_whitespace().next().unwrap_or("").to_string();
+    return NngsMsg::Resigned{player: player};
+  } else if trimmed.starts_with("You can check your score") {
+    return NngsMsg::ScoringStarted;
+  } else if trimmed.starts_with("Removing @") {
+    if let Some(Action::Place{point}) = parse_action_code(trimmed.trim_left_matches("Removing @")) {
+      return NngsMsg::RemovedStone{point: point};
+    }
+  } else if trimmed.starts_with("{Game") {
+    if let Some(msg) = parse_game_result(trimmed) {
+      return msg;
+    }
+  } else if trimmed.starts_with("Player:") {
+    let name = trimmed.trim_left_matches("Player:").trim().to_string();
+    return NngsMsg::StatsPlayer{name: name};
+  } else if trimmed.starts_with("Rating:") {
+    let rank_str = trimmed.trim_left_matches("Rating:").split_whitespace().next().unwrap_or("");
+    return NngsMsg::StatsRating{rank: PlayerRank::parse_str(rank_str)};
+  } else if (trimmed.starts_with("Black(") || trimmed.starts_with("White(")) && trimmed.contains(") : ") {
+    // E.g. "White(guest3) : 9:41".
+    let toks: Vec<_> = trimmed.splitn(2, ") : ").collect();
+    let player = toks[0].splitn(2, '(').nth(1).unwrap_or("").to_string();
+    if let Some(time_left_s) = parse_clock_secs(toks[1]) {
+      return NngsMsg::TimeLeft{player: player, time_left_s: time_left_s};
+    }
+  }
+  NngsMsg::Info{text: trimmed.to_string()}
+}
+
+fn parse_move_info(text: &str) -> NngsMsg {
+  let trimmed = text.trim();
+  if trimmed.starts_with("Game") {
+    // E.g. "Game 1 I: guest3 (0 599 -1) vs guest2 (0 600 -1)".
+    let pre_toks: Vec<_> = trimmed.splitn(2, ':').collect();
+    if pre_toks.len() == 2 {
+      let game_id = pre_toks[0].split_whitespace().nth(1).and_then(|s| s.parse().ok());
+      let pair_toks: Vec<_> = pre_toks[1].splitn(2, " vs ").collect();
+      if pair_toks.len() == 2 {
+        if let (Some(game_id), Some((white, white_clock)), Some((black, black_clock))) =
+            (game_id, parse_player_clock(pair_toks[0]), parse_player_clock(pair_toks[1]))
+        {
+          return NngsMsg::GameHeader{
+            game_id:  game_id,
+            white:    white,
+            white_clock:  white_clock,
+            black:    black,
+            black_clock:  black_clock,
+          };
+        }
+      }
+    }
+  } else {
+    // E.g. "  12(B): D4".
+    let toks: Vec<_> = trimmed.splitn(2, '(').collect();
+    if toks.len() == 2 {
+      let suf_toks: Vec<_> = toks[1].splitn(2, "):").collect();
+      if suf_toks.len() == 2 {
+        if let (Ok(move_number), Some(turn), Some(action)) =
+            (toks[0].trim().parse(), parse_stone_code(suf_toks[0]), parse_action_code(suf_toks[1]))
+        {
+          return NngsMsg::Move{move_number: move_number, turn: turn, action: action};
+        }
+      }
+    }
+  }
+  NngsMsg::Other{code: 15, text: trimmed.to_string()}
+}
+
+/// Parses one line (with or without the trailing newline) of client-mode
+/// output into a typed message. Lines which are not understood are returned
+/// as `Other` or `Line` rather than treated as errors.
+pub fn parse_nngs_line(line: &[u8]) -> NngsMsg {
+  let line_str = String::from_utf8_lossy(line);
+  let line_str = line_str.trim_right_matches(|c: char| c == '\r' || c == '\n');
+  let split = match line_str.find(' ') {
+    Some(split) => split,
+    None => line_str.len(),
+  };
+  let code: i32 = match line_str[ .. split].parse() {
+    Ok(code) => code,
+    Err(_) => return NngsMsg::Line{text: line_str.to_string()},
+  };
+  let text = if split < line_str.len() { &line_str[split + 1 .. ] } else { "" };
+  match code {
+    1 => NngsMsg::Prompt(match text.trim() {
+      "5" => NngsPrompt::Server,
+      "6" => NngsPrompt::Game,
+      "7" => NngsPrompt::Scoring,
+      _   => NngsPrompt::Other,
+    }),
+    5 => NngsMsg::Error{text: text.trim().to_string()},
+    9 => parse_info(text),
+    15 => parse_move_info(text),
+    20 => NngsMsg::Score{text: text.trim().to_string()},
+    21 => match parse_game_result(text) {
+      Some(msg) => msg,
+      None => NngsMsg::Shout{text: text.trim().to_string()},
+    },
+    _ => NngsMsg::Other{code: code, text: text.trim().to_string()},
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NngsPhase {
+  Idle,
+  Challenging,
+  Playing,
+  Scoring,
+  Finished,
+}
+
+#[derive(Clone, Debug)]
+pub enum NngsAction {
+  Write(Vec<u8>),
+  Agent(AgentMsg),
+  /// Call `NngsSession::rechallenge` after this long.
+  Rechallenge{delay_ms: u32},
+  Disconnect,
+}
+
+#[derive(Clone, Debug)]
+struct PendingMatch {
+  opponent:     String,
+  our_stone:    Stone,
+  board_size:   i32,
+  main_time_mins:     i32,
+  byoyomi_time_mins:  i32,
+}
+
+/// The protocol-level state of a (possibly reconnecting) NNGS client. The
+/// session is a pure state machine: server lines and agent messages go in,
+/// and writes and agent messages come out, so it can be driven by a socket or
+/// by a test harness.
+pub struct NngsSession {
+  login:        String,
+  client_cfg:   NngsClientConfig,
+  match_cfg:    Option<NngsMatchConfig>,
+  phase:        NngsPhase,
+  agent_ready:  bool,
+  decline_backoff_ms:   u32,
+  pending:      Option<PendingMatch>,
+  deferred:     Option<PendingMatch>,
+  stats_player: Option<String>,
+  our_stone:    Option<Stone>,
+  opponent:     Option<String>,
+  game_id:      Option<i32>,
+  next_move_number: i32,
+  our_time_left_s:  Option<i32>,
+  sent_done:    bool,
+  unconfirmed:  Option<(i32, Vec<u8>)>,
+  saved_outcome:    Option<GameOutcome>,
+  games_played: usize,
+}
+
+fn stone_code(stone: Stone) -> &'static str {
+  match stone {
+    Stone::Black => "B",
+    Stone::White => "W",
+    _ => unreachable!(),
+  }
+}
+
+impl NngsSession {
+  pub fn new(login: String, client_cfg: NngsClientConfig, match_cfg: Option<NngsMatchConfig>) -> NngsSession {
+    NngsSession{
+      login:        login,
+      client_cfg:   client_cfg,
+      match_cfg:    match_cfg,
+      phase:        NngsPhase::Idle,
+      agent_ready:  false,
+      decline_backoff_ms:   client_cfg.min_backoff_ms,
+      pending:      None,
+      deferred:     None,
+      stats_player: None,
+      our_stone:    None,
+      opponent:     None,
+      game_id:      None,
+      next_move_number: 0,
+      our_time_left_s:  None,
+      sent_done:    false,
+      unconfirmed:  None,
+      saved_outcome:    None,
+      games_played: 0,
+    }
+  }
+
+  pub fn phase(&self) -> NngsPhase {
+    self.phase
+  }
+
+  pub fn games_played(&self) -> usize {
+    self.games_played
+  }
+
+  fn challenge(&mut self, actions: &mut Vec<NngsAction>) {
+    if let Some(ref match_cfg) = self.match_cfg {
+      let match_cmd = format!("match {} {} {} {} {}",
+          match_cfg.opponent,
+          stone_code(match_cfg.our_stone),
+          match_cfg.board_size,
+          match_cfg.main_time,
+          match_cfg.byoyomi_time,
+      );
+      actions.push(NngsAction::Write(match_cmd.into_bytes()));
+      self.phase = NngsPhase::Challenging;
+    }
+  }
+
+  /// Called when the delay of a `NngsAction::Rechallenge` is over; issues our
+  /// challenge again unless something else happened in the meantime.
+  pub fn rechallenge(&mut self, actions: &mut Vec<NngsAction>) {
+    if self.phase == NngsPhase::Idle && self.agent_ready && self.pending.is_none() && self.deferred.is_none() {
+      self.challenge(actions);
+    }
+  }
+
+  /// Called once the connection is (re-)established; logs in and, if we are
+  /// between games, issues our challenge again.
+  pub fn connect(&mut self, password: Option<&str>, actions: &mut Vec<NngsAction>) {
+    actions.push(NngsAction::Write(self.login.as_bytes().to_vec()));
+    if let Some(password) = password {
+      actions.push(NngsAction::Write(password.as_bytes().to_vec()));
+    }
+    actions.push(NngsAction::Write(b"set client TRUE".to_vec()));
+    actions.push(NngsAction::Write(b"set verbose FALSE".to_vec()));
+    match self.phase {
+      NngsPhase::Idle | NngsPhase::Challenging => {
+        self.phase = NngsPhase::Idle;
+        if self.agent_ready {
+          self.challenge(actions);
+        }
+      }
+      NngsPhase::Playing | NngsPhase::Scoring => {
+        // XXX(20160405): The server adjourns the game on disconnect and
+        // resumes it when both players are back; the resumed game replays the
+        // move list, which we skip by move number. Our last move may have been
+        // lost with the connection, so send it again; if the server did get
+        // it, this is only an error line.
+        if let Some((_, ref cmd)) = self.unconfirmed {
+          actions.push(NngsAction::Write(cmd.clone()));
+        }
+      }
+      NngsPhase::Finished => {}
+    }
+  }
+
+  /// Called when the connection is lost.
+  pub fn disconnect(&mut self) {
+    self.pending = None;
+    self.deferred = None;
+    self.sent_done = false;
+    self.stats_player = None;
+  }
+
+  fn finish_game(&mut self, actions: &mut Vec<NngsAction>) {
+    println!("DEBUG: nngs session: finished game {:?}", self.game_id);
+    actions.push(NngsAction::Agent(AgentMsg::FinishMatch));
+    self.games_played += 1;
+    self.our_stone = None;
+    self.opponent = None;
+    self.game_id = None;
+    self.next_move_number = 0;
+    self.our_time_left_s = None;
+    self.sent_done = false;
+    self.unconfirmed = None;
+    self.saved_outcome = None;
+    if self.client_cfg.max_games.map_or(false, |n| self.games_played >= n) {
+      self.phase = NngsPhase::Finished;
+      actions.push(NngsAction::Agent(AgentMsg::Quit));
+      actions.push(NngsAction::Write(b"quit".to_vec()));
+      actions.push(NngsAction::Disconnect);
+    } else {
+      self.phase = NngsPhase::Idle;
+      self.challenge(actions);
+    }
+  }
+
+  fn accept_match_request(&mut self, pending: PendingMatch, actions: &mut Vec<NngsAction>) {
+    // XXX(20160308): Don't accept match request until the agent is ready.
+    // Instead send match details to agent channel.
+    actions.push(NngsAction::Agent(AgentMsg::RequestMatch{
+      passive:      true,
+      opponent:     pending.opponent,
+      our_stone:    pending.our_stone,
+      board_size:   pending.board_size,
+      main_time_secs:       60 * pending.main_time_mins,
+      byoyomi_time_secs:    60 * pending.byoyomi_time_mins,
+    }));
+  }
+
+  fn consider_match_request(&mut self, pending: PendingMatch, actions: &mut Vec<NngsAction>) {
+    let (max_main_time, min_rank) = match self.client_cfg.automatch {
+      Some(ref automatch) => (automatch.max_main_time_mins(), automatch.min_rank()),
+      None => (None, None),
+    };
+    if pending.board_size != 19 || max_main_time.map_or(false, |t| pending.main_time_mins > t) {
+      println!("DEBUG: nngs session: declining match: {:?}", pending);
+      actions.push(NngsAction::Write(format!("decline {}", pending.opponent).into_bytes()));
+    } else if min_rank.is_some() {
+      actions.push(NngsAction::Write(format!("stats {}", pending.opponent).into_bytes()));
+      self.pending = Some(pending);
+    } else {
+      self.accept_match_request(pending, actions);
+    }
+  }
+
+  pub fn handle_server(&mut self, msg: NngsMsg, actions: &mut Vec<NngsAction>) {
+    match msg {
+      NngsMsg::MatchRequest{opponent, our_stone, board_size, main_time_mins, byoyomi_time_mins} => {
+        if self.phase != NngsPhase::Idle {
+          actions.push(NngsAction::Write(format!("decline {}", opponent).into_bytes()));
+          return;
+        }
+        let pending = PendingMatch{
+          opponent:     opponent,
+          our_stone:    our_stone,
+          board_size:   board_size,
+          main_time_mins:     main_time_mins,
+          byoyomi_time_mins:  byoyomi_time_mins,
+        };
+        if !self.agent_ready {
+          // Answer the request once the agent has started up.
+          self.deferred = Some(pending);
+          return;
+        }
+        self.consider_match_request(pending, actions);
+      }
+      NngsMsg::StatsPlayer{name} => {
+        self.stats_player = Some(name);
+      }
+      NngsMsg::StatsRating{rank} => {
+        let is_pending = match (&self.pending, &self.stats_player) {
+          (&Some(ref pending), &Some(ref name)) => &pending.opponent == name,
+          _ => false,
+        };
+        if !is_pending {
+          return;
+        }
+        let pending = self.pending.take().unwrap();
+        let min_rank = self.client_cfg.automatch.as_ref().and_then(|cfg| cfg.min_rank());
+        let strong_enough = match (rank, min_rank) {
+          (_, None) => true,
+          (Some(rank), Some(min_rank)) => rank.strength() >= min_rank.strength(),
+          (None, Some(_)) => false,
+        };
+        if strong_enough {
+          self.accept_match_request(pending, actions);
+        } else {
+          println!("DEBUG: nngs session: declining match (rank {:?}): {:?}", rank, pending);
+          actions.push(NngsAction::Write(format!("decline {}", pending.opponent).into_bytes()));
+        }
+      }
+      NngsMsg::MatchAccepted{opponent} => {
+        if self.phase != NngsPhase::Challenging {
+          return;
+        }
+        if let Some(ref match_cfg) = self.match_cfg {
+          if match_cfg.opponent != opponent {
+            return;
+          }
+          self.decline_backoff_ms = self.client_cfg.min_backoff_ms;
+          actions.push(NngsAction::Agent(AgentMsg::RequestMatch{
+            passive:      false,
+            opponent:     match_cfg.opponent.clone(),
+            our_stone:    match_cfg.our_stone,
+            board_size:   match_cfg.board_size,
+            main_time_secs:       60 * match_cfg.main_time,
+            byoyomi_time_secs:    60 * match_cfg.byoyomi_time,
+          }));
+        }
+      }
+      NngsMsg::MatchDeclined{opponent} => {
+        if self.phase == NngsPhase::Challenging {
+          // XXX(20160422): The agent is still ready, so nothing else would
+          // challenge again; retry after a growing delay.
+          println!("WARNING: nngs session: {} declined our match, retrying in {} ms",
+              opponent, self.decline_backoff_ms);
+          self.phase = NngsPhase::Idle;
+          actions.push(NngsAction::Rechallenge{delay_ms: self.decline_backoff_ms});
+          self.decline_backoff_ms = min(self.client_cfg.max_backoff_ms, 2 * self.decline_backoff_ms);
+        }
+      }
+      NngsMsg::GameHeader{game_id, white, white_clock, black, black_clock} => {
+        if self.phase != NngsPhase::Playing {
+          return;
+        }
+        if white == self.login {
+          self.game_id = Some(game_id);
+          self.our_time_left_s = Some(white_clock.time_left_s);
+        } else if black == self.login {
+          self.game_id = Some(game_id);
+          self.our_time_left_s = Some(black_clock.time_left_s);
+        }
+      }
+      NngsMsg::Move{move_number, turn, action} => {
+        if self.phase != NngsPhase::Playing || self.game_id.is_none() {
+          return;
+        }
+        if move_number < self.next_move_number {
+          // Already seen (the server replays moves when a game is resumed).
+          return;
+        }
+        self.next_move_number = move_number + 1;
+        if Some(turn) == self.our_stone {
+          if self.unconfirmed.as_ref().map_or(false, |&(n, _)| move_number >= n) {
+            self.unconfirmed = None;
+          }
+        }
+        actions.push(NngsAction::Agent(AgentMsg::RecvAction{
+          turn:         turn,
+          action:       action,
+          move_number:  Some(move_number),
+          time_left_s:  self.our_time_left_s,
+        }));
+      }
+      NngsMsg::TimeLeft{player, time_left_s} => {
+        if player == self.login && self.phase == NngsPhase::Playing {
+          self.our_time_left_s = Some(time_left_s);
+          actions.push(NngsAction::Agent(AgentMsg::RecvTime{time_left_s: time_left_s}));
+        }
+      }
+      NngsMsg::ScoringStarted => {
+        if self.phase == NngsPhase::Playing {
+          self.phase = NngsPhase::Scoring;
+        }
+      }
+      NngsMsg::Prompt(NngsPrompt::Scoring) => {
+        if self.phase != NngsPhase::Playing && self.phase != NngsPhase::Scoring {
+          return;
+        }
+        self.phase = NngsPhase::Scoring;
+        // XXX(20160405): The server re-prompts after every removal; remove
+        // our estimate of the dead stones and say "done" only once per game
+        // (or per connection), and otherwise accept what is on the board.
+        if self.sent_done {
+          return;
+        }
+        self.sent_done = true;
+        if let Some(ref outcome) = self.saved_outcome {
+          println!("DEBUG: nngs session: final outcome: {:?}", outcome.outcome);
+          for &point in outcome.dead_stones[0].iter().chain(outcome.dead_stones[1].iter()) {
+            actions.push(NngsAction::Write(point.to_coord().to_bytestring()));
+          }
+        }
+        actions.push(NngsAction::Write(b"done".to_vec()));
+      }
+      NngsMsg::Resigned{player} => {
+        // Resignations in other games are announced too.
+        let is_ours = player == self.login || self.opponent.as_ref().map_or(false, |opponent| opponent == &player);
+        if !is_ours {
+          return;
+        }
+        if self.phase == NngsPhase::Playing || self.phase == NngsPhase::Scoring {
+          self.finish_game(actions);
+        }
+      }
+      NngsMsg::GameResult{game_id, white, black, result} => {
+        let is_ours = white == self.login || black == self.login;
+        if !is_ours || self.game_id.map_or(false, |id| id != game_id) {
+          return;
+        }
+        if self.phase == NngsPhase::Playing || self.phase == NngsPhase::Scoring {
+          if result.contains("adjourned") {
+            return;
+          }
+          println!("DEBUG: nngs session: game result: {}", result);
+          self.finish_game(actions);
+        }
+      }
+      NngsMsg::Prompt(NngsPrompt::Server) => {
+        // Being back at the server prompt after the game started means the
+        // game is over (e.g. after scoring), even if we missed the result.
+        if self.phase == NngsPhase::Scoring {
+          self.finish_game(actions);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  pub fn handle_agent(&mut self, msg: AgentMsg, actions: &mut Vec<NngsAction>) {
+    match msg {
+      AgentMsg::Ready => {
+        self.agent_ready = true;
+        if self.phase == NngsPhase::Idle {
+          if let Some(pending) = self.deferred.take() {
+            self.consider_match_request(pending, actions);
+          } else {
+            self.challenge(actions);
+          }
+        }
+      }
+      AgentMsg::AcceptMatch{passive, opponent, our_stone, board_size, main_time_secs, byoyomi_time_secs} => {
+        if passive {
+          let match_cmd = format!("match {} {} {} {} {}",
+              opponent,
+              stone_code(our_stone),
+              board_size,
+              main_time_secs / 60,
+              byoyomi_time_secs / 60,
+          );
+          actions.push(NngsAction::Write(match_cmd.into_bytes()));
+        }
+        self.phase = NngsPhase::Playing;
+        self.our_stone = Some(our_stone);
+        self.opponent = Some(opponent.clone());
+        self.game_id = None;
+        self.next_move_number = 0;
+        self.our_time_left_s = Some(main_time_secs);
+        actions.push(NngsAction::Agent(AgentMsg::StartMatch{
+          skip_as_black:    self.match_cfg.as_ref().map_or(false, |cfg| cfg.skip_as_black.unwrap_or(false)),
+          opponent:     opponent,
+          our_stone:    our_stone,
+          board_size:   board_size,
+          main_time_secs:       main_time_secs,
+          byoyomi_time_secs:    byoyomi_time_secs,
+        }));
+      }
+      AgentMsg::CheckTime => {
+        if self.phase == NngsPhase::Playing {
+          actions.push(NngsAction::Write(b"time".to_vec()));
+        }
+      }
+      AgentMsg::SubmitAction{
+        turn, action,
+        set_dead_stones,
+        dead_stones, live_stones, territory, outcome,
+      } => {
+        println!("DEBUG: nngs session: received agent action: {:?} {:?}", turn, action);
+        if set_dead_stones {
+          self.saved_outcome = Some(GameOutcome{
+            dead_stones:    dead_stones,
+            live_stones:    live_stones,
+            territory:      territory,
+            outcome:        outcome,
+          });
+        }
+        if self.phase != NngsPhase::Playing {
+          return;
+        }
+        let cmd = match action {
+          Action::Resign => b"resign".to_vec(),
+          Action::Pass => b"pass".to_vec(),
+          Action::Place{point} => point.to_coord().to_bytestring(),
+        };
+        if action != Action::Resign {
+          self.unconfirmed = Some((self.next_move_number, cmd.clone()));
+        }
+        actions.push(NngsAction::Write(cmd));
+      }
+      _ => {}
+    }
+  }
+}
+
+enum ClientEvent {
+  Server{conn_id: usize, msg: NngsMsg},
+  Disconnected{conn_id: usize},
+  Agent(AgentMsg),
+  Rechallenge,
+}
+
+/// A long-running NNGS/IGS client: it keeps one agent across games and
+/// reconnects with exponential backoff when the connection drops.
+pub struct NngsClient<A> where A: AsyncAgent {
+  server_cfg:   NngsServerConfig,
+  client_cfg:   NngsClientConfig,
+  session:      NngsSession,
+  barrier:      Arc<Barrier>,
+  agent_in_tx:  Sender<AgentMsg>,
+  events_tx:    Sender<ClientEvent>,
+  events_rx:    Receiver<ClientEvent>,
+  agent:        JoinHandle<()>,
+  bridge:       JoinHandle<()>,
+  _marker:      PhantomData<A>,
+}
+
+impl<A> NngsClient<A> where A: AsyncAgent {
+  pub fn new(server_cfg: NngsServerConfig, client_cfg: NngsClientConfig, match_cfg: Option<NngsMatchConfig>, agent_cfg: A::Config) -> NngsClient<A> {
+    let barrier = Arc::new(Barrier::new(2));
+    let (agent_in_tx, agent_in_rx) = channel();
+    let (agent_out_tx, agent_out_rx) = channel();
+    let (events_tx, events_rx) = channel();
+
+    let agent_thr = A::spawn_runloop(
+        agent_cfg,
+        barrier.clone(),
+        agent_in_rx,
+        agent_out_tx,
+        match match_cfg {
+          None => None,
+          Some(ref cfg) => cfg.load_save_path.as_ref().map(|p| PathBuf::from(p)),
+        },
+    );
+
+    let bridge_thr = {
+      let events_tx = events_tx.clone();
+      spawn(move || {
+        while let Ok(msg) = agent_out_rx.recv() {
+          if events_tx.send(ClientEvent::Agent(msg)).is_err() {
+            break;
+          }
+        }
+      })
+    };
+
+    let session = NngsSession::new(server_cfg.login.clone(), client_cfg.clone(), match_cfg);
+    NngsClient{
+      server_cfg:   server_cfg,
+      client_cfg:   client_cfg,
+      session:      session,
+      barrier:      barrier,
+      agent_in_tx:  agent_in_tx,
+      events_tx:    events_tx,
+      events_rx:    events_rx,
+      agent:        agent_thr,
+      bridge:       bridge_thr,
+      _marker:      PhantomData,
+    }
+  }
+
+  fn spawn_reader(&self, conn_id: usize, stream: &TcpStream) -> bool {
+    let mut reader = BufReader::new(match stream.try_clone() {
+      Ok(stream) => stream,
+      Err(_) => return false,
+    });
+    let events_tx = self.events_tx.clone();
+    spawn(move || {
+      loop {
+        let mut buf = Vec::with_capacity(160);
+        match reader.read_until(b'\n', &mut buf) {
+          Ok(0) | Err(_) => break,
+          Ok(_) => {}
+        }
+        print!("DEBUG: client telnet: {}", String::from_utf8_lossy(&buf));
+        if events_tx.send(ClientEvent::Server{conn_id: conn_id, msg: parse_nngs_line(&buf)}).is_err() {
+          return;
+        }
+      }
+      let _ = events_tx.send(ClientEvent::Disconnected{conn_id: conn_id});
+    });
+    true
+  }
+
+  pub fn run_loop(&mut self) {
+    let min_backoff_ms = self.client_cfg.min_backoff_ms;
+    let max_backoff_ms = self.client_cfg.max_backoff_ms;
+    let mut backoff_ms = min_backoff_ms;
+    let mut conn_id = 0;
+    let mut actions = vec![];
+    'connect: loop {
+      let mut stream = match TcpStream::connect((&self.server_cfg.host as &str, self.server_cfg.port)) {
+        Ok(stream) => stream,
+        Err(e) => {
+          println!("WARNING: nngs client: failed to connect: {:?}", e);
+          if !self.client_cfg.reconnect {
+            break 'connect;
+          }
+          sleep_ms(backoff_ms);
+          backoff_ms = min(max_backoff_ms, 2 * backoff_ms);
+          continue 'connect;
+        }
+      };
+      conn_id += 1;
+      if !self.spawn_reader(conn_id, &stream) {
+        continue 'connect;
+      }
+      backoff_ms = min_backoff_ms;
+
+      actions.clear();
+      let password = self.server_cfg.password.clone();
+      self.session.connect(password.as_ref().map(|p| p as &str), &mut actions);
+      let mut connected = true;
+      loop {
+        for action in replace(&mut actions, vec![]).into_iter() {
+          match action {
+            NngsAction::Write(cmd) => {
+              if connected {
+                if stream.write_all(&cmd).and_then(|_| stream.write_all(b"\n")).is_err() {
+                  connected = false;
+                }
+              }
+            }
+            NngsAction::Agent(msg) => {
+              if self.agent_in_tx.send(msg).is_err() {
+                println!("WARNING: nngs client: agent hung up");
+                break 'connect;
+              }
+            }
+            NngsAction::Rechallenge{delay_ms} => {
+              let events_tx = self.events_tx.clone();
+              spawn(move || {
+                sleep_ms(delay_ms);
+                let _ = events_tx.send(ClientEvent::Rechallenge);
+              });
+            }
+            NngsAction::Disconnect => {
+              let _ = stream.shutdown(Shutdown::Both);
+              break 'connect;
+            }
+          }
+        }
+        if !connected {
+          let _ = stream.shutdown(Shutdown::Both);
+          self.session.disconnect();
+          break;
+        }
+        match self.events_rx.recv() {
+          Ok(ClientEvent::Server{conn_id: id, msg}) => {
+            if id == conn_id {
+              self.session.handle_server(msg, &mut actions);
+            }
+          }
+          Ok(ClientEvent::Disconnected{conn_id: id}) => {
+            if id == conn_id {
+              println!("WARNING: nngs client: disconnected from server");
+              connected = false;
+            }
+          }
+          Ok(ClientEvent::Agent(msg)) => {
+            self.session.handle_agent(msg, &mut actions);
+          }
+          Ok(ClientEvent::Rechallenge) => {
+            self.session.rechallenge(&mut actions);
+          }
+          Err(_) => break 'connect,
+        }
+      }
+      if !self.client_cfg.reconnect {
+        break 'connect;
+      }
+      sleep_ms(backoff_ms);
+      backoff_ms = min(max_backoff_ms, 2 * backoff_ms);
+    }
+    let _ = self.agent_in_tx.send(AgentMsg::Quit);
+    self.barrier.wait();
+  }
+}
+
 /*pub struct NngsConfig {
   pub host:     String,
   pub port:     u16,
@@ -0,0 +1,664 @@
+use board::{RuleSet};
+use book::{OpeningBook};
+use hyper::{HyperparamConfig};
+use search::decision::{DecisionConfig};
+use search::komi::{DynamicKomiMode, DynamicKomiConfig};
+use search::parallel_tree::{
+  MonteCarloSearchConfig, SearchParallelism, SearchWorkerBatchConfig, TreeMemoryConfig, TreePolicyConfig, HorizonConfig,
+  ShapePriorConfig,
+};
+use search::report::{TreeExportConfig};
+use shapes::{ShapeDictionary};
+use txnstate::{TxnStateConfig};
+
+use std::env;
+use std::fs::{File};
+use std::io::{Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc};
+use toml;
+
+/// The config file read when no path is given and `HOLMES_CONFIG` is unset;
+/// it is optional.
+pub const DEFAULT_ENGINE_CONFIG_PATH: &'static str = "holmes.toml";
+
+#[derive(Clone, RustcDecodable, RustcEncodable, Debug)]
+pub struct EngineSearchConfig {
+  /// One of "visits" (uses `num_rollouts`), "time" (uses `time_budget_ms`),
+  /// or "nodes" (uses `max_nodes`).
+  pub limit:            String,
+  pub num_rollouts:     usize,
+  /// Wall-clock budget per move.
+  pub time_budget_ms:   usize,
+  /// New tree nodes per move.
+  pub max_nodes:        usize,
+  /// Stop once the remaining budget cannot change the most visited move.
+  pub early_stop:       bool,
+  pub batch_size:       usize,
+  /// 0 means the default number of workers (e.g. one per GPU).
+  pub num_workers:      usize,
+  pub worker_batch_capacity:    usize,
+  /// One of "tree" (all workers share one tree) or "root" (the workers are
+  /// split among `root_trees` independent trees).
+  pub parallelism:      String,
+  pub root_trees:       usize,
+}
+
+#[derive(Clone, RustcDecodable, RustcEncodable, Debug)]
+pub struct EngineTreeConfig {
+  /// One of "all", "fixed" (uses `max_horizon`), or "pwide" (uses `pwide_mu`).
+  pub horizon:      String,
+  pub max_horizon:  usize,
+  pub pwide_mu:     f32,
+  pub visit_thresh: usize,
+  pub mc_scale:     f32,
+  pub prior_equiv:  f32,
+  pub rave:         bool,
+  pub rave_equiv:   f32,
+  /// Weight of the virtual loss of each visit in flight; 0 disables it.
+  pub virtual_loss: f32,
+  /// Memory budget of the search tree; 0 means unbounded.
+  pub memory_budget_mb: usize,
+  /// Whether to drop the states of inner nodes before pruning subtrees.
+  pub compact_states:   bool,
+}
+
+#[derive(Clone, Copy, RustcDecodable, RustcEncodable, Debug)]
+pub struct EngineTimeConfig {
+  /// Used when the server does not send time settings.
+  pub main_time_s:      f32,
+  pub byoyomi_time_s:   f32,
+  /// Always pass when less time than this remains.
+  pub min_remaining_s:  f32,
+  /// Expected round trip time to the server.
+  pub rtt_ms:           usize,
+}
+
+#[derive(Clone, RustcDecodable, RustcEncodable, Debug)]
+pub struct EngineRulesConfig {
+  /// Parsed by `RuleSet::parse_sgf_str`, e.g. "japanese" or "chinese".
+  pub ruleset:  String,
+  pub komi:     f32,
+}
+
+#[derive(Clone, RustcDecodable, RustcEncodable, Debug)]
+pub struct EngineKomiConfig {
+  /// One of "off", "handicap", "situational", or "ratchet".
+  pub mode:         String,
+  /// Points per handicap stone.
+  pub stone_value:  f32,
+  /// The handicap mode fades out the shift by this ply.
+  pub fade_ply:     usize,
+  /// The situational modes shift the komi when the win rate leaves
+  /// [`lower_value`, `upper_value`].
+  pub lower_value:  f32,
+  pub upper_value:  f32,
+  /// All modes change the shift in multiples of `step` points.
+  pub step:         f32,
+  pub max_shift:    f32,
+}
+
+#[derive(Clone, Copy, RustcDecodable, RustcEncodable, Debug)]
+pub struct EngineDecisionConfig {
+  /// Resign after `resign_moves` consecutive moves with a win rate below
+  /// `resign_value`; 0 moves never resigns.
+  pub resign_value:       f32,
+  pub resign_moves:       usize,
+  pub resign_min_ply:     usize,
+  pub resign_in_handicap: bool,
+  /// Only pass when at most this many points have an uncertain owner.
+  pub pass_max_unsettled: usize,
+  /// Weight of the capture bias added to the prior values in the cleanup
+  /// phase (`kgs-genmove_cleanup`).
+  pub cleanup_weight:     f32,
+}
+
+#[derive(Clone, RustcDecodable, RustcEncodable, Debug)]
+pub struct EngineNetworkConfig {
+  pub prior_model:      String,
+  pub rollout_model:    String,
+  /// Move feature gammas fitted by `train-patterns`, for the pattern
+  /// policies.
+  pub pattern_gammas:   String,
+}
+
+#[derive(Clone, RustcDecodable, RustcEncodable, Debug)]
+pub struct EngineBookConfig {
+  /// Opening book built by `build-book`; empty for no book.
+  pub path:         String,
+  /// The book is only queried for the first `max_ply` moves.
+  pub max_ply:      usize,
+  pub min_count:    u32,
+  /// 0 always plays the most frequent book move.
+  pub temperature:  f32,
+}
+
+#[derive(Clone, RustcDecodable, RustcEncodable, Debug)]
+pub struct EngineShapesConfig {
+  /// Shape dictionary mined by `mine-shapes`; empty for no shape prior.
+  pub dictionary:   String,
+  /// Weight of the shape play rates added to the prior values.
+  pub prior_weight: f32,
+}
+
+#[derive(Clone, RustcDecodable, RustcEncodable, Debug)]
+pub struct EngineReportConfig {
+  /// Directory for a search report and tree export per move; empty for no
+  /// reports.
+  pub dir:          String,
+  pub pv_depth:     usize,
+  /// The exported trees leave out arms deeper than `max_depth` or with
+  /// fewer than `min_visits` visits.
+  pub max_depth:    usize,
+  pub min_visits:   usize,
+}
+
+/// The UCB and progressive bias constants of the legacy tree policies; the
+/// prior, RAVE, and widening parameters come from `[tree]` (see
+/// `EngineConfig::hyperparam_cfg`).
+#[derive(Clone, Copy, RustcDecodable, RustcEncodable, Debug)]
+pub struct EngineHyperparamConfig {
+  pub ucb_c:        f32,
+  pub pbias_c:      f32,
+  pub pbias_equiv:  f32,
+}
+
+/// The typed engine configuration. See `EngineConfig::load` for how it is
+/// layered.
+#[derive(Clone, RustcDecodable, RustcEncodable, Debug)]
+pub struct EngineConfig {
+  pub search:       EngineSearchConfig,
+  pub tree:         EngineTreeConfig,
+  pub time:         EngineTimeConfig,
+  pub rules:        EngineRulesConfig,
+  pub dynamic_komi: EngineKomiConfig,
+  pub decision:     EngineDecisionConfig,
+  pub network:      EngineNetworkConfig,
+  pub book:         EngineBookConfig,
+  pub shapes:       EngineShapesConfig,
+  pub report:       EngineReportConfig,
+  pub hyperparam:   EngineHyperparamConfig,
+}
+
+impl Default for EngineConfig {
+  fn default() -> EngineConfig {
+    EngineConfig{
+      search:   EngineSearchConfig{
+        limit:          "visits".to_string(),
+        num_rollouts:   1024,
+        time_budget_ms: 5000,
+        max_nodes:      16384,
+        early_stop:     true,
+        batch_size:     16,
+        num_workers:    0,
+        worker_batch_capacity:  256,
+        parallelism:    "tree".to_string(),
+        root_trees:     4,
+      },
+      tree:     EngineTreeConfig{
+        horizon:        "fixed".to_string(),
+        max_horizon:    20,
+        pwide_mu:       1.8,
+        visit_thresh:   1,
+        mc_scale:       1.0,
+        prior_equiv:    16.0,
+        rave:           false,
+        rave_equiv:     0.0,
+        virtual_loss:   0.0,
+        memory_budget_mb:   0,
+        compact_states:     true,
+      },
+      time:     EngineTimeConfig{
+        main_time_s:        0.0,
+        byoyomi_time_s:     0.0,
+        min_remaining_s:    15.0,
+        rtt_ms:             200,
+      },
+      rules:    EngineRulesConfig{
+        ruleset:    "japanese".to_string(),
+        komi:       6.5,
+      },
+      dynamic_komi: EngineKomiConfig{
+        mode:           "off".to_string(),
+        stone_value:    7.0,
+        fade_ply:       200,
+        lower_value:    0.45,
+        upper_value:    0.75,
+        step:           1.0,
+        max_shift:      30.0,
+      },
+      decision: EngineDecisionConfig{
+        resign_value:       0.1,
+        resign_moves:       3,
+        resign_min_ply:     50,
+        resign_in_handicap: false,
+        pass_max_unsettled: 10,
+        cleanup_weight:     1.0,
+      },
+      network:  EngineNetworkConfig{
+        prior_model:    "models/gogodb_w2015-preproc-alphav3m_19x19x32_13layer384multi3.saved".to_string(),
+        rollout_model:  "models/gogodb_w2015-preproc-alphaminiv3m_19x19x16_2layer16-5x5.saved".to_string(),
+        pattern_gammas: "pat_gammas.db".to_string(),
+      },
+      book:     EngineBookConfig{
+        path:           String::new(),
+        max_ply:        20,
+        min_count:      10,
+        temperature:    0.0,
+      },
+      shapes:   EngineShapesConfig{
+        dictionary:     String::new(),
+        prior_weight:   0.5,
+      },
+      report:   EngineReportConfig{
+        dir:            String::new(),
+        pv_depth:       10,
+        max_depth:      3,
+        min_visits:     16,
+      },
+      hyperparam:   EngineHyperparamConfig{
+        ucb_c:          0.9,
+        pbias_c:        0.8,
+        pbias_equiv:    600.0,
+      },
+    }
+  }
+}
+
+impl EngineConfig {
+  /// Loads the config in layers, each overriding the previous one:
+  ///
+  /// 1. the defaults;
+  /// 2. the TOML file at `path`, else at `$HOLMES_CONFIG`, else at
+  ///    `holmes.toml` if it exists;
+  /// 3. env vars `HOLMES_<SECTION>_<KEY>`, e.g. `HOLMES_SEARCH_BATCH_SIZE=32`;
+  /// 4. `overrides` of the form `section.key=value`, e.g. from CLI flags.
+  ///
+  /// Unknown keys and mistyped values are errors, and the result is
+  /// validated. `HOLMES_*` env vars which do not name a config key are
+  /// ignored; mistyped env values are only warned about.
+  pub fn load(path: Option<&Path>, overrides: &[String]) -> Result<EngineConfig, String> {
+    let mut table = match toml::encode(&EngineConfig::default()) {
+      toml::Value::Table(table) => table,
+      _ => unreachable!(),
+    };
+
+    let file_path = match path {
+      Some(path) => Some(path.to_path_buf()),
+      None => match env::var("HOLMES_CONFIG") {
+        Ok(path) => Some(PathBuf::from(&path)),
+        Err(_) => {
+          let path = PathBuf::from(DEFAULT_ENGINE_CONFIG_PATH);
+          if path.exists() { Some(path) } else { None }
+        }
+      },
+    };
+    if let Some(file_path) = file_path {
+      let mut file = match File::open(&file_path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("failed to open config file {:?}: {}", file_path, e)),
+      };
+      let mut text = String::new();
+      if let Err(e) = file.read_to_string(&mut text) {
+        return Err(format!("failed to read config file {:?}: {}", file_path, e));
+      }
+      let mut parser = toml::Parser::new(&text);
+      let file_table = match parser.parse() {
+        Some(file_table) => file_table,
+        None => return Err(format!("failed to parse config file {:?}: {:?}", file_path, parser.errors)),
+      };
+      for (section, section_value) in file_table.into_iter() {
+        let section_table = match section_value {
+          toml::Value::Table(section_table) => section_table,
+          _ => return Err(format!("config file: '{}' should be a section", section)),
+        };
+        for (key, value) in section_table.into_iter() {
+          try!(set_value(&mut table, &section, &key, value));
+        }
+      }
+    }
+
+    let mut env_vars: Vec<_> = env::vars().collect();
+    env_vars.sort();
+    for (var, raw_value) in env_vars.into_iter() {
+      if !var.starts_with("HOLMES_") || var == "HOLMES_CONFIG" {
+        continue;
+      }
+      let name = var["HOLMES_".len() .. ].to_lowercase();
+      // Section names may contain underscores too (e.g. `dynamic_komi`).
+      let section_key = table.keys()
+        .filter(|section| name.starts_with(&format!("{}_", section)))
+        .map(|section| (section.clone(), name[section.len() + 1 .. ].to_string()))
+        .find(|&(ref section, ref key)| lookup_value(&table, section, key).is_some());
+      if let Some((section, key)) = section_key {
+        if let Err(e) = set_raw_value(&mut table, &section, &key, &raw_value) {
+          println!("WARNING: config: ignoring env var {}: {}", var, e);
+        }
+      }
+    }
+
+    for s in overrides.iter() {
+      let (name, raw_value) = match s.find('=') {
+        Some(pos) => (s[ .. pos].trim(), s[pos + 1 .. ].trim()),
+        None => return Err(format!("override should be section.key=value: \"{}\"", s)),
+      };
+      let (section, key) = match name.find('.') {
+        Some(pos) => (&name[ .. pos], &name[pos + 1 .. ]),
+        None => return Err(format!("override should be section.key=value: \"{}\"", s)),
+      };
+      try!(set_raw_value(&mut table, section, key, raw_value));
+    }
+
+    let cfg: EngineConfig = match toml::decode(toml::Value::Table(table)) {
+      Some(cfg) => cfg,
+      None => return Err("failed to decode config (is a count negative?)".to_string()),
+    };
+    try!(cfg.validate());
+    Ok(cfg)
+  }
+
+  pub fn validate(&self) -> Result<(), String> {
+    if self.search.batch_size == 0 {
+      return Err("search.batch_size should be positive".to_string());
+    }
+    if self.search.worker_batch_capacity == 0 {
+      return Err("search.worker_batch_capacity should be positive".to_string());
+    }
+    match &self.search.limit as &str {
+      "visits" => if self.search.num_rollouts < self.search.batch_size {
+        return Err("search.num_rollouts should be at least search.batch_size".to_string());
+      },
+      "time" => if self.search.time_budget_ms == 0 {
+        return Err("search.time_budget_ms should be positive".to_string());
+      },
+      "nodes" => if self.search.max_nodes == 0 {
+        return Err("search.max_nodes should be positive".to_string());
+      },
+      x => return Err(format!("unknown search.limit: \"{}\" (expected visits, time, or nodes)", x)),
+    }
+    match &self.search.parallelism as &str {
+      "tree" => {}
+      "root" => if self.search.root_trees == 0 {
+        return Err("search.root_trees should be positive".to_string());
+      } else if self.search.num_workers > 0 && self.search.root_trees > self.search.num_workers {
+        return Err("search.root_trees should be at most search.num_workers".to_string());
+      },
+      x => return Err(format!("unknown search.parallelism: \"{}\" (expected tree or root)", x)),
+    }
+    match &self.tree.horizon as &str {
+      "all" => {}
+      "fixed" => if self.tree.max_horizon == 0 {
+        return Err("tree.max_horizon should be positive".to_string());
+      },
+      "pwide" => if !(self.tree.pwide_mu > 1.0) {
+        return Err("tree.pwide_mu should be greater than 1".to_string());
+      },
+      x => return Err(format!("unknown tree.horizon: \"{}\" (expected all, fixed, or pwide)", x)),
+    }
+    if self.tree.visit_thresh == 0 {
+      return Err("tree.visit_thresh should be positive".to_string());
+    }
+    if !(self.tree.mc_scale >= 0.0 && self.tree.prior_equiv >= 0.0 && self.tree.rave_equiv >= 0.0) {
+      return Err("tree.mc_scale, tree.prior_equiv, and tree.rave_equiv should be nonnegative".to_string());
+    }
+    if !(self.tree.virtual_loss >= 0.0) {
+      return Err("tree.virtual_loss should be nonnegative".to_string());
+    }
+    if !(self.time.main_time_s >= 0.0 && self.time.byoyomi_time_s >= 0.0 && self.time.min_remaining_s >= 0.0) {
+      return Err("time settings should be nonnegative".to_string());
+    }
+    if RuleSet::parse_sgf_str(&self.rules.ruleset).is_none() {
+      return Err(format!("unknown rules.ruleset: \"{}\"", self.rules.ruleset));
+    }
+    if !(self.rules.komi.abs() <= 100.0) {
+      return Err(format!("bad rules.komi: {}", self.rules.komi));
+    }
+    if DynamicKomiMode::parse(&self.dynamic_komi.mode).is_none() {
+      return Err(format!("unknown dynamic_komi.mode: \"{}\" (expected off, handicap, situational, or ratchet)", self.dynamic_komi.mode));
+    }
+    if self.dynamic_komi.fade_ply == 0 {
+      return Err("dynamic_komi.fade_ply should be positive".to_string());
+    }
+    if !(0.0 <= self.dynamic_komi.lower_value && self.dynamic_komi.lower_value <= self.dynamic_komi.upper_value && self.dynamic_komi.upper_value <= 1.0) {
+      return Err("dynamic_komi values should satisfy 0 <= lower_value <= upper_value <= 1".to_string());
+    }
+    if !(self.dynamic_komi.stone_value >= 0.0 && self.dynamic_komi.step >= 0.0 && self.dynamic_komi.max_shift >= 0.0) {
+      return Err("dynamic_komi.stone_value, dynamic_komi.step, and dynamic_komi.max_shift should be nonnegative".to_string());
+    }
+    if !(0.0 <= self.decision.resign_value && self.decision.resign_value <= 1.0) {
+      return Err("decision.resign_value should be in [0, 1]".to_string());
+    }
+    if !(self.decision.cleanup_weight >= 0.0) {
+      return Err("decision.cleanup_weight should be nonnegative".to_string());
+    }
+    if self.network.prior_model.is_empty() || self.network.rollout_model.is_empty() {
+      return Err("network model paths should not be empty".to_string());
+    }
+    if !(self.book.temperature >= 0.0) {
+      return Err("book.temperature should be nonnegative".to_string());
+    }
+    if !(self.shapes.prior_weight >= 0.0) {
+      return Err("shapes.prior_weight should be nonnegative".to_string());
+    }
+    if self.report.max_depth == 0 {
+      return Err("report.max_depth should be positive".to_string());
+    }
+    Ok(())
+  }
+
+  /// Checks that the network model files exist; only engines which load the
+  /// networks need to call this.
+  pub fn check_network_paths(&self) -> Result<(), String> {
+    for &path in [&self.network.prior_model as &str, &self.network.rollout_model as &str].iter() {
+      if !Path::new(path).exists() {
+        return Err(format!("network model not found: \"{}\"", path));
+      }
+    }
+    Ok(())
+  }
+
+  pub fn to_toml_string(&self) -> String {
+    toml::encode_str(self)
+  }
+
+  /// Dumps the effective config for reproducibility.
+  pub fn log(&self) {
+    for line in self.to_toml_string().lines() {
+      println!("DEBUG: config: {}", line);
+    }
+  }
+
+  pub fn search_cfg(&self) -> MonteCarloSearchConfig {
+    MonteCarloSearchConfig{
+      num_rollouts: self.search.num_rollouts,
+      batch_size:   self.search.batch_size,
+    }
+  }
+
+  /// The search budget per move.
+  pub fn search_batch_cfg(&self) -> SearchWorkerBatchConfig {
+    match &self.search.limit as &str {
+      "visits"  => SearchWorkerBatchConfig::Fixed{num_batches: self.search.num_rollouts / self.search.batch_size},
+      "time"    => SearchWorkerBatchConfig::TimeLimit{budget_ms: self.search.time_budget_ms, tol_ms: 0},
+      "nodes"   => SearchWorkerBatchConfig::NodeLimit{max_nodes: self.search.max_nodes},
+      _ => unreachable!(),
+    }
+  }
+
+  pub fn search_parallelism(&self) -> SearchParallelism {
+    match &self.search.parallelism as &str {
+      "tree"  => SearchParallelism::Tree,
+      "root"  => SearchParallelism::Root{num_trees: self.search.root_trees},
+      _ => unreachable!(),
+    }
+  }
+
+  pub fn num_workers(&self, default_num_workers: usize) -> usize {
+    match self.search.num_workers {
+      0 => default_num_workers,
+      n => n,
+    }
+  }
+
+  pub fn tree_policy_cfg(&self) -> TreePolicyConfig {
+    let horizon_cfg = match &self.tree.horizon as &str {
+      "all"   => HorizonConfig::All,
+      "fixed" => HorizonConfig::Fixed{max_horizon: self.tree.max_horizon},
+      "pwide" => HorizonConfig::Pwide{mu: self.tree.pwide_mu},
+      _ => unreachable!(),
+    };
+    TreePolicyConfig{
+      horizon_cfg:  horizon_cfg,
+      visit_thresh: self.tree.visit_thresh,
+      mc_scale:     self.tree.mc_scale,
+      prior_equiv:  self.tree.prior_equiv,
+      rave:         self.tree.rave,
+      rave_equiv:   self.tree.rave_equiv,
+      virtual_loss: self.tree.virtual_loss,
+    }
+  }
+
+  /// The hyperparameters of the legacy tree policies.
+  pub fn hyperparam_cfg(&self) -> HyperparamConfig {
+    HyperparamConfig{
+      ucb_c:        self.hyperparam.ucb_c,
+      pbias_c:      self.hyperparam.pbias_c,
+      pbias_equiv:  self.hyperparam.pbias_equiv,
+      prior:        self.tree.prior_equiv > 0.0,
+      prior_equiv:  self.tree.prior_equiv,
+      pwide:        &self.tree.horizon as &str == "pwide",
+      pwide_mu:     self.tree.pwide_mu,
+      rave:         self.tree.rave,
+      rave_equiv:   self.tree.rave_equiv,
+    }
+  }
+
+  pub fn tree_memory_cfg(&self) -> Option<TreeMemoryConfig> {
+    match self.tree.memory_budget_mb {
+      0 => None,
+      budget_mb => Some(TreeMemoryConfig{
+        budget_bytes:   budget_mb * 1024 * 1024,
+        compact_states: self.tree.compact_states,
+      }),
+    }
+  }
+
+  /// Opens the opening book, if any.
+  pub fn open_book(&self) -> Result<Option<OpeningBook>, String> {
+    if self.book.path.is_empty() {
+      return Ok(None);
+    }
+    match OpeningBook::open(&PathBuf::from(&self.book.path)) {
+      Ok(book) => Ok(Some(book)),
+      Err(_) => Err(format!("failed to open opening book: \"{}\"", self.book.path)),
+    }
+  }
+
+  /// Opens the shape dictionary of the shape prior, if any.
+  pub fn shape_prior_cfg(&self) -> Result<Option<ShapePriorConfig>, String> {
+    if self.shapes.dictionary.is_empty() {
+      return Ok(None);
+    }
+    match ShapeDictionary::open(&PathBuf::from(&self.shapes.dictionary)) {
+      Ok(dict) => Ok(Some(ShapePriorConfig{
+        dict:   Arc::new(dict),
+        weight: self.shapes.prior_weight,
+      })),
+      Err(_) => Err(format!("failed to open shape dictionary: \"{}\"", self.shapes.dictionary)),
+    }
+  }
+
+  pub fn report_dir(&self) -> Option<PathBuf> {
+    match &self.report.dir as &str {
+      "" => None,
+      dir => Some(PathBuf::from(dir)),
+    }
+  }
+
+  pub fn tree_export_cfg(&self) -> TreeExportConfig {
+    TreeExportConfig{
+      max_depth:  self.report.max_depth,
+      min_visits: self.report.min_visits,
+    }
+  }
+
+  pub fn dynamic_komi_cfg(&self) -> DynamicKomiConfig {
+    DynamicKomiConfig{
+      mode:         DynamicKomiMode::parse(&self.dynamic_komi.mode).unwrap(),
+      stone_value:  self.dynamic_komi.stone_value,
+      fade_ply:     self.dynamic_komi.fade_ply,
+      lower_value:  self.dynamic_komi.lower_value,
+      upper_value:  self.dynamic_komi.upper_value,
+      step:         self.dynamic_komi.step,
+      max_shift:    self.dynamic_komi.max_shift,
+    }
+  }
+
+  pub fn decision_cfg(&self) -> DecisionConfig {
+    DecisionConfig{
+      resign_value:       self.decision.resign_value,
+      resign_moves:       self.decision.resign_moves,
+      resign_min_ply:     self.decision.resign_min_ply,
+      resign_in_handicap: self.decision.resign_in_handicap,
+      pass_max_unsettled: self.decision.pass_max_unsettled,
+    }
+  }
+
+  pub fn state_cfg(&self) -> TxnStateConfig {
+    TxnStateConfig{
+      rules:    RuleSet::parse_sgf_str(&self.rules.ruleset).unwrap().rules(),
+      komi:     self.rules.komi,
+      // The agents undo moves; search states drop the history (`Node::new`).
+      history:  true,
+      .. TxnStateConfig::default()
+    }
+  }
+}
+
+/// Parses a raw (env or CLI) value as a TOML value of the same type as the
+/// current value; string values need no quotes.
+fn set_raw_value(table: &mut toml::Table, section: &str, key: &str, raw_value: &str) -> Result<(), String> {
+  let is_string = match lookup_value(table, section, key) {
+    Some(&toml::Value::String(_)) => true,
+    Some(_) => false,
+    None => return Err(format!("unknown config key: {}.{}", section, key)),
+  };
+  let value = if is_string {
+    toml::Value::String(raw_value.to_string())
+  } else {
+    let text = format!("value = {}", raw_value);
+    match toml::Parser::new(&text).parse().and_then(|mut t| t.remove("value")) {
+      Some(value) => value,
+      None => return Err(format!("bad value for {}.{}: \"{}\"", section, key, raw_value)),
+    }
+  };
+  set_value(table, section, key, value)
+}
+
+fn lookup_value<'a>(table: &'a toml::Table, section: &str, key: &str) -> Option<&'a toml::Value> {
+  match table.get(section) {
+    Some(&toml::Value::Table(ref section_table)) => section_table.get(key),
+    _ => None,
+  }
+}
+
+fn set_value(table: &mut toml::Table, section: &str, key: &str, value: toml::Value) -> Result<(), String> {
+  let value = match (lookup_value(table, section, key), value) {
+    (None, _) => return Err(format!("unknown config key: {}.{}", section, key)),
+    (Some(&toml::Value::Float(_)), toml::Value::Integer(x)) => toml::Value::Float(x as f64),
+    (Some(prev_value), value) => {
+      if prev_value.type_str() != value.type_str() {
+        return Err(format!("{}.{} should be a {}, not a {}",
+            section, key, prev_value.type_str(), value.type_str()));
+      }
+      value
+    }
+  };
+  match table.get_mut(section) {
+    Some(&mut toml::Value::Table(ref mut section_table)) => {
+      section_table.insert(key.to_string(), value);
+    }
+    _ => unreachable!(),
+  }
+  Ok(())
+}
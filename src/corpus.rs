@@ -0,0 +1,594 @@
+use board::{RuleSet, PlayerRank, Stone, Point, Action};
+use gtp_board::{Coord};
+use sgf::{Sgf, SgfRankStyle, parse_result_winner};
+use txnstate::{TxnState, TxnStatus};
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, read_dir};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use toml;
+
+/// Per-game metadata of an SGF file in a corpus.
+#[derive(Clone, RustcDecodable, RustcEncodable, Debug)]
+pub struct CorpusEntry {
+  pub path:         String,
+  pub black_player: String,
+  pub black_rank:   String,
+  pub white_player: String,
+  pub white_rank:   String,
+  pub date:         String,
+  pub rules:        String,
+  pub komi:         Option<f64>,
+  pub handicap:     usize,
+  pub board_dim:    i64,
+  pub result:       String,
+  pub num_moves:    usize,
+  /// Hex hash of the setup stones and moves, and the least such hash over the
+  /// 8 board symmetries; empty if the board size is unsupported.
+  pub moves_hash:     String,
+  pub canonical_hash: String,
+  /// Problems found while parsing and replaying the game, as "kind: detail";
+  /// empty if none.
+  pub errors:       Vec<String>,
+}
+
+impl CorpusEntry {
+  pub fn scan(path: &Path) -> CorpusEntry {
+    let mut text = vec![];
+    let read_res = File::open(path).and_then(|mut file| file.read_to_end(&mut text));
+    let path_str = path.to_str().unwrap();
+    match read_res {
+      Ok(_) => CorpusEntry::from_text(path_str, &text),
+      Err(e) => CorpusEntry::from_error(path_str, format!("failed to read: {}", e)),
+    }
+  }
+
+  fn from_error(path: &str, error: String) -> CorpusEntry {
+    CorpusEntry{
+      path:         path.to_string(),
+      black_player: String::new(),
+      black_rank:   String::new(),
+      white_player: String::new(),
+      white_rank:   String::new(),
+      date:         String::new(),
+      rules:        String::new(),
+      komi:         None,
+      handicap:     0,
+      board_dim:    0,
+      result:       String::new(),
+      num_moves:    0,
+      moves_hash:     String::new(),
+      canonical_hash: String::new(),
+      errors:       vec![error],
+    }
+  }
+
+  /// Parses the game record and replays it to find illegal moves.
+  pub fn from_text(path: &str, text: &[u8]) -> CorpusEntry {
+    // XXX(20160421): The raw SGF parser panics on some malformed records
+    // rather than returning an error, so parse on a separate thread and
+    // record the panic.
+    let text_buf = text.to_vec();
+    let sgf = match thread::spawn(move || Sgf::from_text(&text_buf)).join() {
+      Ok(sgf) => sgf,
+      Err(e) => {
+        let msg = match e.downcast_ref::<String>() {
+          Some(msg) => msg.clone(),
+          None => match e.downcast_ref::<&'static str>() {
+            Some(msg) => msg.to_string(),
+            None => "unknown panic".to_string(),
+          },
+        };
+        return CorpusEntry::from_error(path, format!("failed to parse: {}", msg));
+      }
+    };
+    let mut errors = vec![];
+    let mut moves_hash = String::new();
+    let mut canonical_hash = String::new();
+    if sgf.board_dim != 19 {
+      errors.push(format!("unsupported board size: {}", sgf.board_dim));
+    } else {
+      replay_sgf(&sgf, &mut errors);
+      let hashes = symmetric_moves_hashes(&sgf);
+      moves_hash = format!("{:016x}", hashes[0]);
+      canonical_hash = format!("{:016x}", hashes.iter().min().unwrap());
+    }
+    if let Some(ref rules) = sgf.rules {
+      if RuleSet::parse_sgf_str(rules).is_none() {
+        errors.push(format!("unknown rules: \"{}\"", rules));
+      }
+    }
+    CorpusEntry{
+      path:         path.to_string(),
+      black_player: sgf.black_player.clone(),
+      black_rank:   sgf.black_rank.clone(),
+      white_player: sgf.white_player.clone(),
+      white_rank:   sgf.white_rank.clone(),
+      date:         sgf.date.clone(),
+      rules:        sgf.rules.clone().unwrap_or(String::new()),
+      komi:         sgf.komi,
+      handicap:     sgf.num_handicap(),
+      board_dim:    sgf.board_dim,
+      result:       sgf.result.clone(),
+      num_moves:    sgf.moves.len(),
+      moves_hash:     moves_hash,
+      canonical_hash: canonical_hash,
+      errors:       errors,
+    }
+  }
+
+  /// The winner, if the game was played to a result.
+  pub fn outcome(&self) -> Option<Stone> {
+    parse_result_winner(&self.result)
+  }
+
+  pub fn rule_set(&self) -> Option<RuleSet> {
+    if self.rules.is_empty() {
+      // XXX(20160420): same fallback as `Sgf::infer_state_cfg`.
+      Some(RuleSet::KgsJapanese)
+    } else {
+      RuleSet::parse_sgf_str(&self.rules)
+    }
+  }
+
+  /// The weaker of the two player ranks, if both parse.
+  pub fn min_rank(&self, rank_style: SgfRankStyle) -> Option<PlayerRank> {
+    match (rank_style.parse_rank(&self.black_rank), rank_style.parse_rank(&self.white_rank)) {
+      (Some(b_rank), Some(w_rank)) => if b_rank.strength() <= w_rank.strength() {
+        Some(b_rank)
+      } else {
+        Some(w_rank)
+      },
+      _ => None,
+    }
+  }
+
+  pub fn year(&self) -> Option<i32> {
+    self.date.chars().take(4).collect::<String>().parse().ok()
+  }
+
+  /// The key used to assign splits; this is the file name, so that a corpus
+  /// keeps its splits when moved to another directory.
+  pub fn split_key(&self) -> &str {
+    match self.path.rfind('/') {
+      Some(pos) => &self.path[pos + 1 .. ],
+      None => &self.path,
+    }
+  }
+}
+
+/// Replays a game, stopping at the first illegal move. Repeated whole-board
+/// positions are reported even when the rules allow them.
+fn replay_sgf(sgf: &Sgf, errors: &mut Vec<String>) {
+  let mut state = TxnState::new(sgf.infer_state_cfg(SgfRankStyle::Server), ());
+  state.reset();
+  if state.place_setup_stones(&sgf.setup_stones()).is_err() {
+    errors.push("illegal setup stones".to_string());
+    return;
+  }
+  let mut position_hashes = vec![state.current_hash()];
+  let mut prev_turn = None;
+  for (t, &(ref player, ref mov)) in sgf.moves.iter().enumerate() {
+    let turn = Stone::from_code_str(player);
+    if prev_turn == Some(turn) {
+      errors.push(format!("repeated turn: move {}", t + 1));
+      return;
+    }
+    prev_turn = Some(turn);
+    let action = match mov as &str {
+      "Pass"    => Action::Pass,
+      "Resign"  => Action::Resign,
+      x         => match Coord::parse_code_str(x) {
+        Some(coord) => Action::Place{point: Point::from_coord(coord)},
+        None => {
+          errors.push(format!("bad coordinate: move {} \"{}\"", t + 1, x));
+          return;
+        }
+      },
+    };
+    match state.try_action(turn, action) {
+      Ok(_) => state.commit(),
+      Err(TxnStatus::Illegal(reason)) => {
+        state.undo();
+        errors.push(format!("illegal move: move {} {:?} {} ({:?})", t + 1, turn, mov, reason));
+        return;
+      }
+    }
+    if let Action::Place{..} = action {
+      let hash = state.current_hash();
+      if position_hashes.contains(&hash) {
+        errors.push(format!("superko cycle: move {} {:?} {}", t + 1, turn, mov));
+        return;
+      }
+      position_hashes.push(hash);
+    }
+  }
+}
+
+/// Hashes the setup stones (in sorted order) and the moves under each of the
+/// 8 board symmetries of `Coord::rotate`; index 0 is the identity.
+fn symmetric_moves_hashes(sgf: &Sgf) -> Vec<u64> {
+  let mut hashes = Vec::with_capacity(8);
+  for rot in 0 .. 8 {
+    let mut setup: Vec<(u8, usize)> = sgf.setup_stones().iter()
+      .map(|&(stone, point)| (stone.offset() as u8, point.to_coord().rotate(rot).idx()))
+      .collect();
+    setup.sort();
+    let mut bytes = vec![];
+    for &(stone_off, idx) in setup.iter() {
+      bytes.push(b'+');
+      bytes.push(stone_off);
+      bytes.extend(&[(idx >> 8) as u8, idx as u8]);
+    }
+    for &(ref player, ref mov) in sgf.moves.iter() {
+      bytes.extend(player.as_bytes());
+      match Coord::parse_code_str(mov) {
+        Some(coord) => {
+          let idx = coord.rotate(rot).idx();
+          bytes.extend(&[(idx >> 8) as u8, idx as u8]);
+        }
+        None => bytes.extend(mov.as_bytes()),
+      }
+    }
+    hashes.push(fnv1a_hash(&bytes));
+  }
+  hashes
+}
+
+#[derive(Clone, RustcDecodable, RustcEncodable, Debug)]
+pub struct CorpusIndex {
+  pub entries:  Vec<CorpusEntry>,
+}
+
+impl CorpusIndex {
+  /// Scans `dir` recursively for `.sgf` files, in sorted path order.
+  pub fn scan_dir(dir: &Path) -> CorpusIndex {
+    let mut paths = vec![];
+    find_sgf_paths(dir, &mut paths);
+    paths.sort();
+    let mut entries = Vec::with_capacity(paths.len());
+    for (i, path) in paths.iter().enumerate() {
+      if (i+1) % 1000 == 0 {
+        println!("DEBUG: corpus: scanned {} / {}", i+1, paths.len());
+      }
+      entries.push(CorpusEntry::scan(path));
+    }
+    CorpusIndex{entries: entries}
+  }
+
+  pub fn open(path: &Path) -> Result<CorpusIndex, ()> {
+    let mut file = match File::open(path) {
+      Ok(file) => file,
+      Err(_) => return Err(()),
+    };
+    let mut buf = String::new();
+    file.read_to_string(&mut buf).unwrap();
+    let index: CorpusIndex = match toml::decode_str(&buf) {
+      Some(x) => x,
+      None => return Err(()),
+    };
+    Ok(index)
+  }
+
+  pub fn save(&self, path: &Path) {
+    let mut file = File::create(path).unwrap();
+    file.write_all(toml::encode_str(self).as_bytes()).unwrap();
+  }
+
+  /// Collects the replay errors of every game, and additionally flags bad
+  /// results, games shorter than `min_moves`, and exact or symmetric
+  /// duplicates (by move sequence, ignoring metadata). The first game of
+  /// each duplicate group, in index order, is kept. Returns the report and
+  /// the indexes of the clean games.
+  pub fn validate(&self, min_moves: usize) -> (CorpusReport, Vec<usize>) {
+    let mut issues = vec![];
+    let mut clean_idxs = vec![];
+    let mut canonical_idxs: HashMap<&str, usize> = HashMap::new();
+    for (idx, entry) in self.entries.iter().enumerate() {
+      let num_issues = issues.len();
+      for error in entry.errors.iter() {
+        issues.push(CorpusIssue::from_error(&entry.path, error));
+      }
+      if !is_known_result(&entry.result) {
+        issues.push(CorpusIssue::new(&entry.path, "bad result", format!("\"{}\"", entry.result)));
+      }
+      if entry.num_moves < min_moves {
+        issues.push(CorpusIssue::new(&entry.path, "truncated", format!("{} moves", entry.num_moves)));
+      }
+      if !entry.canonical_hash.is_empty() {
+        match canonical_idxs.get(&entry.canonical_hash as &str) {
+          Some(&orig_idx) => {
+            let orig = &self.entries[orig_idx];
+            let kind = if orig.moves_hash == entry.moves_hash {
+              "duplicate"
+            } else {
+              "symmetric duplicate"
+            };
+            issues.push(CorpusIssue::new(&entry.path, kind, orig.path.clone()));
+          }
+          None => {}
+        }
+        canonical_idxs.entry(&entry.canonical_hash).or_insert(idx);
+      }
+      if issues.len() == num_issues {
+        clean_idxs.push(idx);
+      }
+    }
+    let report = CorpusReport{
+      num_games:    self.entries.len(),
+      num_clean:    clean_idxs.len(),
+      issues:       issues,
+    };
+    (report, clean_idxs)
+  }
+}
+
+fn is_known_result(result: &str) -> bool {
+  let result = result.trim();
+  if result.starts_with("B+") || result.starts_with("W+") {
+    return true;
+  }
+  match result {
+    "0" | "Draw" | "Jigo" | "Void" | "?" => true,
+    _ => false,
+  }
+}
+
+#[derive(Clone, PartialEq, RustcDecodable, RustcEncodable, Debug)]
+pub struct CorpusIssue {
+  pub path:     String,
+  pub kind:     String,
+  pub detail:   String,
+}
+
+impl CorpusIssue {
+  pub fn new(path: &str, kind: &str, detail: String) -> CorpusIssue {
+    CorpusIssue{
+      path:     path.to_string(),
+      kind:     kind.to_string(),
+      detail:   detail,
+    }
+  }
+
+  fn from_error(path: &str, error: &str) -> CorpusIssue {
+    match error.find(':') {
+      Some(pos) => CorpusIssue::new(path, &error[ .. pos], error[pos + 1 .. ].trim().to_string()),
+      None => CorpusIssue::new(path, error, String::new()),
+    }
+  }
+}
+
+/// The result of `CorpusIndex::validate`, saved as TOML.
+#[derive(Clone, RustcDecodable, RustcEncodable, Debug)]
+pub struct CorpusReport {
+  pub num_games:    usize,
+  pub num_clean:    usize,
+  pub issues:       Vec<CorpusIssue>,
+}
+
+impl CorpusReport {
+  pub fn count_by_kind(&self) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for issue in self.issues.iter() {
+      *counts.entry(issue.kind.clone()).or_insert(0) += 1;
+    }
+    counts
+  }
+
+  pub fn save(&self, path: &Path) {
+    let mut file = File::create(path).unwrap();
+    file.write_all(toml::encode_str(self).as_bytes()).unwrap();
+  }
+}
+
+fn find_sgf_paths(dir: &Path, paths: &mut Vec<PathBuf>) {
+  let entries = match read_dir(dir) {
+    Ok(entries) => entries,
+    Err(_) => {
+      println!("WARNING: corpus: failed to read dir: {:?}", dir);
+      return;
+    }
+  };
+  for entry in entries {
+    let path = entry.unwrap().path();
+    if path.is_dir() {
+      find_sgf_paths(&path, paths);
+    } else if path.extension().map_or(false, |ext| ext == "sgf" || ext == "SGF") {
+      paths.push(path);
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CorpusField {
+  Size,
+  Komi,
+  Handicap,
+  MinRank,
+  Moves,
+  Year,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CorpusOp {
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+}
+
+impl CorpusOp {
+  fn eval(&self, lhs: f64, rhs: f64) -> bool {
+    match *self {
+      // Komi values are halves or quarters, so this is exact enough.
+      CorpusOp::Eq => (lhs - rhs).abs() < 0.01,
+      CorpusOp::Ne => (lhs - rhs).abs() >= 0.01,
+      CorpusOp::Lt => lhs < rhs,
+      CorpusOp::Le => lhs <= rhs + 0.01,
+      CorpusOp::Gt => lhs > rhs,
+      CorpusOp::Ge => lhs >= rhs - 0.01,
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CorpusTerm {
+  Compare(CorpusField, CorpusOp, f64),
+  Rules(CorpusOp, RuleSet),
+  /// The game has a "B+..." or "W+..." result.
+  Completed,
+  /// The game parsed and replayed without errors.
+  NoErrors,
+}
+
+/// A conjunction of terms, e.g.
+/// `size = 19 and handicap = 0 and komi >= 5.5 and komi <= 7.5 and rank >= 1d
+/// and rules != chinese and completed and ok`.
+/// Terms may also be separated by `,` or `&&`. A term whose field is missing
+/// from a game (e.g. unparseable ranks or no komi) does not match.
+#[derive(Clone, Debug)]
+pub struct CorpusQuery {
+  pub rank_style:   SgfRankStyle,
+  pub terms:        Vec<CorpusTerm>,
+}
+
+impl CorpusQuery {
+  pub fn parse(query_str: &str, rank_style: SgfRankStyle) -> Result<CorpusQuery, String> {
+    let mut terms = vec![];
+    let query_str = query_str.replace("&&", ",").replace(" and ", ",");
+    for term_str in query_str.split(',') {
+      let term_str = term_str.trim();
+      if term_str.is_empty() {
+        continue;
+      }
+      terms.push(try!(parse_term(term_str, rank_style)));
+    }
+    Ok(CorpusQuery{
+      rank_style:   rank_style,
+      terms:        terms,
+    })
+  }
+
+  pub fn matches(&self, entry: &CorpusEntry) -> bool {
+    self.terms.iter().all(|term| match *term {
+      CorpusTerm::Compare(field, op, rhs) => {
+        let lhs = match field {
+          CorpusField::Size     => Some(entry.board_dim as f64),
+          CorpusField::Komi     => entry.komi,
+          CorpusField::Handicap => Some(entry.handicap as f64),
+          CorpusField::MinRank  => entry.min_rank(self.rank_style).map(|rank| rank.strength() as f64),
+          CorpusField::Moves    => Some(entry.num_moves as f64),
+          CorpusField::Year     => entry.year().map(|year| year as f64),
+        };
+        lhs.map_or(false, |lhs| op.eval(lhs, rhs))
+      }
+      CorpusTerm::Rules(op, rule_set) => match entry.rule_set() {
+        Some(entry_rule_set) => match op {
+          CorpusOp::Ne => entry_rule_set != rule_set,
+          _ => entry_rule_set == rule_set,
+        },
+        None => false,
+      },
+      CorpusTerm::Completed => entry.outcome().is_some(),
+      CorpusTerm::NoErrors  => entry.errors.is_empty(),
+    })
+  }
+}
+
+fn parse_term(term_str: &str, rank_style: SgfRankStyle) -> Result<CorpusTerm, String> {
+  match term_str {
+    "completed" => return Ok(CorpusTerm::Completed),
+    "ok"        => return Ok(CorpusTerm::NoErrors),
+    _ => {}
+  }
+  let ops = [
+    (">=", CorpusOp::Ge), ("<=", CorpusOp::Le),
+    ("!=", CorpusOp::Ne), ("==", CorpusOp::Eq),
+    (">", CorpusOp::Gt), ("<", CorpusOp::Lt), ("=", CorpusOp::Eq),
+  ];
+  for &(op_str, op) in ops.iter() {
+    if let Some(pos) = term_str.find(op_str) {
+      let key = term_str[ .. pos].trim();
+      let value = term_str[pos + op_str.len() .. ].trim();
+      let field = match key {
+        "size"      => CorpusField::Size,
+        "komi"      => CorpusField::Komi,
+        "handicap"  => CorpusField::Handicap,
+        "rank"      => CorpusField::MinRank,
+        "moves"     => CorpusField::Moves,
+        "year"      => CorpusField::Year,
+        "rules"     => {
+          if op != CorpusOp::Eq && op != CorpusOp::Ne {
+            return Err(format!("rules can only be compared with = or !=: \"{}\"", term_str));
+          }
+          return match RuleSet::parse_sgf_str(value) {
+            Some(rule_set) => Ok(CorpusTerm::Rules(op, rule_set)),
+            None => Err(format!("unknown rules: \"{}\"", value)),
+          };
+        }
+        _ => return Err(format!("unknown query field: \"{}\"", key)),
+      };
+      let rhs = if field == CorpusField::MinRank {
+        match rank_style.parse_rank(value) {
+          Some(rank) => rank.strength() as f64,
+          None => return Err(format!("bad rank: \"{}\"", value)),
+        }
+      } else {
+        match value.parse() {
+          Ok(x) => x,
+          Err(_) => return Err(format!("bad number: \"{}\"", value)),
+        }
+      };
+      return Ok(CorpusTerm::Compare(field, op, rhs));
+    }
+  }
+  Err(format!("bad query term: \"{}\"", term_str))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CorpusSplit {
+  Train,
+  Valid,
+  Test,
+}
+
+impl CorpusSplit {
+  pub fn to_str(&self) -> &'static str {
+    match *self {
+      CorpusSplit::Train => "train",
+      CorpusSplit::Valid => "valid",
+      CorpusSplit::Test  => "test",
+    }
+  }
+
+  /// Deterministically assigns a split by hashing `key`; the same key always
+  /// lands in the same split for the same fractions.
+  pub fn assign(key: &str, valid_frac: f64, test_frac: f64) -> CorpusSplit {
+    let u = (fnv1a_hash(key.as_bytes()) % 1_000_000) as f64 / 1_000_000.0;
+    if u < test_frac {
+      CorpusSplit::Test
+    } else if u < test_frac + valid_frac {
+      CorpusSplit::Valid
+    } else {
+      CorpusSplit::Train
+    }
+  }
+
+  pub fn index_path(&self, prefix: &Path) -> PathBuf {
+    PathBuf::from(&format!("{}_{}_index", prefix.to_str().unwrap(), self.to_str()))
+  }
+}
+
+/// FNV-1a; unlike the std hashers this is stable across builds.
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+  let mut h: u64 = 0xcbf29ce484222325;
+  for &b in bytes {
+    h ^= b as u64;
+    h = h.wrapping_mul(0x100000001b3);
+  }
+  h
+}
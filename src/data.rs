@@ -1,6 +1,6 @@
 use board::{RuleSet, PlayerRank, Coord, Stone, Point, Action};
-use sgf::{Sgf, parse_raw_sgf};
-use txnstate::{TxnStateConfig, TxnState};
+use sgf::{Sgf, SgfRankStyle, parse_raw_sgf};
+use txnstate::{TxnState};
 use txnstate::extras::{TxnStateNodeData};
 
 use array_new::{Shape};
@@ -128,12 +128,18 @@ impl EpisodePreproc for GogodbEpisodePreproc {
 
     let mut history = vec![];
     let mut state = TxnState::new(
-        // FIXME(20160308): infer correct state cfg (namely, komi and ranks).
-        TxnStateConfig::default(),
+        sgf.infer_state_cfg(SgfRankStyle::Gogod),
         TxnStateNodeData::new(),
         //TxnStateLibFeaturesData::new(),
     );
     state.reset();
+    if state.place_setup_stones(&sgf.setup_stones()).is_err() {
+      println!("WARNING: try_load: found illegal setup stones!");
+      return None;
+    }
+    if sgf.num_handicap() > 0 {
+      state.unsafe_set_current_turn(Stone::White);
+    }
     for (t, &(ref player, ref mov)) in sgf.moves.iter().enumerate() {
       let turn = match player as &str {
         "B" => Stone::Black,
@@ -145,7 +151,7 @@ impl EpisodePreproc for GogodbEpisodePreproc {
         if t == 0 {
           state.unsafe_set_current_turn(turn);
         } else {
-          println!("WARNING: try_load: repeated move!");
+          println!("WARNING: try_load: repeated turn: move {} {:?} {}", t + 1, turn, mov);
           history.clear();
           break;
         }
@@ -160,8 +166,8 @@ impl EpisodePreproc for GogodbEpisodePreproc {
         Ok(_) => {
           state.commit();
         }
-        Err(_) => {
-          println!("WARNING: try_load: found an illegal action!");
+        Err(e) => {
+          println!("WARNING: try_load: illegal move: move {} {:?} {} ({:?})", t + 1, turn, mov, e);
           history.clear();
           break;
         }
@@ -0,0 +1,206 @@
+use board::{Board};
+use txnstate::feature_sets::{FeatureSet, lookup_feature_set};
+use txnstate::features::{TxnStateSerialFeatsData};
+
+use std::fs::{File};
+use std::io::{Read, Write};
+use std::mem::{size_of};
+use std::path::{Path, PathBuf};
+use time::{get_time};
+use toml;
+
+/// Bumped whenever the on-disk layout of the episode databases changes.
+pub const DATASET_FORMAT_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DatasetLabel {
+  /// i32 LE point index; 361 is resign and 362 is pass.
+  Action,
+  /// i32 LE; 1 if the player to move won, else 0.
+  Value,
+  /// `Board::SIZE` f32 LE, the normalized root visit distribution.
+  Visits,
+}
+
+impl DatasetLabel {
+  pub fn to_str(&self) -> &'static str {
+    match *self {
+      DatasetLabel::Action => "action",
+      DatasetLabel::Value  => "value",
+      DatasetLabel::Visits => "visits",
+    }
+  }
+
+  pub fn parse_str(s: &str) -> Option<DatasetLabel> {
+    match s {
+      "action"  => Some(DatasetLabel::Action),
+      "value"   => Some(DatasetLabel::Value),
+      "visits"  => Some(DatasetLabel::Visits),
+      _ => None,
+    }
+  }
+
+  pub fn frame_size(&self) -> usize {
+    match *self {
+      DatasetLabel::Action => size_of::<i32>(),
+      DatasetLabel::Value  => size_of::<i32>(),
+      DatasetLabel::Visits => Board::SIZE * size_of::<f32>(),
+    }
+  }
+}
+
+#[derive(Clone, PartialEq, RustcDecodable, RustcEncodable, Debug)]
+pub struct DatasetProvenance {
+  pub tool:         String,
+  pub sources:      Vec<String>,
+  pub created_unix_s:   i64,
+}
+
+impl DatasetProvenance {
+  pub fn new(tool: &str, sources: Vec<String>) -> DatasetProvenance {
+    DatasetProvenance{
+      tool:         tool.to_string(),
+      sources:      sources,
+      created_unix_s:   get_time().sec,
+    }
+  }
+}
+
+/// Describes a split of an episode dataset; it is stored next to the episode
+/// databases as `{prefix}_header_{split}.toml`.
+#[derive(Clone, PartialEq, RustcDecodable, RustcEncodable, Debug)]
+pub struct DatasetHeader {
+  pub format_version:   u32,
+  pub feature_set:      String,
+  pub feature_version:  u32,
+  pub board_dim:        usize,
+  /// Bit planes come first in a frame, followed by byte planes.
+  pub bit_planes:       usize,
+  pub byte_planes:      usize,
+  pub frame_size:       usize,
+  pub labels:           Vec<String>,
+  pub provenance:       DatasetProvenance,
+}
+
+impl DatasetHeader {
+  pub fn new<F>(labels: &[DatasetLabel], provenance: DatasetProvenance) -> DatasetHeader
+  where F: TxnStateSerialFeatsData {
+    DatasetHeader::with_feature_set(F::feature_set(), labels, provenance)
+  }
+
+  pub fn with_feature_set(feature_set: &FeatureSet, labels: &[DatasetLabel], provenance: DatasetProvenance) -> DatasetHeader {
+    DatasetHeader{
+      format_version:   DATASET_FORMAT_VERSION,
+      feature_set:      feature_set.name.to_string(),
+      feature_version:  feature_set.version,
+      board_dim:        Board::DIM,
+      bit_planes:       feature_set.num_bit_planes(),
+      byte_planes:      feature_set.num_byte_planes(),
+      frame_size:       feature_set.serial_frame_size(),
+      labels:           labels.iter().map(|label| label.to_str().to_string()).collect(),
+      provenance:       provenance,
+    }
+  }
+
+  pub fn split_path(prefix: &Path, split: &str) -> PathBuf {
+    PathBuf::from(&format!("{}_header_{}.toml", prefix.to_str().unwrap(), split))
+  }
+
+  pub fn open(path: &Path) -> Result<DatasetHeader, ()> {
+    let mut file = match File::open(path) {
+      Ok(file) => file,
+      Err(_) => return Err(()),
+    };
+    let mut buf = String::new();
+    file.read_to_string(&mut buf).unwrap();
+    let header: DatasetHeader = match toml::decode_str(&buf) {
+      Some(x) => x,
+      None => return Err(()),
+    };
+    Ok(header)
+  }
+
+  pub fn open_split(prefix: &Path, split: &str) -> Result<DatasetHeader, ()> {
+    DatasetHeader::open(&DatasetHeader::split_path(prefix, split))
+  }
+
+  pub fn save(&self, path: &Path) {
+    let mut file = File::create(path).unwrap();
+    file.write_all(toml::encode_str(self).as_bytes()).unwrap();
+  }
+
+  /// Looks up the registered feature set, if its version still matches.
+  pub fn feature_set(&self) -> Option<&'static FeatureSet> {
+    match lookup_feature_set(&self.feature_set) {
+      Some(feature_set) => if feature_set.version == self.feature_version {
+        Some(feature_set)
+      } else {
+        None
+      },
+      None => None,
+    }
+  }
+
+  pub fn num_planes(&self) -> usize {
+    self.bit_planes + self.byte_planes
+  }
+
+  pub fn input_dims(&self) -> (usize, usize, usize) {
+    (self.board_dim, self.board_dim, self.num_planes())
+  }
+
+  pub fn has_label(&self, label: DatasetLabel) -> bool {
+    self.labels.iter().any(|s| DatasetLabel::parse_str(s) == Some(label))
+  }
+
+  pub fn check_format(&self) -> Result<(), String> {
+    if self.format_version != DATASET_FORMAT_VERSION {
+      return Err(format!("dataset format version {} is not supported (expected {})",
+          self.format_version, DATASET_FORMAT_VERSION));
+    }
+    for s in self.labels.iter() {
+      if DatasetLabel::parse_str(s).is_none() {
+        return Err(format!("unknown dataset label type: \"{}\"", s));
+      }
+    }
+    Ok(())
+  }
+
+  /// Checks that a network with input `dims` reading frames as bit planes
+  /// (then `byte_planes` byte planes), trained on `label`, fits this dataset.
+  pub fn check_network(&self, dims: (usize, usize, usize), byte_planes: usize, label: DatasetLabel) -> Result<(), String> {
+    try!(self.check_format());
+    if dims != self.input_dims() {
+      return Err(format!("network input dims {:?} do not match dataset '{}' v{} dims {:?}",
+          dims, self.feature_set, self.feature_version, self.input_dims()));
+    }
+    if byte_planes != self.byte_planes {
+      return Err(format!("network reads {} byte planes but dataset '{}' has {}",
+          byte_planes, self.feature_set, self.byte_planes));
+    }
+    if !self.has_label(label) {
+      return Err(format!("dataset '{}' has no {} labels (has {:?})",
+          self.feature_set, label.to_str(), self.labels));
+    }
+    Ok(())
+  }
+
+  pub fn check_features<F>(&self) -> Result<(), String>
+  where F: TxnStateSerialFeatsData {
+    self.check_feature_set(F::feature_set())
+  }
+
+  pub fn check_feature_set(&self, feature_set: &FeatureSet) -> Result<(), String> {
+    try!(self.check_format());
+    if self.feature_set != feature_set.name || self.feature_version != feature_set.version {
+      return Err(format!("dataset features '{}' v{} do not match '{}' v{}",
+          self.feature_set, self.feature_version,
+          feature_set.name, feature_set.version));
+    }
+    if self.frame_size != feature_set.serial_frame_size() {
+      return Err(format!("dataset frame size {} does not match '{}' frame size {}",
+          self.frame_size, feature_set.name, feature_set.serial_frame_size()));
+    }
+    Ok(())
+  }
+}
@@ -394,7 +394,7 @@ impl<C: GtpClient> GtpEngine<C> {
       b"kgs-game_over"          => self.client.reply_kgs_game_over(args),
       b"kgs-rules"              => self.client.reply_kgs_rules(args),
       b"kgs-time_settings"      => self.client.reply_kgs_time_settings(args),
-      b"kgs-genmove_cleanup"    => self.client.reply_kgs_genmove_cleanup(),
+      b"kgs-genmove_cleanup"    => self.client.reply_kgs_genmove_cleanup(args),
       b"loadsgf"                => self.client.reply_loadsgf(),
       b"reg_genmove"            => self.client.reply_reg_genmove(),
       b"showboard"              => self.client.reply_showboard(),
@@ -463,7 +463,7 @@ pub trait GtpClient {
   fn reply_kgs_game_over(&mut self, _args: &[Vec<u8>]) -> Vec<Entity>     { unimplemented!(); }
   fn reply_kgs_rules(&mut self, _args: &[Vec<u8>]) -> Vec<Entity>         { unimplemented!(); }
   fn reply_kgs_time_settings(&mut self, _args: &[Vec<u8>]) -> Vec<Entity> { unimplemented!(); }
-  fn reply_kgs_genmove_cleanup(&mut self) -> Vec<Entity>                  { unimplemented!(); }
+  fn reply_kgs_genmove_cleanup(&mut self, _args: &[Vec<u8>]) -> Vec<Entity> { unimplemented!(); }
 
   // Regression commands.
   fn reply_loadsgf(&mut self) -> Vec<Entity>;
@@ -141,19 +141,34 @@ impl Coord {
 
   pub fn rotate(self, rot: u8) -> Coord {
     let (u, v) = (self.x as i8 - Board::HALF as i8, self.y as i8 - Board::HALF as i8);
-    let (nu, nv) = match rot {
-      0 => ( u,  v),
-      1 => (-v,  u), // Rot L
-      2 => ( v, -u), // Rot R
-      3 => (-u,  v), // Flip H
-      4 => ( u, -v), // Flip V
-      5 => ( v,  u), // Rot L + Flip H
-      6 => (-u, -v), // Rot R + Flip H
-      7 => (-v, -u), // Rot 180
-      _ => unreachable!(),
-    };
+    let (nu, nv) = rotate_offset(rot, u, v);
     Coord{x: (nu + Board::HALF as i8) as u8, y: (nv + Board::HALF as i8) as u8}
   }
+
+  /// Inverse of `rotate`; only the two quarter turns are not involutions.
+  pub fn unrotate(self, rot: u8) -> Coord {
+    match rot {
+      1 => self.rotate(2),
+      2 => self.rotate(1),
+      rot => self.rotate(rot),
+    }
+  }
+}
+
+/// Applies one of the 8 board symmetries to an offset from the center (or
+/// from any other point).
+pub fn rotate_offset(rot: u8, u: i8, v: i8) -> (i8, i8) {
+  match rot {
+    0 => ( u,  v),
+    1 => (-v,  u), // Rot L
+    2 => ( v, -u), // Rot R
+    3 => (-u,  v), // Flip H
+    4 => ( u, -v), // Flip V
+    5 => ( v,  u), // Rot L + Flip H
+    6 => (-u, -v), // Rot R + Flip H
+    7 => (-v, -u), // Rot 180
+    _ => unreachable!(),
+  }
 }
 
 #[derive(Clone, Copy, Eq, PartialEq)]
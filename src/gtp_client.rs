@@ -31,6 +31,42 @@ impl<A> Client<A> where A: Agent {
       agent: agent,
     }
   }
+
+  fn place_handicap(&mut self, points: &[Point]) -> Vec<Entity> {
+    if self.agent.place_handicap(points).is_err() {
+      return vec![ErrorEntity(b"board not empty".to_vec())];
+    }
+    points.iter().map(|&pt| VertexEntity(Vertex::Play(pt.to_coord()))).collect()
+  }
+
+  fn genmove(&mut self, args: &[Vec<u8>], cleanup: bool) -> Vec<Entity> {
+    let player = match Entity::parse_color(&args[0]) {
+      ColorEntity(player) => player,
+      _ => return vec![ErrorEntity(b"syntax error".to_vec())],
+    };
+    /*if !self.agent.is_valid() {
+      self.agent_builder.own_color(Stone::from_player(player));
+      self.agent_builder.remaining_defaults();
+      self.agent = self.agent_builder.build();
+    }
+    let vertex = self.agent.gen_move_external(player);*/
+    let turn = match player {
+      Player::Black => Stone::Black,
+      Player::White => Stone::White,
+    };
+    let action = if cleanup {
+      self.agent.act_cleanup(turn)
+    } else {
+      self.agent.act(turn)
+    };
+    self.agent.apply_action(turn, action);
+    let vertex = match action {
+      Action::Resign        => Vertex::Resign,
+      Action::Pass          => Vertex::Pass,
+      Action::Place{point}  => Vertex::Play(point.to_coord()),
+    };
+    vec![VertexEntity(vertex)]
+  }
 }
 
 impl<A> GtpClient for Client<A> where A: Agent {
@@ -68,6 +104,9 @@ impl<A> GtpClient for Client<A> where A: Agent {
       b"boardsize"              => true,
       b"clear_board"            => true,
       b"komi"                   => true,
+      b"fixed_handicap"         => true,
+      b"place_free_handicap"    => true,
+      b"set_free_handicap"      => true,
       b"play"                   => true,
       b"genmove"                => true,
       b"undo"                   => true,
@@ -98,6 +137,9 @@ impl<A> GtpClient for Client<A> where A: Agent {
       StringEntity(b"boardsize".to_vec()),
       StringEntity(b"clear_board".to_vec()),
       StringEntity(b"komi".to_vec()),
+      StringEntity(b"fixed_handicap".to_vec()),
+      StringEntity(b"place_free_handicap".to_vec()),
+      StringEntity(b"set_free_handicap".to_vec()),
       StringEntity(b"play".to_vec()),
       StringEntity(b"genmove".to_vec()),
       StringEntity(b"undo".to_vec()),
@@ -166,27 +208,8 @@ impl<A> GtpClient for Client<A> where A: Agent {
     if !(num_stones >= 2 && num_stones <= 9) {
       return vec![ErrorEntity(b"invalid number of stones".to_vec())];
     }
-    // XXX: The black player places the handicap stones.
-    /*if !self.agent.is_valid() {
-      self.agent_builder.own_color(Stone::Black);
-      self.agent_builder.remaining_defaults();
-      self.agent = self.agent_builder.build();
-    }*/
-    // TODO(20151108)
-    unimplemented!();
-    // TODO(20151008): check for empty board.
-    /*if self.agent.is_valid() {
-      return vec![ErrorEntity(b"board not empty".to_vec())];
-    }*/
-    let mut vertexes = vec![];
-    for &pos in self.pre_game.fixed_handicap_positions(num_stones).iter() {
-      // TODO(20151108)
-      /*let coord = pos.to_coord();
-      self.agent_builder.place_handicap(pos);
-      vertexes.push(VertexEntity(Vertex::Play(coord)));*/
-      unimplemented!();
-    }
-    vertexes
+    let points = self.pre_game.fixed_handicap_positions(num_stones);
+    self.place_handicap(&points)
   }
 
   fn reply_place_free_handicap(&mut self, args: &[Vec<u8>]) -> Vec<Entity> {
@@ -198,52 +221,31 @@ impl<A> GtpClient for Client<A> where A: Agent {
       return vec![ErrorEntity(b"invalid number of stones".to_vec())];
     }
     // XXX: The black player places the handicap stones.
-    // TODO(20151108)
-    /*if !self.agent.is_valid() {
-      self.agent_builder.own_color(Stone::Black);
-      self.agent_builder.remaining_defaults();
-      self.agent = self.agent_builder.build();
-    }*/
-    unimplemented!();
-    // TODO(20151008): check for empty board.
-    /*if self.agent.is_valid() {
-      return vec![ErrorEntity(b"board not empty".to_vec())];
-    }*/
-    let mut vertexes = vec![];
-    for &pos in self.pre_game.prefer_handicap_positions(num_stones).iter() {
-      // TODO(20151108)
-      /*let coord = pos.to_coord();
-      self.agent_builder.place_handicap(pos);
-      vertexes.push(VertexEntity(Vertex::Play(coord)));*/
-      unimplemented!();
-    }
-    vertexes
+    let points = self.agent.choose_free_handicap(num_stones);
+    self.place_handicap(&points)
   }
 
   fn reply_set_free_handicap(&mut self, args: &[Vec<u8>]) -> Vec<Entity> {
     // XXX: The black player places the handicap stones.
-    // TODO(20151108)
-    /*if !self.agent.is_valid() {
-      self.agent_builder.own_color(Stone::White);
-      self.agent_builder.remaining_defaults();
-      self.agent = self.agent_builder.build();
-    }*/
-    unimplemented!();
-    // TODO(20151008): check for empty board.
-    /*if self.agent.is_valid() {
-      return vec![ErrorEntity(b"board not empty".to_vec())];
-    }*/
+    let mut points = vec![];
     for arg in args {
       let coord = match Entity::parse_vertex(arg) {
         VertexEntity(Vertex::Play(coord)) => coord,
         _ => return vec![ErrorEntity(b"bad vertex list".to_vec())],
       };
-      // TODO(20151108)
-      /*let pos = Pos::from_coord(coord);
-      self.agent_builder.place_handicap(pos);*/
-      unimplemented!();
+      let point = Point::from_coord(coord);
+      if points.contains(&point) {
+        return vec![ErrorEntity(b"bad vertex list".to_vec())];
+      }
+      points.push(point);
+    }
+    if points.len() < 2 {
+      return vec![ErrorEntity(b"bad vertex list".to_vec())];
+    }
+    match self.agent.place_handicap(&points) {
+      Ok(_) => vec![],
+      Err(_) => vec![ErrorEntity(b"board not empty".to_vec())],
     }
-    vec![]
   }
 
   // Core play commands.
@@ -279,40 +281,14 @@ impl<A> GtpClient for Client<A> where A: Agent {
   }
 
   fn reply_genmove(&mut self, args: &[Vec<u8>]) -> Vec<Entity> {
-    let player = match Entity::parse_color(&args[0]) {
-      ColorEntity(player) => player,
-      _ => return vec![ErrorEntity(b"syntax error".to_vec())],
-    };
-    /*if !self.agent.is_valid() {
-      self.agent_builder.own_color(Stone::from_player(player));
-      self.agent_builder.remaining_defaults();
-      self.agent = self.agent_builder.build();
-    }
-    let vertex = self.agent.gen_move_external(player);*/
-    let turn = match player {
-      Player::Black => Stone::Black,
-      Player::White => Stone::White,
-    };
-    let action = self.agent.act(turn);
-    self.agent.apply_action(turn, action);
-    let vertex = match action {
-      Action::Resign        => Vertex::Resign,
-      Action::Pass          => Vertex::Pass,
-      Action::Place{point}  => Vertex::Play(point.to_coord()),
-    };
-    vec![VertexEntity(vertex)]
+    self.genmove(args, false)
   }
 
   fn reply_undo(&mut self) -> Vec<Entity> {
-    /*if !self.agent.is_valid() {
-      return vec![ErrorEntity(b"cannot undo".to_vec())];
-    }
     match self.agent.undo() {
       UndoResult::Okay        => vec![],
       UndoResult::CannotUndo  => vec![ErrorEntity(b"cannot undo".to_vec())],
-    }*/
-    self.agent.undo();
-    vec![]
+    }
   }
 
   // Tournament commands.
@@ -457,8 +433,8 @@ impl<A> GtpClient for Client<A> where A: Agent {
     vec![]
   }
 
-  fn reply_kgs_genmove_cleanup(&mut self) -> Vec<Entity> {
-    vec![]
+  fn reply_kgs_genmove_cleanup(&mut self, args: &[Vec<u8>]) -> Vec<Entity> {
+    self.genmove(args, true)
   }
 
   // Regression commands.
@@ -1,96 +1,31 @@
-use toml;
-
-use rustc_serialize::json;
-use std::collections::{BTreeMap};
-use std::env;
-use std::fmt::{Debug};
-use std::fs::{File};
-use std::io::{Read};
-use std::path::{PathBuf};
-
-//thread_local!(static HYPERPARAM_MAP: BTreeMap<String, json::Json> = {
-thread_local!(static HYPERPARAM_MAP: BTreeMap<String, toml::Value> = {
-  let path = match env::var("HYPERPARAM_PATH") {
-    Ok(path)  => PathBuf::from(&path),
-    Err(e)    => panic!("no env variable for hyperparam instance path: {:?}", e),
-  };
-  let mut file = match File::open(&path) {
-    Ok(file)  => file,
-    Err(e)    => panic!("failed to open hyperparam instance file: {:?}", e),
-  };
-  /*let instance_map: BTreeMap<String, json::Json> = match json::Json::from_reader(&mut file) {
-    Ok(json::Json::Object(obj)) => obj,
-    Ok(js)  => panic!("unexpected json variant: {:?}", js),
-    Err(e)  => panic!("failed to read json object: {:?}", e),
-  };
-  instance_map*/
-  let mut s = String::new();
-  file.read_to_string(&mut s).unwrap();
-  let mut parser = toml::Parser::new(&s);
-  parser.parse().unwrap()
-});
-
-thread_local!(pub static UCB_C:       f32   = load_hyperparam("ucb_c"));
-thread_local!(pub static PBIAS_C:     f32   = load_hyperparam("pbias_c"));
-thread_local!(pub static PBIAS_EQUIV: f32   = load_hyperparam("pbias_equiv"));
-
-thread_local!(pub static PRIOR_EQUIV: f32   = load_hyperparam("prior_equiv"));
-thread_local!(pub static PWIDE:       bool  = load_hyperparam("pwide"));
-thread_local!(pub static PWIDE_MU:    f32   = load_hyperparam("pwide_mu"));
-thread_local!(pub static RAVE:        bool  = load_hyperparam("rave"));
-thread_local!(pub static RAVE_EQUIV:  f32   = load_hyperparam("rave_equiv"));
-
-pub trait Hyperparam {
-  fn from_json(js: &json::Json) -> Option<Self> where Self: Sized;
-  fn from_toml(tm: &toml::Value) -> Option<Self> where Self: Sized;
-}
-
-impl Hyperparam for bool {
-  fn from_json(js: &json::Json) -> Option<bool> {
-    match js {
-      &json::Json::Boolean(x) => Some(x),
-      _ => None,
-    }
-  }
-
-  fn from_toml(tm: &toml::Value) -> Option<bool> {
-    match tm {
-      &toml::Value::Boolean(x) => Some(x),
-      _ => None,
-    }
-  }
-}
-
-impl Hyperparam for f32 {
-  fn from_json(js: &json::Json) -> Option<f32> {
-    match js {
-      &json::Json::F64(x) => Some(x as f32),
-      _ => None,
-    }
-  }
-
-  fn from_toml(tm: &toml::Value) -> Option<f32> {
-    match tm {
-      &toml::Value::Float(x) => Some(x as f32),
-      _ => None,
-    }
-  }
+/// Tree policy hyperparameters. These used to be read from the file at
+/// `HYPERPARAM_PATH`; they are now built from the engine config (see
+/// `config::EngineConfig::hyperparam_cfg`).
+#[derive(Clone, Copy, RustcDecodable, RustcEncodable, Debug)]
+pub struct HyperparamConfig {
+  pub ucb_c:        f32,
+  pub pbias_c:      f32,
+  pub pbias_equiv:  f32,
+  pub prior:        bool,
+  pub prior_equiv:  f32,
+  pub pwide:        bool,
+  pub pwide_mu:     f32,
+  pub rave:         bool,
+  pub rave_equiv:   f32,
 }
 
-pub fn load_hyperparam<T>(key: &str) -> T where T: Hyperparam + Debug {
-  let res = HYPERPARAM_MAP.with(|instance| {
-    let value = match instance.get(key) {
-      Some(value) => value,
-      None => panic!("failed to find hyperparam: '{}'", key)
-    };
-    //Hyperparam::from_json(value)
-    Hyperparam::from_toml(value)
-  });
-  match res {
-    Some(x) => {
-      println!("DEBUG: hyperparam: key: '{}' value: {:?}", key, x);
-      x
+impl Default for HyperparamConfig {
+  fn default() -> HyperparamConfig {
+    HyperparamConfig{
+      ucb_c:        0.9,
+      pbias_c:      0.8,
+      pbias_equiv:  600.0,
+      prior:        true,
+      prior_equiv:  100.0,
+      pwide:        true,
+      pwide_mu:     1.8,
+      rave:         true,
+      rave_equiv:   3000.0,
     }
-    None => panic!("failed to load parameter: '{}'", key),
   }
 }
@@ -33,12 +33,15 @@ extern crate vec_map;
 pub mod agents;
 pub mod array_util;
 pub mod board;
-//pub mod book;
+pub mod book;
 pub mod client;
+pub mod config;
 pub mod contains;
+pub mod corpus;
 //pub mod convnet;
 pub mod convnet_new;
 pub mod data;
+pub mod dataset;
 pub mod discrete;
 //pub mod fastboard;
 pub mod fix;
@@ -48,12 +51,15 @@ pub mod gtp_board;
 pub mod gtp_client;
 pub mod gtp_ctrl;
 pub mod hyper;
+pub mod mm;
 pub mod pattern;
 pub mod pg;
 pub mod policygrad;
 pub mod random;
 pub mod search;
+pub mod selfplay;
 pub mod sgf;
+pub mod shapes;
 pub mod stats;
 //pub mod table;
 pub mod txnstate;
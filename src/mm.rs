@@ -0,0 +1,277 @@
+//! Minorization-maximization (Coulom, 2007) fitting of generalized
+//! Bradley-Terry gammas for the move features of `pattern`. Every position of
+//! a game is a competition between the legal moves, each a team of its
+//! features, which the played move wins.
+
+use board::{Board, Stone, Point, Action};
+use gtp_board::{Coord};
+use pattern::{
+  MoveFeature, MoveFeatureGroup, MOVE_FEATURE_GROUPS,
+  PatternGammaDatabase, extract_move_features,
+};
+use sgf::{Sgf, SgfRankStyle};
+use txnstate::{TxnStateConfig, TxnState, check_legal_move_simple};
+
+use std::collections::{HashMap, HashSet};
+
+/// The legal moves of a position, grouped by their feature combinations.
+#[derive(Clone, Debug)]
+struct MmPosition {
+  /// Combination ids and the number of legal moves with each combination.
+  candidates:   Vec<(u32, u32)>,
+  /// The combination id of the played move.
+  winner:       u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MmEval {
+  pub num_positions:  usize,
+  /// The expected fraction of positions in which the played move has the
+  /// greatest gamma, breaking ties uniformly.
+  pub accuracy:       f64,
+  /// The mean log-likelihood of the played moves.
+  pub log_likelihood: f64,
+}
+
+pub struct MmTrainer {
+  combos:       Vec<Vec<MoveFeature>>,
+  combo_ids:    HashMap<Vec<MoveFeature>, u32>,
+  train:        Vec<MmPosition>,
+  valid:        Vec<MmPosition>,
+  /// The number of training positions won by each feature.
+  wins:         HashMap<MoveFeature, u32>,
+  train_feats:  HashSet<MoveFeature>,
+  gammas:       HashMap<MoveFeature, f64>,
+}
+
+impl MmTrainer {
+  pub fn new() -> MmTrainer {
+    MmTrainer{
+      combos:       vec![],
+      combo_ids:    HashMap::new(),
+      train:        vec![],
+      valid:        vec![],
+      wins:         HashMap::new(),
+      train_feats:  HashSet::new(),
+      gammas:       HashMap::new(),
+    }
+  }
+
+  pub fn num_train_positions(&self) -> usize {
+    self.train.len()
+  }
+
+  pub fn num_valid_positions(&self) -> usize {
+    self.valid.len()
+  }
+
+  pub fn num_features(&self) -> usize {
+    self.gammas.len()
+  }
+
+  fn intern_combo(&mut self, features: &[MoveFeature]) -> u32 {
+    let mut combo = features.to_vec();
+    combo.sort();
+    if let Some(&id) = self.combo_ids.get(&combo) {
+      return id;
+    }
+    for &feature in combo.iter() {
+      self.gammas.entry(feature).or_insert(1.0);
+    }
+    let id = self.combos.len() as u32;
+    self.combos.push(combo.clone());
+    self.combo_ids.insert(combo, id);
+    id
+  }
+
+  /// Adds every placement of a game as a training or validation position. The
+  /// game is only added if the setup stones and all moves are legal.
+  pub fn add_game(&mut self, state_cfg: TxnStateConfig, setup: &[(Stone, Point)], moves: &[(Stone, Action)], valid: bool) -> Result<usize, ()> {
+    let mut state = TxnState::new(state_cfg, ());
+    state.reset();
+    try!(state.place_setup_stones(setup));
+    let mut features = vec![];
+    let mut positions = vec![];
+    for &(turn, action) in moves.iter() {
+      if let Action::Place{point: play_point} = action {
+        let mut counts: HashMap<u32, u32> = HashMap::new();
+        let mut winner = None;
+        for p in 0 .. Board::SIZE {
+          let point = Point::from_idx(p);
+          if state.current_stone(point) != Stone::Empty {
+            continue;
+          }
+          let legal = match check_legal_move_simple(&state.position, &state.chains, turn, point) {
+            Some(Ok(())) => true,
+            _ => {
+              let legal = state.try_place(turn, point).is_ok();
+              state.undo();
+              legal
+            }
+          };
+          if !legal {
+            continue;
+          }
+          extract_move_features(&state, turn, point, &mut features);
+          let id = self.intern_combo(&features);
+          *counts.entry(id).or_insert(0) += 1;
+          if point == play_point {
+            winner = Some(id);
+          }
+        }
+        if let Some(winner) = winner {
+          let mut candidates: Vec<_> = counts.into_iter().collect();
+          candidates.sort();
+          positions.push(MmPosition{candidates: candidates, winner: winner});
+        }
+      }
+      if state.try_action(turn, action).is_err() {
+        state.undo();
+        return Err(());
+      }
+      state.commit();
+    }
+    let num_positions = positions.len();
+    for position in positions.into_iter() {
+      if valid {
+        self.valid.push(position);
+      } else {
+        for &feature in self.combos[position.winner as usize].iter() {
+          *self.wins.entry(feature).or_insert(0) += 1;
+        }
+        for &(id, _) in position.candidates.iter() {
+          for &feature in self.combos[id as usize].iter() {
+            self.train_feats.insert(feature);
+          }
+        }
+        self.train.push(position);
+      }
+    }
+    Ok(num_positions)
+  }
+
+  pub fn add_sgf(&mut self, sgf: &Sgf, rank_style: SgfRankStyle, valid: bool) -> Result<usize, ()> {
+    if sgf.board_dim != Board::DIM as i64 {
+      return Err(());
+    }
+    let mut moves = Vec::with_capacity(sgf.moves.len());
+    for &(ref player, ref mov) in sgf.moves.iter() {
+      let action = match mov as &str {
+        "Pass"    => Action::Pass,
+        "Resign"  => Action::Resign,
+        x         => match Coord::parse_code_str(x) {
+          Some(coord) => Action::Place{point: Point::from_coord(coord)},
+          None => return Err(()),
+        },
+      };
+      moves.push((Stone::from_code_str(player), action));
+    }
+    self.add_game(sgf.infer_state_cfg(rank_style), &sgf.setup_stones(), &moves, valid)
+  }
+
+  fn combo_gammas(&self) -> Vec<f64> {
+    self.combos.iter()
+      .map(|combo| combo.iter().fold(1.0, |acc, feature| acc * self.gammas[feature]))
+      .collect()
+  }
+
+  /// Runs one MM iteration, updating the features of each group in turn. Each
+  /// feature has a prior of one virtual win and one virtual loss against a
+  /// virtual opponent of gamma 1, which keeps the gammas of rare features
+  /// finite and nonzero.
+  pub fn iterate(&mut self) {
+    for &group in MOVE_FEATURE_GROUPS.iter() {
+      let combo_gammas = self.combo_gammas();
+      let mut denoms: HashMap<MoveFeature, f64> = HashMap::new();
+      for position in self.train.iter() {
+        let total_gamma = position.candidates.iter()
+          .fold(0.0, |acc, &(id, count)| acc + count as f64 * combo_gammas[id as usize]);
+        for &(id, count) in position.candidates.iter() {
+          for &feature in self.combos[id as usize].iter() {
+            if feature.group() == Some(group) {
+              // The gamma of the feature's teammates.
+              let others_gamma = combo_gammas[id as usize] / self.gammas[&feature];
+              *denoms.entry(feature).or_insert(0.0) += count as f64 * others_gamma / total_gamma;
+            }
+          }
+        }
+      }
+      for (feature, gamma) in self.gammas.iter_mut() {
+        if feature.group() != Some(group) {
+          continue;
+        }
+        let wins = match self.wins.get(feature) {
+          Some(&wins) => wins,
+          None => 0,
+        };
+        let denom = match denoms.get(feature) {
+          Some(&denom) => denom,
+          None => 0.0,
+        };
+        *gamma = (wins as f64 + 1.0) / (denom + 2.0 / (*gamma + 1.0));
+      }
+    }
+  }
+
+  fn evaluate(&self, positions: &[MmPosition]) -> MmEval {
+    let combo_gammas = self.combo_gammas();
+    let mut accuracy = 0.0;
+    let mut log_likelihood = 0.0;
+    for position in positions.iter() {
+      let mut total_gamma = 0.0;
+      let mut max_gamma = 0.0;
+      let mut max_count = 0;
+      for &(id, count) in position.candidates.iter() {
+        let gamma = combo_gammas[id as usize];
+        total_gamma += count as f64 * gamma;
+        if gamma > max_gamma {
+          max_gamma = gamma;
+          max_count = count;
+        } else if gamma == max_gamma {
+          max_count += count;
+        }
+      }
+      let winner_gamma = combo_gammas[position.winner as usize];
+      if winner_gamma == max_gamma {
+        accuracy += 1.0 / max_count as f64;
+      }
+      log_likelihood += (winner_gamma / total_gamma).ln();
+    }
+    let n = positions.len();
+    MmEval{
+      num_positions:  n,
+      accuracy:       if n > 0 { accuracy / n as f64 } else { 0.0 },
+      log_likelihood: if n > 0 { log_likelihood / n as f64 } else { 0.0 },
+    }
+  }
+
+  pub fn evaluate_train(&self) -> MmEval {
+    self.evaluate(&self.train)
+  }
+
+  pub fn evaluate_valid(&self) -> MmEval {
+    self.evaluate(&self.valid)
+  }
+
+  /// The fitted gammas; features seen only in validation games are left out
+  /// and so have gamma 1.
+  pub fn to_database(&self) -> PatternGammaDatabase {
+    let mut db = PatternGammaDatabase::new();
+    for (&feature, &gamma) in self.gammas.iter() {
+      if self.train_feats.contains(&feature) {
+        db.gammas.insert(feature, gamma as f32);
+      }
+    }
+    db
+  }
+
+  /// The gammas of one feature group, in feature order.
+  pub fn group_gammas(&self, group: MoveFeatureGroup) -> Vec<(MoveFeature, f64)> {
+    let mut gammas: Vec<_> = self.gammas.iter()
+      .filter(|&(feature, _)| feature.group() == Some(group))
+      .map(|(&feature, &gamma)| (feature, gamma))
+      .collect();
+    gammas.sort_by(|a, b| a.0.cmp(&b.0));
+    gammas
+  }
+}
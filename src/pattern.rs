@@ -1,14 +1,17 @@
 use board::{Board, Stone, Point};
-use txnstate::{TxnPosition, TxnChainsList, for_each_x8};
+use txnstate::{TxnStateData, TxnState, TxnPosition, TxnChainsList, for_each_adjacent, for_each_x8};
 
-use byteorder::{ReadBytesExt, LittleEndian};
+use byteorder::{ReadBytesExt, WriteBytesExt, LittleEndian};
 
-use std::cmp::{min};
+use std::cmp::{max, min};
 use std::collections::{HashMap, HashSet};
 use std::fs::{File};
-use std::io::{Read, BufRead, BufReader};
+use std::io::{Read, Write, BufRead, BufReader, BufWriter};
 use std::path::{Path};
 
+pub const GAMMA_DB_MAGIC: &'static [u8; 4] = b"HPG\0";
+pub const GAMMA_DB_FORMAT_VERSION: u32 = 1;
+
 /*static MASK_8_UV_COORDS: [(i8, i8); 8] = [
   (-1, -1), (0, -1), (1, -1),
   (-1, 0), (1, 0),
@@ -30,6 +33,16 @@ static MASK_8_TRANS_MAPS: [[u8; 8]; 8] = [
 pub struct Pattern3x3(pub u16);
 
 impl Pattern3x3 {
+  /// Exchanges black and white stones, e.g. to make the pattern relative to
+  /// the player to move.
+  pub fn swap_colors(self) -> Pattern3x3 {
+    // Black (0x1) and white (0x2) are the fields with exactly one bit set.
+    let lo = self.0 & 0x5555;
+    let hi = (self.0 >> 1) & 0x5555;
+    let one_bit = lo ^ hi;
+    Pattern3x3(self.0 ^ (one_bit | (one_bit << 1)))
+  }
+
   pub fn to_invariant(self) -> InvariantPattern3x3 {
     let mut min_mask8: u16 = 0xffffffff;
     for t in 0 .. 8 {
@@ -45,7 +58,7 @@ impl Pattern3x3 {
   }
 }
 
-#[derive(Clone, Copy, Eq, PartialEq, RustcDecodable, RustcEncodable)]
+#[derive(Clone, Copy, Eq, PartialEq, Hash, RustcDecodable, RustcEncodable, Debug)]
 pub struct InvariantPattern3x3(u16);
 
 impl InvariantPattern3x3 {
@@ -142,39 +155,232 @@ impl PatternDatabase {
   }
 }
 
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum MoveFeatureGroup {
+  /// The invariant 3x3 pattern around the move, relative to the player.
+  Pattern,
+  /// The move captures an opponent chain.
+  Capture,
+  /// The move puts an opponent chain in atari.
+  Atari,
+  /// The move puts its own chain in atari.
+  SelfAtari,
+  /// The distance `dx + dy + max(dx, dy)` to the previous move, from 2 to 16.
+  PrevDistance,
+}
+
+pub static MOVE_FEATURE_GROUPS: [MoveFeatureGroup; 5] = [
+  MoveFeatureGroup::Pattern,
+  MoveFeatureGroup::Capture,
+  MoveFeatureGroup::Atari,
+  MoveFeatureGroup::SelfAtari,
+  MoveFeatureGroup::PrevDistance,
+];
+
+pub const MAX_PREV_DISTANCE: u32 = 16;
+
+impl MoveFeatureGroup {
+  pub fn idx(self) -> u32 {
+    match self {
+      MoveFeatureGroup::Pattern       => 0,
+      MoveFeatureGroup::Capture       => 1,
+      MoveFeatureGroup::Atari         => 2,
+      MoveFeatureGroup::SelfAtari     => 3,
+      MoveFeatureGroup::PrevDistance  => 4,
+    }
+  }
+
+  pub fn to_str(self) -> &'static str {
+    match self {
+      MoveFeatureGroup::Pattern       => "pattern",
+      MoveFeatureGroup::Capture       => "capture",
+      MoveFeatureGroup::Atari         => "atari",
+      MoveFeatureGroup::SelfAtari     => "self_atari",
+      MoveFeatureGroup::PrevDistance  => "prev_distance",
+    }
+  }
+}
+
+/// A generalized Bradley-Terry feature of a candidate move: the group index in
+/// the high byte and the level within the group in the low 24 bits. A move has
+/// at most one feature of each group.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct MoveFeature(pub u32);
+
+impl MoveFeature {
+  pub fn new(group: MoveFeatureGroup, level: u32) -> MoveFeature {
+    assert!(level < 0x0100_0000);
+    MoveFeature((group.idx() << 24) | level)
+  }
+
+  pub fn group(self) -> Option<MoveFeatureGroup> {
+    MOVE_FEATURE_GROUPS.get((self.0 >> 24) as usize).map(|&group| group)
+  }
+
+  pub fn level(self) -> u32 {
+    self.0 & 0x00ff_ffff
+  }
+}
+
+/// Extracts the features of placing a stone of `turn` at the empty `point`,
+/// without trying the move.
+pub fn extract_move_features<Data>(state: &TxnState<Data>, turn: Stone, point: Point, features: &mut Vec<MoveFeature>)
+where Data: TxnStateData + Clone {
+  extract_local_move_features(state, turn, point, features);
+  if let Some((_, prev_point)) = state.last_play() {
+    let dist = prev_distance(point, prev_point);
+    if dist <= MAX_PREV_DISTANCE {
+      features.push(MoveFeature::new(MoveFeatureGroup::PrevDistance, dist));
+    }
+  }
+}
+
+/// The features of `extract_move_features` other than the distance to the
+/// previous move. They only depend on the stones and liberties around
+/// `point`, so they can be cached between moves elsewhere on the board.
+pub fn extract_local_move_features<Data>(state: &TxnState<Data>, turn: Stone, point: Point, features: &mut Vec<MoveFeature>)
+where Data: TxnStateData + Clone {
+  features.clear();
+
+  let mut pattern = state.current_pat3x3(point);
+  if turn == Stone::White {
+    pattern = pattern.swap_colors();
+  }
+  features.push(MoveFeature::new(MoveFeatureGroup::Pattern, pattern.to_invariant().idx()));
+
+  let opp_turn = turn.opponent();
+  let capture = state.is_capture(opp_turn, point);
+  let mut atari = false;
+  // XXX(20160421): Liberties shared between adjacent chains are counted more
+  // than once, so this undercounts self-ataris but never reports a false one.
+  let mut num_libs = 0;
+  let mut safe = capture;
+  for_each_adjacent(point, |adj_point| {
+    let adj_stone = state.current_stone(adj_point);
+    if adj_stone == Stone::Empty {
+      num_libs += 1;
+    } else if adj_stone == opp_turn {
+      if state.current_libs_up_to_3(adj_point) == 2 {
+        atari = true;
+      }
+    } else {
+      match state.current_libs_up_to_3(adj_point) {
+        3 => safe = true,
+        libs => num_libs += max(libs, 1) - 1,
+      }
+    }
+  });
+  if capture {
+    features.push(MoveFeature::new(MoveFeatureGroup::Capture, 0));
+  }
+  if atari {
+    features.push(MoveFeature::new(MoveFeatureGroup::Atari, 0));
+  }
+  if !safe && num_libs <= 1 {
+    features.push(MoveFeature::new(MoveFeatureGroup::SelfAtari, 0));
+  }
+}
+
+/// The level of the `PrevDistance` feature: `dx + dy + max(dx, dy)`.
+pub fn prev_distance(point: Point, prev_point: Point) -> u32 {
+  let (coord, prev_coord) = (point.to_coord(), prev_point.to_coord());
+  let dx = (coord.x as i32 - prev_coord.x as i32).abs() as u32;
+  let dy = (coord.y as i32 - prev_coord.y as i32).abs() as u32;
+  dx + dy + max(dx, dy)
+}
+
+/// Gammas of move features fitted by the MM trainer (see `mm`); the strength
+/// of a move is the product of the gammas of its features, and features
+/// missing from the database have gamma 1. On disk it is a little endian
+/// binary file: the magic, the format version, the number of features, then
+/// each feature and its gamma in feature order.
+#[derive(Clone, Default, Debug)]
 pub struct PatternGammaDatabase {
-  num_bare_feats:   usize,
-  num_pat_feats:    usize,
-  bare_gammas:  Vec<f32>,
-  pat_gammas:   HashMap<InvariantLibPattern3x3, f32>,
+  pub gammas:   HashMap<MoveFeature, f32>,
 }
 
 impl PatternGammaDatabase {
-  pub fn open(db_path: &Path) -> PatternGammaDatabase {
+  pub fn new() -> PatternGammaDatabase {
+    PatternGammaDatabase::default()
+  }
+
+  pub fn open(db_path: &Path) -> Result<PatternGammaDatabase, ()> {
     let file = match File::open(db_path) {
       Ok(file) => file,
-      Err(e) => panic!("failed to open pattern db: {:?}", e),
+      Err(_) => return Err(()),
     };
     let mut reader = BufReader::new(file);
-    let num_bare_feats = reader.read_u32::<LittleEndian>().unwrap() as usize;
-    let num_pat_feats = reader.read_u32::<LittleEndian>().unwrap() as usize;
-    let mut bare_gammas = Vec::with_capacity(num_bare_feats);
-    for _ in 0 .. num_bare_feats {
-      let gamma = reader.read_f32::<LittleEndian>().unwrap();
-      bare_gammas.push(gamma);
+    let mut magic = [0; 4];
+    if reader.read_exact(&mut magic).is_err() || &magic != GAMMA_DB_MAGIC {
+      println!("WARNING: pattern: not a gamma database: {:?}", db_path);
+      return Err(());
     }
-    let mut pat_gammas = HashMap::with_capacity(num_pat_feats);
-    for _ in 0 .. num_pat_feats {
-      let index = reader.read_u32::<LittleEndian>().unwrap();
-      let pat = InvariantLibPattern3x3{index: index};
-      let gamma = reader.read_f32::<LittleEndian>().unwrap();
-      pat_gammas.insert(pat, gamma);
+    let version = try!(reader.read_u32::<LittleEndian>().map_err(|_| ()));
+    if version != GAMMA_DB_FORMAT_VERSION {
+      println!("WARNING: pattern: gamma database version mismatch: {} (expected {})",
+          version, GAMMA_DB_FORMAT_VERSION);
+      return Err(());
     }
-    PatternGammaDatabase{
-      num_bare_feats:   num_bare_feats,
-      num_pat_feats:    num_pat_feats,
-      bare_gammas:  bare_gammas,
-      pat_gammas:   pat_gammas,
+    let num_feats = try!(reader.read_u32::<LittleEndian>().map_err(|_| ())) as usize;
+    let mut gammas = HashMap::with_capacity(num_feats);
+    for _ in 0 .. num_feats {
+      let feature = MoveFeature(try!(reader.read_u32::<LittleEndian>().map_err(|_| ())));
+      let gamma = try!(reader.read_f32::<LittleEndian>().map_err(|_| ()));
+      if feature.group().is_none() || !(gamma >= 0.0) {
+        return Err(());
+      }
+      gammas.insert(feature, gamma);
     }
+    Ok(PatternGammaDatabase{gammas: gammas})
+  }
+
+  pub fn save(&self, db_path: &Path) {
+    let file = match File::create(db_path) {
+      Ok(file) => file,
+      Err(e) => panic!("failed to create gamma database: {:?}", e),
+    };
+    let mut writer = BufWriter::new(file);
+    writer.write_all(GAMMA_DB_MAGIC).unwrap();
+    writer.write_u32::<LittleEndian>(GAMMA_DB_FORMAT_VERSION).unwrap();
+    writer.write_u32::<LittleEndian>(self.gammas.len() as u32).unwrap();
+    let mut features: Vec<_> = self.gammas.keys().map(|&f| f).collect();
+    features.sort();
+    for &feature in features.iter() {
+      writer.write_u32::<LittleEndian>(feature.0).unwrap();
+      writer.write_f32::<LittleEndian>(self.gammas[&feature]).unwrap();
+    }
+  }
+
+  pub fn feature_gamma(&self, feature: MoveFeature) -> f32 {
+    match self.gammas.get(&feature) {
+      Some(&gamma) => gamma,
+      None => 1.0,
+    }
+  }
+
+  pub fn features_gamma(&self, features: &[MoveFeature]) -> f32 {
+    features.iter().fold(1.0, |acc, &feature| acc * self.feature_gamma(feature))
+  }
+
+  /// The gamma of placing a stone of `turn` at the empty `point`; `features`
+  /// is scratch space.
+  pub fn move_gamma<Data>(&self, state: &TxnState<Data>, turn: Stone, point: Point, features: &mut Vec<MoveFeature>) -> f32
+  where Data: TxnStateData + Clone {
+    extract_move_features(state, turn, point, features);
+    self.features_gamma(features)
+  }
+
+  /// The gamma of the features of `extract_local_move_features`.
+  pub fn local_move_gamma<Data>(&self, state: &TxnState<Data>, turn: Stone, point: Point, features: &mut Vec<MoveFeature>) -> f32
+  where Data: TxnStateData + Clone {
+    extract_local_move_features(state, turn, point, features);
+    self.features_gamma(features)
+  }
+
+  /// The gammas of the `PrevDistance` levels, indexed by `prev_distance`.
+  pub fn prev_distance_gammas(&self) -> Vec<f32> {
+    (0 .. MAX_PREV_DISTANCE + 1).map(|dist| {
+      self.feature_gamma(MoveFeature::new(MoveFeatureGroup::PrevDistance, dist))
+    }).collect()
   }
 }
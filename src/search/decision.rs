@@ -0,0 +1,162 @@
+//! Resignation and pass decisions on top of the search: the search always
+//! returns its best placement, and `DecisionPolicy` decides whether to play it,
+//! pass, or resign, from the win rate and the ownership estimate.
+
+use board::{Board, Stone, Action};
+use search::parallel_tree::{MonteCarloSearchResult};
+use txnstate::{TxnStateData, TxnState};
+
+#[derive(Clone, Copy, Debug)]
+pub struct DecisionConfig {
+  /// Resign after `resign_moves` consecutive searches with a win rate below
+  /// `resign_value`; 0 moves never resigns.
+  pub resign_value:       f32,
+  pub resign_moves:       usize,
+  /// Never resign before this ply.
+  pub resign_min_ply:     usize,
+  /// Whether to resign in handicap games at all; the handicap is often
+  /// enough to make the win rate look hopeless early on.
+  pub resign_in_handicap: bool,
+  /// Only pass when at most this many points have an uncertain owner.
+  pub pass_max_unsettled: usize,
+}
+
+impl Default for DecisionConfig {
+  fn default() -> DecisionConfig {
+    DecisionConfig{
+      resign_value:       0.1,
+      resign_moves:       3,
+      resign_min_ply:     50,
+      resign_in_handicap: false,
+      pass_max_unsettled: 10,
+    }
+  }
+}
+
+/// Estimates the final score (positive if white wins) were the game to end in
+/// the searched position, under the rules and komi of `state`. Points owned in
+/// at least 90% of the rollouts count for their owner; under territory rules
+/// the dead stones also count as prisoners, and the handicap komi is added.
+pub fn estimate_final_score<Data>(state: &TxnState<Data>, result: &MonteCarloSearchResult) -> f32
+where Data: TxnStateData + Clone {
+  let rules = state.config.rules;
+  let mut scores = [0.0, 0.0];
+  for &stone in [Stone::Black, Stone::White].iter() {
+    let k = stone.offset();
+    let mut score = 0;
+    if rules.score_stones {
+      score += result.live_stones[k].len();
+    }
+    if rules.score_territory {
+      score += result.territory[k].len();
+    }
+    if rules.score_captures {
+      score += state.current_captures(stone) + result.dead_stones[stone.opponent().offset()].len();
+    }
+    scores[k] = score as f32;
+  }
+  scores[1] - scores[0] + state.config.komi + state.handicap_komi()
+}
+
+#[derive(Clone, Debug)]
+pub struct DecisionPolicy {
+  cfg:        DecisionConfig,
+  num_low:    usize,
+}
+
+impl DecisionPolicy {
+  pub fn new(cfg: DecisionConfig) -> DecisionPolicy {
+    DecisionPolicy{
+      cfg:        cfg,
+      num_low:    0,
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.num_low = 0;
+  }
+
+  /// The number of consecutive searches with a win rate below the threshold.
+  pub fn num_low(&self) -> usize {
+    self.num_low
+  }
+
+  /// Whether a search win rate of `value` in the current position of `state`
+  /// counts toward resigning. The value should be under the game komi.
+  pub fn is_low_value<Data>(&self, state: &TxnState<Data>, value: f32) -> bool
+  where Data: TxnStateData + Clone {
+    let may_resign =
+        self.cfg.resign_moves > 0
+        && state.current_ply() >= self.cfg.resign_min_ply
+        && (self.cfg.resign_in_handicap || state.num_handicap_stones() == 0);
+    may_resign && value < self.cfg.resign_value
+  }
+
+  /// Whether the position is settled and passing does not lose for `turn`
+  /// under the active rules. `state` should hold the game komi rather than a
+  /// dynamic komi.
+  pub fn should_pass<Data>(&self, state: &TxnState<Data>, turn: Stone, result: &MonteCarloSearchResult) -> bool
+  where Data: TxnStateData + Clone {
+    let num_settled = result.b_mc_alive + result.w_mc_alive;
+    if Board::SIZE.saturating_sub(num_settled) > self.cfg.pass_max_unsettled {
+      return false;
+    }
+    let score = estimate_final_score(state, result);
+    match turn {
+      Stone::Black => score < 0.0,
+      Stone::White => score > 0.0,
+      Stone::Empty => unreachable!(),
+    }
+  }
+
+  /// Decides on the action for `turn` after a search of the current position
+  /// of `state`. Must be called once per search for the resignation count to
+  /// be meaningful.
+  pub fn decide<Data>(&mut self, state: &TxnState<Data>, turn: Stone, result: &MonteCarloSearchResult) -> Action
+  where Data: TxnStateData + Clone {
+    if self.is_low_value(state, result.expected_value) {
+      self.num_low += 1;
+    } else {
+      self.num_low = 0;
+    }
+    if self.cfg.resign_moves > 0 && self.num_low >= self.cfg.resign_moves {
+      return Action::Resign;
+    }
+    match result.action {
+      Action::Place{..} => {}
+      action => return action,
+    }
+    if self.should_pass(state, turn, result) {
+      return Action::Pass;
+    }
+    result.action
+  }
+  /// Decides for agents without a search, e.g. the policy network agent, which
+  /// play their best legal placement `action`. Without a win rate or an
+  /// ownership estimate they never resign, and only pass after the opponent
+  /// passed when the Tromp-Taylor score of the position, with every stone
+  /// counted alive, does not lose for `turn`.
+  pub fn decide_unsearched<Data>(&mut self, state: &TxnState<Data>, turn: Stone, action: Action) -> Action
+  where Data: TxnStateData + Clone {
+    self.num_low = 0;
+    let opp_passed = match state.position.last_move {
+      Some((_, Action::Pass)) => true,
+      _ => false,
+    };
+    if !opp_passed {
+      return action;
+    }
+    let mut territory = vec![0; Board::SIZE];
+    let score = state.current_score_tromp_taylor_undead(state.config.komi, &mut territory);
+    let pass_wins = match turn {
+      Stone::Black => score < 0.0,
+      Stone::White => score > 0.0,
+      Stone::Empty => unreachable!(),
+    };
+    if pass_wins {
+      Action::Pass
+    } else {
+      action
+    }
+  }
+}
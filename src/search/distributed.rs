@@ -0,0 +1,570 @@
+use board::{Board, Stone, Point};
+use search::parallel_policies::{
+  SearchPolicyWorkerBuilder, SearchPolicyWorker,
+  PriorPolicy, RolloutLeafs,
+};
+use search::parallel_policies::uniform::{UniformPriorPolicy};
+use search::parallel_tree::{
+  TreeOps, TreeTraj, RolloutTraj,
+  SharedTree,
+  MonteCarloSearchResult, MonteCarloSearchStats,
+  search_result,
+};
+use txnstate::{TxnState};
+use txnstate::extras::{TxnStateNodeData};
+
+use bincode::{SizeLimit};
+use bincode::rustc_serialize::{encode_into, decode_from};
+use rand::{thread_rng};
+use rng::xorshift::{Xorshiftplus128Rng};
+use std::cmp::{max, min};
+use std::collections::{HashMap};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::iter::{repeat};
+use std::net::{TcpListener, TcpStream, SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration};
+use time::{get_time};
+
+// XXX(20160422): Protocol. Every message is bincode encoded, back to back on
+// one TCP stream per worker. A worker asks for a batch, the master replies
+// with a batch of leaf states (or tells it to quit), and the worker rolls
+// them out and sends back the scores, along with the prior values of the newly
+// expanded leafs if the master asked for them; then it asks again.
+
+#[derive(RustcDecodable, RustcEncodable, Debug)]
+pub enum WorkerMessage {
+  /// Asks for at most this many leafs.
+  RequestBatch{max_batch_size: usize},
+  Results{
+    batch_id:       u64,
+    rollouts:       Vec<RemoteRollout>,
+    /// Monte Carlo live stone counts summed over the rollouts.
+    mc_live_counts: Vec<Vec<usize>>,
+  },
+}
+
+#[derive(RustcDecodable, RustcEncodable)]
+pub enum MasterMessage {
+  Batch{
+    batch_id:     u64,
+    komi:         f32,
+    leaf_states:  Vec<TxnState<TxnStateNodeData>>,
+    /// Which leafs to evaluate the prior policy on.
+    eval_priors:  Vec<bool>,
+  },
+  Quit,
+}
+
+/// The outcome of one rollout, enough for the master to back it up.
+#[derive(Clone, RustcDecodable, RustcEncodable, Debug)]
+pub struct RemoteRollout {
+  pub score:        f32,
+  pub sim_pairs:    Vec<(Stone, Point)>,
+  /// The prior values of the legal moves of the leaf, if asked for.
+  pub prior_values: Vec<(Point, f32)>,
+}
+
+/// Fills in the prior values a remote worker evaluated.
+struct RemotePriorPolicy<'a> {
+  prior_values: &'a [(Point, f32)],
+}
+
+impl<'a> PriorPolicy for RemotePriorPolicy<'a> {
+  fn fill_prior_values(&mut self, _state: &TxnState<TxnStateNodeData>, valid_moves: &[Point], prior_values: &mut Vec<(Point, f32)>) {
+    prior_values.clear();
+    for &point in valid_moves.iter() {
+      let prior = self.prior_values.iter()
+        .find(|&&(pt, _)| pt == point)
+        .map_or(0.0, |&(_, p)| p);
+      prior_values.push((point, prior));
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DistributedSearchConfig {
+  pub num_rollouts:       usize,
+  /// Most leafs handed to a worker at once.
+  pub max_batch_size:     usize,
+  /// Gives up on the rollouts still in flight once no result has arrived for
+  /// this long.
+  pub result_timeout_ms:  usize,
+  /// Expands new leafs with uniform priors and has the workers evaluate their
+  /// own prior policies (e.g. a convnet) on them instead; the remote priors
+  /// replace the uniform ones unless an arm of the leaf was visited first.
+  /// The root is still expanded by the master.
+  pub remote_priors:      bool,
+}
+
+struct PendingBatch {
+  conn_id:    usize,
+  search_id:  u64,
+  komi:       f32,
+  tree_trajs: Vec<TreeTraj>,
+}
+
+struct MasterState {
+  tree:           Option<SharedTree>,
+  search_id:      u64,
+  max_batch_size: usize,
+  remote_priors:  bool,
+  num_rollouts:   usize,
+  num_issued:     usize,
+  num_completed:  usize,
+  next_batch_id:  u64,
+  pending:        HashMap<u64, PendingBatch>,
+  num_conns:      usize,
+  shutdown:       bool,
+}
+
+struct MasterShared {
+  state:  Mutex<MasterState>,
+  cond:   Condvar,
+}
+
+impl MasterShared {
+  /// Reverts the virtual losses of the pending batches of a dropped worker
+  /// and returns their leafs to the current search.
+  fn drop_conn(&self, conn_id: usize) {
+    let mut state = self.state.lock().unwrap();
+    state.num_conns -= 1;
+    let batch_ids: Vec<u64> = state.pending.iter()
+      .filter(|&(_, batch)| batch.conn_id == conn_id)
+      .map(|(&batch_id, _)| batch_id)
+      .collect();
+    for batch_id in batch_ids.iter() {
+      let batch = state.pending.remove(batch_id).unwrap();
+      for tree_traj in batch.tree_trajs.iter() {
+        TreeOps::revert_virtual_losses(tree_traj);
+      }
+      if batch.search_id == state.search_id {
+        state.num_issued -= batch.tree_trajs.len();
+      }
+    }
+    println!("DEBUG: distributed master: worker {} disconnected, dropped batches: {}",
+        conn_id, batch_ids.len());
+    self.cond.notify_all();
+  }
+}
+
+/// Owns the search tree and serves leaf batches to remote workers (see
+/// `DistributedSearchWorker`), backing up their results. Leafs in flight hold
+/// virtual losses, so the tree policy should use them; the virtual losses of
+/// leafs whose results never arrive in time are reverted and their late
+/// results are dropped.
+pub struct DistributedSearchMaster<W> where W: SearchPolicyWorker {
+  addr:   SocketAddr,
+  shared: Arc<MasterShared>,
+  // Expands the root nodes.
+  worker: W,
+  rng:    Xorshiftplus128Rng,
+}
+
+impl<W> DistributedSearchMaster<W> where W: SearchPolicyWorker {
+  pub fn bind<A, B>(addr: A, worker_builder: B) -> io::Result<DistributedSearchMaster<W>>
+  where A: ToSocketAddrs, B: 'static + SearchPolicyWorkerBuilder<Worker=W> {
+    let listener = try!(TcpListener::bind(addr));
+    let addr = try!(listener.local_addr());
+    let shared = Arc::new(MasterShared{
+      state:  Mutex::new(MasterState{
+        tree:           None,
+        search_id:      0,
+        max_batch_size: 0,
+        remote_priors:  false,
+        num_rollouts:   0,
+        num_issued:     0,
+        num_completed:  0,
+        next_batch_id:  0,
+        pending:        HashMap::new(),
+        num_conns:      0,
+        shutdown:       false,
+      }),
+      cond:   Condvar::new(),
+    });
+    let worker = worker_builder.clone().into_worker(0, 1, 1);
+    {
+      let shared = shared.clone();
+      thread::spawn(move || {
+        let mut conn_id = 0;
+        for stream in listener.incoming() {
+          let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+              println!("WARNING: distributed master: failed to accept: {:?}", e);
+              continue;
+            }
+          };
+          conn_id += 1;
+          {
+            let mut state = shared.state.lock().unwrap();
+            if state.shutdown {
+              break;
+            }
+            state.num_conns += 1;
+            shared.cond.notify_all();
+          }
+          println!("DEBUG: distributed master: worker {} connected: {:?}", conn_id, stream.peer_addr());
+          let shared = shared.clone();
+          let worker_builder = worker_builder.clone();
+          thread::spawn(move || {
+            serve_worker(conn_id, stream, &shared, worker_builder);
+            shared.drop_conn(conn_id);
+          });
+        }
+      });
+    }
+    Ok(DistributedSearchMaster{
+      addr:   addr,
+      shared: shared,
+      worker: worker,
+      rng:    Xorshiftplus128Rng::new(&mut thread_rng()),
+    })
+  }
+
+  pub fn local_addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  pub fn num_workers(&self) -> usize {
+    self.shared.state.lock().unwrap().num_conns
+  }
+
+  /// Blocks until at least `num_workers` workers are connected.
+  pub fn wait_for_workers(&self, num_workers: usize) {
+    let mut state = self.shared.state.lock().unwrap();
+    while state.num_conns < num_workers {
+      state = self.shared.cond.wait(state).unwrap();
+    }
+  }
+
+  pub fn search(&mut self,
+      cfg:          DistributedSearchConfig,
+      init_state:   &TxnState<TxnStateNodeData>,
+      shared_tree:  SharedTree)
+      -> (MonteCarloSearchResult, MonteCarloSearchStats)
+  {
+    let start_time = get_time();
+    shared_tree.try_reset(init_state.clone(), self.worker.prior_policy(), &mut self.rng);
+    shared_tree.reset_search_counters();
+    if shared_tree.tree_cfg().virtual_loss <= 0.0 {
+      println!("WARNING: distributed master: no virtual loss, concurrent batches will overlap");
+    }
+
+    let mut state = self.shared.state.lock().unwrap();
+    state.tree = Some(shared_tree.clone());
+    state.search_id += 1;
+    state.max_batch_size = cfg.max_batch_size;
+    state.remote_priors = cfg.remote_priors;
+    state.num_rollouts = cfg.num_rollouts;
+    state.num_issued = 0;
+    state.num_completed = 0;
+    self.shared.cond.notify_all();
+
+    let timeout = Duration::from_millis(cfg.result_timeout_ms as u64);
+    let mut prev_completed = 0;
+    let mut progress_time = get_time();
+    while state.num_completed < state.num_rollouts {
+      state = self.shared.cond.wait_timeout(state, timeout).unwrap().0;
+      if state.num_completed > prev_completed {
+        prev_completed = state.num_completed;
+        progress_time = get_time();
+      } else if (get_time() - progress_time).num_milliseconds() as usize >= cfg.result_timeout_ms {
+        println!("WARNING: distributed master: timed out, completed: {}/{} in flight: {}",
+            state.num_completed, state.num_rollouts, state.num_issued - state.num_completed);
+        break;
+      }
+    }
+
+    // Results still in flight are stale: revert their virtual losses now and
+    // drop them when they arrive.
+    state.tree = None;
+    let search_id = state.search_id;
+    let batch_ids: Vec<u64> = state.pending.iter()
+      .filter(|&(_, batch)| batch.search_id == search_id)
+      .map(|(&batch_id, _)| batch_id)
+      .collect();
+    for batch_id in batch_ids.iter() {
+      let batch = state.pending.remove(batch_id).unwrap();
+      for tree_traj in batch.tree_trajs.iter() {
+        TreeOps::revert_virtual_losses(tree_traj);
+      }
+    }
+    let num_rollouts = state.num_completed;
+    let num_workers = state.num_conns;
+    drop(state);
+
+    shared_tree.record_search_end(num_rollouts);
+    let elapsed_ms = (get_time() - start_time).num_milliseconds() as usize;
+    search_result(&shared_tree, max(1, num_workers), elapsed_ms)
+  }
+
+  /// Tells the workers to quit at their next request.
+  pub fn shutdown(&self) {
+    let mut state = self.shared.state.lock().unwrap();
+    state.shutdown = true;
+    self.shared.cond.notify_all();
+  }
+}
+
+impl<W> Drop for DistributedSearchMaster<W> where W: SearchPolicyWorker {
+  fn drop(&mut self) {
+    self.shutdown();
+  }
+}
+
+fn serve_worker<B>(conn_id: usize, stream: TcpStream, shared: &MasterShared, worker_builder: B)
+where B: SearchPolicyWorkerBuilder {
+  let mut reader = match stream.try_clone() {
+    Ok(stream) => BufReader::new(stream),
+    Err(e) => {
+      println!("WARNING: distributed master: failed to clone stream: {:?}", e);
+      return;
+    }
+  };
+  let mut writer = BufWriter::new(stream);
+  let mut worker = worker_builder.into_worker(conn_id, 1, 1);
+  let mut rng = Xorshiftplus128Rng::new(&mut thread_rng());
+  let use_rave = worker.tree_policy().use_rave();
+  let mut rollout_traj = RolloutTraj::new();
+
+  loop {
+    let msg: WorkerMessage = match decode_from(&mut reader, SizeLimit::Infinite) {
+      Ok(msg) => msg,
+      Err(_) => return,
+    };
+    match msg {
+      WorkerMessage::RequestBatch{max_batch_size} => {
+        let reply = next_batch(conn_id, max_batch_size, shared, &mut worker, &mut rng);
+        if encode_into(&reply, &mut writer, SizeLimit::Infinite).is_err() || writer.flush().is_err() {
+          return;
+        }
+        if let MasterMessage::Quit = reply {
+          return;
+        }
+      }
+
+      WorkerMessage::Results{batch_id, rollouts, mc_live_counts} => {
+        let (batch, tree) = {
+          let mut state = shared.state.lock().unwrap();
+          (state.pending.remove(&batch_id), state.tree.clone())
+        };
+        let (batch, tree) = match (batch, tree) {
+          (Some(batch), Some(tree)) => (batch, tree),
+          _ => {
+            println!("DEBUG: distributed master: dropping stale batch {} from worker {}", batch_id, conn_id);
+            continue;
+          }
+        };
+        if rollouts.len() != batch.tree_trajs.len() {
+          println!("WARNING: distributed master: batch {} has {} results for {} leafs",
+              batch_id, rollouts.len(), batch.tree_trajs.len());
+          for tree_traj in batch.tree_trajs.iter() {
+            TreeOps::revert_virtual_losses(tree_traj);
+          }
+          let mut state = shared.state.lock().unwrap();
+          if batch.search_id == state.search_id {
+            state.num_issued -= batch.tree_trajs.len();
+          }
+          shared.cond.notify_all();
+          continue;
+        }
+        let horizon_cfg = tree.tree_cfg().horizon_cfg;
+        let virtual_loss = tree.tree_cfg().virtual_loss > 0.0;
+        let mut num_stale_priors = 0;
+        for (tree_traj, rollout) in batch.tree_trajs.iter().zip(rollouts.iter()) {
+          if !rollout.prior_values.is_empty() {
+            let leaf_node = tree_traj.leaf_node.as_ref().unwrap();
+            let mut remote_prior = RemotePriorPolicy{prior_values: &rollout.prior_values};
+            let reset = tree.with_tree_priors(&mut remote_prior, |prior_policy| {
+              leaf_node.write().unwrap().try_reset_priors(prior_policy)
+            });
+            if !reset {
+              num_stale_priors += 1;
+            }
+          }
+          rollout_traj.reset_remote(&rollout.sim_pairs, rollout.score);
+          TreeOps::backup(use_rave, batch.komi, horizon_cfg, virtual_loss, tree_traj, &mut rollout_traj, None, &mut rng);
+        }
+        if num_stale_priors > 0 {
+          println!("DEBUG: distributed master: batch {}: leafs visited before their priors arrived: {}",
+              batch_id, num_stale_priors);
+        }
+        let scores: Vec<_> = rollouts.iter().map(|rollout| rollout.score).collect();
+        tree.record_rollouts(batch.komi, &scores, &mc_live_counts);
+        let mut state = shared.state.lock().unwrap();
+        if batch.search_id == state.search_id {
+          state.num_completed += rollouts.len();
+        }
+        shared.cond.notify_all();
+      }
+    }
+  }
+}
+
+/// Waits for the current search to have leafs left to issue, then traverses
+/// the tree for a batch of them.
+fn next_batch<W>(conn_id: usize, max_batch_size: usize, shared: &MasterShared, worker: &mut W, rng: &mut Xorshiftplus128Rng) -> MasterMessage
+where W: SearchPolicyWorker {
+  loop {
+    let (tree, search_id, batch_id, batch_size, remote_priors) = {
+      let mut state = shared.state.lock().unwrap();
+      loop {
+        if state.shutdown {
+          return MasterMessage::Quit;
+        }
+        if state.tree.is_some() && state.num_issued < state.num_rollouts {
+          break;
+        }
+        state = shared.cond.wait(state).unwrap();
+      }
+      let batch_size = min(min(max_batch_size, state.max_batch_size), state.num_rollouts - state.num_issued);
+      let batch_id = state.next_batch_id;
+      state.next_batch_id += 1;
+      state.num_issued += batch_size;
+      (state.tree.clone().unwrap(), state.search_id, batch_id, batch_size, state.remote_priors)
+    };
+
+    let tree_cfg = tree.tree_cfg();
+    let root_node = tree.root_node();
+    let komi = root_node.read().unwrap().state().config.komi;
+    let mut tree_trajs = Vec::with_capacity(batch_size);
+    let mut leaf_states = Vec::with_capacity(batch_size);
+    let mut eval_priors = Vec::with_capacity(batch_size);
+    {
+      let mut uniform_prior = UniformPriorPolicy;
+      let (worker_prior, tree_policy) = worker.exploration_policies();
+      let prior_policy: &mut PriorPolicy = if remote_priors {
+        &mut uniform_prior
+      } else {
+        worker_prior
+      };
+      tree.with_tree_priors(prior_policy, |prior_policy| {
+        for _ in 0 .. batch_size {
+          let mut tree_traj = TreeTraj::new();
+          TreeOps::traverse(tree_cfg, root_node.clone(), &mut tree_traj, None, prior_policy, tree_policy, rng);
+          tree.record_traversal(&tree_traj);
+          leaf_states.push(tree_traj.leaf_node.as_ref().unwrap().read().unwrap().state().clone());
+          eval_priors.push(remote_priors && tree_traj.expanded);
+          tree_trajs.push(tree_traj);
+        }
+      });
+    }
+
+    let mut state = shared.state.lock().unwrap();
+    if state.search_id != search_id || state.tree.is_none() {
+      // The search ended during the traversals.
+      for tree_traj in tree_trajs.iter() {
+        TreeOps::revert_virtual_losses(tree_traj);
+      }
+      continue;
+    }
+    state.pending.insert(batch_id, PendingBatch{
+      conn_id:    conn_id,
+      search_id:  search_id,
+      komi:       komi,
+      tree_trajs: tree_trajs,
+    });
+    return MasterMessage::Batch{
+      batch_id:     batch_id,
+      komi:         komi,
+      leaf_states:  leaf_states,
+      eval_priors:  eval_priors,
+    };
+  }
+}
+
+/// Connects to a `DistributedSearchMaster` and rolls out the leafs it hands
+/// out with its own rollout policy, and evaluates its prior policy on the new
+/// leafs when the master searches with remote priors.
+pub struct DistributedSearchWorker<W> where W: SearchPolicyWorker {
+  reader:         BufReader<TcpStream>,
+  writer:         BufWriter<TcpStream>,
+  max_batch_size: usize,
+  worker:         W,
+  rollout_trajs:  Vec<RolloutTraj>,
+  rng:            Xorshiftplus128Rng,
+}
+
+impl<W> DistributedSearchWorker<W> where W: SearchPolicyWorker {
+  pub fn connect<A, B>(addr: A, max_batch_size: usize, worker_builder: B) -> io::Result<DistributedSearchWorker<W>>
+  where A: ToSocketAddrs, B: SearchPolicyWorkerBuilder<Worker=W> {
+    let stream = try!(TcpStream::connect(addr));
+    try!(stream.set_nodelay(true));
+    let reader = BufReader::new(try!(stream.try_clone()));
+    Ok(DistributedSearchWorker{
+      reader:         reader,
+      writer:         BufWriter::new(stream),
+      max_batch_size: max_batch_size,
+      worker:         worker_builder.into_worker(0, 1, max_batch_size),
+      rollout_trajs:  repeat(RolloutTraj::new()).take(max_batch_size).collect(),
+      rng:            Xorshiftplus128Rng::new(&mut thread_rng()),
+    })
+  }
+
+  /// Serves batches until the master says to quit or the connection closes,
+  /// and returns the number of rollouts.
+  pub fn run(&mut self) -> usize {
+    let mut num_rollouts = 0;
+    loop {
+      let request = WorkerMessage::RequestBatch{max_batch_size: self.max_batch_size};
+      if encode_into(&request, &mut self.writer, SizeLimit::Infinite).is_err() || self.writer.flush().is_err() {
+        break;
+      }
+      let msg: MasterMessage = match decode_from(&mut self.reader, SizeLimit::Infinite) {
+        Ok(msg) => msg,
+        Err(_) => break,
+      };
+      let (batch_id, komi, leaf_states, eval_priors) = match msg {
+        MasterMessage::Quit => break,
+        MasterMessage::Batch{batch_id, komi, leaf_states, eval_priors} => (batch_id, komi, leaf_states, eval_priors),
+      };
+      let batch_size = leaf_states.len();
+      assert!(batch_size <= self.max_batch_size);
+      for batch_idx in 0 .. batch_size {
+        self.rollout_trajs[batch_idx].reset_rollout(&leaf_states[batch_idx]);
+      }
+      self.worker.rollout_policy().rollout_batch(
+          batch_size,
+          RolloutLeafs::LeafStates(&leaf_states),
+          &mut self.rollout_trajs,
+          None,
+          None,
+          &mut self.rng);
+      let mut mc_live_counts: Vec<Vec<usize>> = vec![
+        repeat(0).take(Board::SIZE).collect(),
+        repeat(0).take(Board::SIZE).collect(),
+      ];
+      let mut rollouts = Vec::with_capacity(batch_size);
+      let mut valid_moves = vec![];
+      for batch_idx in 0 .. batch_size {
+        let mut prior_values = vec![];
+        if eval_priors[batch_idx] {
+          let leaf_state = &leaf_states[batch_idx];
+          valid_moves.clear();
+          leaf_state.get_data().legality.fill_legal_points(leaf_state.current_turn(), &mut valid_moves);
+          self.worker.prior_policy().fill_prior_values(leaf_state, &valid_moves, &mut prior_values);
+        }
+        let rollout_traj = &mut self.rollout_trajs[batch_idx];
+        rollout_traj.update_score(komi);
+        rollout_traj.update_mc_live_counts(&mut mc_live_counts);
+        rollouts.push(RemoteRollout{
+          score:        rollout_traj.score.unwrap(),
+          sim_pairs:    rollout_traj.sim_pairs.clone(),
+          prior_values: prior_values,
+        });
+      }
+      let results = WorkerMessage::Results{
+        batch_id:       batch_id,
+        rollouts:       rollouts,
+        mc_live_counts: mc_live_counts,
+      };
+      if encode_into(&results, &mut self.writer, SizeLimit::Infinite).is_err() || self.writer.flush().is_err() {
+        break;
+      }
+      num_rollouts += batch_size;
+    }
+    num_rollouts
+  }
+}
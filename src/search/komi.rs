@@ -0,0 +1,174 @@
+//! Dynamic komi: the search scores rollouts and backs up tree values with an
+//! effective komi which departs from the game komi, so that the win rates stay
+//! informative in handicap and lopsided games. Shifts are in points demanded
+//! of the engine: with a positive shift the engine plays as if it had to win
+//! by that many more points.
+
+use board::{Stone};
+use txnstate::{TxnStateData, TxnState};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DynamicKomiMode {
+  Off,
+  /// Starts from the handicap shift and fades it out linearly by `fade_ply`,
+  /// in whole steps so that the search tree is kept between steps.
+  Handicap,
+  /// Starts from the handicap shift, then after every search steps the shift
+  /// up when the win rate is above `upper_value` and down when it is below
+  /// `lower_value`.
+  Situational,
+  /// As `Situational`, except that the shift never again rises to a level at
+  /// which the win rate fell below `lower_value`.
+  Ratchet,
+}
+
+impl DynamicKomiMode {
+  pub fn parse(s: &str) -> Option<DynamicKomiMode> {
+    match s {
+      "off"         => Some(DynamicKomiMode::Off),
+      "handicap"    => Some(DynamicKomiMode::Handicap),
+      "situational" => Some(DynamicKomiMode::Situational),
+      "ratchet"     => Some(DynamicKomiMode::Ratchet),
+      _ => None,
+    }
+  }
+
+  pub fn to_str(self) -> &'static str {
+    match self {
+      DynamicKomiMode::Off          => "off",
+      DynamicKomiMode::Handicap     => "handicap",
+      DynamicKomiMode::Situational  => "situational",
+      DynamicKomiMode::Ratchet      => "ratchet",
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DynamicKomiConfig {
+  pub mode:         DynamicKomiMode,
+  /// Points per handicap stone.
+  pub stone_value:  f32,
+  pub fade_ply:     usize,
+  pub lower_value:  f32,
+  pub upper_value:  f32,
+  pub step:         f32,
+  pub max_shift:    f32,
+}
+
+impl Default for DynamicKomiConfig {
+  fn default() -> DynamicKomiConfig {
+    DynamicKomiConfig{
+      mode:         DynamicKomiMode::Off,
+      stone_value:  7.0,
+      fade_ply:     200,
+      lower_value:  0.45,
+      upper_value:  0.75,
+      step:         1.0,
+      max_shift:    30.0,
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct DynamicKomi {
+  cfg:      DynamicKomiConfig,
+  shift:    f32,
+  ratchet:  Option<f32>,
+  started:  bool,
+}
+
+impl DynamicKomi {
+  pub fn new(cfg: DynamicKomiConfig) -> DynamicKomi {
+    DynamicKomi{
+      cfg:      cfg,
+      shift:    0.0,
+      ratchet:  None,
+      started:  false,
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.shift = 0.0;
+    self.ratchet = None;
+    self.started = false;
+  }
+
+  pub fn mode(&self) -> DynamicKomiMode {
+    self.cfg.mode
+  }
+
+  /// The shift of the situational modes for the next search.
+  pub fn shift(&self) -> f32 {
+    self.shift
+  }
+
+  /// Black is ahead by about `stone_value` per handicap stone.
+  pub fn handicap_shift<Data>(&self, state: &TxnState<Data>, player: Stone) -> f32
+  where Data: TxnStateData + Clone {
+    let num_stones = state.num_handicap_stones();
+    let shift = (self.cfg.stone_value * num_stones as f32).min(self.cfg.max_shift);
+    match player {
+      Stone::Black => shift,
+      Stone::White => -shift,
+      Stone::Empty => unreachable!(),
+    }
+  }
+
+  /// The komi to search the current position with, for the engine playing
+  /// `player`; `game_komi` is the komi of the game.
+  pub fn effective_komi<Data>(&mut self, state: &TxnState<Data>, player: Stone, game_komi: f32) -> f32
+  where Data: TxnStateData + Clone {
+    let shift = match self.cfg.mode {
+      DynamicKomiMode::Off => 0.0,
+      DynamicKomiMode::Handicap => {
+        let ply = state.current_ply();
+        if ply >= self.cfg.fade_ply {
+          0.0
+        } else {
+          let shift = self.handicap_shift(state, player) * (1.0 - ply as f32 / self.cfg.fade_ply as f32);
+          if self.cfg.step > 0.0 {
+            shift.signum() * (shift.abs() / self.cfg.step).floor() * self.cfg.step
+          } else {
+            shift
+          }
+        }
+      }
+      DynamicKomiMode::Situational | DynamicKomiMode::Ratchet => {
+        if !self.started {
+          self.shift = self.handicap_shift(state, player);
+          self.started = true;
+        }
+        self.shift
+      }
+    };
+    match player {
+      Stone::Black => game_komi + shift,
+      Stone::White => game_komi - shift,
+      Stone::Empty => unreachable!(),
+    }
+  }
+
+  /// Adapts the shift to the engine's win rate in the last search, which used
+  /// the komi from `effective_komi`.
+  pub fn update(&mut self, value: f32) {
+    match self.cfg.mode {
+      DynamicKomiMode::Situational | DynamicKomiMode::Ratchet => {}
+      _ => return,
+    }
+    if value < self.cfg.lower_value {
+      self.shift -= self.cfg.step;
+      if self.cfg.mode == DynamicKomiMode::Ratchet {
+        self.ratchet = Some(match self.ratchet {
+          Some(ratchet) => ratchet.min(self.shift),
+          None => self.shift,
+        });
+      }
+    } else if value > self.cfg.upper_value {
+      self.shift += self.cfg.step;
+      if let Some(ratchet) = self.ratchet {
+        self.shift = self.shift.min(ratchet);
+      }
+    }
+    self.shift = self.shift.max(-self.cfg.max_shift).min(self.cfg.max_shift);
+  }
+}
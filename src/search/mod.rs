@@ -1,10 +1,14 @@
 use board::{Stone, Action};
 
+pub mod decision;
+pub mod distributed;
+pub mod komi;
 //pub mod minimax;
 pub mod parallel_policies;
 pub mod parallel_trace;
 pub mod parallel_tree;
 //pub mod policies;
+pub mod report;
 //pub mod tree;
 
 #[derive(Clone, Copy)]
@@ -116,6 +116,14 @@ impl ConvnetPolicyWorkerBuilder {
       rollout_shared2:      Arc::new(()),
     }
   }
+
+  /// Overrides the saved model paths (e.g. from the engine config); the
+  /// paths must match the hardcoded architectures.
+  pub fn with_save_paths(mut self, prior_save_path: PathBuf, rollout_save_path: PathBuf) -> ConvnetPolicyWorkerBuilder {
+    self.prior_save_path = prior_save_path;
+    self.rollout_save_path = rollout_save_path;
+    self
+  }
 }
 
 impl SearchPolicyWorkerBuilder for ConvnetPolicyWorkerBuilder {
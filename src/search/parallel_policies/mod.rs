@@ -15,6 +15,7 @@ pub mod pattern;
 //pub mod shaped_thompson;
 pub mod thompson;
 pub mod ucb;
+pub mod uniform;
 
 pub trait SearchPolicyWorkerBuilder: Send + Clone {
   type Worker: SearchPolicyWorker;
@@ -82,7 +83,7 @@ impl<'a> RolloutLeafs<'a> {
   where F: FnMut(&TxnState<TxnStateNodeData>) {
     match self {
       &RolloutLeafs::TreeTrajs(tree_trajs) => {
-        f(&tree_trajs[idx].leaf_node.as_ref().unwrap().read().unwrap().state);
+        f(tree_trajs[idx].leaf_node.as_ref().unwrap().read().unwrap().state());
       }
       &RolloutLeafs::LeafStates(leaf_states) => {
         f(&leaf_states[idx]);
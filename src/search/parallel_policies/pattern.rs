@@ -6,8 +6,10 @@ use discrete::{DiscreteFilter};
 use discrete::bfilter::{BFilter};
 use random::{choose_without_replace};
 use pattern::{
+  MAX_PREV_DISTANCE,
+  MoveFeature,
   PatternGammaDatabase,
-  InvariantLibPattern3x3,
+  prev_distance,
 };
 use search::parallel_policies::{
   SearchPolicyWorkerBuilder, SearchPolicyWorker,
@@ -21,9 +23,15 @@ use search::parallel_policies::convnet::{
 use search::parallel_policies::thompson::{
   ThompsonTreePolicy,
 };
+use search::parallel_policies::uniform::{
+  UniformPriorPolicy,
+};
 use search::parallel_tree::{TreePolicyConfig, TreeTraj, RolloutTraj, QuickTrace};
 use search::parallel_trace::{SearchTraceBatch};
-use txnstate::{TxnState, check_good_move_fast};
+use txnstate::{
+  TxnState, TxnPosition, TxnChainsList,
+  check_good_move_fast, for_each_adjacent, for_each_diagonal,
+};
 use txnstate::extras::{TxnStateNodeData, for_each_touched_empty};
 
 use array_cuda::device::{DeviceContext, for_all_devices};
@@ -36,6 +44,7 @@ use rembrandt::data_new::{SampleLabel};
 use rembrandt::layer_new::{Phase};
 use rng::xorshift::{Xorshiftplus128Rng};
 
+use bit_set::{BitSet};
 use rand::{Rng, thread_rng};
 use std::path::{Path, PathBuf};
 use std::rc::{Rc};
@@ -48,10 +57,12 @@ pub struct ConvnetPatternPolicyWorkerBuilder {
   prior_save_path:      PathBuf,
   prior_shared:         Arc<PipelineArchSharedData>,
   prior_shared2:        Arc<()>,
+  gamma_db:             Arc<PatternGammaDatabase>,
 }
 
 impl ConvnetPatternPolicyWorkerBuilder {
-  pub fn new(tree_cfg: TreePolicyConfig, num_workers: usize, worker_tree_batch_size: usize, worker_batch_size: usize) -> ConvnetPatternPolicyWorkerBuilder {
+  pub fn new(tree_cfg: TreePolicyConfig, num_workers: usize, worker_tree_batch_size: usize, worker_batch_size: usize, gamma_db_path: &Path) -> Result<ConvnetPatternPolicyWorkerBuilder, String> {
+    let gamma_db = try!(open_gamma_db(gamma_db_path));
     let prior_arch_cfg = build_13layer384multi3_19x19x32_arch_nodir(worker_tree_batch_size);
     let prior_save_path = PathBuf::from("models/gogodb_w2015-preproc-alphav3m_19x19x32_13layer384multi3.saved");
 
@@ -59,13 +70,14 @@ impl ConvnetPatternPolicyWorkerBuilder {
       Arc::new(PipelineArchSharedData::new(num_workers, &prior_arch_cfg, contexts))
     });
 
-    ConvnetPatternPolicyWorkerBuilder{
+    Ok(ConvnetPatternPolicyWorkerBuilder{
       tree_cfg:             tree_cfg,
       prior_arch_cfg:       prior_arch_cfg,
       prior_save_path:      prior_save_path,
       prior_shared:         prior_shared,
       prior_shared2:        Arc::new(()),
-    }
+      gamma_db:             gamma_db,
+    })
   }
 }
 
@@ -92,10 +104,7 @@ impl SearchPolicyWorkerBuilder for ConvnetPatternPolicyWorkerBuilder {
       arch:     prior_arch,
     };
     let tree_policy = ThompsonTreePolicy::new(self.tree_cfg);
-    let rollout_policy = PatternRolloutPolicy{
-      batch_size:   worker_batch_size,
-      pattern_db:   PatternGammaDatabase::open(&PathBuf::from("pat_gammas.db")),
-    };
+    let rollout_policy = PatternRolloutPolicy::new(worker_batch_size, self.gamma_db);
     ConvnetPatternPolicyWorker{
       prior_policy:     prior_policy,
       tree_policy:      tree_policy,
@@ -132,9 +141,169 @@ impl SearchPolicyWorker for ConvnetPatternPolicyWorker {
   }
 }
 
+fn open_gamma_db(path: &Path) -> Result<Arc<PatternGammaDatabase>, String> {
+  match PatternGammaDatabase::open(path) {
+    Ok(db) => Ok(Arc::new(db)),
+    Err(_) => Err(format!("failed to open pattern gamma database: {:?}", path)),
+  }
+}
+
+/// A search policy worker which needs no GPU: priors and rollouts both
+/// weight moves by their pattern gammas.
+#[derive(Clone)]
+pub struct PatternPolicyWorkerBuilder {
+  tree_cfg: TreePolicyConfig,
+  gamma_db: Arc<PatternGammaDatabase>,
+}
+
+impl PatternPolicyWorkerBuilder {
+  /// The gamma database is usually `network.pattern_gammas` of the engine
+  /// config; old format or missing files are an error.
+  pub fn new(tree_cfg: TreePolicyConfig, gamma_db_path: &Path) -> Result<PatternPolicyWorkerBuilder, String> {
+    Ok(PatternPolicyWorkerBuilder{
+      tree_cfg: tree_cfg,
+      gamma_db: try!(open_gamma_db(gamma_db_path)),
+    })
+  }
+}
+
+impl SearchPolicyWorkerBuilder for PatternPolicyWorkerBuilder {
+  type Worker = PatternPolicyWorker;
+
+  fn into_worker(self, _tid: usize, _worker_tree_batch_capacity: usize, worker_rollout_batch_capacity: usize) -> PatternPolicyWorker {
+    PatternPolicyWorker{
+      prior_policy:       PatternPriorPolicy::new(self.gamma_db.clone()),
+      diff_prior_policy:  UniformPriorPolicy,
+      tree_policy:        ThompsonTreePolicy::new(self.tree_cfg),
+      rollout_policy:     PatternRolloutPolicy::new(worker_rollout_batch_capacity, self.gamma_db),
+    }
+  }
+}
+
+pub struct PatternPolicyWorker {
+  prior_policy:       PatternPriorPolicy,
+  // XXX(20160421): The pattern prior is not differentiable.
+  diff_prior_policy:  UniformPriorPolicy,
+  tree_policy:        ThompsonTreePolicy,
+  rollout_policy:     PatternRolloutPolicy,
+}
+
+impl SearchPolicyWorker for PatternPolicyWorker {
+  fn prior_policy(&mut self) -> &mut PriorPolicy {
+    &mut self.prior_policy
+  }
+
+  fn diff_prior_policy(&mut self) -> &mut DiffPriorPolicy {
+    &mut self.diff_prior_policy
+  }
+
+  fn tree_policy(&mut self) -> &mut TreePolicy<R=Xorshiftplus128Rng> {
+    &mut self.tree_policy
+  }
+
+  fn exploration_policies(&mut self) -> (&mut PriorPolicy, &mut TreePolicy<R=Xorshiftplus128Rng>) {
+    (&mut self.prior_policy, &mut self.tree_policy)
+  }
+
+  fn rollout_policy(&mut self) -> &mut RolloutPolicy<R=Xorshiftplus128Rng> {
+    &mut self.rollout_policy
+  }
+}
+
+/// Priors proportional to the move gammas.
+pub struct PatternPriorPolicy {
+  gamma_db: Arc<PatternGammaDatabase>,
+  features: Vec<MoveFeature>,
+}
+
+impl PatternPriorPolicy {
+  pub fn new(gamma_db: Arc<PatternGammaDatabase>) -> PatternPriorPolicy {
+    PatternPriorPolicy{
+      gamma_db: gamma_db,
+      features: vec![],
+    }
+  }
+}
+
+impl PriorPolicy for PatternPriorPolicy {
+  fn fill_prior_values(&mut self, state: &TxnState<TxnStateNodeData>, valid_moves: &[Point], prior_values: &mut Vec<(Point, f32)>) {
+    prior_values.clear();
+    let turn = state.current_turn();
+    let mut gamma_sum = 0.0;
+    for &point in valid_moves.iter() {
+      let gamma = self.gamma_db.move_gamma(state, turn, point, &mut self.features);
+      prior_values.push((point, gamma));
+      gamma_sum += gamma;
+    }
+    if !(gamma_sum > 0.0) {
+      let prior = 1.0 / valid_moves.len() as f32;
+      for &mut (_, ref mut value) in prior_values.iter_mut() {
+        *value = prior;
+      }
+      return;
+    }
+    for &mut (_, ref mut value) in prior_values.iter_mut() {
+      *value /= gamma_sum;
+    }
+  }
+}
+
+/// Rollouts which sample moves in proportion to their gammas. The local
+/// gammas are cached per point and only recomputed around the last move; the
+/// distance to the previous move is weighed in when sampling.
 pub struct PatternRolloutPolicy {
   batch_size:   usize,
-  pattern_db:   PatternGammaDatabase,
+  gamma_db:     Arc<PatternGammaDatabase>,
+  prev_gammas:  Vec<f32>,
+  features:     Vec<MoveFeature>,
+  local_gammas: Vec<Vec<f32>>,
+  dirty:        BitSet,
+  weights:      Vec<f32>,
+}
+
+impl PatternRolloutPolicy {
+  pub fn new(batch_size: usize, gamma_db: Arc<PatternGammaDatabase>) -> PatternRolloutPolicy {
+    let prev_gammas = gamma_db.prev_distance_gammas();
+    PatternRolloutPolicy{
+      batch_size:   batch_size,
+      gamma_db:     gamma_db,
+      prev_gammas:  prev_gammas,
+      features:     vec![],
+      local_gammas: vec![vec![0.0; Board::SIZE], vec![0.0; Board::SIZE]],
+      dirty:        BitSet::with_capacity(Board::SIZE),
+      weights:      vec![],
+    }
+  }
+}
+
+/// Marks the empty points whose local features may have changed after the
+/// last placement: the 3x3 neighborhoods of the placed and captured stones,
+/// and the liberties of the chains next to them.
+fn mark_dirty_points(position: &TxnPosition, chains: &TxnChainsList, dirty: &mut BitSet) {
+  let place_point = match position.last_placed {
+    Some((_, place_point)) => place_point,
+    None => return,
+  };
+  let kill_points = position.last_killed[0].iter().chain(position.last_killed[1].iter());
+  for &point in Some(place_point).iter().chain(kill_points) {
+    dirty.insert(point.idx());
+    for_each_diagonal(point, |diag_point| {
+      dirty.insert(diag_point.idx());
+    });
+    for_each_adjacent(point, |adj_point| {
+      dirty.insert(adj_point.idx());
+      if position.stones[adj_point.idx()] == Stone::Empty {
+        return;
+      }
+      chains.iter_chain(chains.find_chain(adj_point), |chain_point| {
+        for_each_adjacent(chain_point, |lib_point| {
+          if position.stones[lib_point.idx()] == Stone::Empty {
+            dirty.insert(lib_point.idx());
+          }
+        });
+      });
+    });
+  }
 }
 
 impl RolloutPolicy for PatternRolloutPolicy {
@@ -152,53 +321,155 @@ impl RolloutPolicy for PatternRolloutPolicy {
       leafs:            RolloutLeafs,
       rollout_trajs:    &mut [RolloutTraj],
       _pass_only:       Option<Stone>,
-      mut _trace_batch: Option<&mut SearchTraceBatch>,
+      mut trace_batch:  Option<&mut SearchTraceBatch>,
       rng:              &mut Xorshiftplus128Rng)
   {
     assert!(batch_size <= rollout_trajs.len());
     assert!(_pass_only.is_none());
-    assert!(_trace_batch.is_none());
-
-    let mut valid_move_set = vec![vec![], vec![]];
-    // XXX(20160124): Valid move iterator is an "upper bound" on the valid moves,
-    // since it is easy to add elements but hard to remove them.
-    let mut valid_move_iter: Vec<Vec<Vec<usize>>> = vec![vec![], vec![]];
-    let mut bad_moves: Vec<Vec<Vec<Point>>> = vec![vec![], vec![]];
-    let mut turn_pass = vec![vec![], vec![]];
-    let mut num_both_passed = 0;
-    let mut filters = vec![vec![], vec![]];
+
     for batch_idx in 0 .. batch_size {
+      if !rollout_trajs[batch_idx].rollout {
+        continue;
+      }
+
+      // XXX(20160421): As in the uniform rollouts, the candidate moves are an
+      // upper bound on the valid moves; touched points are added back after
+      // every placement.
+      let mut candidates: Vec<Vec<usize>> = vec![vec![], vec![]];
+      let mut candidate_set: Vec<BitSet> = vec![BitSet::with_capacity(Board::SIZE), BitSet::with_capacity(Board::SIZE)];
       leafs.with_leaf_state(batch_idx, |leaf_state| {
-        valid_move_set[0].push(leaf_state.get_data().legality.legal_points(Stone::Black));
-        valid_move_set[1].push(leaf_state.get_data().legality.legal_points(Stone::White));
-        valid_move_iter[0].push(valid_move_set[0][batch_idx].iter().collect());
-        valid_move_iter[1].push(valid_move_set[1][batch_idx].iter().collect());
+        for &turn in [Stone::Black, Stone::White].iter() {
+          for p in leaf_state.get_data().legality.get_legal_points(turn).iter() {
+            candidates[turn.offset()].push(p);
+            candidate_set[turn.offset()].insert(p);
+          }
+        }
       });
-      bad_moves[0].push(vec![]);
-      bad_moves[1].push(vec![]);
-      turn_pass[0].push(false);
-      turn_pass[1].push(false);
-      filters[0].push(BFilter::with_capacity(Board::SIZE));
-      filters[1].push(BFilter::with_capacity(Board::SIZE));
-    }
-
-    for batch_idx in 0 .. batch_size {
-      // FIXME(20160413): initialize discrete samplers.
-      //filters[0][batch_idx].reset();
-      //filters[1][batch_idx].reset();
+      {
+        let sim_state = &rollout_trajs[batch_idx].sim_state;
+        for &turn in [Stone::Black, Stone::White].iter() {
+          let turn_off = turn.offset();
+          for &p in candidates[turn_off].iter() {
+            self.local_gammas[turn_off][p] = self.gamma_db.local_move_gamma(sim_state, turn, Point::from_idx(p), &mut self.features);
+          }
+        }
+      }
 
+      let mut num_passes = 0;
       let max_iters = 361 + 361 / 2 + rng.gen_range(0, 2);
-      for t in 0 .. max_iters {
-        let turn = rollout_trajs[batch_idx].sim_state.current_turn();
-        // FIXME(20160413)
-        unimplemented!();
-        /*let pat = InvariantLibPattern3x3::from_repr_bytes(
-            rollout_trajs[batch_idx].sim_state.get_data().features,
-        );*/
+      for _ in 0 .. max_iters {
+        if num_passes >= 2 {
+          break;
+        }
+        let traj = &mut rollout_trajs[batch_idx];
+        let sim_turn = traj.sim_state.current_turn();
+        let sim_turn_off = sim_turn.offset();
+
+        // Drop the filled candidates and weigh the rest.
+        {
+          let sim_state = &traj.sim_state;
+          let candidate_set = &mut candidate_set[sim_turn_off];
+          candidates[sim_turn_off].retain(|&p| {
+            let empty = sim_state.current_stone(Point::from_idx(p)) == Stone::Empty;
+            if !empty {
+              candidate_set.remove(p);
+            }
+            empty
+          });
+        }
+        let prev_point = traj.sim_state.last_play().map(|(_, prev_point)| prev_point);
+        self.weights.clear();
+        let mut weight_sum = 0.0;
+        for &p in candidates[sim_turn_off].iter() {
+          let mut gamma = self.local_gammas[sim_turn_off][p];
+          if let Some(prev_point) = prev_point {
+            let dist = prev_distance(Point::from_idx(p), prev_point);
+            if dist <= MAX_PREV_DISTANCE {
+              gamma *= self.prev_gammas[dist as usize];
+            }
+          }
+          self.weights.push(gamma);
+          weight_sum += gamma;
+        }
+
+        let mut made_move = false;
+        while weight_sum > 0.0 {
+          let mut u = rng.gen::<f32>() * weight_sum;
+          let mut rank = self.weights.len() - 1;
+          for (r, &w) in self.weights.iter().enumerate() {
+            if w > 0.0 && u < w {
+              rank = r;
+              break;
+            }
+            u -= w;
+          }
+          if !(self.weights[rank] > 0.0) {
+            // Rounding left `u` past the last positive weight.
+            weight_sum = 0.0;
+            break;
+          }
+          weight_sum -= self.weights[rank];
+          self.weights[rank] = 0.0;
+          let sim_point = Point::from_idx(candidates[sim_turn_off][rank]);
+          if !check_good_move_fast(&traj.sim_state.position, &traj.sim_state.chains, sim_turn, sim_point) {
+            continue;
+          }
+          if traj.sim_state.try_place(sim_turn, sim_point).is_err() {
+            traj.sim_state.undo();
+            continue;
+          }
+          traj.sim_state.commit();
+          traj.sim_pairs.push((sim_turn, sim_point));
+          if let Some(ref mut trace_batch) = trace_batch {
+            trace_batch.traj_traces[batch_idx]
+              .rollout_trace.actions.push(Action::Place{point: sim_point});
+          }
+          made_move = true;
+          break;
+        }
+
+        if made_move {
+          num_passes = 0;
+        } else {
+          if let Some(ref mut trace_batch) = trace_batch {
+            trace_batch.traj_traces[batch_idx]
+              .rollout_trace.actions.push(Action::Pass);
+          }
+          traj.sim_state.try_action(sim_turn, Action::Pass).unwrap();
+          traj.sim_state.commit();
+          num_passes += 1;
+          // A pass leaves the local gammas as they are.
+          continue;
+        }
+
+        self.dirty.clear();
+        mark_dirty_points(&traj.sim_state.position, &traj.sim_state.chains, &mut self.dirty);
+        {
+          let dirty = &mut self.dirty;
+          for_each_touched_empty(&traj.sim_state.position, &traj.sim_state.chains, |position, _, pt| {
+            let p = pt.idx();
+            if position.stones[p] != Stone::Empty {
+              return;
+            }
+            for turn_off in 0 .. 2 {
+              if !candidate_set[turn_off].contains(p) {
+                candidate_set[turn_off].insert(p);
+                candidates[turn_off].push(p);
+                dirty.insert(p);
+              }
+            }
+          });
+        }
+        for p in self.dirty.iter() {
+          let point = Point::from_idx(p);
+          if traj.sim_state.current_stone(point) != Stone::Empty {
+            continue;
+          }
+          for &turn in [Stone::Black, Stone::White].iter() {
+            self.local_gammas[turn.offset()][p] = self.gamma_db.local_move_gamma(&traj.sim_state, turn, point, &mut self.features);
+          }
+        }
       }
     }
-
-    // FIXME(20160413)
-    unimplemented!();
   }
 }
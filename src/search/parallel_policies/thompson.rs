@@ -55,7 +55,9 @@ impl TreePolicy for ThompsonTreePolicy {
   fn execute_search(&mut self, node: &Node, rng: &mut Xorshiftplus128Rng) -> (Option<(Point, usize)>, usize) {
     let horizon = node.values.horizon();
     for j in 0 .. horizon {
-      let n = self.cfg.mc_scale * node.values.num_trials[j].load(Ordering::Acquire) as f32;
+      // Visits in flight count as losses.
+      let vl = self.cfg.virtual_loss * node.values.virtual_losses[j].load(Ordering::Acquire) as f32;
+      let n = self.cfg.mc_scale * (node.values.num_trials[j].load(Ordering::Acquire) as f32 + vl);
       let s = self.cfg.mc_scale * node.values.num_succs[j].load(Ordering::Acquire) as f32;
       /*let (pn, ps) = if !self.prior {
         (2.0, 1.0)
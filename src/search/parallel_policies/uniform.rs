@@ -0,0 +1,212 @@
+use board::{Board, Action, Stone, Point};
+use random::{choose_without_replace};
+use search::parallel_policies::{
+  SearchPolicyWorkerBuilder, SearchPolicyWorker,
+  PriorPolicy, DiffPriorPolicy, TreePolicy,
+  GradSyncMode,
+  RolloutLeafs, RolloutPolicy,
+};
+use search::parallel_policies::thompson::{
+  ThompsonTreePolicy,
+};
+use search::parallel_tree::{TreePolicyConfig, RolloutTraj};
+use search::parallel_trace::{SearchTraceBatch};
+use txnstate::{TxnState, check_good_move_fast};
+use txnstate::extras::{TxnStateNodeData, for_each_touched_empty};
+
+use rng::xorshift::{Xorshiftplus128Rng};
+
+use bit_set::{BitSet};
+use rand::{Rng};
+
+/// A search policy worker which needs no GPU: uniform priors, Thompson
+/// sampling in the tree, and uniformly random rollouts.
+#[derive(Clone)]
+pub struct UniformPolicyWorkerBuilder {
+  tree_cfg: TreePolicyConfig,
+}
+
+impl UniformPolicyWorkerBuilder {
+  pub fn new(tree_cfg: TreePolicyConfig) -> UniformPolicyWorkerBuilder {
+    UniformPolicyWorkerBuilder{
+      tree_cfg: tree_cfg,
+    }
+  }
+}
+
+impl SearchPolicyWorkerBuilder for UniformPolicyWorkerBuilder {
+  type Worker = UniformPolicyWorker;
+
+  fn into_worker(self, _tid: usize, _worker_tree_batch_capacity: usize, worker_rollout_batch_capacity: usize) -> UniformPolicyWorker {
+    UniformPolicyWorker{
+      prior_policy:     UniformPriorPolicy,
+      tree_policy:      ThompsonTreePolicy::new(self.tree_cfg),
+      rollout_policy:   UniformRolloutPolicy{
+        batch_size:     worker_rollout_batch_capacity,
+      },
+    }
+  }
+}
+
+pub struct UniformPolicyWorker {
+  prior_policy:     UniformPriorPolicy,
+  tree_policy:      ThompsonTreePolicy,
+  rollout_policy:   UniformRolloutPolicy,
+}
+
+impl SearchPolicyWorker for UniformPolicyWorker {
+  fn prior_policy(&mut self) -> &mut PriorPolicy {
+    &mut self.prior_policy
+  }
+
+  fn diff_prior_policy(&mut self) -> &mut DiffPriorPolicy {
+    &mut self.prior_policy
+  }
+
+  fn tree_policy(&mut self) -> &mut TreePolicy<R=Xorshiftplus128Rng> {
+    &mut self.tree_policy
+  }
+
+  fn exploration_policies(&mut self) -> (&mut PriorPolicy, &mut TreePolicy<R=Xorshiftplus128Rng>) {
+    (&mut self.prior_policy, &mut self.tree_policy)
+  }
+
+  fn rollout_policy(&mut self) -> &mut RolloutPolicy<R=Xorshiftplus128Rng> {
+    &mut self.rollout_policy
+  }
+}
+
+pub struct UniformPriorPolicy;
+
+impl PriorPolicy for UniformPriorPolicy {
+  fn fill_prior_values(&mut self, _state: &TxnState<TxnStateNodeData>, valid_moves: &[Point], prior_values: &mut Vec<(Point, f32)>) {
+    prior_values.clear();
+    let prior = 1.0 / valid_moves.len() as f32;
+    for &point in valid_moves.iter() {
+      prior_values.push((point, prior));
+    }
+  }
+}
+
+impl DiffPriorPolicy for UniformPriorPolicy {
+  fn expose_input_buffer(&mut self, batch_idx: usize) -> &mut [u8] { unimplemented!(); }
+  fn preload_action_label(&mut self, batch_idx: usize, action: Action) { unimplemented!(); }
+  fn preload_loss_weight(&mut self, batch_idx: usize, weight: f32) { unimplemented!(); }
+  fn load_inputs(&mut self, batch_size: usize) { unimplemented!(); }
+  fn forward(&mut self, batch_size: usize) { unimplemented!(); }
+  fn backward(&mut self, batch_size: usize) { unimplemented!(); }
+  fn read_values(&mut self, batch_size: usize) { unimplemented!(); }
+  fn sync_gradients(&mut self, sync_mode: GradSyncMode) { unimplemented!(); }
+  fn reset_gradients(&mut self) { unimplemented!(); }
+  fn descend_params(&mut self, step_size: f32) { unimplemented!(); }
+}
+
+pub struct UniformRolloutPolicy {
+  batch_size:   usize,
+}
+
+impl RolloutPolicy for UniformRolloutPolicy {
+  fn batch_size(&self) -> usize {
+    self.batch_size
+  }
+
+  fn max_rollout_len(&self) -> usize {
+    let max_iters = 361 + 361 / 2 + 1;
+    max_iters
+  }
+
+  fn rollout_batch(&mut self,
+      batch_size:       usize,
+      leafs:            RolloutLeafs,
+      rollout_trajs:    &mut [RolloutTraj],
+      _pass_only:       Option<Stone>,
+      mut trace_batch:  Option<&mut SearchTraceBatch>,
+      rng:              &mut Xorshiftplus128Rng)
+  {
+    assert!(batch_size <= rollout_trajs.len());
+    assert!(_pass_only.is_none());
+
+    for batch_idx in 0 .. batch_size {
+      if !rollout_trajs[batch_idx].rollout {
+        continue;
+      }
+
+      // XXX(20160420): As in the convnet rollouts, the candidate moves are an
+      // upper bound on the valid moves; touched points are added back after
+      // every placement.
+      let mut candidates: Vec<Vec<usize>> = vec![vec![], vec![]];
+      let mut candidate_set: Vec<BitSet> = vec![BitSet::with_capacity(Board::SIZE), BitSet::with_capacity(Board::SIZE)];
+      leafs.with_leaf_state(batch_idx, |leaf_state| {
+        for &turn in [Stone::Black, Stone::White].iter() {
+          for p in leaf_state.get_data().legality.get_legal_points(turn).iter() {
+            candidates[turn.offset()].push(p);
+            candidate_set[turn.offset()].insert(p);
+          }
+        }
+      });
+
+      let mut num_passes = 0;
+      let max_iters = 361 + 361 / 2 + rng.gen_range(0, 2);
+      for _ in 0 .. max_iters {
+        if num_passes >= 2 {
+          break;
+        }
+        let traj = &mut rollout_trajs[batch_idx];
+        let sim_turn = traj.sim_state.current_turn();
+        let sim_turn_off = sim_turn.offset();
+
+        let mut made_move = false;
+        let mut bad_moves = vec![];
+        while let Some(p) = choose_without_replace(&mut candidates[sim_turn_off], rng) {
+          candidate_set[sim_turn_off].remove(p);
+          let sim_point = Point::from_idx(p);
+          if !check_good_move_fast(&traj.sim_state.position, &traj.sim_state.chains, sim_turn, sim_point) {
+            // XXX: Bad moves are not technically illegal.
+            bad_moves.push(p);
+            continue;
+          }
+          if traj.sim_state.try_place(sim_turn, sim_point).is_err() {
+            traj.sim_state.undo();
+            continue;
+          }
+          traj.sim_state.commit();
+          traj.sim_pairs.push((sim_turn, sim_point));
+          if let Some(ref mut trace_batch) = trace_batch {
+            trace_batch.traj_traces[batch_idx]
+              .rollout_trace.actions.push(Action::Place{point: sim_point});
+          }
+          made_move = true;
+          break;
+        }
+        for &p in bad_moves.iter() {
+          candidates[sim_turn_off].push(p);
+          candidate_set[sim_turn_off].insert(p);
+        }
+
+        if made_move {
+          num_passes = 0;
+        } else {
+          if let Some(ref mut trace_batch) = trace_batch {
+            trace_batch.traj_traces[batch_idx]
+              .rollout_trace.actions.push(Action::Pass);
+          }
+          traj.sim_state.try_action(sim_turn, Action::Pass).unwrap();
+          traj.sim_state.commit();
+          num_passes += 1;
+        }
+        for_each_touched_empty(&traj.sim_state.position, &traj.sim_state.chains, |position, _, pt| {
+          let p = pt.idx();
+          if position.stones[p] != Stone::Empty {
+            return;
+          }
+          for turn_off in 0 .. 2 {
+            if !candidate_set[turn_off].contains(p) {
+              candidate_set[turn_off].insert(p);
+              candidates[turn_off].push(p);
+            }
+          }
+        });
+      }
+    }
+  }
+}
@@ -42,7 +42,7 @@ impl SearchTrace {
   //pub fn reset(&mut self, tree_cfg: TreePolicyConfig, root_state: TxnState<TxnStateNodeData>) {
   pub fn start_instance(&mut self, tree_cfg: TreePolicyConfig, root_node: &Node) {
     self.tree_cfg = Some(tree_cfg);
-    self.root_state = Some(root_node.state.clone());
+    self.root_state = Some(root_node.state().clone());
     self.root_trace = Some(TreeExpansionTrace::new(root_node));
     self.root_value = None;
     self.batches.clear();
@@ -24,6 +24,7 @@ use search::parallel_tree::{
   SearchWorkerConfig,
   SearchWorkerBatchConfig,
   SearchWorkerCommand,
+  SearchWorkerGroup,
 };
 use txnstate::{TxnStateConfig, TxnState};
 use txnstate::extras::{TxnStateNodeData};
@@ -725,6 +726,7 @@ impl MetaLevelSearch {
       batch_cfg:            SearchWorkerBatchConfig::Fixed{num_batches: worker_num_batches},
       tree_batch_size:      None,
       rollout_batch_size:   worker_batch_size,
+      early_stop:           false,
     };
 
     match (objective, input) {
@@ -737,6 +739,7 @@ impl MetaLevelSearch {
             server.enqueue(tid, SearchWorkerCommand::ResetSearch{
               cfg:            worker_cfg,
               shared_tree:    shared_tree.clone(),
+              group:          SearchWorkerGroup::all(tid, num_workers),
               init_state:     state.clone(),
               record_search:  true,
             });
@@ -1,6 +1,6 @@
 use array_util::{array_argmax};
 use board::{Board, RuleSet, PlayerRank, Stone, Point, Action};
-use hyper::{load_hyperparam};
+use random::{XorShift128PlusRng};
 use search::{SearchStats, translate_score_to_reward};
 use search::parallel_policies::{
   SearchPolicyWorkerBuilder, SearchPolicyWorker,
@@ -20,10 +20,12 @@ use search::parallel_trace::omega::{
   MetaLevelObjective,
   //MetaLevelWorker,
 };
+use search::report::{PvStepReport};
+use shapes::{ShapeDictionary};
 use txnstate::{
   TxnStateConfig, TxnState,
   //BensonScratch,
-  check_good_move_fast, is_eyelike,
+  check_good_move_fast, for_each_adjacent, is_eyelike,
 };
 use txnstate::extras::{
   TxnStateNodeData,
@@ -41,16 +43,20 @@ use threadpool::{ThreadPool};
 
 use bit_set::{BitSet};
 use rand::{Rng, SeedableRng, thread_rng};
+use rand::distributions::{IndependentSample};
+use rand::distributions::gamma::{Gamma};
 use std::cell::{RefCell, Ref, RefMut};
 use std::cmp::{max, min};
 use std::iter::{repeat};
 use std::marker::{PhantomData};
+use std::mem::{size_of};
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 use std::rc::{Rc};
 use std::sync::{Arc, Barrier, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, fence};
 use std::sync::mpsc::{Sender, Receiver, channel};
+use std::thread;
 use time::{get_time};
 use vec_map::{VecMap};
 
@@ -66,28 +72,6 @@ use vec_map::{VecMap};
   }
 }*/
 
-pub struct HyperparamConfig {
-  pub prior:        bool,
-  pub prior_equiv:  f32,
-  pub pwide:        bool,
-  pub pwide_mu:     f32,
-  pub rave:         bool,
-  pub rave_equiv:   f32,
-}
-
-impl HyperparamConfig {
-  pub fn new() -> HyperparamConfig {
-    HyperparamConfig{
-      prior:        load_hyperparam("prior"),
-      prior_equiv:  load_hyperparam("prior_equiv"),
-      pwide:        load_hyperparam("pwide"),
-      pwide_mu:     load_hyperparam("pwide_mu"),
-      rave:         load_hyperparam("rave"),
-      rave_equiv:   load_hyperparam("rave_equiv"),
-    }
-  }
-}
-
 #[derive(Clone, Copy, Debug)]
 pub struct TreePolicyConfig {
   pub horizon_cfg:  HorizonConfig,
@@ -96,7 +80,25 @@ pub struct TreePolicyConfig {
   pub prior_equiv:  f32,
   pub rave:         bool,
   pub rave_equiv:   f32,
-  pub virtual_loss: bool,
+  /// Each visit in flight through an arm counts as this many lost trials in
+  /// the tree policy, to spread out concurrent workers; 0 disables it.
+  pub virtual_loss: f32,
+}
+
+impl TreePolicyConfig {
+  /// The small fixed horizon configuration of the tests and tools which
+  /// search with the GPU-free uniform policies.
+  pub fn test_default() -> TreePolicyConfig {
+    TreePolicyConfig{
+      horizon_cfg:  HorizonConfig::Fixed{max_horizon: 20},
+      visit_thresh: 1,
+      mc_scale:     1.0,
+      prior_equiv:  16.0,
+      rave:         false,
+      rave_equiv:   0.0,
+      virtual_loss: 1.0,
+    }
+  }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -110,6 +112,11 @@ pub enum HorizonConfig {
 pub struct TreeTraj {
   pub backup_triples: Vec<(Arc<RwLock<Node>>, Point, usize)>,
   pub leaf_node:      Option<Arc<RwLock<Node>>>,
+  /// Whether the traversal expanded a new leaf node.
+  pub expanded:       bool,
+  /// Estimated bytes allocated by the traversal, for new nodes and restored
+  /// node states.
+  pub alloc_bytes:    usize,
 }
 
 impl TreeTraj {
@@ -117,12 +124,16 @@ impl TreeTraj {
     TreeTraj{
       backup_triples: vec![],
       leaf_node:      None,
+      expanded:       false,
+      alloc_bytes:    0,
     }
   }
 
   pub fn reset(&mut self) {
     self.backup_triples.clear();
     self.leaf_node = None;
+    self.expanded = false;
+    self.alloc_bytes = 0;
   }
 }
 
@@ -219,6 +230,19 @@ impl RolloutTraj {
     self.mc_live_mask[1].clear();
   }
 
+  /// Resets to a rollout which was simulated and scored elsewhere, e.g. by a
+  /// remote worker, for backup.
+  pub fn reset_remote(&mut self, sim_pairs: &[(Stone, Point)], score: f32) {
+    self.rollout = true;
+    self.sim_pairs.clear();
+    self.sim_pairs.extend_from_slice(sim_pairs);
+    self.score = Some(score);
+    self.rave_mask[0].clear();
+    self.rave_mask[1].clear();
+    self.mc_live_mask[0].clear();
+    self.mc_live_mask[1].clear();
+  }
+
   //pub fn score(&mut self, komi: f32, expected_score: f32) {
   pub fn update_score(&mut self, komi: f32) {
     /*if !self.rollout {
@@ -434,6 +458,10 @@ impl NodeValues for RefCell<FloatNodeValues> {
   }
 }
 
+// XXX(20160422): The arm statistics are contiguous atomic arrays, so that the
+// tree policy and the backup only ever take a node's read lock; the write lock
+// is only for expanding children. The prior values are fixed when the node is
+// created.
 pub struct AtomicNodeValues {
   pub prior_values:     Vec<f32>,
   pub horizon:          AtomicUsize,
@@ -441,6 +469,8 @@ pub struct AtomicNodeValues {
   pub total_score:      AtomicUsize,
   pub num_trials:       Vec<AtomicUsize>,
   pub num_succs:        Vec<AtomicUsize>,
+  /// Visits in flight through each arm.
+  pub virtual_losses:   Vec<AtomicUsize>,
   //pub num_raw_succs:    Vec<AtomicUsize>,
   /*pub num_trials_rave:  Vec<AtomicUsize>,
   pub num_succs_rave:   Vec<AtomicUsize>,*/
@@ -472,6 +502,22 @@ impl AtomicNodeValues {
     let j_score = score / trials;
     j_score
   }
+
+  /// Whether `remaining` more trials could make some other arm the most
+  /// visited one.
+  pub fn can_overtake_best(&self, remaining: usize) -> bool {
+    let mut top = [0, 0];
+    for trials in self.num_trials.iter() {
+      let n = trials.load(Ordering::Acquire);
+      if n > top[0] {
+        top[1] = top[0];
+        top[0] = n;
+      } else if n > top[1] {
+        top[1] = n;
+      }
+    }
+    top[1] + remaining >= top[0]
+  }
 }
 
 impl NodeValues for AtomicNodeValues {
@@ -484,6 +530,10 @@ impl NodeValues for AtomicNodeValues {
     for _ in 0 .. num_arms {
       num_succs.push(AtomicUsize::new(0));
     }
+    let mut virtual_losses = Vec::with_capacity(num_arms);
+    for _ in 0 .. num_arms {
+      virtual_losses.push(AtomicUsize::new(0));
+    }
     /*let mut num_raw_succs = Vec::with_capacity(num_arms);
     for _ in 0 .. num_arms {
       num_raw_succs.push(AtomicUsize::new(0));
@@ -537,6 +587,7 @@ impl NodeValues for AtomicNodeValues {
       total_score:      AtomicUsize::new(0),
       num_trials:       num_trials,
       num_succs:        num_succs,
+      virtual_losses:   virtual_losses,
       //num_raw_succs:    num_raw_succs,
       /*num_trials_rave:  num_trials_rave,
       num_succs_rave:   num_succs_rave,*/
@@ -580,9 +631,22 @@ impl NodeValues for AtomicNodeValues {
   }
 }
 
+/// A rough estimate of the heap data of a node state per board point: the
+/// feature planes, the position and chains, and the legality test state. The
+/// history, the superko hashes, and the shape hashes vary in size and are
+/// counted separately (see `Node::state_bytes`).
+const STATE_HEAP_BYTES_PER_POINT: usize = 96;
+
+/// The value below which the principal variation resigns.
+const PV_RESIGN_VALUE: f32 = 0.1;
+
 //pub struct Node<N=ArcNodeBox, V=AtomicNodeValues> where N: NodeBox, V: NodeValues {
 pub struct Node {
-  pub state:        TxnState<TxnStateNodeData>,
+  // XXX(20160422): Interior nodes may be compacted by dropping their state,
+  // which is then replayed from an ancestor when needed (see `replay_state`).
+  // The root node and new leaf nodes always have a state.
+  state:            Option<TxnState<TxnStateNodeData>>,
+  pub turn:         Stone,
   pub valid_moves:  Vec<Point>,
   pub action_idxs:  VecMap<usize>,
   pub child_nodes:  Vec<Option<Arc<RwLock<Node>>>>,
@@ -593,7 +657,11 @@ pub struct Node {
 
 //impl<N, V> Node<N, V> where N: NodeBox, V: NodeValues {
 impl Node {
-  pub fn new(state: TxnState<TxnStateNodeData>, prior_policy: &mut PriorPolicy, horizon_cfg: HorizonConfig) -> Node/*<N, V>*/ {
+  pub fn new(mut state: TxnState<TxnStateNodeData>, prior_policy: &mut PriorPolicy, horizon_cfg: HorizonConfig) -> Node/*<N, V>*/ {
+    // XXX(20160421): Child and rollout states are cloned from node states, so
+    // drop the game history of the root state here.
+    state.disable_history();
+
     let mut valid_moves = vec![];
     state.get_data().legality.fill_legal_points(state.current_turn(), &mut valid_moves);
     let num_arms = valid_moves.len();
@@ -603,35 +671,101 @@ impl Node {
     //let horizon_cfg = HorizonConfig::Fixed{max_horizon: 10};
     //let horizon_cfg = HorizonConfig::Pwide{mu: 1.8};
 
+    let child_nodes: Vec<_> = repeat(None).take(num_arms).collect();
+    let turn = state.current_turn();
+    let mut node = Node{
+      state:        Some(state),
+      turn:         turn,
+      //horizon:      AtomicUsize::new(init_horizon),
+      valid_moves:  valid_moves,
+      child_nodes:  child_nodes,
+      action_idxs:  VecMap::with_capacity(Board::SIZE),
+      values:       AtomicNodeValues::new(num_arms, horizon_cfg),
+      //_marker:  PhantomData,
+    };
+    node.load_priors(prior_policy);
+    node
+  }
+
+  fn load_priors(&mut self, prior_policy: &mut PriorPolicy) {
     // XXX(20151224): Sort moves by descending value for progressive widening.
-    let mut values = AtomicNodeValues::new(num_arms, horizon_cfg);
+    let num_arms = self.valid_moves.len();
     let mut action_priors = Vec::with_capacity(num_arms);
-    prior_policy.fill_prior_values(&state, &valid_moves, &mut action_priors);
+    prior_policy.fill_prior_values(self.state(), &self.valid_moves, &mut action_priors);
     action_priors.sort_by(|left, right| {
       F32InfNan(right.1).cmp(&F32InfNan(left.1))
     });
     //println!("DEBUG: node top actions: {:?}", &action_priors[ .. min(10, action_priors.len())]);
+    self.action_idxs.clear();
     for j in 0 .. num_arms {
-      valid_moves[j] = action_priors[j].0;
-      values.prior_values[j] = action_priors[j].1;
+      self.valid_moves[j] = action_priors[j].0;
+      self.values.prior_values[j] = action_priors[j].1;
+      self.action_idxs.insert(self.valid_moves[j].idx(), j);
     }
+  }
 
-    let child_nodes: Vec<_> = repeat(None).take(num_arms).collect();
-    let mut action_idxs = VecMap::with_capacity(Board::SIZE);
-    for j in 0 .. num_arms {
-      action_idxs.insert(valid_moves[j].idx(), j);
+  /// Replaces the priors of a node whose arms are all still unvisited, e.g.
+  /// with priors evaluated elsewhere after the node was expanded. Returns
+  /// whether the priors were replaced.
+  pub fn try_reset_priors(&mut self, prior_policy: &mut PriorPolicy) -> bool {
+    if self.state.is_none() || self.has_children() {
+      return false;
     }
-    Node{
-      state:        state,
-      //horizon:      AtomicUsize::new(init_horizon),
-      valid_moves:  valid_moves,
-      child_nodes:  child_nodes,
-      action_idxs:  action_idxs,
-      values:       values,
-      //_marker:  PhantomData,
+    for j in 0 .. self.valid_moves.len() {
+      if self.values.num_trials[j].load(Ordering::Acquire) > 0
+          || self.values.virtual_losses[j].load(Ordering::Acquire) > 0
+      {
+        return false;
+      }
+    }
+    self.load_priors(prior_policy);
+    true
+  }
+
+  pub fn state(&self) -> &TxnState<TxnStateNodeData> {
+    match self.state {
+      Some(ref state) => state,
+      None => panic!("FATAL: node state was compacted"),
     }
   }
 
+  pub fn has_state(&self) -> bool {
+    self.state.is_some()
+  }
+
+  pub fn has_children(&self) -> bool {
+    self.child_nodes.iter().any(|child| child.is_some())
+  }
+
+  /// The estimated size of a node state.
+  pub fn state_bytes(state: &TxnState<TxnStateNodeData>) -> usize {
+    let shapes_bytes = match state.get_data().shapes {
+      Some(ref shapes) => shapes.approx_heap_bytes(),
+      None => 0,
+    };
+    size_of::<TxnState<TxnStateNodeData>>()
+        + STATE_HEAP_BYTES_PER_POINT * Board::SIZE
+        + state.approx_history_bytes()
+        + shapes_bytes
+  }
+
+  /// The estimated size of this node, excluding its children.
+  pub fn approx_bytes(&self) -> usize {
+    let num_arms = self.valid_moves.len();
+    let mut bytes = size_of::<Node>()
+        + Board::SIZE * size_of::<Option<usize>>()
+        + num_arms * (
+            size_of::<Point>()
+            + size_of::<Option<Arc<RwLock<Node>>>>()
+            + size_of::<f32>()
+            + 4 * size_of::<AtomicUsize>()
+        );
+    if let Some(ref state) = self.state {
+      bytes += Node::state_bytes(state);
+    }
+    bytes
+  }
+
   pub fn is_terminal(&self) -> bool {
     self.valid_moves.is_empty()
   }
@@ -649,16 +783,16 @@ impl Node {
     }
   }
 
-  pub fn update_virtual_loss(&self) {
-    // XXX(20160413): Optionally, apply virtual loss.
-    self.values.total_trials.fetch_add(1, Ordering::AcqRel);
+  pub fn add_virtual_loss(&self, j: usize) {
+    self.values.virtual_losses[j].fetch_add(1, Ordering::AcqRel);
   }
 
-  pub fn update_visits(&self, horizon_cfg: HorizonConfig, virtual_loss: bool, score: f32) {
-    // XXX(20160413): Optionally, apply virtual loss.
-    if !virtual_loss {
-      self.values.total_trials.fetch_add(1, Ordering::AcqRel);
-    }
+  pub fn revert_virtual_loss(&self, j: usize) {
+    self.values.virtual_losses[j].fetch_sub(1, Ordering::AcqRel);
+  }
+
+  pub fn update_visits(&self, horizon_cfg: HorizonConfig, score: f32) {
+    self.values.total_trials.fetch_add(1, Ordering::AcqRel);
 
     let uint_score = (2.0 * score).round() as isize as usize;
     self.values.total_score.fetch_add(uint_score, Ordering::AcqRel);
@@ -692,7 +826,7 @@ impl Node {
     let uint_score = (2.0 * score).round() as isize as usize;
     self.values.scores[j].fetch_add(uint_score, Ordering::AcqRel);
 
-    let turn = self.state.current_turn();
+    let turn = self.turn;
     if (Stone::White == turn && score >= 0.0) ||
         (Stone::Black == turn && score < 0.0)
     {
@@ -767,44 +901,74 @@ impl Node {
   }
 }
 
-pub fn search_principal_variation(init_node: Arc<RwLock<Node>>, depth: usize) -> Vec<(Stone, Action, usize, usize, f32, f32)> {
+/// Follows the most visited arms from `init_node`, recording at each step
+/// the runner-up and why the move was chosen. A move whose value is below
+/// `PV_RESIGN_VALUE` becomes a resign and ends the variation.
+pub fn search_principal_variation(init_node: Arc<RwLock<Node>>, depth: usize) -> Vec<PvStepReport> {
   let mut pv = vec![];
   let mut node = init_node;
-  for t in 0 .. depth {
-    let mut next_node = None;
-    {
+  for _ in 0 .. depth {
+    let next_node = {
       let node = node.read().unwrap();
-      let turn = node.state.current_turn();
-      //let score = node.values.mean_score();
-      let total_trials = node.values.total_trials.load(Ordering::Acquire);
-      let num_trials = node.values.num_trials_float();
-      let (action, arm_trials, arm_score, raw_value) = if let Some(argmax_j) = array_argmax(&num_trials) {
-        next_node = node.child_nodes[argmax_j].clone();
-        let argmax_point = node.valid_moves[argmax_j];
-        let j_score = node.values.arm_score(argmax_j);
-        let j_trials = num_trials[argmax_j];
-        //let j_raw_succs = node.values.num_raw_succs[argmax_j].load(Ordering::Acquire);
-        let j_succs = node.values.num_succs[argmax_j].load(Ordering::Acquire);
-        let value = j_succs as f32 / j_trials;
-        //let raw_value = j_raw_succs as f32 / j_trials;
-        //if raw_value < 0.1 {
-        if value < 0.1 {
-          (Action::Resign, j_trials as usize, j_score, value)
+      let node_visits = node.values.total_trials.load(Ordering::Acquire);
+      let mut ranked: Vec<(usize, usize)> = (0 .. node.valid_moves.len())
+        .map(|j| (node.values.num_trials[j].load(Ordering::Acquire), j))
+        .filter(|&(n, _)| n > 0)
+        .collect();
+      ranked.sort_by(|a, b| b.0.cmp(&a.0));
+      if ranked.is_empty() {
+        let reason = if node.valid_moves.is_empty() {
+          "no valid moves".to_string()
         } else {
-          (Action::Place{point: argmax_point}, j_trials as usize, j_score, value)
-        }
+          "no visited moves".to_string()
+        };
+        pv.push(PvStepReport{
+          turn:         node.turn,
+          action:       "pass".to_string(),
+          node_visits:  node_visits,
+          visits:       0,
+          value:        0.5,
+          mean_score:   0.0,
+          reason:       reason,
+        });
+        break;
+      }
+      let (visits, j) = ranked[0];
+      let wins = node.values.num_succs[j].load(Ordering::Acquire);
+      let value = wins as f32 / visits as f32;
+      let mut reason = format!("most visits: {} of {} ({:.1}%)",
+          visits, node_visits, 100.0 * visits as f32 / node_visits as f32);
+      if ranked.len() >= 2 {
+        let (next_visits, next_j) = ranked[1];
+        let next_value = node.values.num_succs[next_j].load(Ordering::Acquire) as f32 / next_visits as f32;
+        reason.push_str(&format!("; runner-up: {} with {} visits, value {:.3}",
+            node.valid_moves[next_j].to_coord().to_string(), next_visits, next_value));
       } else {
-        (Action::Pass, 0, 0.0, 0.5)
+        reason.push_str("; only visited move");
+      }
+      let action = if value < PV_RESIGN_VALUE {
+        reason.push_str(&format!("; value {:.3} is below {:.2}, so resign", value, PV_RESIGN_VALUE));
+        "resign".to_string()
+      } else {
+        node.valid_moves[j].to_coord().to_string()
       };
-      pv.push((turn, action, total_trials, arm_trials, arm_score, raw_value));
-      if action == Action::Resign || action == Action::Pass {
+      pv.push(PvStepReport{
+        turn:         node.turn,
+        action:       action,
+        node_visits:  node_visits,
+        visits:       visits,
+        value:        value,
+        mean_score:   node.values.arm_score(j),
+        reason:       reason,
+      });
+      if value < PV_RESIGN_VALUE {
         break;
       }
+      node.child_nodes[j].clone()
     };
-    if let Some(n) = next_node {
-      node = n;
-    } else {
-      break;
+    match next_node {
+      Some(next_node) => node = next_node,
+      None => break,
     }
   }
   pv
@@ -859,11 +1023,204 @@ pub enum TreeResult {
   Terminal,
 }
 
+/// Dirichlet noise mixed into the root prior values, for exploration during
+/// self-play.
+#[derive(Clone, Copy, Debug)]
+pub struct RootNoiseConfig {
+  pub dirichlet_alpha:  f32,
+  pub noise_frac:       f32,
+}
+
+struct RootNoisePriorPolicy<'a, R> where R: 'a + Rng {
+  noise_cfg:    RootNoiseConfig,
+  prior_policy: &'a mut PriorPolicy,
+  // The worker rng, so that seeded searches are reproducible.
+  rng:          &'a mut R,
+}
+
+impl<'a, R> PriorPolicy for RootNoisePriorPolicy<'a, R> where R: 'a + Rng {
+  fn fill_prior_values(&mut self, state: &TxnState<TxnStateNodeData>, valid_moves: &[Point], prior_values: &mut Vec<(Point, f32)>) {
+    self.prior_policy.fill_prior_values(state, valid_moves, prior_values);
+    if prior_values.is_empty() {
+      return;
+    }
+    let gamma = Gamma::new(self.noise_cfg.dirichlet_alpha as f64, 1.0);
+    let mut etas = Vec::with_capacity(prior_values.len());
+    let mut eta_sum = 0.0;
+    for _ in 0 .. prior_values.len() {
+      let eta = gamma.ind_sample(&mut *self.rng) as f32;
+      etas.push(eta);
+      eta_sum += eta;
+    }
+    if !(eta_sum > 0.0) {
+      return;
+    }
+    let frac = self.noise_cfg.noise_frac;
+    for (j, &mut (_, ref mut prior)) in prior_values.iter_mut().enumerate() {
+      *prior = (1.0 - frac) * *prior + frac * etas[j] / eta_sum;
+    }
+  }
+}
+
+/// Mixes the shape dictionary play rates into the prior values. The search
+/// states should carry shape hashes (`TxnStateNodeData::with_shapes`).
+#[derive(Clone)]
+pub struct ShapePriorConfig {
+  pub dict:     Arc<ShapeDictionary>,
+  pub weight:   f32,
+}
+
+struct ShapePriorPolicy<'a> {
+  shape_cfg:    ShapePriorConfig,
+  prior_policy: &'a mut PriorPolicy,
+}
+
+impl<'a> PriorPolicy for ShapePriorPolicy<'a> {
+  fn fill_prior_values(&mut self, state: &TxnState<TxnStateNodeData>, valid_moves: &[Point], prior_values: &mut Vec<(Point, f32)>) {
+    self.prior_policy.fill_prior_values(state, valid_moves, prior_values);
+    let turn = state.current_turn();
+    let dict = &self.shape_cfg.dict;
+    let shapes = state.get_data().shapes.as_ref();
+    let mut prior_sum = 0.0;
+    for &mut (point, ref mut prior) in prior_values.iter_mut() {
+      // XXX(20160421): The hashes are only maintained if the root state was
+      // created with `TxnStateNodeData::with_shapes`; otherwise compute them
+      // from scratch.
+      let bonus = match shapes {
+        Some(shapes) => dict.shape_bonus(shapes, turn, point),
+        None => dict.match_bonus(state, turn, point),
+      };
+      *prior += self.shape_cfg.weight * bonus;
+      prior_sum += *prior;
+    }
+    if !(prior_sum > 0.0) {
+      return;
+    }
+    for &mut (_, ref mut prior) in prior_values.iter_mut() {
+      *prior /= prior_sum;
+    }
+  }
+}
+
+/// Biases the prior values of `turn` toward capturing moves and moves next
+/// to `dead_stones`, for the cleanup phase.
+#[derive(Clone)]
+pub struct CleanupPriorConfig {
+  pub turn:         Stone,
+  pub dead_stones:  Arc<Vec<Point>>,
+  pub weight:       f32,
+}
+
+struct CleanupPriorPolicy<'a> {
+  cleanup_cfg:  CleanupPriorConfig,
+  prior_policy: &'a mut PriorPolicy,
+}
+
+impl<'a> PriorPolicy for CleanupPriorPolicy<'a> {
+  fn fill_prior_values(&mut self, state: &TxnState<TxnStateNodeData>, valid_moves: &[Point], prior_values: &mut Vec<(Point, f32)>) {
+    self.prior_policy.fill_prior_values(state, valid_moves, prior_values);
+    let turn = state.current_turn();
+    if turn != self.cleanup_cfg.turn {
+      return;
+    }
+    let opp_turn = turn.opponent();
+    let mut prior_sum = 0.0;
+    for &mut (point, ref mut prior) in prior_values.iter_mut() {
+      if state.is_capture(opp_turn, point) {
+        *prior += self.cleanup_cfg.weight;
+      } else {
+        let mut next_to_dead = false;
+        for_each_adjacent(point, |adj_point| {
+          if state.current_stone(adj_point) == opp_turn && self.cleanup_cfg.dead_stones.contains(&adj_point) {
+            next_to_dead = true;
+          }
+        });
+        if next_to_dead {
+          *prior += 0.5 * self.cleanup_cfg.weight;
+        }
+      }
+      prior_sum += *prior;
+    }
+    if !(prior_sum > 0.0) {
+      return;
+    }
+    for &mut (_, ref mut prior) in prior_values.iter_mut() {
+      *prior /= prior_sum;
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TreeMemoryConfig {
+  /// When the estimated size of the tree exceeds the budget, it is brought
+  /// down to three quarters of the budget.
+  pub budget_bytes:     usize,
+  /// Whether to first drop the states of the least visited inner nodes,
+  /// before pruning the least visited subtrees.
+  pub compact_states:   bool,
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+struct TreeGcStats {
+  num_compacted:    usize,
+  num_pruned:       usize,
+  freed_bytes:      usize,
+}
+
+/// How a search stopped, recorded by the first worker.
+#[derive(Clone, Copy, Default, Debug)]
+struct SearchStopInfo {
+  num_rollouts:     usize,
+  num_expanded:     usize,
+  stopped_early:    bool,
+  saved_rollouts:   usize,
+  saved_ms:         usize,
+}
+
+/// Calls `f` with `prior_policy` wrapped in the given shape and cleanup
+/// priors. The configs are passed in rather than read from a `SharedTree`, so
+/// that callers which hold the tree lock can use it too.
+fn with_prior_wrappers<U, F>(shape_prior: Option<ShapePriorConfig>, cleanup_prior: Option<CleanupPriorConfig>, prior_policy: &mut PriorPolicy, f: F) -> U
+where F: FnOnce(&mut PriorPolicy) -> U {
+  let mut shape_policy;
+  let prior_policy: &mut PriorPolicy = match shape_prior {
+    Some(shape_cfg) => {
+      shape_policy = ShapePriorPolicy{
+        shape_cfg:    shape_cfg,
+        prior_policy: prior_policy,
+      };
+      &mut shape_policy
+    }
+    None => prior_policy,
+  };
+  let mut cleanup_policy;
+  let prior_policy: &mut PriorPolicy = match cleanup_prior {
+    Some(cleanup_cfg) => {
+      cleanup_policy = CleanupPriorPolicy{
+        cleanup_cfg:  cleanup_cfg,
+        prior_policy: prior_policy,
+      };
+      &mut cleanup_policy
+    }
+    None => prior_policy,
+  };
+  f(prior_policy)
+}
+
 /*pub struct UniqueTree<N> where N: NodeBox {
   root_node:        Option<N>,
   mean_raw_score:   f32,
 }*/
 
+/// Bins of the rollout scores from the root with the search komi taken out,
+/// in half points.
+const NUM_ROOT_SCORE_BINS: usize = 4 * Board::SIZE + 1;
+
+fn root_score_bin(score: f32, komi: f32) -> usize {
+  let half_points = (2.0 * (score - komi)).round() as isize + 2 * Board::SIZE as isize;
+  max(0, min(NUM_ROOT_SCORE_BINS as isize - 1, half_points)) as usize
+}
+
 struct InnerTree {
   //root_node:        Option<Arc<RwLock<Node<ArcNodeBox, AtomicNodeValues>>>>,
   root_node:        Option<Arc<RwLock<Node>>>,
@@ -871,9 +1228,22 @@ struct InnerTree {
   mean_raw_score:   f32,
   rollout_count:    Arc<AtomicUsize>,
   mc_live_counts:   Vec<Arc<Vec<AtomicUsize>>>,
+  // Rollouts of the current search by `root_score_bin`, so that the root can
+  // be valued under another komi without searching again.
+  root_score_counts:    Arc<Vec<AtomicUsize>>,
 
   explore_elapsed_ms:   AtomicUsize,
   rollout_elapsed_ms:   AtomicUsize,
+  expand_count:     Arc<AtomicUsize>,
+  stop_info:        SearchStopInfo,
+  // The estimated size of the nodes in the tree.
+  node_bytes:       Arc<AtomicUsize>,
+  memory_cfg:       Option<TreeMemoryConfig>,
+  gc_stats:         TreeGcStats,
+
+  root_noise:       Option<RootNoiseConfig>,
+  shape_prior:      Option<ShapePriorConfig>,
+  cleanup_prior:    Option<CleanupPriorConfig>,
 }
 
 #[derive(Clone)]
@@ -904,9 +1274,19 @@ impl SharedTree {
           Arc::new(b_mc_live_counts),
           Arc::new(w_mc_live_counts),
         ],
+        root_score_counts:  Arc::new((0 .. NUM_ROOT_SCORE_BINS).map(|_| AtomicUsize::new(0)).collect()),
 
         explore_elapsed_ms: AtomicUsize::new(0),
         rollout_elapsed_ms: AtomicUsize::new(0),
+        expand_count:   Arc::new(AtomicUsize::new(0)),
+        stop_info:      Default::default(),
+        node_bytes:     Arc::new(AtomicUsize::new(0)),
+        memory_cfg:     None,
+        gc_stats:       Default::default(),
+
+        root_noise:     None,
+        shape_prior:    None,
+        cleanup_prior:  None,
       })),
 
       // XXX(20160208): HACK: This is where the horizon policy is specified.
@@ -919,16 +1299,270 @@ impl SharedTree {
     }
   }
 
-  pub fn try_reset(&self, init_state: TxnState<TxnStateNodeData>, prior_policy: &mut PriorPolicy) {
+  pub fn with_root_noise(tree_cfg: TreePolicyConfig, noise_cfg: RootNoiseConfig) -> SharedTree {
+    let tree = SharedTree::new(tree_cfg);
+    tree.inner.lock().unwrap().root_noise = Some(noise_cfg);
+    tree
+  }
+
+  pub fn with_shape_prior(tree_cfg: TreePolicyConfig, shape_cfg: ShapePriorConfig) -> SharedTree {
+    let tree = SharedTree::new(tree_cfg);
+    tree.inner.lock().unwrap().shape_prior = Some(shape_cfg);
+    tree
+  }
+
+  pub fn shape_prior(&self) -> Option<ShapePriorConfig> {
+    self.inner.lock().unwrap().shape_prior.clone()
+  }
+
+  /// Only affects nodes expanded afterwards, so it should be set on a new
+  /// tree.
+  pub fn set_cleanup_prior(&self, cleanup_cfg: CleanupPriorConfig) {
+    self.inner.lock().unwrap().cleanup_prior = Some(cleanup_cfg);
+  }
+
+  pub fn cleanup_prior(&self) -> Option<CleanupPriorConfig> {
+    self.inner.lock().unwrap().cleanup_prior.clone()
+  }
+
+  pub fn tree_cfg(&self) -> TreePolicyConfig {
+    self.tree_cfg
+  }
+
+  /// Panics if the tree has not been reset yet.
+  pub fn root_node(&self) -> Arc<RwLock<Node>> {
+    match self.inner.lock().unwrap().root_node {
+      Some(ref root_node) => root_node.clone(),
+      None => panic!("FATAL: shared tree has no root node"),
+    }
+  }
+
+  /// Calls `f` with `prior_policy` wrapped in the shape and cleanup priors of
+  /// the tree, as the search workers do.
+  pub fn with_tree_priors<U, F>(&self, prior_policy: &mut PriorPolicy, f: F) -> U
+  where F: FnOnce(&mut PriorPolicy) -> U {
+    let (shape_prior, cleanup_prior) = {
+      let inner = self.inner.lock().unwrap();
+      (inner.shape_prior.clone(), inner.cleanup_prior.clone())
+    };
+    with_prior_wrappers(shape_prior, cleanup_prior, prior_policy, f)
+  }
+
+  /// Clears the counters of the previous search.
+  pub fn reset_search_counters(&self) {
+    let mut inner = self.inner.lock().unwrap();
+    inner.rollout_count.store(0, Ordering::Release);
+    inner.expand_count.store(0, Ordering::Release);
+    inner.gc_stats = Default::default();
+    for p in 0 .. Board::SIZE {
+      inner.mc_live_counts[0][p].store(0, Ordering::Release);
+      inner.mc_live_counts[1][p].store(0, Ordering::Release);
+    }
+    for count in inner.root_score_counts.iter() {
+      count.store(0, Ordering::Release);
+    }
+  }
+
+  /// Accounts for the nodes expanded and the memory allocated by a
+  /// traversal which was not run by a search worker.
+  pub fn record_traversal(&self, tree_traj: &TreeTraj) {
+    let inner = self.inner.lock().unwrap();
+    if tree_traj.expanded {
+      inner.expand_count.fetch_add(1, Ordering::AcqRel);
+    }
+    if tree_traj.alloc_bytes > 0 {
+      inner.node_bytes.fetch_add(tree_traj.alloc_bytes, Ordering::AcqRel);
+    }
+  }
+
+  /// Accounts for rollouts which were not run by a search worker, given
+  /// their scores under `komi` and the Monte Carlo live stone counts summed
+  /// over them.
+  pub fn record_rollouts(&self, komi: f32, scores: &[f32], mc_live_counts: &[Vec<usize>]) {
+    let inner = self.inner.lock().unwrap();
+    inner.rollout_count.fetch_add(scores.len(), Ordering::AcqRel);
+    for &score in scores.iter() {
+      inner.root_score_counts[root_score_bin(score, komi)].fetch_add(1, Ordering::AcqRel);
+    }
+    for p in 0 .. Board::SIZE {
+      inner.mc_live_counts[0][p].fetch_add(mc_live_counts[0][p], Ordering::AcqRel);
+      inner.mc_live_counts[1][p].fetch_add(mc_live_counts[1][p], Ordering::AcqRel);
+    }
+  }
+
+  /// The rollouts of the last search which `turn` would have won with
+  /// `komi` instead of the search komi, and the number of rollouts. This
+  /// values the root, not its best move.
+  pub fn root_wins_with_komi(&self, turn: Stone, komi: f32) -> (usize, usize) {
+    let inner = self.inner.lock().unwrap();
+    let mut num_wins = 0;
+    let mut num_rollouts = 0;
+    for (bin, count) in inner.root_score_counts.iter().enumerate() {
+      let count = count.load(Ordering::Acquire);
+      let score = 0.5 * (bin as isize - 2 * Board::SIZE as isize) as f32 + komi;
+      // Same as `update_arm`: white wins ties.
+      let win = match turn {
+        Stone::White => score >= 0.0,
+        _ => score < 0.0,
+      };
+      if win {
+        num_wins += count;
+      }
+      num_rollouts += count;
+    }
+    (num_wins, num_rollouts)
+  }
+
+  /// Records the end of a search which was not run by the search workers.
+  pub fn record_search_end(&self, num_rollouts: usize) {
+    let mut inner = self.inner.lock().unwrap();
+    inner.stop_info = SearchStopInfo{
+      num_rollouts: num_rollouts,
+      num_expanded: inner.expand_count.load(Ordering::Acquire),
+      .. Default::default()
+    };
+  }
+
+  pub fn set_memory_cfg(&self, memory_cfg: TreeMemoryConfig) {
+    self.inner.lock().unwrap().memory_cfg = Some(memory_cfg);
+  }
+
+  /// The estimated size of the tree.
+  pub fn memory_bytes(&self) -> usize {
+    self.inner.lock().unwrap().node_bytes.load(Ordering::Acquire)
+  }
+
+  /// If the tree is over its memory budget, compacts the least visited inner
+  /// nodes and then prunes the least visited subtrees until it is back to
+  /// three quarters of the budget. Must not run concurrently with traversals.
+  pub fn collect_garbage(&self) {
+    let mut inner = self.inner.lock().unwrap();
+    let memory_cfg = match inner.memory_cfg {
+      Some(memory_cfg) => memory_cfg,
+      None => return,
+    };
+    let total_bytes = inner.node_bytes.load(Ordering::Acquire);
+    if total_bytes <= memory_cfg.budget_bytes {
+      return;
+    }
+    let target_bytes = memory_cfg.budget_bytes / 4 * 3;
+    let root_node = match inner.root_node {
+      Some(ref root_node) => root_node.clone(),
+      None => return,
+    };
+
+    // Every node but the root, with its number of visits.
+    let mut nodes = vec![];
+    {
+      let mut stack = vec![root_node.clone()];
+      while let Some(node) = stack.pop() {
+        let node = node.read().unwrap();
+        for child in node.child_nodes.iter() {
+          if let Some(ref child) = *child {
+            let visits = child.read().unwrap().values.total_trials.load(Ordering::Acquire);
+            nodes.push((visits, child.clone()));
+            stack.push(child.clone());
+          }
+        }
+      }
+    }
+    nodes.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut stats: TreeGcStats = Default::default();
+    if memory_cfg.compact_states {
+      for &(_, ref node) in nodes.iter() {
+        if total_bytes.saturating_sub(stats.freed_bytes) <= target_bytes {
+          break;
+        }
+        let mut node = node.write().unwrap();
+        if !node.has_children() {
+          continue;
+        }
+        if let Some(state) = node.state.take() {
+          stats.num_compacted += 1;
+          stats.freed_bytes += Node::state_bytes(&state);
+        }
+      }
+    }
+
+    if total_bytes.saturating_sub(stats.freed_bytes) > target_bytes {
+      // Visit counts only decrease going down the tree, so the nodes with
+      // fewer visits than the threshold form whole subtrees.
+      let mut visit_thresh = 0;
+      let mut cut_bytes = 0;
+      for &(visits, ref node) in nodes.iter() {
+        if total_bytes.saturating_sub(stats.freed_bytes + cut_bytes) <= target_bytes {
+          break;
+        }
+        cut_bytes += node.read().unwrap().approx_bytes();
+        visit_thresh = visits + 1;
+      }
+      let mut pruned = vec![];
+      let mut stack = vec![root_node.clone()];
+      while let Some(node) = stack.pop() {
+        let mut node = node.write().unwrap();
+        for child in node.child_nodes.iter_mut() {
+          let prune = match *child {
+            Some(ref child_node) => child_node.read().unwrap().values.total_trials.load(Ordering::Acquire) < visit_thresh,
+            None => continue,
+          };
+          if prune {
+            pruned.push(child.take().unwrap());
+          } else {
+            stack.push(child.as_ref().unwrap().clone());
+          }
+        }
+      }
+      for subtree in pruned.iter() {
+        let (num_nodes, bytes) = subtree_size(subtree);
+        stats.num_pruned += num_nodes;
+        stats.freed_bytes += bytes;
+      }
+      free_in_background(pruned);
+    }
+
+    let freed_bytes = min(stats.freed_bytes, total_bytes);
+    inner.node_bytes.fetch_sub(freed_bytes, Ordering::AcqRel);
+    inner.gc_stats.num_compacted += stats.num_compacted;
+    inner.gc_stats.num_pruned += stats.num_pruned;
+    inner.gc_stats.freed_bytes += stats.freed_bytes;
+    println!("DEBUG: tree gc: size: {} -> {} bytes compacted: {} pruned: {}",
+        total_bytes, total_bytes - freed_bytes, stats.num_compacted, stats.num_pruned);
+  }
+
+  /// Expands the root node from `init_state` if the tree has none; `rng`
+  /// draws the root noise.
+  pub fn try_reset<R>(&self, init_state: TxnState<TxnStateNodeData>, prior_policy: &mut PriorPolicy, rng: &mut R) where R: Rng {
     let mut inner = self.inner.lock().unwrap();
     if inner.root_node.is_none() {
-      inner.root_node = Some(Arc::new(RwLock::new(Node::new(init_state, prior_policy, self.tree_cfg.horizon_cfg))));
+      // XXX(20160422): `inner` stays locked, so the priors are wrapped from
+      // clones of its configs rather than by `with_tree_priors`.
+      let horizon_cfg = self.tree_cfg.horizon_cfg;
+      let root_noise = inner.root_noise;
+      let root_node = with_prior_wrappers(inner.shape_prior.clone(), inner.cleanup_prior.clone(), prior_policy, |prior_policy| {
+        match root_noise {
+          None => Node::new(init_state, prior_policy, horizon_cfg),
+          Some(noise_cfg) => {
+            let mut noise_policy = RootNoisePriorPolicy{
+              noise_cfg:    noise_cfg,
+              prior_policy: prior_policy,
+              rng:          rng,
+            };
+            Node::new(init_state, &mut noise_policy, horizon_cfg)
+          }
+        }
+      });
+      inner.node_bytes.store(root_node.approx_bytes(), Ordering::Release);
+      inner.root_node = Some(Arc::new(RwLock::new(root_node)));
       inner.mean_raw_score = 0.0;
       inner.rollout_count.store(0, Ordering::Release);
       for p in 0 .. Board::SIZE {
         inner.mc_live_counts[0][p].store(0, Ordering::Release);
         inner.mc_live_counts[1][p].store(0, Ordering::Release);
       }
+      for count in inner.root_score_counts.iter() {
+        count.store(0, Ordering::Release);
+      }
       // FIXME(20160223): should reset other stats here too.
     }
   }
@@ -942,6 +1576,11 @@ impl SharedTree {
       }
       Action::Place{point} => {
         let mut inner = self.inner.lock().unwrap();
+        if inner.root_noise.is_some() {
+          // XXX(20160420): Child nodes were expanded without noise, so do not
+          // reuse them as the next root.
+          return false;
+        }
         let maybe_next_node = {
           let p = point.idx();
           let maybe_root_node = inner.root_node.as_ref().map(|n| n.read().unwrap());
@@ -950,7 +1589,7 @@ impl SharedTree {
             return false;
           }
           let root_node = maybe_root_node.unwrap();
-          let root_turn = root_node.state.current_turn();
+          let root_turn = root_node.turn;
           if root_turn != turn {
             println!("WARNING: try_advance: root node turn is {:?} but attempted turn is {:?}",
                 root_turn, turn);
@@ -962,13 +1601,36 @@ impl SharedTree {
             return false;
           }
           let j = maybe_j.unwrap();
-          root_node.child_nodes[j].clone()
+          root_node.child_nodes[j].clone().map(|n| (n, j))
         };
         if maybe_next_node.is_none() {
           //println!("DEBUG: try_advance: j-th child node does not exist yet");
           return false;
         }
-        inner.root_node = maybe_next_node;
+        let (next_node, j) = maybe_next_node.unwrap();
+        let prev_root_node = inner.root_node.take().unwrap();
+        {
+          // The root must have a state; the previous root always does.
+          let mut prev_root_node = prev_root_node.write().unwrap();
+          let mut next_root_node = next_node.write().unwrap();
+          if next_root_node.state.is_none() {
+            let mut state = prev_root_node.state().clone();
+            match state.try_place(turn, point) {
+              Ok(_) => state.commit(),
+              Err(e) => panic!("FATAL: try_advance: illegal move: {:?}", e),
+            }
+            inner.node_bytes.fetch_add(Node::state_bytes(&state), Ordering::AcqRel);
+            next_root_node.state = Some(state);
+          }
+          prev_root_node.child_nodes[j] = None;
+        }
+        inner.root_node = Some(next_node);
+        // XXX(20160422): Free the rest of the previous tree off the move path.
+        // Its size is subtracted here, so the tree size is exact as soon as
+        // this returns; only the drop itself is left to the background.
+        let freed_bytes = subtree_size(&prev_root_node).1;
+        inner.node_bytes.fetch_sub(freed_bytes, Ordering::AcqRel);
+        free_in_background(vec![prev_root_node]);
         true
       }
     }
@@ -992,6 +1654,57 @@ impl SharedTree {
       rng: &mut Xorshiftplus128Rng);
 }*/
 
+/// The number of nodes and the estimated size of a subtree.
+fn subtree_size(root_node: &Arc<RwLock<Node>>) -> (usize, usize) {
+  let mut num_nodes = 0;
+  let mut bytes = 0;
+  let mut stack = vec![root_node.clone()];
+  while let Some(node) = stack.pop() {
+    let node = node.read().unwrap();
+    num_nodes += 1;
+    bytes += node.approx_bytes();
+    for child in node.child_nodes.iter() {
+      if let Some(ref child) = *child {
+        stack.push(child.clone());
+      }
+    }
+  }
+  (num_nodes, bytes)
+}
+
+/// Drops detached subtrees in a background thread, so that the caller does
+/// not wait on freeing them. The caller accounts for their size.
+fn free_in_background(subtrees: Vec<Arc<RwLock<Node>>>) {
+  if subtrees.is_empty() {
+    return;
+  }
+  thread::spawn(move || {
+    drop(subtrees);
+  });
+}
+
+/// Reconstructs the state of the node reached by `path` from its nearest
+/// ancestor which still has a state; the root always does.
+fn replay_state(path: &[(Arc<RwLock<Node>>, Point, usize)]) -> TxnState<TxnStateNodeData> {
+  let start = match path.iter().rposition(|&(ref node, _, _)| node.read().unwrap().has_state()) {
+    Some(start) => start,
+    None => panic!("FATAL: replay_state: no ancestor has a state"),
+  };
+  let mut state = path[start].0.read().unwrap().state().clone();
+  for &(_, point, _) in path[start .. ].iter() {
+    let turn = state.current_turn();
+    match state.try_place(turn, point) {
+      Ok(_) => {
+        state.commit();
+      }
+      Err(e) => {
+        panic!("FATAL: replay_state: illegal move: {:?}", e);
+      }
+    }
+  }
+  state
+}
+
 pub struct TreeOps;
 
 impl TreeOps {
@@ -1019,79 +1732,107 @@ impl TreeOps {
       if ply >= 20 {
         break;
       }
-      let cursor_trials = cursor_node.read().unwrap().values.total_trials.load(Ordering::Acquire);
-      if cursor_trials < tree_cfg.visit_thresh {
-        // Not enough trials, stop the walk and do a rollout.
-        //stats.old_leaf_count += 1;
-        break;
-      //if cursor_trials >= 1 {
-      } else {
+      // XXX(20160422): Each step of the walk holds the cursor node's read lock
+      // once, for the trials check, the tree policy, and the child lookup.
+      let (res, horizon, maybe_child, has_state) = {
+        let cursor_node = cursor_node.read().unwrap();
+        let cursor_trials = cursor_node.values.total_trials.load(Ordering::Acquire);
+        if cursor_trials < tree_cfg.visit_thresh {
+          // Not enough trials, stop the walk and do a rollout.
+          //stats.old_leaf_count += 1;
+          break;
+        }
         // Try to walk through the current node using the exploration policy.
         //stats.edge_count += 1;
-        let (res, horizon) = {
-          let cursor_node = cursor_node.read().unwrap();
-          let (res, horizon) = tree_policy.execute_search(&*cursor_node, rng);
-          if tree_cfg.virtual_loss {
-            cursor_node.update_virtual_loss();
+        let (res, horizon) = tree_policy.execute_search(&*cursor_node, rng);
+        let maybe_child = match res {
+          Some((_, j)) => {
+            if tree_cfg.virtual_loss > 0.0 {
+              cursor_node.add_virtual_loss(j);
+            }
+            cursor_node.child_nodes[j].clone()
           }
-          (res, horizon)
+          None => None,
         };
-        match res {
-          Some((place_point, j)) => {
-            tree_traj.backup_triples.push((cursor_node.clone(), place_point, j));
-            if let Some(ref mut tree_trace) = tree_trace {
-              tree_trace.decisions.push(TreeDecisionTrace::new(Action::Place{point: place_point}, j, horizon));
-            }
-            let has_child = cursor_node.read().unwrap().child_nodes[j].is_some();
-            if has_child {
-              // Existing inner node, simply update the cursor.
-              let child_node = cursor_node.read().unwrap().child_nodes[j].as_ref().unwrap().clone();
-              cursor_node = child_node;
-              //stats.inner_edge_count += 1;
+        (res, horizon, maybe_child, cursor_node.has_state())
+      };
+      match res {
+        Some((place_point, j)) => {
+          tree_traj.backup_triples.push((cursor_node.clone(), place_point, j));
+          if let Some(ref mut tree_trace) = tree_trace {
+            tree_trace.decisions.push(TreeDecisionTrace::new(Action::Place{point: place_point}, j, horizon));
+          }
+          if let Some(child_node) = maybe_child {
+            // Existing inner node, simply update the cursor.
+            cursor_node = child_node;
+            //stats.inner_edge_count += 1;
+          } else {
+            let restored_state = if !has_state {
+              let path_len = tree_traj.backup_triples.len() - 1;
+              Some(replay_state(&tree_traj.backup_triples[ .. path_len]))
             } else {
-              let leaf_node = {
-                // XXX(20160111): Try to insert a new leaf node, but check for a
-                // race if another thread has done so first.
-                let mut cursor_node = cursor_node.write().unwrap();
-                if cursor_node.child_nodes[j].is_none() {
-                  // Create a new leaf node and stop the walk.
-                  let mut leaf_state = cursor_node.state.clone();
-                  let turn = leaf_state.current_turn();
-                  match leaf_state.try_place(turn, place_point) {
-                    Ok(_) => {
-                      leaf_state.commit();
-                    }
-                    Err(e) => {
-                      // XXX: this means the legal moves features gave an incorrect result.
-                      panic!("walk failed due to illegal move: {:?}", e);
-                    }
+              None
+            };
+            let leaf_node = {
+              // XXX(20160111): Try to insert a new leaf node, but check for a
+              // race if another thread has done so first.
+              let mut cursor_node = cursor_node.write().unwrap();
+              if let Some(state) = restored_state {
+                if cursor_node.state.is_none() {
+                  tree_traj.alloc_bytes += Node::state_bytes(&state);
+                  cursor_node.state = Some(state);
+                }
+              }
+              if cursor_node.child_nodes[j].is_none() {
+                // Create a new leaf node and stop the walk.
+                let mut leaf_state = cursor_node.state().clone();
+                let turn = leaf_state.current_turn();
+                match leaf_state.try_place(turn, place_point) {
+                  Ok(_) => {
+                    leaf_state.commit();
                   }
-                  let inner_leaf_node = Node::new(leaf_state, prior_policy, tree_cfg.horizon_cfg);
-                  if let Some(ref mut tree_trace) = tree_trace {
-                    tree_trace.expansion = Some(TreeExpansionTrace::new(&inner_leaf_node));
+                  Err(e) => {
+                    // XXX: this means the legal moves features gave an incorrect result.
+                    panic!("walk failed due to illegal move: {:?}", e);
                   }
-                  let mut leaf_node = Arc::new(RwLock::new(inner_leaf_node));
-                  cursor_node.child_nodes[j] = Some(leaf_node.clone());
-                  //stats.new_leaf_count += 1;
-                  leaf_node
-                } else {
-                  cursor_node.child_nodes[j].as_ref().unwrap().clone()
                 }
-              };
-              cursor_node = leaf_node;
-              break;
-            }
-          }
-          None => {
-            // Terminal node, stop the walk.
-            //stats.term_edge_count += 1;
+                let inner_leaf_node = Node::new(leaf_state, prior_policy, tree_cfg.horizon_cfg);
+                if let Some(ref mut tree_trace) = tree_trace {
+                  tree_trace.expansion = Some(TreeExpansionTrace::new(&inner_leaf_node));
+                }
+                tree_traj.alloc_bytes += inner_leaf_node.approx_bytes();
+                let mut leaf_node = Arc::new(RwLock::new(inner_leaf_node));
+                cursor_node.child_nodes[j] = Some(leaf_node.clone());
+                tree_traj.expanded = true;
+                //stats.new_leaf_count += 1;
+                leaf_node
+              } else {
+                cursor_node.child_nodes[j].as_ref().unwrap().clone()
+              }
+            };
+            cursor_node = leaf_node;
             break;
           }
         }
+        None => {
+          // Terminal node, stop the walk.
+          //stats.term_edge_count += 1;
+          break;
+        }
       }
     }
 
     //stats.max_ply = max(stats.max_ply, ply);
+    // The walk may stop at a compacted inner node, whose state the rollout
+    // needs.
+    if !cursor_node.read().unwrap().has_state() {
+      let state = replay_state(&tree_traj.backup_triples);
+      let mut cursor_node = cursor_node.write().unwrap();
+      if cursor_node.state.is_none() {
+        tree_traj.alloc_bytes += Node::state_bytes(&state);
+        cursor_node.state = Some(state);
+      }
+    }
     tree_traj.leaf_node = Some(cursor_node.clone());
     let terminal = cursor_node.read().unwrap().is_terminal();
     if terminal {
@@ -1104,6 +1845,14 @@ impl TreeOps {
     }
   }
 
+  /// Undoes the virtual losses added by a traversal whose rollout will never
+  /// be backed up.
+  pub fn revert_virtual_losses(tree_traj: &TreeTraj) {
+    for &(ref node, _, update_j) in tree_traj.backup_triples.iter() {
+      node.read().unwrap().revert_virtual_loss(update_j);
+    }
+  }
+
   pub fn backup(
       use_rave:         bool,
       komi:             f32,
@@ -1123,7 +1872,10 @@ impl TreeOps {
       Some(adj_score) => adj_score,
       None => panic!("missing adj score for backup!"),
     };*/
-    rollout_traj.update_score(komi);
+    // Remote rollouts arrive already scored.
+    if rollout_traj.score.is_none() {
+      rollout_traj.update_score(komi);
+    }
     //rollout_traj.update_mc_live_mask();
 
     let score = match rollout_traj.score {
@@ -1143,7 +1895,7 @@ impl TreeOps {
     if rollout_traj.sim_pairs.len() >= 1 {
       assert!(rollout_traj.rollout);
       let leaf_node = tree_traj.leaf_node.as_ref().unwrap().read().unwrap();
-      let leaf_turn = leaf_node.state.current_turn();
+      let leaf_turn = leaf_node.turn;
 
       // XXX(20151120): Currently not tracking pass pairs, so need to check that
       // the first rollout pair belongs to the leaf node turn.
@@ -1190,14 +1942,14 @@ impl TreeOps {
 
     {
       let leaf_node = tree_traj.leaf_node.as_ref().unwrap().read().unwrap();
-      leaf_node.update_visits(horizon_cfg, virtual_loss, score);
+      leaf_node.update_visits(horizon_cfg, score);
     }
 
     for (i, &(ref node, update_point, update_j)) in tree_traj.backup_triples.iter().enumerate().rev() {
       let node = node.read().unwrap();
 
       assert_eq!(update_point, node.valid_moves[update_j]);
-      let update_turn = node.state.current_turn();
+      let update_turn = node.turn;
       //node.update_arm(update_j, adj_score[update_turn.offset()], raw_score);
       node.update_arm(update_j, score);
 
@@ -1223,7 +1975,10 @@ impl TreeOps {
         }
       }
 
-      node.update_visits(horizon_cfg, virtual_loss, score);
+      if virtual_loss {
+        node.revert_virtual_loss(update_j);
+      }
+      node.update_visits(horizon_cfg, score);
     }
   }
 }
@@ -1399,6 +2154,7 @@ pub enum SearchWorkerCommand {
   ResetSearch{
     cfg:            SearchWorkerConfig,
     shared_tree:    SharedTree,
+    group:          SearchWorkerGroup,
     init_state:     TxnState<TxnStateNodeData>,
     record_search:  bool,
   },
@@ -1466,6 +2222,9 @@ pub struct SearchWorkerConfig {
   pub batch_cfg:    SearchWorkerBatchConfig,
   pub tree_batch_size:      Option<usize>,
   pub rollout_batch_size:   usize,
+  /// Stop as soon as the remaining budget cannot change the most visited
+  /// root move.
+  pub early_stop:   bool,
   //pub batch_size:   usize,
   //pub num_batches:      usize,
   //pub komi:         f32,
@@ -1478,6 +2237,116 @@ pub struct SearchWorkerConfig {
 pub enum SearchWorkerBatchConfig {
   Fixed{num_batches: usize},
   TimeLimit{budget_ms: usize, tol_ms: usize},
+  /// Stops once this many new nodes have been expanded.
+  NodeLimit{max_nodes: usize},
+}
+
+/// The workers searching the same tree. Tree parallel searches put every
+/// worker in one group; root parallel searches split the workers into one
+/// group per tree.
+#[derive(Clone)]
+pub struct SearchWorkerGroup {
+  /// The leader resets the tree counters, collects garbage, and decides when
+  /// its tree has used up the budget.
+  pub leader:       bool,
+  pub num_workers:  usize,
+  /// Seeds the worker RNGs for one search so that the groups sample
+  /// independently; each worker mixes in its own id.
+  pub rng_seed:     Option<[u64; 2]>,
+  /// The barrier and stop signal of the group, if it runs apart from the
+  /// other groups; otherwise all workers share those of the server.
+  pub sync:         Option<SearchGroupSync>,
+}
+
+impl SearchWorkerGroup {
+  pub fn all(tid: usize, num_workers: usize) -> SearchWorkerGroup {
+    SearchWorkerGroup{
+      leader:       tid == 0,
+      num_workers:  num_workers,
+      rng_seed:     None,
+      sync:         None,
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct SearchGroupSync {
+  pub barrier:      Arc<Barrier>,
+  pub stop_signal:  Arc<AtomicBool>,
+  /// The index of the group in `early_stop`.
+  pub group_idx:    usize,
+  /// Decides early stopping over all of the groups, if they are merged.
+  pub early_stop:   Option<Arc<MergedEarlyStop>>,
+}
+
+const UNKNOWN_REMAINING: usize = ::std::usize::MAX;
+
+/// Early stopping for root parallel searches: the leader of each group
+/// publishes the budget its tree has left, and the search stops once no
+/// other move could overtake the most visited move summed over the trees.
+pub struct MergedEarlyStop {
+  trees:        Vec<SharedTree>,
+  // Unknown until the leader of the group has finished a batch.
+  remaining:    Vec<AtomicUsize>,
+  stop_signals: Vec<Arc<AtomicBool>>,
+}
+
+impl MergedEarlyStop {
+  pub fn new(trees: Vec<SharedTree>, stop_signals: Vec<Arc<AtomicBool>>) -> MergedEarlyStop {
+    assert_eq!(trees.len(), stop_signals.len());
+    MergedEarlyStop{
+      remaining:    (0 .. trees.len()).map(|_| AtomicUsize::new(UNKNOWN_REMAINING)).collect(),
+      trees:        trees,
+      stop_signals: stop_signals,
+    }
+  }
+
+  /// Records the rollouts group `k` has left, and returns whether the merged
+  /// best move is decided. The trees are only read once every group has
+  /// recorded its budget, by when they all have a root.
+  pub fn update(&self, k: usize, remaining: usize) -> bool {
+    self.remaining[k].store(remaining, Ordering::Release);
+    let mut total_remaining = 0;
+    for r in self.remaining.iter() {
+      let r = r.load(Ordering::Acquire);
+      if r == UNKNOWN_REMAINING {
+        return false;
+      }
+      total_remaining += r;
+    }
+    let mut visits: Vec<usize> = repeat(0).take(Board::SIZE).collect();
+    for tree in self.trees.iter() {
+      let root_node = tree.root_node();
+      let root_node = root_node.read().unwrap();
+      for j in 0 .. root_node.valid_moves.len() {
+        visits[root_node.valid_moves[j].idx()] += root_node.values.num_trials[j].load(Ordering::Acquire);
+      }
+    }
+    let mut top = [0, 0];
+    for &n in visits.iter() {
+      if n > top[0] {
+        top[1] = top[0];
+        top[0] = n;
+      } else if n > top[1] {
+        top[1] = n;
+      }
+    }
+    top[1] + total_remaining < top[0]
+  }
+
+  /// The rollouts the groups had left when the search stopped.
+  pub fn saved_rollouts(&self) -> usize {
+    self.remaining.iter()
+      .map(|r| r.load(Ordering::Acquire))
+      .filter(|&r| r != UNKNOWN_REMAINING)
+      .fold(0, |acc, r| acc + r)
+  }
+
+  pub fn stop_all(&self) {
+    for stop_signal in self.stop_signals.iter() {
+      stop_signal.store(true, Ordering::Release);
+    }
+  }
 }
 
 pub struct ParallelMonteCarloSearchServer<W> where W: SearchPolicyWorker {
@@ -1627,13 +2496,33 @@ impl<W> ParallelMonteCarloSearchServer<W> where W: SearchPolicyWorker {
               unimplemented!();
             }
 
-            SearchWorkerCommand::ResetSearch{cfg, shared_tree, /*green_stone,*/ init_state, record_search} => {
+            SearchWorkerCommand::ResetSearch{cfg, shared_tree, group, /*green_stone,*/ init_state, record_search} => {
               let timer_start = get_time();
+              let (barrier, shared_signal) = match group.sync {
+                Some(ref sync) => (sync.barrier.clone(), sync.stop_signal.clone()),
+                None => (barrier.clone(), shared_signal.clone()),
+              };
+              let merged_stop = group.sync.as_ref().and_then(|sync| {
+                sync.early_stop.as_ref().map(|early_stop| (sync.group_idx, early_stop.clone()))
+              });
+              // XXX(20160422): The group seed only holds for this search, and
+              // is mixed with the worker id so that the workers of a group do
+              // not draw the same samples.
+              let mut rng = match group.rng_seed {
+                Some(seed) => {
+                  let mix = (tid as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                  let worker_seed = [seed[0] ^ mix, seed[1] ^ mix.rotate_left(32)];
+                  Xorshiftplus128Rng::new(&mut XorShift128PlusRng::from_seed(worker_seed))
+                }
+                None => Xorshiftplus128Rng::new(&mut rng),
+              };
 
               // XXX(20160107): If the tree has no root node, this sets it;
               // otherwise use the existing root node.
               let tree = shared_tree;
-              tree.try_reset(init_state, worker.borrow_mut().prior_policy());
+              tree.try_reset(init_state, worker.borrow_mut().prior_policy(), &mut rng);
+              let shape_prior = tree.shape_prior();
+              let cleanup_prior = tree.cleanup_prior();
 
               // FIXME(20160308): handle different tree and rollout batch sizes.
               // If tree batch size is greater, offload to remote workers.
@@ -1649,8 +2538,8 @@ impl<W> ParallelMonteCarloSearchServer<W> where W: SearchPolicyWorker {
               }
               let tree_cfg = tree.tree_cfg;
 
-              let root_node = {
-                let inner = tree.inner.lock().unwrap();
+              let (root_node, shared_expand_count, shared_node_bytes, shared_root_score_counts) = {
+                let mut inner = tree.inner.lock().unwrap();
 
                 /*if tid == 0 {
                   let shared_mc_live_counts = inner.mc_live_counts.clone();
@@ -1659,17 +2548,26 @@ impl<W> ParallelMonteCarloSearchServer<W> where W: SearchPolicyWorker {
                     shared_mc_live_counts[1][p].store(0, Ordering::Release);
                   }
                 }*/
-                if tid == 0 {
+                if group.leader {
                   inner.rollout_count.store(0, Ordering::Release);
+                  inner.expand_count.store(0, Ordering::Release);
+                  inner.gc_stats = Default::default();
                   for p in 0 .. Board::SIZE {
                     inner.mc_live_counts[0][p].store(0, Ordering::Release);
                     inner.mc_live_counts[1][p].store(0, Ordering::Release);
                   }
+                  for count in inner.root_score_counts.iter() {
+                    count.store(0, Ordering::Release);
+                  }
                 }
 
-                inner.root_node.as_ref().unwrap().clone()
+                ( inner.root_node.as_ref().unwrap().clone(),
+                  inner.expand_count.clone(),
+                  inner.node_bytes.clone(),
+                  inner.root_score_counts.clone(),
+                )
               };
-              let komi = root_node.read().unwrap().state.config.komi;
+              let komi = root_node.read().unwrap().state().config.komi;
 
               let mut explore_elapsed_ms = 0;
               let mut rollout_elapsed_ms = 0;
@@ -1699,7 +2597,7 @@ impl<W> ParallelMonteCarloSearchServer<W> where W: SearchPolicyWorker {
                 }
               }*/
 
-              if tid == 0 {
+              if group.leader {
                 shared_signal.store(false, Ordering::Release);
               }
               barrier.wait();
@@ -1720,40 +2618,48 @@ impl<W> ParallelMonteCarloSearchServer<W> where W: SearchPolicyWorker {
                 {
                   let mut worker = worker.borrow_mut();
                   let (prior_policy, tree_policy) = worker.exploration_policies();
-                  for batch_idx in 0 .. batch_size {
-                    let tree_traj = &mut tree_trajs[batch_idx];
-                    let rollout_traj = &mut rollout_trajs[batch_idx];
-                    let tree_trace = if record_search {
-                      Some(&mut search_trace.batches[batch].traj_traces[batch_idx].tree_trace)
-                    } else {
-                      None
-                    };
-                    match TreeOps::traverse(tree_cfg, root_node.clone(), tree_traj, tree_trace, prior_policy, tree_policy, /*&mut stats,*/ &mut rng) {
-                      /*TreeResult::Terminal => {
-                        let leaf_state = &tree_traj.leaf_node.as_ref().unwrap().read().unwrap().state;
-                        rollout_traj.reset_terminal(leaf_state);
+                  with_prior_wrappers(shape_prior.clone(), cleanup_prior.clone(), prior_policy, |prior_policy| {
+                    for batch_idx in 0 .. batch_size {
+                      let tree_traj = &mut tree_trajs[batch_idx];
+                      let rollout_traj = &mut rollout_trajs[batch_idx];
+                      let tree_trace = if record_search {
+                        Some(&mut search_trace.batches[batch].traj_traces[batch_idx].tree_trace)
+                      } else {
+                        None
+                      };
+                      match TreeOps::traverse(tree_cfg, root_node.clone(), tree_traj, tree_trace, prior_policy, tree_policy, /*&mut stats,*/ &mut rng) {
+                        /*TreeResult::Terminal => {
+                          let leaf_state = &tree_traj.leaf_node.as_ref().unwrap().read().unwrap().state;
+                          rollout_traj.reset_terminal(leaf_state);
+                        }
+                        TreeResult::NonTerminal => {
+                          let leaf_state = &tree_traj.leaf_node.as_ref().unwrap().read().unwrap().state;
+                          rollout_traj.reset_rollout(leaf_state);
+                        }*/
+                        // XXX(20160223): Rolling out whether the leaf state is
+                        // terminal or not. This is because the tree traversal
+                        // does not account for passing at all, whereas rollouts
+                        // do and should be less biased.
+                        TreeResult::NonTerminal | TreeResult::Terminal => {
+                          let leaf_state = tree_traj.leaf_node.as_ref().unwrap().read().unwrap();
+                          rollout_traj.reset_rollout(leaf_state.state());
+                        }
                       }
-                      TreeResult::NonTerminal => {
-                        let leaf_state = &tree_traj.leaf_node.as_ref().unwrap().read().unwrap().state;
-                        rollout_traj.reset_rollout(leaf_state);
-                      }*/
-                      // XXX(20160223): Rolling out whether the leaf state is
-                      // terminal or not. This is because the tree traversal
-                      // does not account for passing at all, whereas rollouts
-                      // do and should be less biased.
-                      TreeResult::NonTerminal | TreeResult::Terminal => {
-                        let leaf_state = &tree_traj.leaf_node.as_ref().unwrap().read().unwrap().state;
-                        rollout_traj.reset_rollout(leaf_state);
+                      if tree_traj.expanded {
+                        shared_expand_count.fetch_add(1, Ordering::AcqRel);
+                      }
+                      if tree_traj.alloc_bytes > 0 {
+                        shared_node_bytes.fetch_add(tree_traj.alloc_bytes, Ordering::AcqRel);
                       }
-                    }
 
-                    /*let triples: Vec<_> = tree_traj.backup_triples.iter().map(|x| (x.1, x.2)).collect();
-                    println!("DEBUG: search: batch: {}/{} batch_idx: {}/{} tree traj: {:?}",
-                        batch, num_batches,
-                        batch_idx, batch_size,
-                        triples,
-                    );*/
-                  }
+                      /*let triples: Vec<_> = tree_traj.backup_triples.iter().map(|x| (x.1, x.2)).collect();
+                      println!("DEBUG: search: batch: {}/{} batch_idx: {}/{} tree traj: {:?}",
+                          batch, num_batches,
+                          batch_idx, batch_size,
+                          triples,
+                      );*/
+                    }
+                  });
                 }
                 barrier.wait();
                 fence(Ordering::AcqRel);
@@ -1789,7 +2695,9 @@ impl<W> ParallelMonteCarloSearchServer<W> where W: SearchPolicyWorker {
                     None
                   };
                   rollout_traj.update_mc_live_counts(&mut mc_live_counts);
-                  TreeOps::backup(use_rave, komi, tree_cfg.horizon_cfg, tree_cfg.virtual_loss, tree_traj, rollout_traj, rollout_trace, &mut rng);
+                  TreeOps::backup(use_rave, komi, tree_cfg.horizon_cfg, tree_cfg.virtual_loss > 0.0, tree_traj, rollout_traj, rollout_trace, &mut rng);
+                  let score_bin = root_score_bin(rollout_traj.score.unwrap(), komi);
+                  shared_root_score_counts[score_bin].fetch_add(1, Ordering::AcqRel);
 
                   /*//let raw_score = rollout_traj.raw_score.unwrap();
                   let score = rollout_traj.score.unwrap();
@@ -1815,30 +2723,81 @@ impl<W> ParallelMonteCarloSearchServer<W> where W: SearchPolicyWorker {
                 }*/
 
                 batch += 1;
-                match cfg.batch_cfg {
-                  SearchWorkerBatchConfig::Fixed{num_batches} => {
-                    if batch >= num_batches {
-                      break;
+                if group.leader {
+                  // The other workers are waiting at the barrier below, so the
+                  // tree can be collected here.
+                  tree.collect_garbage();
+                  let elapsed_ms = (get_time() - timer_start).num_milliseconds() as usize;
+                  let num_rollouts = batch * batch_size * group.num_workers;
+                  let num_expanded = shared_expand_count.load(Ordering::Acquire);
+                  // The remaining budget in rollouts, or None if exhausted; the
+                  // time and node budgets are converted at the current rates.
+                  let remaining = match cfg.batch_cfg {
+                    SearchWorkerBatchConfig::Fixed{num_batches} => {
+                      if batch >= num_batches {
+                        None
+                      } else {
+                        Some((num_batches - batch) * batch_size * group.num_workers)
+                      }
                     }
-                  }
-                  SearchWorkerBatchConfig::TimeLimit{budget_ms, tol_ms} => {
-                    if tid == 0 {
-                      let timer_lap = get_time();
-                      let elapsed_ms = (timer_lap - timer_start).num_milliseconds() as usize;
+                    SearchWorkerBatchConfig::TimeLimit{budget_ms, tol_ms} => {
                       if elapsed_ms + tol_ms > budget_ms {
-                        shared_signal.store(true, Ordering::Release);
+                        println!("DEBUG: server: reached time limit, num batches: {}", batch);
+                        None
+                      } else {
+                        Some((budget_ms - tol_ms - elapsed_ms) * num_rollouts / max(1, elapsed_ms))
                       }
                     }
-                    barrier.wait();
-                    fence(Ordering::AcqRel);
-                    let signal = shared_signal.load(Ordering::Acquire);
-                    if signal {
-                      if tid == 0 {
-                        println!("DEBUG: server: reached time limit, num batches: {}", batch);
+                    SearchWorkerBatchConfig::NodeLimit{max_nodes} => {
+                      if num_expanded >= max_nodes {
+                        println!("DEBUG: server: reached node limit, num batches: {}", batch);
+                        None
+                      } else {
+                        Some((max_nodes - num_expanded) * num_rollouts / max(1, num_expanded))
+                      }
+                    }
+                  };
+                  let mut stop_info = SearchStopInfo{
+                    num_rollouts:   num_rollouts,
+                    num_expanded:   num_expanded,
+                    .. Default::default()
+                  };
+                  match remaining {
+                    None => {
+                      if let Some((k, ref merged_stop)) = merged_stop {
+                        merged_stop.update(k, 0);
+                      }
+                      shared_signal.store(true, Ordering::Release);
+                    }
+                    Some(remaining) => {
+                      // With merged groups, the best move is decided by the
+                      // visits summed over all of the trees.
+                      let decided = match merged_stop {
+                        Some((k, ref merged_stop)) => {
+                          let decided = merged_stop.update(k, remaining);
+                          if cfg.early_stop && decided {
+                            merged_stop.stop_all();
+                          }
+                          decided
+                        }
+                        None => !root_node.read().unwrap().values.can_overtake_best(remaining),
+                      };
+                      if cfg.early_stop && decided {
+                        println!("DEBUG: server: best move is decided, num batches: {} saved rollouts: {}",
+                            batch, remaining);
+                        stop_info.stopped_early = true;
+                        stop_info.saved_rollouts = remaining;
+                        stop_info.saved_ms = remaining * elapsed_ms / max(1, num_rollouts);
+                        shared_signal.store(true, Ordering::Release);
                       }
-                      break;
                     }
                   }
+                  tree.inner.lock().unwrap().stop_info = stop_info;
+                }
+                barrier.wait();
+                fence(Ordering::AcqRel);
+                if shared_signal.load(Ordering::Acquire) {
+                  break;
                 }
               }
 
@@ -2193,20 +3152,39 @@ pub struct MonteCarloSearchResult {
   pub w_alive_ch:       usize,
   pub w_alive_ter:      usize,*/
   pub top_prior_values: Vec<(Action, f32)>,
-  pub pv:               Vec<(Stone, Action, usize, usize, f32, f32)>,
+  pub pv:               Vec<PvStepReport>,
   pub dead_stones:      Vec<Vec<Point>>,
   pub live_stones:      Vec<Vec<Point>>,
   pub territory:        Vec<Vec<Point>>,
   pub outcome:          Option<Stone>,
+  pub root_visits:      Vec<(Point, usize)>,
+  /// The komi the search scored with, which may be a dynamic komi.
+  pub komi:             f32,
 }
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, RustcEncodable, Debug)]
 pub struct MonteCarloSearchStats {
   pub argmax_rank:      Option<usize>,
   pub argmax_ntrials:   usize,
   pub elapsed_ms:       usize,
   pub avg_explore_elapsed_ms:   usize,
   pub avg_rollout_elapsed_ms:   usize,
+  pub num_rollouts:     usize,
+  /// New nodes expanded by this search.
+  pub num_expanded:     usize,
+  /// Whether the search stopped before its budget because no other move
+  /// could have overtaken the most visited one.
+  pub stopped_early:    bool,
+  /// The budget left unspent by stopping early, as estimated rollouts and
+  /// milliseconds.
+  pub saved_rollouts:   usize,
+  pub saved_ms:         usize,
+  /// The estimated size of the tree after the search.
+  pub tree_bytes:       usize,
+  /// Inner nodes compacted and nodes pruned by garbage collection during the
+  /// search.
+  pub num_compacted:    usize,
+  pub num_pruned:       usize,
 }
 
 /*#[derive(Default)]
@@ -2305,6 +3283,7 @@ impl ParallelMonteCarloSearch {
       server.enqueue(tid, SearchWorkerCommand::ResetSearch{
         cfg:            worker_cfg,
         shared_tree:    shared_tree.clone(),
+        group:          SearchWorkerGroup::all(tid, num_workers),
         //green_stone:    Stone::White, // FIXME FIXME FIXME(20160208): use correct color.
         init_state:     init_state.clone(),
         record_search:  false,
@@ -2314,198 +3293,385 @@ impl ParallelMonteCarloSearch {
     let end_time = get_time();
     let elapsed_ms = (end_time - start_time).num_milliseconds();
 
-    let mut stats: MonteCarloSearchStats = Default::default();
-    stats.elapsed_ms = elapsed_ms as usize;
-
-    /*let root_node_opt = tree.root_node.read().unwrap();
-    let root_node = root_node_opt.as_ref().unwrap().read().unwrap();*/
-    let (root_node, /*mean_raw_score,*/ shared_rollout_count, shared_mc_live_counts) = {
-      let inner_tree = shared_tree.inner.lock().unwrap();
-
-      stats.avg_explore_elapsed_ms = inner_tree.explore_elapsed_ms.load(Ordering::Acquire) / num_workers;
-      stats.avg_rollout_elapsed_ms = inner_tree.rollout_elapsed_ms.load(Ordering::Acquire) / num_workers;
-
-      // FIXME(20160219): should reset these above.
-      inner_tree.explore_elapsed_ms.store(0, Ordering::Release);
-      inner_tree.rollout_elapsed_ms.store(0, Ordering::Release);
-
-      ( inner_tree.root_node.as_ref().unwrap().clone(),
-        //inner_tree.mean_raw_score,
-        inner_tree.rollout_count.clone(),
-        inner_tree.mc_live_counts.clone(),
-      )
-    };
-
-    // FIXME(20160308): count monte carlo dead/alive;
-    // requires number of trajectories.
-    // FIXME(20160315): track live/dead stones (required for Category A).
-    let mut b_mc_alive = 0;
-    let mut w_mc_alive = 0;
-    let mut dead_stones = vec![vec![], vec![]];
-    let mut live_stones = vec![vec![], vec![]];
-    let mut territory = vec![vec![], vec![]];
-    let mut outcome = None;
-    //let live_thresh = (0.9 * (worker_batch_size * worker_num_batches * num_workers) as f32).ceil() as usize;
-    {
-      let root_node = root_node.read().unwrap();
-      //let rollout_count = root_node.values.total_trials.load(Ordering::Acquire);
-      let rollout_count = shared_rollout_count.load(Ordering::Acquire);
-      let live_thresh = (0.9 * rollout_count as f32).ceil() as usize;
-      /*println!("DEBUG: liveness counting: rollout count: {}, thresh: {}, mc count[B][0]: {}",
-          rollout_count,
-          live_thresh,
-          shared_mc_live_counts[0][0].load(Ordering::Acquire),
-      );*/
-      for p in 0 .. Board::SIZE {
-        let pt = Point::from_idx(p);
-        if shared_mc_live_counts[0][p].load(Ordering::Acquire) >= live_thresh {
-          b_mc_alive += 1;
-          match root_node.state.current_stone(pt) {
-            Stone::Black => live_stones[0].push(pt),
-            Stone::White => territory[0].push(pt),
-            Stone::Empty => territory[0].push(pt),
-          }
-        } else {
-          match root_node.state.current_stone(pt) {
-            Stone::Black => dead_stones[0].push(pt),
-            _ => {}
-          }
-        }
-        if shared_mc_live_counts[1][p].load(Ordering::Acquire) >= live_thresh {
-          w_mc_alive += 1;
-          match root_node.state.current_stone(pt) {
-            Stone::Black => territory[1].push(pt),
-            Stone::White => live_stones[1].push(pt),
-            Stone::Empty => territory[1].push(pt),
-          }
-        } else {
-          match root_node.state.current_stone(pt) {
-            Stone::White => dead_stones[1].push(pt),
-            _ => {}
-          }
-        }
-      }
-      if w_mc_alive as f32 - b_mc_alive as f32 + 6.5 < 0.0 {
-        outcome = Some(Stone::Black);
-      } else if w_mc_alive as f32 - b_mc_alive as f32 + 6.5 < 0.0 {
-        outcome = Some(Stone::White);
-      }
-    }
-
-    let root_pv = search_principal_variation(root_node.clone(), 3);
+    search_result(&shared_tree, num_workers, elapsed_ms as usize)
+  }
+}
 
+/// Summarizes the root of a finished search; `num_workers` is the number of
+/// workers which searched the tree.
+pub fn search_result(shared_tree: &SharedTree, num_workers: usize, elapsed_ms: usize) -> (MonteCarloSearchResult, MonteCarloSearchStats) {
+  let mut stats: MonteCarloSearchStats = Default::default();
+  stats.elapsed_ms = elapsed_ms;
+
+  /*let root_node_opt = tree.root_node.read().unwrap();
+  let root_node = root_node_opt.as_ref().unwrap().read().unwrap();*/
+  let (root_node, /*mean_raw_score,*/ shared_rollout_count, shared_mc_live_counts) = {
+    let inner_tree = shared_tree.inner.lock().unwrap();
+
+    stats.avg_explore_elapsed_ms = inner_tree.explore_elapsed_ms.load(Ordering::Acquire) / num_workers;
+    stats.avg_rollout_elapsed_ms = inner_tree.rollout_elapsed_ms.load(Ordering::Acquire) / num_workers;
+    let stop_info = inner_tree.stop_info;
+    stats.num_rollouts = stop_info.num_rollouts;
+    stats.num_expanded = stop_info.num_expanded;
+    stats.stopped_early = stop_info.stopped_early;
+    stats.saved_rollouts = stop_info.saved_rollouts;
+    stats.saved_ms = stop_info.saved_ms;
+    stats.tree_bytes = inner_tree.node_bytes.load(Ordering::Acquire);
+    stats.num_compacted = inner_tree.gc_stats.num_compacted;
+    stats.num_pruned = inner_tree.gc_stats.num_pruned;
+
+    // FIXME(20160219): should reset these above.
+    inner_tree.explore_elapsed_ms.store(0, Ordering::Release);
+    inner_tree.rollout_elapsed_ms.store(0, Ordering::Release);
+
+    ( inner_tree.root_node.as_ref().unwrap().clone(),
+      //inner_tree.mean_raw_score,
+      inner_tree.rollout_count.clone(),
+      inner_tree.mc_live_counts.clone(),
+    )
+  };
+
+  // FIXME(20160308): count monte carlo dead/alive;
+  // requires number of trajectories.
+  // FIXME(20160315): track live/dead stones (required for Category A).
+  let mut b_mc_alive = 0;
+  let mut w_mc_alive = 0;
+  let mut dead_stones = vec![vec![], vec![]];
+  let mut live_stones = vec![vec![], vec![]];
+  let mut territory = vec![vec![], vec![]];
+  let mut outcome = None;
+  let komi = root_node.read().unwrap().state().config.komi;
+  // The rollouts are scored with the handicap komi as well.
+  let total_komi = komi + root_node.read().unwrap().state().handicap_komi();
+  //let live_thresh = (0.9 * (worker_batch_size * worker_num_batches * num_workers) as f32).ceil() as usize;
+  {
     let root_node = root_node.read().unwrap();
-    let root_turn = root_node.state.current_turn();
-
-    let mut top_prior_values = Vec::with_capacity(5);
-    for k in 0 .. min(5, root_node.valid_moves.len()) {
-      let pt = Action::Place{point: root_node.valid_moves[k]};
-      let prior = root_node.values.prior_values[k];
-      top_prior_values.push((pt, prior));
-    }
-
-    /*let mut scratch = BensonScratch::new();
-    let (b_alive_ch, b_alive_ter) = root_node.state.count_unconditionally_alive(Stone::Black, &mut scratch);
-    let (w_alive_ch, w_alive_ter) = root_node.state.count_unconditionally_alive(Stone::White, &mut scratch);*/
-
-    // XXX(20160209): Maximize the winning rate rather than the number of plays.
-    // The argument is that it should be highly unlikely for an arm with high
-    // value to be played only a small number of times.
-    // FIXME(20160209): the above, except in the case of progressive widening
-    // horizon, where there _may_ be such arms; a hacky remedy may be to stop
-    // widening at some fixed fraction of the total rollout number.
-    //let root_ratios = root_node.values.succ_ratios_float();
-    //let (action, adj_value, raw_value) = if let Some(argmax_j) = array_argmax(&root_ratios) {
-    let root_trials = root_node.values.num_trials_float();
-    let (action, expected_score, value) = if let Some(argmax_j) = array_argmax(&root_trials) {
-      stats.argmax_rank = Some(argmax_j);
-      stats.argmax_ntrials = root_trials[argmax_j] as usize;
-
-      let argmax_point = root_node.valid_moves[argmax_j];
-      let j_score = root_node.values.arm_score(argmax_j);
-      let j_adj_succs = root_node.values.num_succs[argmax_j].load(Ordering::Acquire);
-      //let j_raw_succs = root_node.values.num_raw_succs[argmax_j].load(Ordering::Acquire);
-      let j_trials = root_trials[argmax_j];
-      let value = j_adj_succs as f32 / j_trials;
-      //let raw_value = j_raw_succs as f32 / j_trials;
-
-      /*if root_turn == Stone::Black && -(b_mc_alive as f32) + cfg.komi < 0.0 {
-        (Action::Pass, j_score, value, raw_value)
-      } else if root_turn == Stone::White && w_mc_alive as f32 + cfg.komi >= 0.0 {
-        (Action::Pass, j_score, value, raw_value)
-      } else */
-
-      //if raw_value < 0.1 {
-      if value < 0.1 {
-        (Action::Resign, j_score, value)
-      } else {
-        (Action::Place{point: argmax_point}, j_score, value)
-      }
-    } else {
-      stats.argmax_rank = None;
-      (Action::Pass, 0.0, 0.5)
-    };
-
-    // FIXME(20160318): update the dead/alive stones stats for the just placed
-    // point.
-    if let Action::Place{point} = action {
-      //let rollout_count = root_node.values.total_trials.load(Ordering::Acquire);
-      let rollout_count = shared_rollout_count.load(Ordering::Acquire);
-      let live_thresh = (0.9 * rollout_count as f32).ceil() as usize;
-      let p = point.idx();
+    //let rollout_count = root_node.values.total_trials.load(Ordering::Acquire);
+    let rollout_count = shared_rollout_count.load(Ordering::Acquire);
+    let live_thresh = (0.9 * rollout_count as f32).ceil() as usize;
+    /*println!("DEBUG: liveness counting: rollout count: {}, thresh: {}, mc count[B][0]: {}",
+        rollout_count,
+        live_thresh,
+        shared_mc_live_counts[0][0].load(Ordering::Acquire),
+    );*/
+    for p in 0 .. Board::SIZE {
+      let pt = Point::from_idx(p);
       if shared_mc_live_counts[0][p].load(Ordering::Acquire) >= live_thresh {
         b_mc_alive += 1;
-        match root_turn {
-          Stone::Black => live_stones[0].push(point),
-          Stone::White => territory[0].push(point),
-          _ => {}
+        match root_node.state().current_stone(pt) {
+          Stone::Black => live_stones[0].push(pt),
+          Stone::White => territory[0].push(pt),
+          Stone::Empty => territory[0].push(pt),
         }
       } else {
-        match root_turn {
-          Stone::Black => dead_stones[0].push(point),
+        match root_node.state().current_stone(pt) {
+          Stone::Black => dead_stones[0].push(pt),
           _ => {}
         }
       }
       if shared_mc_live_counts[1][p].load(Ordering::Acquire) >= live_thresh {
         w_mc_alive += 1;
-        match root_turn {
-          Stone::Black => territory[1].push(point),
-          Stone::White => live_stones[1].push(point),
-          _ => {}
+        match root_node.state().current_stone(pt) {
+          Stone::Black => territory[1].push(pt),
+          Stone::White => live_stones[1].push(pt),
+          Stone::Empty => territory[1].push(pt),
         }
       } else {
-        match root_turn {
-          Stone::White => dead_stones[1].push(point),
+        match root_node.state().current_stone(pt) {
+          Stone::White => dead_stones[1].push(pt),
           _ => {}
         }
       }
     }
-    if w_mc_alive as f32 - b_mc_alive as f32 + 6.5 < 0.0 {
+    if w_mc_alive as f32 - b_mc_alive as f32 + total_komi < 0.0 {
       outcome = Some(Stone::Black);
-    } else if w_mc_alive as f32 - b_mc_alive as f32 + 6.5 > 0.0 {
+    } else if w_mc_alive as f32 - b_mc_alive as f32 + total_komi > 0.0 {
       outcome = Some(Stone::White);
     }
+  }
+
+  let root_pv = search_principal_variation(root_node.clone(), 3);
+
+  let root_node = root_node.read().unwrap();
+  let root_turn = root_node.turn;
+
+  let mut top_prior_values = Vec::with_capacity(5);
+  for k in 0 .. min(5, root_node.valid_moves.len()) {
+    let pt = Action::Place{point: root_node.valid_moves[k]};
+    let prior = root_node.values.prior_values[k];
+    top_prior_values.push((pt, prior));
+  }
+
+  let mut root_visits = Vec::with_capacity(root_node.valid_moves.len());
+  for j in 0 .. root_node.valid_moves.len() {
+    let n_j = root_node.values.num_trials[j].load(Ordering::Acquire);
+    root_visits.push((root_node.valid_moves[j], n_j));
+  }
+
+  /*let mut scratch = BensonScratch::new();
+  let (b_alive_ch, b_alive_ter) = root_node.state.count_unconditionally_alive(Stone::Black, &mut scratch);
+  let (w_alive_ch, w_alive_ter) = root_node.state.count_unconditionally_alive(Stone::White, &mut scratch);*/
+
+  // XXX(20160209): Maximize the winning rate rather than the number of plays.
+  // The argument is that it should be highly unlikely for an arm with high
+  // value to be played only a small number of times.
+  // FIXME(20160209): the above, except in the case of progressive widening
+  // horizon, where there _may_ be such arms; a hacky remedy may be to stop
+  // widening at some fixed fraction of the total rollout number.
+  //let root_ratios = root_node.values.succ_ratios_float();
+  //let (action, adj_value, raw_value) = if let Some(argmax_j) = array_argmax(&root_ratios) {
+  let root_trials = root_node.values.num_trials_float();
+  let (action, expected_score, value) = if let Some(argmax_j) = array_argmax(&root_trials) {
+    stats.argmax_rank = Some(argmax_j);
+    stats.argmax_ntrials = root_trials[argmax_j] as usize;
+
+    let argmax_point = root_node.valid_moves[argmax_j];
+    let j_score = root_node.values.arm_score(argmax_j);
+    let j_adj_succs = root_node.values.num_succs[argmax_j].load(Ordering::Acquire);
+    //let j_raw_succs = root_node.values.num_raw_succs[argmax_j].load(Ordering::Acquire);
+    let j_trials = root_trials[argmax_j];
+    let value = j_adj_succs as f32 / j_trials;
+    //let raw_value = j_raw_succs as f32 / j_trials;
+
+    // XXX(20160422): Resigning and passing are up to the caller, see
+    // `search::decision`.
+    (Action::Place{point: argmax_point}, j_score, value)
+  } else {
+    stats.argmax_rank = None;
+    (Action::Pass, 0.0, 0.5)
+  };
+
+  // FIXME(20160318): update the dead/alive stones stats for the just placed
+  // point.
+  if let Action::Place{point} = action {
+    //let rollout_count = root_node.values.total_trials.load(Ordering::Acquire);
+    let rollout_count = shared_rollout_count.load(Ordering::Acquire);
+    let live_thresh = (0.9 * rollout_count as f32).ceil() as usize;
+    let p = point.idx();
+    if shared_mc_live_counts[0][p].load(Ordering::Acquire) >= live_thresh {
+      b_mc_alive += 1;
+      match root_turn {
+        Stone::Black => live_stones[0].push(point),
+        Stone::White => territory[0].push(point),
+        _ => {}
+      }
+    } else {
+      match root_turn {
+        Stone::Black => dead_stones[0].push(point),
+        _ => {}
+      }
+    }
+    if shared_mc_live_counts[1][p].load(Ordering::Acquire) >= live_thresh {
+      w_mc_alive += 1;
+      match root_turn {
+        Stone::Black => territory[1].push(point),
+        Stone::White => live_stones[1].push(point),
+        _ => {}
+      }
+    } else {
+      match root_turn {
+        Stone::White => dead_stones[1].push(point),
+        _ => {}
+      }
+    }
+  }
+  if w_mc_alive as f32 - b_mc_alive as f32 + total_komi < 0.0 {
+    outcome = Some(Stone::Black);
+  } else if w_mc_alive as f32 - b_mc_alive as f32 + total_komi > 0.0 {
+    outcome = Some(Stone::White);
+  }
+
+  (MonteCarloSearchResult{
+    turn:   root_node.turn,
+    action: action,
+    //expected_score:   mean_raw_score,
+    expected_score:   expected_score,
+    //expected_adj_val: value,
+    expected_value:   value,
+    b_mc_alive:       b_mc_alive,
+    w_mc_alive:       w_mc_alive,
+    /*b_alive_ch:       b_alive_ch,
+    b_alive_ter:      b_alive_ter,
+    w_alive_ch:       w_alive_ch,
+    w_alive_ter:      w_alive_ter,*/
+    top_prior_values: top_prior_values,
+    pv:               root_pv,
+    dead_stones:      dead_stones,
+    live_stones:      live_stones,
+    territory:        territory,
+    outcome:          outcome,
+    root_visits:      root_visits,
+    komi:             komi,
+  }, stats)
+}
+
+/// How the search workers share the work of one search.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SearchParallelism {
+  /// All workers search one shared tree.
+  Tree,
+  /// The workers are split among `num_trees` independent trees whose root
+  /// statistics are merged (see `RootParallelMonteCarloSearch`).
+  Root{num_trees: usize},
+}
+
+/// Runs independent searches from the same root, one per tree, each on its
+/// own group of workers with its own RNG seed, barrier, and stop signal, and
+/// merges them by visit-weighted voting: the visits of every root move are
+/// summed over the trees and the most visited move is chosen. Early stopping
+/// is decided on the summed visits as well (see `MergedEarlyStop`).
+pub struct RootParallelMonteCarloSearch;
 
-    (MonteCarloSearchResult{
-      turn:   root_node.state.current_turn(),
-      action: action,
-      //expected_score:   mean_raw_score,
-      expected_score:   expected_score,
-      //expected_adj_val: value,
-      expected_value:   value,
-      b_mc_alive:       b_mc_alive,
-      w_mc_alive:       w_mc_alive,
-      /*b_alive_ch:       b_alive_ch,
-      b_alive_ter:      b_alive_ter,
-      w_alive_ch:       w_alive_ch,
-      w_alive_ter:      w_alive_ter,*/
-      top_prior_values: top_prior_values,
-      pv:               root_pv,
-      dead_stones:      dead_stones,
-      live_stones:      live_stones,
-      territory:        territory,
-      outcome:          outcome,
-    }, stats)
+impl RootParallelMonteCarloSearch {
+  pub fn new() -> RootParallelMonteCarloSearch {
+    RootParallelMonteCarloSearch
+  }
+
+  pub fn join<W>(&self,
+      worker_cfg:   SearchWorkerConfig,
+      server:       &ParallelMonteCarloSearchServer<W>,
+      green_stone:  Stone,
+      init_state:   &TxnState<TxnStateNodeData>,
+      shared_trees: &[SharedTree],
+      rng:          &mut Xorshiftplus128Rng)
+      -> (MonteCarloSearchResult, MonteCarloSearchStats)
+      where W: SearchPolicyWorker
+  {
+    let num_workers = server.num_workers();
+    let num_trees = shared_trees.len();
+    if num_trees == 0 || num_trees > num_workers {
+      panic!("FATAL: root parallel search: {} trees for {} workers", num_trees, num_workers);
+    }
+    let seeds: Vec<[u64; 2]> = (0 .. num_trees).map(|_| [rng.next_u64(), rng.next_u64()]).collect();
+
+    // Tree `k` is searched by the workers in [k * n / K, (k + 1) * n / K).
+    let tree_start = |k: usize| k * num_workers / num_trees;
+    let stop_signals: Vec<_> = (0 .. num_trees).map(|_| Arc::new(AtomicBool::new(false))).collect();
+    let merged_stop = Arc::new(MergedEarlyStop::new(shared_trees.to_vec(), stop_signals.clone()));
+    let start_time = get_time();
+    for k in 0 .. num_trees {
+      let group_barrier = Arc::new(Barrier::new(tree_start(k + 1) - tree_start(k)));
+      for tid in tree_start(k) .. tree_start(k + 1) {
+        server.enqueue(tid, SearchWorkerCommand::ResetSearch{
+          cfg:            worker_cfg,
+          shared_tree:    shared_trees[k].clone(),
+          group:          SearchWorkerGroup{
+            leader:       tid == tree_start(k),
+            num_workers:  tree_start(k + 1) - tree_start(k),
+            rng_seed:     Some(seeds[k]),
+            sync:         Some(SearchGroupSync{
+              barrier:      group_barrier.clone(),
+              stop_signal:  stop_signals[k].clone(),
+              group_idx:    k,
+              early_stop:   Some(merged_stop.clone()),
+            }),
+          },
+          init_state:     init_state.clone(),
+          record_search:  false,
+        });
+      }
+    }
+    server.join();
+    let end_time = get_time();
+    let elapsed_ms = (end_time - start_time).num_milliseconds() as usize;
+
+    let tree_results: Vec<_> = (0 .. num_trees).map(|k| {
+      search_result(&shared_trees[k], tree_start(k + 1) - tree_start(k), elapsed_ms)
+    }).collect();
+
+    // Sum the root statistics of every tree by point.
+    let mut visits: Vec<usize> = repeat(0).take(Board::SIZE).collect();
+    let mut succs: Vec<usize> = repeat(0).take(Board::SIZE).collect();
+    let mut scores: Vec<f32> = repeat(0.0).take(Board::SIZE).collect();
+    for shared_tree in shared_trees.iter() {
+      let root_node = shared_tree.inner.lock().unwrap().root_node.as_ref().unwrap().clone();
+      let root_node = root_node.read().unwrap();
+      for j in 0 .. root_node.valid_moves.len() {
+        let p = root_node.valid_moves[j].idx();
+        let n_j = root_node.values.num_trials[j].load(Ordering::Acquire);
+        visits[p] += n_j;
+        succs[p] += root_node.values.num_succs[j].load(Ordering::Acquire);
+        scores[p] += n_j as f32 * root_node.values.arm_score(j);
+      }
+    }
+
+    let mut argmax_p = None;
+    for p in 0 .. Board::SIZE {
+      if visits[p] > 0 && argmax_p.map_or(true, |q| visits[p] > visits[q]) {
+        argmax_p = Some(p);
+      }
+    }
+
+    // The tree which voted most for the chosen move provides the rest of the
+    // result, e.g. the principal variation and the dead stones.
+    let rep_k = match argmax_p {
+      Some(p) => {
+        let mut rep_k = 0;
+        let mut rep_visits = 0;
+        for k in 0 .. num_trees {
+          let k_visits = tree_results[k].0.root_visits.iter()
+            .find(|&&(pt, _)| pt.idx() == p)
+            .map_or(0, |&(_, n)| n);
+          if k_visits > rep_visits {
+            rep_k = k;
+            rep_visits = k_visits;
+          }
+        }
+        rep_k
+      }
+      None => 0,
+    };
+    let (mut result, mut stats) = tree_results[rep_k].clone();
+
+    result.root_visits = (0 .. Board::SIZE)
+      .filter(|&p| visits[p] > 0)
+      .map(|p| (Point::from_idx(p), visits[p]))
+      .collect();
+    match argmax_p {
+      Some(p) => {
+        result.action = Action::Place{point: Point::from_idx(p)};
+        result.expected_score = scores[p] / visits[p] as f32;
+        result.expected_value = succs[p] as f32 / visits[p] as f32;
+        let root_node = shared_trees[rep_k].inner.lock().unwrap().root_node.as_ref().unwrap().clone();
+        let root_node = root_node.read().unwrap();
+        stats.argmax_rank = root_node.valid_moves.iter().position(|pt| pt.idx() == p);
+        stats.argmax_ntrials = visits[p];
+      }
+      None => {
+        result.action = Action::Pass;
+        result.expected_score = 0.0;
+        result.expected_value = 0.5;
+        stats.argmax_rank = None;
+        stats.argmax_ntrials = 0;
+      }
+    }
+
+    stats.elapsed_ms = elapsed_ms;
+    stats.num_rollouts = 0;
+    stats.num_expanded = 0;
+    stats.tree_bytes = 0;
+    stats.num_compacted = 0;
+    stats.num_pruned = 0;
+    stats.stopped_early = false;
+    for &(_, ref tree_stats) in tree_results.iter() {
+      stats.num_rollouts += tree_stats.num_rollouts;
+      stats.num_expanded += tree_stats.num_expanded;
+      stats.tree_bytes += tree_stats.tree_bytes;
+      stats.num_compacted += tree_stats.num_compacted;
+      stats.num_pruned += tree_stats.num_pruned;
+      stats.stopped_early |= tree_stats.stopped_early;
+    }
+    if stats.stopped_early {
+      stats.saved_rollouts = merged_stop.saved_rollouts();
+      stats.saved_ms = stats.saved_rollouts * elapsed_ms / max(1, stats.num_rollouts);
+    } else {
+      stats.saved_rollouts = 0;
+      stats.saved_ms = 0;
+    }
+    println!("DEBUG: root parallel search: trees: {} votes: {:?}",
+        num_trees,
+        tree_results.iter().map(|&(ref r, _)| r.action).collect::<Vec<_>>());
+    (result, stats)
   }
 }
 
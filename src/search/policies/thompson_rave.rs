@@ -1,10 +1,6 @@
 use array_util::{array_argmax};
 use board::{Board, Stone, Point};
-use hyper::{
-  RAVE,
-  RAVE_EQUIV,
-  PRIOR_EQUIV,
-};
+use hyper::{HyperparamConfig};
 use search::tree::{Node};
 use search::policies::{TreePolicy};
 
@@ -22,11 +18,11 @@ pub struct ThompsonRaveTreePolicy {
 }
 
 impl ThompsonRaveTreePolicy {
-  pub fn new() -> ThompsonRaveTreePolicy {
+  pub fn new(hyper: &HyperparamConfig) -> ThompsonRaveTreePolicy {
     ThompsonRaveTreePolicy{
-      rave:         RAVE.with(|x| *x),
-      rave_equiv:   RAVE_EQUIV.with(|x| *x),
-      prior_equiv:  PRIOR_EQUIV.with(|x| *x),
+      rave:         hyper.rave,
+      rave_equiv:   hyper.rave_equiv,
+      prior_equiv:  hyper.prior_equiv,
 
       tmp_values:   repeat(0.0).take(Board::SIZE).collect(),
     }
@@ -1,11 +1,6 @@
 use array_util::{array_argmax};
 use board::{Stone, Point};
-use hyper::{
-  UCB_C,
-  RAVE,
-  RAVE_EQUIV,
-  PBIAS_EQUIV,
-};
+use hyper::{HyperparamConfig};
 use search::tree::{Node};
 use search::policies::{TreePolicy};
 
@@ -17,12 +12,12 @@ pub struct UctRaveTreePolicy {
 }
 
 impl UctRaveTreePolicy {
-  pub fn new() -> UctRaveTreePolicy {
+  pub fn new(hyper: &HyperparamConfig) -> UctRaveTreePolicy {
     UctRaveTreePolicy{
-      ucb_c:        UCB_C.with(|x| *x),
-      rave:         RAVE.with(|x| *x),
-      rave_equiv:   RAVE_EQUIV.with(|x| *x),
-      pbias_equiv:  PBIAS_EQUIV.with(|x| *x),
+      ucb_c:        hyper.ucb_c,
+      rave:         hyper.rave,
+      rave_equiv:   hyper.rave_equiv,
+      pbias_equiv:  hyper.pbias_equiv,
     }
   }
 }
@@ -0,0 +1,262 @@
+use board::{Board, Stone, Point};
+use search::parallel_tree::{
+  Node, SharedTree,
+  MonteCarloSearchStats,
+  search_principal_variation,
+};
+
+use rustc_serialize::json;
+use std::fs::{File};
+use std::io::{self, Write};
+use std::path::{Path};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{Ordering};
+
+/// The statistics of one arm of the root node.
+#[derive(Clone, RustcEncodable, Debug)]
+pub struct ChildReport {
+  /// GTP coordinate.
+  pub point:          String,
+  pub visits:         usize,
+  pub wins:           usize,
+  pub value:          f32,
+  pub mean_score:     f32,
+  pub prior:          f32,
+  /// Visits in flight when the report was made.
+  pub virtual_losses: usize,
+  /// Always None for now: the atomic node values do not track RAVE
+  /// statistics (see `Node::rave_update_arm`).
+  pub rave_visits:    Option<usize>,
+  pub rave_value:     Option<f32>,
+  /// Nodes in the subtree of this arm, 0 if it was never expanded.
+  pub subtree_nodes:  usize,
+}
+
+/// One step of the principal variation, with why it was chosen.
+#[derive(Clone, RustcEncodable, Debug)]
+pub struct PvStepReport {
+  pub turn:         Stone,
+  /// GTP coordinate, "pass", or "resign".
+  pub action:       String,
+  pub node_visits:  usize,
+  pub visits:       usize,
+  pub value:        f32,
+  pub mean_score:   f32,
+  pub reason:       String,
+}
+
+#[derive(Clone, RustcEncodable, Debug)]
+pub struct SearchReport {
+  pub turn:         Stone,
+  pub komi:         f32,
+  pub root_visits:  usize,
+  pub root_value:   f32,
+  pub stats:        MonteCarloSearchStats,
+  /// Sorted by decreasing visits.
+  pub children:     Vec<ChildReport>,
+  /// The number of nodes at each depth below the root, the root included.
+  pub depth_histogram:  Vec<usize>,
+  pub pv:           Vec<PvStepReport>,
+}
+
+impl SearchReport {
+  pub fn new(tree: &SharedTree, stats: &MonteCarloSearchStats, pv_depth: usize) -> SearchReport {
+    let root_node = tree.root_node();
+    let mut depth_histogram = vec![];
+    count_depths(&root_node, 0, &mut depth_histogram);
+    let pv = search_principal_variation(root_node.clone(), pv_depth);
+
+    let root = root_node.read().unwrap();
+    let mut children = Vec::with_capacity(root.valid_moves.len());
+    for j in 0 .. root.valid_moves.len() {
+      let visits = root.values.num_trials[j].load(Ordering::Acquire);
+      let wins = root.values.num_succs[j].load(Ordering::Acquire);
+      let subtree_nodes = match root.child_nodes[j] {
+        Some(ref child) => {
+          let mut child_histogram = vec![];
+          count_depths(child, 0, &mut child_histogram);
+          child_histogram.iter().fold(0, |acc, &n| acc + n)
+        }
+        None => 0,
+      };
+      children.push(ChildReport{
+        point:          root.valid_moves[j].to_coord().to_string(),
+        visits:         visits,
+        wins:           wins,
+        value:          if visits > 0 { wins as f32 / visits as f32 } else { 0.0 },
+        mean_score:     if visits > 0 { root.values.arm_score(j) } else { 0.0 },
+        prior:          root.values.prior_values[j],
+        virtual_losses: root.values.virtual_losses[j].load(Ordering::Acquire),
+        rave_visits:    None,
+        rave_value:     None,
+        subtree_nodes:  subtree_nodes,
+      });
+    }
+    children.sort_by(|a, b| b.visits.cmp(&a.visits));
+
+    let root_visits = root.values.total_trials.load(Ordering::Acquire);
+    let root_wins = children.iter().fold(0, |acc, c| acc + c.wins);
+    SearchReport{
+      turn:         root.turn,
+      komi:         root.state().config.komi,
+      root_visits:  root_visits,
+      root_value:   if root_visits > 0 { root_wins as f32 / root_visits as f32 } else { 0.5 },
+      stats:        *stats,
+      children:     children,
+      depth_histogram:  depth_histogram,
+      pv:           pv,
+    }
+  }
+
+  pub fn to_json(&self) -> String {
+    format!("{}", json::as_pretty_json(self))
+  }
+
+  pub fn save_json(&self, path: &Path) -> io::Result<()> {
+    let mut file = try!(File::create(path));
+    try!(file.write_all(self.to_json().as_bytes()));
+    Ok(())
+  }
+}
+
+fn count_depths(node: &Arc<RwLock<Node>>, depth: usize, histogram: &mut Vec<usize>) {
+  if histogram.len() <= depth {
+    histogram.push(0);
+  }
+  histogram[depth] += 1;
+  let node = node.read().unwrap();
+  for child in node.child_nodes.iter() {
+    if let Some(ref child) = *child {
+      count_depths(child, depth + 1, histogram);
+    }
+  }
+}
+
+/// Limits which part of the tree is exported: arms with fewer visits than
+/// `min_visits` or deeper than `max_depth` are left out.
+#[derive(Clone, Copy, Debug)]
+pub struct TreeExportConfig {
+  pub max_depth:  usize,
+  pub min_visits: usize,
+}
+
+/// The visited arms of a node which pass the export thresholds, by
+/// decreasing visits.
+fn export_arms(node: &Node, cfg: TreeExportConfig) -> Vec<(usize, usize)> {
+  let mut arms: Vec<(usize, usize)> = (0 .. node.valid_moves.len())
+    .map(|j| (node.values.num_trials[j].load(Ordering::Acquire), j))
+    .filter(|&(n, _)| n > 0 && n >= cfg.min_visits)
+    .collect();
+  arms.sort_by(|a, b| b.0.cmp(&a.0));
+  arms
+}
+
+fn arm_comment(node: &Node, j: usize, visits: usize) -> String {
+  let wins = node.values.num_succs[j].load(Ordering::Acquire);
+  format!("visits: {} value: {:.3} score: {:.1} prior: {:.4}",
+      visits, wins as f32 / visits as f32, node.values.arm_score(j), node.values.prior_values[j])
+}
+
+fn stone_label(stone: Stone) -> &'static str {
+  match stone {
+    Stone::Black => "B",
+    Stone::White => "W",
+    Stone::Empty => "E",
+  }
+}
+
+/// Exports the tree as a Graphviz digraph; each edge is labeled with its
+/// move and statistics.
+pub fn tree_to_dot(tree: &SharedTree, cfg: TreeExportConfig) -> String {
+  let mut s = String::new();
+  s.push_str("digraph search {\n");
+  s.push_str("  node [shape=box, fontname=\"monospace\"];\n");
+  let mut num_nodes = 0;
+  write_dot_node(&tree.root_node(), 0, cfg, &mut num_nodes, &mut s);
+  s.push_str("}\n");
+  s
+}
+
+fn write_dot_node(node: &Arc<RwLock<Node>>, depth: usize, cfg: TreeExportConfig, num_nodes: &mut usize, s: &mut String) -> usize {
+  let id = *num_nodes;
+  *num_nodes += 1;
+  let node = node.read().unwrap();
+  s.push_str(&format!("  n{} [label=\"{} to play\\n{} visits\"];\n",
+      id, stone_label(node.turn), node.values.total_trials.load(Ordering::Acquire)));
+  if depth >= cfg.max_depth {
+    return id;
+  }
+  for (visits, j) in export_arms(&node, cfg).into_iter() {
+    let label = format!("{}\\n{}", node.valid_moves[j].to_coord().to_string(), arm_comment(&node, j, visits));
+    let child_id = match node.child_nodes[j] {
+      Some(ref child) => write_dot_node(child, depth + 1, cfg, num_nodes, s),
+      None => {
+        let leaf_id = *num_nodes;
+        *num_nodes += 1;
+        s.push_str(&format!("  n{} [label=\"leaf\", shape=plaintext];\n", leaf_id));
+        leaf_id
+      }
+    };
+    s.push_str(&format!("  n{} -> n{} [label=\"{}\"];\n", id, child_id, label));
+  }
+  id
+}
+
+fn escape_sgf(text: &str) -> String {
+  text.replace("\\", "\\\\").replace("]", "\\]")
+}
+
+/// Exports the tree as an SGF game tree from the root position, with one
+/// variation per exported arm and the arm statistics as move comments.
+pub fn tree_to_sgf(tree: &SharedTree, cfg: TreeExportConfig) -> String {
+  let root_node = tree.root_node();
+  let root = root_node.read().unwrap();
+  let state = root.state();
+  let mut s = String::new();
+  s.push_str("(;GM[1]FF[4]\n");
+  s.push_str(&format!("SZ[{}]KM[{:.1}]\n", Board::DIM, state.config.komi));
+  for &stone in [Stone::Black, Stone::White].iter() {
+    let points: Vec<_> = (0 .. Board::SIZE)
+      .map(|p| Point::from_idx(p))
+      .filter(|&pt| state.current_stone(pt) == stone)
+      .collect();
+    if !points.is_empty() {
+      s.push_str(&format!("A{}", stone_label(stone)));
+      for pt in points.iter() {
+        s.push_str(&format!("[{}]", pt.to_coord().to_sgf()));
+      }
+      s.push('\n');
+    }
+  }
+  s.push_str(&format!("PL[{}]C[{}]\n", stone_label(root.turn),
+      escape_sgf(&format!("root visits: {}", root.values.total_trials.load(Ordering::Acquire)))));
+  write_sgf_variations(&root, 0, cfg, &mut s);
+  s.push_str(")\n");
+  s
+}
+
+fn write_sgf_variations(node: &Node, depth: usize, cfg: TreeExportConfig, s: &mut String) {
+  if depth >= cfg.max_depth {
+    return;
+  }
+  for (visits, j) in export_arms(node, cfg).into_iter() {
+    s.push_str(&format!("(;{}[{}]C[{}]\n",
+        stone_label(node.turn),
+        node.valid_moves[j].to_coord().to_sgf(),
+        escape_sgf(&arm_comment(node, j, visits))));
+    if let Some(ref child) = node.child_nodes[j] {
+      write_sgf_variations(&child.read().unwrap(), depth + 1, cfg, s);
+    }
+    s.push_str(")\n");
+  }
+}
+
+/// Writes `<prefix>.json`, `<prefix>.dot`, and `<prefix>.sgf`.
+pub fn save_search_report(prefix: &Path, report: &SearchReport, tree: &SharedTree, cfg: TreeExportConfig) -> io::Result<()> {
+  try!(report.save_json(&prefix.with_extension("json")));
+  let mut dot_file = try!(File::create(&prefix.with_extension("dot")));
+  try!(dot_file.write_all(tree_to_dot(tree, cfg).as_bytes()));
+  let mut sgf_file = try!(File::create(&prefix.with_extension("sgf")));
+  try!(sgf_file.write_all(tree_to_sgf(tree, cfg).as_bytes()));
+  Ok(())
+}
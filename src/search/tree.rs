@@ -1,6 +1,6 @@
 use array_util::{array_argmax};
 use board::{Board, RuleSet, PlayerRank, Stone, Point, Action};
-use hyper::{PWIDE, PWIDE_MU};
+use hyper::{HyperparamConfig};
 use random::{XorShift128PlusRng, choose_without_replace};
 use search::{SearchResult, SearchStats, ProgWideConfig};
 use search::policies::{PriorPolicy, TreePolicy, RolloutPolicy};
@@ -246,10 +246,10 @@ pub struct Tree {
 }
 
 impl Tree {
-  pub fn new(init_state: TxnState<TxnStateNodeData>, prior_policy: &mut PriorPolicy, tree_policy: &mut TreePolicy<R=XorShift128PlusRng>/*, rng: &mut XorShift128PlusRng*/) -> Tree {
+  pub fn new(init_state: TxnState<TxnStateNodeData>, hyper: &HyperparamConfig, prior_policy: &mut PriorPolicy, tree_policy: &mut TreePolicy<R=XorShift128PlusRng>/*, rng: &mut XorShift128PlusRng*/) -> Tree {
     let pwide_cfg = ProgWideConfig{
-      pwide:      PWIDE.with(|x| *x),
-      pwide_mu:   PWIDE_MU.with(|x| *x),
+      pwide:      hyper.pwide,
+      pwide_mu:   hyper.pwide_mu,
     };
     let mut root_node = Rc::new(RefCell::new(Node::new(None, init_state, prior_policy, &pwide_cfg)));
     // FIXME(20151124): calling this in SearchProblem.join() because RNG is
@@ -0,0 +1,340 @@
+use board::{Board, Stone, Point, Action};
+use dataset::{DatasetHeader, DatasetLabel, DatasetProvenance};
+use search::decision::{DecisionConfig, DecisionPolicy};
+use search::parallel_policies::{SearchPolicyWorker};
+use search::parallel_tree::{
+  TreePolicyConfig,
+  MonteCarloSearchResult,
+  ParallelMonteCarloSearchServer,
+  ParallelMonteCarloSearch,
+  SearchWorkerConfig,
+  SearchWorkerBatchConfig,
+  SharedTree,
+  RootNoiseConfig,
+};
+use txnstate::{TxnStateConfig, TxnState};
+use txnstate::extras::{TxnStateNodeData};
+use txnstate::feature_sets::{FeatureSet, lookup_feature_set, has_feature_extractor, build_feature_extractor};
+
+use byteorder::{WriteBytesExt, LittleEndian};
+use episodb::{EpisoDb};
+use rng::xorshift::{Xorshiftplus128Rng};
+
+use rand::{Rng};
+use std::fs::{File};
+use std::io::{Read, Cursor};
+use std::iter::{repeat};
+use std::mem::{size_of};
+use std::path::{Path, PathBuf};
+use toml;
+
+#[derive(Clone, Copy, RustcDecodable, Debug)]
+pub struct SelfPlayConfig {
+  pub num_games:        usize,
+  pub num_rollouts:     usize,
+  pub batch_size:       usize,
+  /// Dirichlet concentration of the root noise; no noise if zero.
+  pub dirichlet_alpha:  f32,
+  pub noise_frac:       f32,
+  /// Moves are sampled in proportion to `n^(1/temperature)` for the first
+  /// `temperature_plies` plies, then chosen by the maximum visit count.
+  pub temperature:      f32,
+  pub temperature_plies:    usize,
+  pub max_plies:        usize,
+  pub komi:             f32,
+}
+
+impl Default for SelfPlayConfig {
+  fn default() -> SelfPlayConfig {
+    SelfPlayConfig{
+      num_games:        1,
+      num_rollouts:     1024,
+      batch_size:       16,
+      dirichlet_alpha:  0.03,
+      noise_frac:       0.25,
+      temperature:      1.0,
+      temperature_plies:    30,
+      max_plies:        450,
+      komi:             6.5,
+    }
+  }
+}
+
+impl SelfPlayConfig {
+  pub fn open() -> Result<SelfPlayConfig, ()> {
+    let path = PathBuf::from("selfplay.config");
+    let mut file = match File::open(&path) {
+      Ok(file) => file,
+      Err(_) => return Err(()),
+    };
+    let mut buf = String::new();
+    file.read_to_string(&mut buf).unwrap();
+    let cfg: SelfPlayConfig = match toml::decode_str(&buf) {
+      Some(x) => x,
+      None => return Err(()),
+    };
+    Ok(cfg)
+  }
+
+  pub fn root_noise(&self) -> Option<RootNoiseConfig> {
+    if self.dirichlet_alpha > 0.0 && self.noise_frac > 0.0 {
+      Some(RootNoiseConfig{
+        dirichlet_alpha:  self.dirichlet_alpha,
+        noise_frac:       self.noise_frac,
+      })
+    } else {
+      None
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct SelfPlayPosition {
+  pub turn:         Stone,
+  pub action:       Action,
+  /// Root visit counts of the search at this position.
+  pub root_visits:  Vec<(Point, usize)>,
+}
+
+#[derive(Clone)]
+pub struct SelfPlayEpisode {
+  pub state_cfg:    TxnStateConfig,
+  pub positions:    Vec<SelfPlayPosition>,
+  pub outcome:      Option<Stone>,
+}
+
+/// Plays the parallel search against itself. Works with any search policy
+/// worker, so the GPU-free `UniformPolicyWorkerBuilder` may be used too.
+pub struct SelfPlayDriver<W> where W: SearchPolicyWorker {
+  cfg:          SelfPlayConfig,
+  tree_cfg:     TreePolicyConfig,
+  state_cfg:    TxnStateConfig,
+  server:       ParallelMonteCarloSearchServer<W>,
+  decision:     DecisionPolicy,
+  rng:          Xorshiftplus128Rng,
+}
+
+impl<W> SelfPlayDriver<W> where W: SearchPolicyWorker {
+  pub fn new(cfg: SelfPlayConfig, tree_cfg: TreePolicyConfig, state_cfg: TxnStateConfig, server: ParallelMonteCarloSearchServer<W>, rng: Xorshiftplus128Rng) -> SelfPlayDriver<W> {
+    SelfPlayDriver{
+      cfg:          cfg,
+      tree_cfg:     tree_cfg,
+      state_cfg:    state_cfg,
+      server:       server,
+      decision:     DecisionPolicy::new(DecisionConfig::default()),
+      rng:          rng,
+    }
+  }
+
+  pub fn with_decision_cfg(mut self, decision_cfg: DecisionConfig) -> SelfPlayDriver<W> {
+    self.decision = DecisionPolicy::new(decision_cfg);
+    self
+  }
+
+  fn select_action(&mut self, ply: usize, result: &MonteCarloSearchResult) -> Action {
+    match result.action {
+      Action::Resign | Action::Pass => return result.action,
+      Action::Place{..} => {}
+    }
+    if ply >= self.cfg.temperature_plies || self.cfg.temperature <= 0.0 {
+      return result.action;
+    }
+    let inv_temp = 1.0 / self.cfg.temperature;
+    let weights: Vec<f32> = result.root_visits.iter()
+      .map(|&(_, n)| (n as f32).powf(inv_temp))
+      .collect();
+    let total_weight: f32 = weights.iter().fold(0.0, |acc, &w| acc + w);
+    if !(total_weight > 0.0) {
+      return result.action;
+    }
+    let u = self.rng.gen_range(0.0, total_weight);
+    let mut acc = 0.0;
+    for (j, &w) in weights.iter().enumerate() {
+      acc += w;
+      if u < acc {
+        return Action::Place{point: result.root_visits[j].0};
+      }
+    }
+    result.action
+  }
+
+  pub fn play_episode(&mut self) -> SelfPlayEpisode {
+    let mut state = TxnState::new(self.state_cfg, TxnStateNodeData::new());
+    state.reset();
+    let mut positions = vec![];
+    let mut last_outcome = None;
+    let mut resigned = None;
+    let mut num_passes = 0;
+    self.decision.reset();
+
+    for ply in 0 .. self.cfg.max_plies {
+      if num_passes >= 2 {
+        break;
+      }
+      let turn = state.current_turn();
+      let shared_tree = match self.cfg.root_noise() {
+        Some(noise_cfg) => SharedTree::with_root_noise(self.tree_cfg, noise_cfg),
+        None => SharedTree::new(self.tree_cfg),
+      };
+      let worker_cfg = SearchWorkerConfig{
+        batch_cfg:    SearchWorkerBatchConfig::Fixed{num_batches: self.cfg.num_rollouts / self.cfg.batch_size},
+        tree_batch_size:      None,
+        rollout_batch_size:   self.cfg.batch_size,
+        // Keep the full visit counts, which are recorded as policy targets.
+        early_stop:           false,
+      };
+      let mut search = ParallelMonteCarloSearch::new();
+      let (result, _) = search.join(
+          worker_cfg,
+          &mut self.server,
+          turn,
+          &state,
+          shared_tree,
+          &mut self.rng);
+      last_outcome = result.outcome;
+
+      let action = match self.decision.decide(&state, turn, &result) {
+        Action::Place{..} => self.select_action(ply, &result),
+        action => action,
+      };
+      positions.push(SelfPlayPosition{
+        turn:         turn,
+        action:       action,
+        root_visits:  result.root_visits,
+      });
+      match action {
+        Action::Resign => {
+          resigned = Some(turn);
+          break;
+        }
+        Action::Pass => num_passes += 1,
+        Action::Place{..} => num_passes = 0,
+      }
+      match state.try_action(turn, action) {
+        Ok(_) => state.commit(),
+        Err(_) => panic!("self-play: search returned an illegal action: {:?} {:?}", turn, action),
+      }
+    }
+
+    let outcome = if let Some(resign_turn) = resigned {
+      Some(resign_turn.opponent())
+    } else if last_outcome.is_some() {
+      last_outcome
+    } else {
+      // XXX(20160420): Without live stone estimates from the search, fall
+      // back to a plain area count.
+      let mut territory: Vec<u8> = repeat(0).take(Board::SIZE).collect();
+      let score = state.current_score_tromp_taylor_undead(self.state_cfg.komi, &mut territory);
+      if score > 0.0 {
+        Some(Stone::White)
+      } else if score < 0.0 {
+        Some(Stone::Black)
+      } else {
+        None
+      }
+    };
+    println!("DEBUG: self-play: episode: plies: {} outcome: {:?}", positions.len(), outcome);
+
+    SelfPlayEpisode{
+      state_cfg:    self.state_cfg,
+      positions:    positions,
+      outcome:      outcome,
+    }
+  }
+}
+
+/// Writes self-play episodes in the same layout as `extract-gogod-episodes`,
+/// plus a fourth database of root visit distributions, along with a dataset
+/// header listing all three label types.
+pub struct SelfPlayEpisodeWriter {
+  feature_set:      &'static FeatureSet,
+  frames_db:        EpisoDb,
+  action_labels_db: EpisoDb,
+  value_labels_db:  EpisoDb,
+  visits_db:        EpisoDb,
+}
+
+impl SelfPlayEpisodeWriter {
+  pub fn create(prefix: &Path, suffix: &str, feature_set_name: &str, num_episodes: usize, max_plies: usize) -> SelfPlayEpisodeWriter {
+    let feature_set = match lookup_feature_set(feature_set_name) {
+      Some(feature_set) => feature_set,
+      None => panic!("self-play: unknown feature set: \"{}\"", feature_set_name),
+    };
+    if !has_feature_extractor(feature_set.name) {
+      panic!("self-play: feature set needs more than a state config: \"{}\"", feature_set.name);
+    }
+    let prefix = prefix.to_str().unwrap();
+    let frames_db_path = PathBuf::from(&format!("{}_frames_{}.episodb", prefix, suffix));
+    let action_labels_db_path = PathBuf::from(&format!("{}_labels_action_{}.episodb", prefix, suffix));
+    let value_labels_db_path = PathBuf::from(&format!("{}_labels_value_{}.episodb", prefix, suffix));
+    let visits_db_path = PathBuf::from(&format!("{}_labels_visits_{}.episodb", prefix, suffix));
+    let frame_sz = feature_set.serial_frame_size();
+    let header = DatasetHeader::with_feature_set(feature_set,
+        &[DatasetLabel::Action, DatasetLabel::Value, DatasetLabel::Visits],
+        DatasetProvenance::new("holmes-selfplay", vec![]),
+    );
+    header.save(&DatasetHeader::split_path(&PathBuf::from(prefix), suffix));
+    SelfPlayEpisodeWriter{
+      feature_set:      feature_set,
+      frames_db:        EpisoDb::create(frames_db_path, num_episodes, max_plies, frame_sz),
+      action_labels_db: EpisoDb::create(action_labels_db_path, num_episodes, max_plies, size_of::<i32>()),
+      value_labels_db:  EpisoDb::create(value_labels_db_path, num_episodes, max_plies, size_of::<i32>()),
+      visits_db:        EpisoDb::create(visits_db_path, num_episodes, max_plies, DatasetLabel::Visits.frame_size()),
+    }
+  }
+
+  /// Appends an episode; drawn games (no outcome) are skipped, as they are
+  /// when extracting SGF collections.
+  pub fn append_episode(&mut self, episode: &SelfPlayEpisode) -> bool {
+    let outcome = match episode.outcome {
+      Some(outcome) => outcome,
+      None => return false,
+    };
+    self.frames_db.append_episode();
+    self.action_labels_db.append_episode();
+    self.value_labels_db.append_episode();
+    self.visits_db.append_episode();
+
+    let mut state = build_feature_extractor(self.feature_set.name, episode.state_cfg).unwrap();
+    for position in episode.positions.iter() {
+      let turn = position.turn;
+      let serial_frame = state.extract_relative_serial_frame(turn);
+      self.frames_db.append_frame(&serial_frame);
+
+      let action_label = match position.action {
+        Action::Place{point} => point.0 as i32,
+        Action::Resign => 361,
+        Action::Pass => 362,
+      };
+      let mut action_label_cursor = Cursor::new(Vec::with_capacity(4));
+      action_label_cursor.write_i32::<LittleEndian>(action_label).unwrap();
+      self.action_labels_db.append_frame(action_label_cursor.get_ref());
+
+      let value_label = if outcome == turn { 1i32 } else { 0i32 };
+      let mut value_label_cursor = Cursor::new(Vec::with_capacity(4));
+      value_label_cursor.write_i32::<LittleEndian>(value_label).unwrap();
+      self.value_labels_db.append_frame(value_label_cursor.get_ref());
+
+      let total_visits = position.root_visits.iter().fold(0, |acc, &(_, n)| acc + n);
+      let mut visits: Vec<f32> = repeat(0.0).take(Board::SIZE).collect();
+      if total_visits > 0 {
+        for &(point, n) in position.root_visits.iter() {
+          visits[point.idx()] = n as f32 / total_visits as f32;
+        }
+      }
+      let mut visits_cursor = Cursor::new(Vec::with_capacity(Board::SIZE * size_of::<f32>()));
+      for &v in visits.iter() {
+        visits_cursor.write_f32::<LittleEndian>(v).unwrap();
+      }
+      self.visits_db.append_frame(visits_cursor.get_ref());
+
+      match position.action {
+        Action::Resign => break,
+        action => {
+          state.try_action(turn, action).unwrap();
+        }
+      }
+    }
+    true
+  }
+}
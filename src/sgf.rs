@@ -1,7 +1,37 @@
+use board::{RuleSet, PlayerRank, Stone, Point};
 use gtp_board::{Coord};
+use txnstate::{TxnStateConfig};
 
 use std::str::{from_utf8};
 
+/// How a game record writes player ranks in `BR`/`WR`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SgfRankStyle {
+  /// GoGoD: professional ranks are "9d" (or "9p"), amateur ranks are "4a".
+  Gogod,
+  /// Go servers: amateur ranks are "3d", professional ranks are "9p".
+  Server,
+}
+
+impl SgfRankStyle {
+  pub fn parse_rank(&self, rank_str: &str) -> Option<PlayerRank> {
+    match *self {
+      SgfRankStyle::Gogod => {
+        let s = rank_str.trim();
+        if s.ends_with('a') {
+          PlayerRank::parse_str(&format!("{}d", &s[ .. s.len() - 1]))
+        } else {
+          match PlayerRank::parse_str(s) {
+            Some(PlayerRank::Ama(d)) => Some(PlayerRank::Dan(d)),
+            x => x,
+          }
+        }
+      }
+      SgfRankStyle::Server => PlayerRank::parse_str(rank_str),
+    }
+  }
+}
+
 #[derive(Clone, RustcDecodable, RustcEncodable)]
 pub struct Sgf {
   pub black_player: String,
@@ -14,6 +44,7 @@ pub struct Sgf {
   pub board_dim:    i64,
   pub rules:        Option<String>,
   pub komi:         Option<f64>,
+  pub handicap:     Option<i64>,
   pub moves:        Vec<(String, String)>,
   pub black_pos:    Vec<String>,
   pub white_pos:    Vec<String>,
@@ -36,6 +67,7 @@ impl Sgf {
     let mut board_dim:    i64     = Default::default();
     let mut rules:        Option<String> = Default::default();
     let mut komi:         Option<f64> = Default::default();
+    let mut handicap:     Option<i64> = Default::default();
     let mut moves:        Vec<(String, String)> = Default::default();
     let mut black_pos:    Vec<String> = Default::default();
     let mut white_pos:    Vec<String> = Default::default();
@@ -55,6 +87,7 @@ impl Sgf {
           &GameInfoProperty::Result(ref x)      => result = x.clone(),
           &GameInfoProperty::Rules(ref x)       => rules = Some(x.clone()),
           &GameInfoProperty::GoKomi(x)          => komi = x,
+          &GameInfoProperty::GoHandicap(x)      => handicap = x,
           _ => {}
         },
         &Property::Setup(ref setup) => match setup {
@@ -88,11 +121,71 @@ impl Sgf {
       board_dim:    board_dim,
       rules:        rules,
       komi:         komi,
+      handicap:     handicap,
       moves:        moves,
       black_pos:    black_pos,
       white_pos:    white_pos,
     }
   }
+
+  /// The winner according to `RE`, if any.
+  pub fn outcome(&self) -> Option<Stone> {
+    parse_result_winner(&self.result)
+  }
+
+  /// The number of handicap stones; `HA` values below 2 mean no handicap.
+  pub fn num_handicap(&self) -> usize {
+    match self.handicap {
+      Some(h) if h >= 2 => h as usize,
+      _ => 0,
+    }
+  }
+
+  /// The `AB`/`AW` setup stones, black stones first.
+  pub fn setup_stones(&self) -> Vec<(Stone, Point)> {
+    let mut stones = vec![];
+    for code in self.black_pos.iter() {
+      stones.push((Stone::Black, Point::from_coord(Coord::from_code_str(code))));
+    }
+    for code in self.white_pos.iter() {
+      stones.push((Stone::White, Point::from_coord(Coord::from_code_str(code))));
+    }
+    stones
+  }
+
+  /// Maps `RU`, `KM`, `HA`, and `BR`/`WR` to a state config. Unknown rules
+  /// fall back to Japanese; a missing komi is 0.5 in handicap games and the
+  /// default komi otherwise; unparseable ranks (e.g. relay games) fall back to
+  /// the default ranks.
+  pub fn infer_state_cfg(&self, rank_style: SgfRankStyle) -> TxnStateConfig {
+    let default_cfg = TxnStateConfig::default();
+    let rule_set = match self.rules {
+      Some(ref rules_str) => match RuleSet::parse_sgf_str(rules_str) {
+        Some(rule_set) => rule_set,
+        None => {
+          println!("WARNING: sgf: unknown rules \"{}\", using Japanese", rules_str);
+          RuleSet::KgsJapanese
+        }
+      },
+      None => RuleSet::KgsJapanese,
+    };
+    let komi = match self.komi {
+      Some(komi) => komi as f32,
+      None => if self.num_handicap() > 0 {
+        0.5
+      } else {
+        default_cfg.komi
+      },
+    };
+    let b_rank = rank_style.parse_rank(&self.black_rank).unwrap_or(default_cfg.ranks[0]);
+    let w_rank = rank_style.parse_rank(&self.white_rank).unwrap_or(default_cfg.ranks[1]);
+    TxnStateConfig{
+      rules:  rule_set.rules(),
+      ranks:  [b_rank, w_rank],
+      komi:   komi,
+      history: false,
+    }
+  }
 }
 
 #[derive(Debug)]
@@ -160,6 +253,16 @@ pub struct RawSgf {
   pub nodes: Vec<Node>,
 }
 
+/// Parses the winner from an `RE` value such as "B+2.5" or "W+Resign"; draws,
+/// voids, and unknown results have no winner.
+pub fn parse_result_winner(result: &str) -> Option<Stone> {
+  match result.split('+').next().and_then(|tok| tok.split_whitespace().next()) {
+    Some("B") if result.contains('+') => Some(Stone::Black),
+    Some("W") if result.contains('+') => Some(Stone::White),
+    _ => None,
+  }
+}
+
 pub fn parse_raw_sgf(text: &[u8]) -> RawSgf {
   let mut nodes = Vec::new();
   let mut ptr = 0;
@@ -0,0 +1,566 @@
+//! Larger shapes than the 3x3 patterns of `pattern`: the radius 2 diamond and
+//! the 5x5 square around a point, and the corner-anchored region containing a
+//! point. Shape hashes are invariant under the 8 board symmetries and relative
+//! to the player to move, and are matched against a `ShapeDictionary` mined
+//! from game records.
+
+use board::{Board, Stone, Point, Action};
+use gtp_board::{Coord, rotate_offset};
+use sgf::{Sgf, SgfRankStyle};
+use txnstate::{TxnStateConfig, TxnStateData, TxnState, TxnPosition, TxnChainsList};
+use txnstate::feature_sets::{FeatureSet, SHAPE_V1};
+use txnstate::features::{TxnStateFeatures, TxnStateSerialFeatsData};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, LittleEndian};
+use std::cmp::{min};
+use std::collections::{HashMap};
+use std::fs::{File};
+use std::io::{Read, Write, BufReader, BufWriter};
+use std::mem::{size_of};
+use std::path::{Path};
+use std::sync::{Arc};
+
+pub const SHAPE_DICT_MAGIC: &'static [u8; 4] = b"HSD\0";
+pub const SHAPE_DICT_FORMAT_VERSION: u32 = 1;
+
+/// Side of the square corner regions; the regions of the 4 corners do not
+/// overlap on 19x19.
+pub const CORNER_DIM: i8 = 7;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShapeKind {
+  /// Points within manhattan distance 2.
+  Diamond,
+  /// Points within chebyshev distance 2.
+  Square5x5,
+  /// The `CORNER_DIM` x `CORNER_DIM` corner region and the point within it.
+  Corner,
+}
+
+pub static SHAPE_KINDS: [ShapeKind; 3] = [ShapeKind::Diamond, ShapeKind::Square5x5, ShapeKind::Corner];
+
+static LOCAL_KINDS: [ShapeKind; 2] = [ShapeKind::Diamond, ShapeKind::Square5x5];
+
+impl ShapeKind {
+  pub fn idx(self) -> usize {
+    match self {
+      ShapeKind::Diamond    => 0,
+      ShapeKind::Square5x5  => 1,
+      ShapeKind::Corner     => 2,
+    }
+  }
+
+  pub fn to_str(self) -> &'static str {
+    match self {
+      ShapeKind::Diamond    => "diamond",
+      ShapeKind::Square5x5  => "5x5",
+      ShapeKind::Corner     => "corner",
+    }
+  }
+
+  fn contains_offset(self, dx: i8, dy: i8) -> bool {
+    if dx == 0 && dy == 0 {
+      return false;
+    }
+    match self {
+      ShapeKind::Diamond    => dx.abs() + dy.abs() <= 2,
+      ShapeKind::Square5x5  => dx.abs() <= 2 && dy.abs() <= 2,
+      ShapeKind::Corner     => unreachable!(),
+    }
+  }
+
+  /// Offsets from the center of a local shape.
+  fn offsets(self) -> Vec<(i8, i8)> {
+    let mut offsets = vec![];
+    for dy in -2 .. 3 {
+      for dx in -2 .. 3 {
+        if self.contains_offset(dx, dy) {
+          offsets.push((dx, dy));
+        }
+      }
+    }
+    offsets
+  }
+}
+
+// The contents of a shape point; empty points do not contribute to the hash.
+const BLACK_CONTENT:    u64 = 0;
+const WHITE_CONTENT:    u64 = 1;
+const OFFBOARD_CONTENT: u64 = 2;
+// Marks the point itself within a corner region.
+const CENTER_CONTENT:   u64 = 3;
+
+fn splitmix64(x: u64) -> u64 {
+  let mut z = x.wrapping_mul(0x9e3779b97f4a7c15);
+  z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+  z ^ (z >> 31)
+}
+
+fn shape_key(kind: ShapeKind, dx: i8, dy: i8, content: u64) -> u64 {
+  let x = ((kind.idx() as u64 + 1) << 32)
+      | (((dx as u8) as u64) << 16)
+      | (((dy as u8) as u64) << 8)
+      | content;
+  splitmix64(x)
+}
+
+fn stone_content(stone: Stone, swap: usize) -> u64 {
+  match (stone, swap) {
+    (Stone::Black, 0) | (Stone::White, 1) => BLACK_CONTENT,
+    (Stone::White, 0) | (Stone::Black, 1) => WHITE_CONTENT,
+    _ => unreachable!(),
+  }
+}
+
+/// Colors are swapped for White to move, so "black" is always the player to
+/// move.
+fn turn_swap(turn: Stone) -> usize {
+  match turn {
+    Stone::Black => 0,
+    Stone::White => 1,
+    Stone::Empty => unreachable!(),
+  }
+}
+
+fn point_xy(point: Point) -> (i8, i8) {
+  let coord = point.to_coord();
+  (coord.x as i8, coord.y as i8)
+}
+
+fn xy_point(x: i8, y: i8) -> Option<Point> {
+  let dim = Board::DIM as i8;
+  if x < 0 || x >= dim || y < 0 || y >= dim {
+    None
+  } else {
+    Some(Point::from_coord(Coord::new(x as u8, y as u8)))
+  }
+}
+
+/// The corner whose region contains the point, with the point's coordinates
+/// relative to that corner.
+fn corner_of(x: i8, y: i8) -> Option<(usize, i8, i8)> {
+  let last = Board::DIM as i8 - 1;
+  let (cx, cy) = (min(x, last - x), min(y, last - y));
+  if cx >= CORNER_DIM || cy >= CORNER_DIM {
+    return None;
+  }
+  let corner = match (x <= last - x, y <= last - y) {
+    (true, true)    => 0,
+    (false, true)   => 1,
+    (true, false)   => 2,
+    (false, false)  => 3,
+  };
+  Some((corner, cx, cy))
+}
+
+fn corner_key(cx: i8, cy: i8, flip: usize, content: u64) -> u64 {
+  let (u, v) = match flip {
+    0 => (cx, cy),
+    _ => (cy, cx),
+  };
+  shape_key(ShapeKind::Corner, u, v, content)
+}
+
+fn kind_tag(kind: ShapeKind) -> u64 {
+  splitmix64(0x5348415045 + kind.idx() as u64)
+}
+
+/// Shape hashes of all points, updated incrementally as moves are committed.
+/// For each local shape kind and point there is one hash per symmetry and per
+/// color orientation; for each corner there is one hash per diagonal flip and
+/// per color orientation.
+#[derive(Clone, RustcDecodable, RustcEncodable)]
+pub struct TxnStateShapeData {
+  local_hashes:     Vec<u64>,
+  corner_hashes:    Vec<u64>,
+  stones:           Vec<Stone>,
+  dict:             Option<Arc<ShapeDictionary>>,
+}
+
+impl TxnStateShapeData {
+  /// The dictionary is only needed to extract features.
+  pub fn new(dict: Option<Arc<ShapeDictionary>>) -> TxnStateShapeData {
+    let mut data = TxnStateShapeData{
+      local_hashes:     vec![0; LOCAL_KINDS.len() * Board::SIZE * 16],
+      corner_hashes:    vec![0; 4 * 4],
+      stones:           vec![Stone::Empty; Board::SIZE],
+      dict:             dict,
+    };
+    data.reset();
+    data
+  }
+
+  /// The size of the hashes, which dominate the data.
+  pub fn approx_heap_bytes(&self) -> usize {
+    (self.local_hashes.capacity() + self.corner_hashes.capacity()) * size_of::<u64>()
+        + self.stones.capacity() * size_of::<Stone>()
+  }
+
+  fn local_idx(kind: ShapeKind, p: usize, rot: u8, swap: usize) -> usize {
+    ((kind.idx() * Board::SIZE + p) * 8 + rot as usize) * 2 + swap
+  }
+
+  fn toggle_stone(&mut self, stone: Stone, point: Point) {
+    let (x, y) = point_xy(point);
+    for &kind in LOCAL_KINDS.iter() {
+      for (dx, dy) in kind.offsets() {
+        // The stone is at offset (dx, dy) from the center (x - dx, y - dy).
+        if let Some(center) = xy_point(x - dx, y - dy) {
+          for rot in 0 .. 8 {
+            let (u, v) = rotate_offset(rot, dx, dy);
+            for swap in 0 .. 2 {
+              let idx = Self::local_idx(kind, center.idx(), rot, swap);
+              self.local_hashes[idx] ^= shape_key(kind, u, v, stone_content(stone, swap));
+            }
+          }
+        }
+      }
+    }
+    if let Some((corner, cx, cy)) = corner_of(x, y) {
+      for flip in 0 .. 2 {
+        for swap in 0 .. 2 {
+          self.corner_hashes[(corner * 2 + flip) * 2 + swap] ^= corner_key(cx, cy, flip, stone_content(stone, swap));
+        }
+      }
+    }
+  }
+
+  fn set_stone(&mut self, point: Point, stone: Stone) {
+    let prev_stone = self.stones[point.idx()];
+    if prev_stone == stone {
+      return;
+    }
+    if prev_stone != Stone::Empty {
+      self.toggle_stone(prev_stone, point);
+    }
+    if stone != Stone::Empty {
+      self.toggle_stone(stone, point);
+    }
+    self.stones[point.idx()] = stone;
+  }
+
+  /// The shape hash of `kind` around `point` for `turn` to move; there is no
+  /// corner shape outside the corner regions.
+  pub fn shape_hash(&self, kind: ShapeKind, turn: Stone, point: Point) -> Option<u64> {
+    let swap = turn_swap(turn);
+    match kind {
+      ShapeKind::Corner => {
+        let (x, y) = point_xy(point);
+        corner_of(x, y).map(|(corner, cx, cy)| {
+          let mut hash = 0xffffffffffffffff;
+          for flip in 0 .. 2 {
+            let h = self.corner_hashes[(corner * 2 + flip) * 2 + swap] ^ corner_key(cx, cy, flip, CENTER_CONTENT);
+            hash = min(hash, h);
+          }
+          hash ^ kind_tag(kind)
+        })
+      }
+      kind => {
+        let mut hash = 0xffffffffffffffff;
+        for rot in 0 .. 8 {
+          hash = min(hash, self.local_hashes[Self::local_idx(kind, point.idx(), rot, swap)]);
+        }
+        Some(hash ^ kind_tag(kind))
+      }
+    }
+  }
+}
+
+impl TxnStateData for TxnStateShapeData {
+  fn reset(&mut self) {
+    for h in self.local_hashes.iter_mut() {
+      *h = 0;
+    }
+    for h in self.corner_hashes.iter_mut() {
+      *h = 0;
+    }
+    for s in self.stones.iter_mut() {
+      *s = Stone::Empty;
+    }
+    for p in 0 .. Board::SIZE {
+      let (x, y) = point_xy(Point::from_idx(p));
+      for &kind in LOCAL_KINDS.iter() {
+        for (dx, dy) in kind.offsets() {
+          if xy_point(x + dx, y + dy).is_some() {
+            continue;
+          }
+          for rot in 0 .. 8 {
+            let (u, v) = rotate_offset(rot, dx, dy);
+            let key = shape_key(kind, u, v, OFFBOARD_CONTENT);
+            for swap in 0 .. 2 {
+              self.local_hashes[Self::local_idx(kind, p, rot, swap)] ^= key;
+            }
+          }
+        }
+      }
+    }
+  }
+
+  fn update(&mut self, position: &TxnPosition, _chains: &TxnChainsList, update_turn: Stone, update_action: Action) {
+    if let Action::Place{point} = update_action {
+      self.set_stone(point, update_turn);
+      for &kill_point in position.last_killed[0].iter().chain(position.last_killed[1].iter()) {
+        self.set_stone(kill_point, Stone::Empty);
+      }
+    }
+  }
+}
+
+impl TxnStateFeatures for TxnStateShapeData {
+  /// One byte plane per shape kind: the quantized dictionary play rate of the
+  /// shape at each empty point.
+  fn extract_relative_features(&self, turn: Stone, dst_buf: &mut [u8]) {
+    assert_eq!(SHAPE_KINDS.len() * Board::SIZE, dst_buf.len());
+    for x in dst_buf.iter_mut() {
+      *x = 0;
+    }
+    let dict = match self.dict {
+      Some(ref dict) => dict,
+      None => return,
+    };
+    for (k, &kind) in SHAPE_KINDS.iter().enumerate() {
+      for p in 0 .. Board::SIZE {
+        if self.stones[p] != Stone::Empty {
+          continue;
+        }
+        if let Some(hash) = self.shape_hash(kind, turn, Point::from_idx(p)) {
+          dst_buf[k * Board::SIZE + p] = (255.0 * dict.play_rate(hash)).round() as u8;
+        }
+      }
+    }
+  }
+}
+
+impl TxnStateSerialFeatsData for TxnStateShapeData {
+  fn feature_set() -> &'static FeatureSet {
+    &SHAPE_V1
+  }
+}
+
+/// Computes the same hash as `TxnStateShapeData::shape_hash` directly from the
+/// stones of any state, e.g. a search node state which does not maintain the
+/// shape hashes.
+pub fn compute_shape_hash<Data>(state: &TxnState<Data>, kind: ShapeKind, turn: Stone, point: Point) -> Option<u64>
+where Data: TxnStateData + Clone {
+  let swap = turn_swap(turn);
+  let (x, y) = point_xy(point);
+  match kind {
+    ShapeKind::Corner => {
+      corner_of(x, y).map(|(corner, cx, cy)| {
+        let mut hashes = [corner_key(cx, cy, 0, CENTER_CONTENT), corner_key(cx, cy, 1, CENTER_CONTENT)];
+        let last = Board::DIM as i8 - 1;
+        for ry in 0 .. CORNER_DIM {
+          for rx in 0 .. CORNER_DIM {
+            let ax = if corner == 0 || corner == 2 { rx } else { last - rx };
+            let ay = if corner == 0 || corner == 1 { ry } else { last - ry };
+            let stone = state.current_stone(xy_point(ax, ay).unwrap());
+            if stone != Stone::Empty {
+              for flip in 0 .. 2 {
+                hashes[flip] ^= corner_key(rx, ry, flip, stone_content(stone, swap));
+              }
+            }
+          }
+        }
+        min(hashes[0], hashes[1]) ^ kind_tag(kind)
+      })
+    }
+    kind => {
+      let mut hashes = [0u64; 8];
+      for (dx, dy) in kind.offsets() {
+        let content = match xy_point(x + dx, y + dy) {
+          None => OFFBOARD_CONTENT,
+          Some(q) => match state.current_stone(q) {
+            Stone::Empty => continue,
+            stone => stone_content(stone, swap),
+          },
+        };
+        for rot in 0 .. 8 {
+          let (u, v) = rotate_offset(rot, dx, dy);
+          hashes[rot as usize] ^= shape_key(kind, u, v, content);
+        }
+      }
+      Some(hashes.iter().fold(0xffffffffffffffff, |acc, &h| min(acc, h)) ^ kind_tag(kind))
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Default, RustcDecodable, RustcEncodable, Debug)]
+pub struct ShapeStats {
+  /// Number of times the shape was played into.
+  pub played:   u32,
+  /// Number of times the shape was seen around an empty point.
+  pub seen:     u32,
+}
+
+/// Shape statistics mined from game records. On disk it is a little endian
+/// binary file: the magic, the format version, the number of entries, then
+/// the hash, played, and seen counts of each entry in hash order.
+#[derive(Clone, Default, RustcDecodable, RustcEncodable)]
+pub struct ShapeDictionary {
+  pub entries:  HashMap<u64, ShapeStats>,
+}
+
+impl ShapeDictionary {
+  pub fn new() -> ShapeDictionary {
+    ShapeDictionary::default()
+  }
+
+  pub fn open(path: &Path) -> Result<ShapeDictionary, ()> {
+    let file = match File::open(path) {
+      Ok(file) => file,
+      Err(_) => return Err(()),
+    };
+    let mut reader = BufReader::new(file);
+    let mut magic = [0; 4];
+    if reader.read_exact(&mut magic).is_err() || &magic != SHAPE_DICT_MAGIC {
+      println!("WARNING: shapes: not a shape dictionary: {:?}", path);
+      return Err(());
+    }
+    let version = try!(reader.read_u32::<LittleEndian>().map_err(|_| ()));
+    if version != SHAPE_DICT_FORMAT_VERSION {
+      println!("WARNING: shapes: format version mismatch: {} (expected {})",
+          version, SHAPE_DICT_FORMAT_VERSION);
+      return Err(());
+    }
+    let num_entries = try!(reader.read_u32::<LittleEndian>().map_err(|_| ())) as usize;
+    let mut entries = HashMap::with_capacity(num_entries);
+    for _ in 0 .. num_entries {
+      let hash = try!(reader.read_u64::<LittleEndian>().map_err(|_| ()));
+      let played = try!(reader.read_u32::<LittleEndian>().map_err(|_| ()));
+      let seen = try!(reader.read_u32::<LittleEndian>().map_err(|_| ()));
+      entries.insert(hash, ShapeStats{played: played, seen: seen});
+    }
+    Ok(ShapeDictionary{entries: entries})
+  }
+
+  pub fn save(&self, path: &Path) {
+    let file = match File::create(path) {
+      Ok(file) => file,
+      Err(e) => panic!("failed to create shape dictionary: {:?}", e),
+    };
+    let mut writer = BufWriter::new(file);
+    writer.write_all(SHAPE_DICT_MAGIC).unwrap();
+    writer.write_u32::<LittleEndian>(SHAPE_DICT_FORMAT_VERSION).unwrap();
+    writer.write_u32::<LittleEndian>(self.entries.len() as u32).unwrap();
+    let mut hashes: Vec<_> = self.entries.keys().map(|&h| h).collect();
+    hashes.sort();
+    for &hash in hashes.iter() {
+      let stats = self.entries[&hash];
+      writer.write_u64::<LittleEndian>(hash).unwrap();
+      writer.write_u32::<LittleEndian>(stats.played).unwrap();
+      writer.write_u32::<LittleEndian>(stats.seen).unwrap();
+    }
+  }
+
+  /// Counts the shapes around every empty point before each move, and the
+  /// shapes played into, for the first `max_ply` moves (0 for all moves).
+  pub fn add_game(&mut self, state_cfg: TxnStateConfig, setup: &[(Stone, Point)], moves: &[(Stone, Action)], max_ply: usize) -> Result<(), ()> {
+    let mut state = TxnState::new(state_cfg, TxnStateShapeData::new(None));
+    state.reset();
+    try!(state.place_setup_stones(setup));
+    let mut counts: HashMap<u64, ShapeStats> = HashMap::new();
+    for (t, &(turn, action)) in moves.iter().enumerate() {
+      if max_ply > 0 && t >= max_ply {
+        break;
+      }
+      if let Action::Place{point: play_point} = action {
+        let data = state.get_data();
+        for p in 0 .. Board::SIZE {
+          let point = Point::from_idx(p);
+          if state.current_stone(point) != Stone::Empty {
+            continue;
+          }
+          for &kind in SHAPE_KINDS.iter() {
+            if let Some(hash) = data.shape_hash(kind, turn, point) {
+              let stats = counts.entry(hash).or_insert(ShapeStats::default());
+              stats.seen += 1;
+              if point == play_point {
+                stats.played += 1;
+              }
+            }
+          }
+        }
+      }
+      if state.try_action(turn, action).is_err() {
+        state.undo();
+        return Err(());
+      }
+      state.commit();
+    }
+    for (hash, stats) in counts.into_iter() {
+      let entry = self.entries.entry(hash).or_insert(ShapeStats::default());
+      entry.played += stats.played;
+      entry.seen += stats.seen;
+    }
+    Ok(())
+  }
+
+  pub fn add_sgf(&mut self, sgf: &Sgf, rank_style: SgfRankStyle, max_ply: usize) -> Result<(), ()> {
+    if sgf.board_dim != Board::DIM as i64 {
+      return Err(());
+    }
+    let mut moves = Vec::with_capacity(sgf.moves.len());
+    for &(ref player, ref mov) in sgf.moves.iter() {
+      let action = match mov as &str {
+        "Pass"    => Action::Pass,
+        "Resign"  => Action::Resign,
+        x         => match Coord::parse_code_str(x) {
+          Some(coord) => Action::Place{point: Point::from_coord(coord)},
+          None => return Err(()),
+        },
+      };
+      moves.push((Stone::from_code_str(player), action));
+    }
+    self.add_game(sgf.infer_state_cfg(rank_style), &sgf.setup_stones(), &moves, max_ply)
+  }
+
+  /// Drops the shapes seen fewer than `min_seen` times, or never played.
+  pub fn prune(&mut self, min_seen: u32) {
+    let hashes: Vec<_> = self.entries.iter()
+      .filter(|&(_, stats)| stats.seen < min_seen || stats.played == 0)
+      .map(|(&hash, _)| hash)
+      .collect();
+    for hash in hashes.iter() {
+      self.entries.remove(hash);
+    }
+  }
+
+  pub fn lookup(&self, hash: u64) -> Option<ShapeStats> {
+    self.entries.get(&hash).map(|&stats| stats)
+  }
+
+  /// The fraction of times the shape was played into when seen; 0 for
+  /// unknown shapes.
+  pub fn play_rate(&self, hash: u64) -> f32 {
+    match self.entries.get(&hash) {
+      Some(stats) if stats.seen > 0 => stats.played as f32 / stats.seen as f32,
+      _ => 0.0,
+    }
+  }
+
+  /// The greatest play rate over the shape kinds matched at `point`, from the
+  /// incrementally updated hashes of `shapes`.
+  pub fn shape_bonus(&self, shapes: &TxnStateShapeData, turn: Stone, point: Point) -> f32 {
+    let mut bonus: f32 = 0.0;
+    for &kind in SHAPE_KINDS.iter() {
+      if let Some(hash) = shapes.shape_hash(kind, turn, point) {
+        bonus = bonus.max(self.play_rate(hash));
+      }
+    }
+    bonus
+  }
+
+  /// Like `shape_bonus`, but computes the hashes from the stones of any
+  /// state.
+  pub fn match_bonus<Data>(&self, state: &TxnState<Data>, turn: Stone, point: Point) -> f32
+  where Data: TxnStateData + Clone {
+    let mut bonus: f32 = 0.0;
+    for &kind in SHAPE_KINDS.iter() {
+      if let Some(hash) = compute_shape_hash(state, kind, turn, point) {
+        bonus = bonus.max(self.play_rate(hash));
+      }
+    }
+    bonus
+  }
+}
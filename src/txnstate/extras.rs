@@ -1,4 +1,5 @@
 use board::{Board, Rules, RuleSet, Coord, PlayerRank, Stone, Point, Action};
+use shapes::{TxnStateShapeData};
 use txnstate::{
   TxnStateData, TxnStateConfig, TxnState, TxnPosition, TxnChainsList,
   for_each_adjacent,
@@ -220,6 +221,9 @@ pub struct TxnStateNodeData {
   //pub features: TxnStateAlphaV3FeatsData,
   pub features: TxnStateAlphaMiniV3FeatsData,
   pub legality: TxnStateLegalityData,
+  /// Incremental shape hashes for the shape prior; they are large (about
+  /// 90 KB per state), so they are only kept when a shape prior is used.
+  pub shapes:   Option<TxnStateShapeData>,
 }
 
 impl TxnStateNodeData {
@@ -231,8 +235,15 @@ impl TxnStateNodeData {
       //features: TxnStateAlphaV3FeatsData::new(),
       features: TxnStateAlphaMiniV3FeatsData::new(),
       legality: TxnStateLegalityData::new(false),
+      shapes:   None,
     }
   }
+
+  pub fn with_shapes() -> TxnStateNodeData {
+    let mut data = TxnStateNodeData::new();
+    data.shapes = Some(TxnStateShapeData::new(None));
+    data
+  }
 }
 
 //impl<Feats> TxnStateData for TxnStateNodeData<Feats> where Feats: TxnStateData {
@@ -240,11 +251,17 @@ impl TxnStateData for TxnStateNodeData {
   fn reset(&mut self) {
     self.features.reset();
     self.legality.reset();
+    if let Some(ref mut shapes) = self.shapes {
+      shapes.reset();
+    }
   }
 
   fn update(&mut self, position: &TxnPosition, chains: &TxnChainsList, update_turn: Stone, update_action: Action) {
     self.features.update(position, chains, update_turn, update_action);
     self.legality.update(position, chains, update_turn, update_action);
+    if let Some(ref mut shapes) = self.shapes {
+      shapes.update(position, chains, update_turn, update_action);
+    }
   }
 }
 
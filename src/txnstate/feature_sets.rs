@@ -0,0 +1,534 @@
+use board::{Board, Stone, Point, Action};
+use shapes::{ShapeDictionary, TxnStateShapeData};
+use txnstate::{TxnStateConfig, TxnState};
+use txnstate::features::{
+  TxnStateFeatures,
+  TxnStateSerialFeatsData,
+  TxnStateFeaturesData,
+  TxnStateLibFeaturesData,
+  TxnStateExtLibFeatsData,
+  TxnStateAlphaFeatsV1Data,
+  TxnStateAlphaFeatsV2Data,
+  TxnStateAlphaV3FeatsData,
+  TxnStateAlphaMiniV3FeatsData,
+  TxnStateAlphaPatternV3FeatsData,
+  TxnStateAlphaPatternNoKoV3FeatsData,
+};
+
+use array_new::{NdArraySerialize, Array3d, BitArray3d};
+
+use std::iter::{repeat};
+use std::sync::{Arc};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FeaturePlaneKind {
+  /// 0/1 valued; packed into a bit array when serialized.
+  Bit,
+  /// Full byte valued.
+  Byte,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FeaturePlane {
+  pub name:     &'static str,
+  pub kind:     FeaturePlaneKind,
+  /// Whether the plane is relative to the player to move (e.g. friendly vs.
+  /// enemy stones) rather than absolute.
+  pub relative: bool,
+}
+
+/// A named, versioned set of extracted feature planes. In a serialized frame
+/// all bit planes precede all byte planes.
+#[derive(Debug)]
+pub struct FeatureSet {
+  pub name:     &'static str,
+  pub version:  u32,
+  pub planes:   &'static [FeaturePlane],
+}
+
+impl FeatureSet {
+  pub fn num_planes(&self) -> usize {
+    self.planes.len()
+  }
+
+  pub fn num_bit_planes(&self) -> usize {
+    self.planes.iter().filter(|plane| plane.kind == FeaturePlaneKind::Bit).count()
+  }
+
+  pub fn num_byte_planes(&self) -> usize {
+    self.planes.iter().filter(|plane| plane.kind == FeaturePlaneKind::Byte).count()
+  }
+
+  pub fn input_dims(&self) -> (usize, usize, usize) {
+    (Board::DIM, Board::DIM, self.num_planes())
+  }
+
+  pub fn plane_index(&self, name: &str) -> Option<usize> {
+    self.planes.iter().position(|plane| plane.name == name)
+  }
+
+  pub fn serial_frame_size(&self) -> usize {
+    let num_bit_planes = self.num_bit_planes();
+    let num_byte_planes = self.num_byte_planes();
+    let mut size = 0;
+    if num_bit_planes > 0 {
+      size += BitArray3d::serial_size((Board::DIM, Board::DIM, num_bit_planes));
+    }
+    if num_byte_planes > 0 {
+      size += Array3d::<u8>::serial_size((Board::DIM, Board::DIM, num_byte_planes));
+    }
+    size
+  }
+
+  /// Serializes extracted planes (`num_planes() * Board::SIZE` bytes, in plane
+  /// order) into a frame.
+  pub fn serialize_frame(&self, planes_buf: &[u8]) -> Vec<u8> {
+    assert_eq!(self.num_planes() * Board::SIZE, planes_buf.len());
+    let num_bit_planes = self.num_bit_planes();
+    let num_byte_planes = self.num_byte_planes();
+    for (k, plane) in self.planes.iter().enumerate() {
+      // XXX(20160420): The serialized layout requires this ordering.
+      assert!(plane.kind == FeaturePlaneKind::Byte || k < num_bit_planes,
+          "feature set {}: bit plane {} follows a byte plane", self.name, plane.name);
+    }
+    let split = num_bit_planes * Board::SIZE;
+    let mut serial_frame: Vec<u8> = Vec::with_capacity(self.serial_frame_size());
+    if num_bit_planes > 0 {
+      let bit_arr = BitArray3d::from_byte_array(&Array3d::with_data(
+          planes_buf[ .. split].to_vec(),
+          (Board::DIM, Board::DIM, num_bit_planes)));
+      bit_arr.serialize(&mut serial_frame).unwrap();
+    }
+    if num_byte_planes > 0 {
+      let bytes_arr = Array3d::with_data(
+          planes_buf[split .. ].to_vec(),
+          (Board::DIM, Board::DIM, num_byte_planes));
+      bytes_arr.serialize(&mut serial_frame).unwrap();
+    }
+    serial_frame
+  }
+}
+
+macro_rules! plane {
+  ($name:expr, Bit, $relative:expr) => {
+    FeaturePlane{name: $name, kind: FeaturePlaneKind::Bit, relative: $relative}
+  };
+  ($name:expr, Byte, $relative:expr) => {
+    FeaturePlane{name: $name, kind: FeaturePlaneKind::Byte, relative: $relative}
+  };
+}
+
+static ALPHA_V3_PLANES: [FeaturePlane; 32] = [
+  plane!("baseline",    Bit, false),
+  plane!("empty",       Bit, false),
+  plane!("friend",      Bit, true),
+  plane!("enemy",       Bit, true),
+  plane!("enemy_rank_ama",  Bit, true),
+  plane!("enemy_rank_pro",  Bit, true),
+  plane!("turns_1",     Bit, false),
+  plane!("turns_2",     Bit, false),
+  plane!("turns_3",     Bit, false),
+  plane!("turns_4",     Bit, false),
+  plane!("turns_5",     Bit, false),
+  plane!("turns_6",     Bit, false),
+  plane!("turns_7",     Bit, false),
+  plane!("turns_8",     Bit, false),
+  plane!("libs_1",      Bit, false),
+  plane!("libs_2",      Bit, false),
+  plane!("libs_3",      Bit, false),
+  plane!("libs_4",      Bit, false),
+  plane!("libs_5",      Bit, false),
+  plane!("libs_6",      Bit, false),
+  plane!("libs_7",      Bit, false),
+  plane!("libs_8",      Bit, false),
+  plane!("caps_1",      Bit, false),
+  plane!("caps_2",      Bit, false),
+  plane!("caps_3",      Bit, false),
+  plane!("caps_4",      Bit, false),
+  plane!("caps_5",      Bit, false),
+  plane!("caps_6",      Bit, false),
+  plane!("caps_7",      Bit, false),
+  plane!("caps_8",      Bit, false),
+  plane!("ko",          Bit, false),
+  plane!("center",      Byte, false),
+];
+
+static ALPHA_MINI_V3_PLANES: [FeaturePlane; 16] = [
+  plane!("baseline",    Bit, false),
+  plane!("empty",       Bit, false),
+  plane!("friend",      Bit, true),
+  plane!("enemy",       Bit, true),
+  plane!("turns_1",     Bit, false),
+  plane!("turns_2",     Bit, false),
+  plane!("turns_3",     Bit, false),
+  plane!("turns_4",     Bit, false),
+  plane!("libs_1",      Bit, false),
+  plane!("libs_2",      Bit, false),
+  plane!("libs_3",      Bit, false),
+  plane!("libs_4",      Bit, false),
+  plane!("caps_1",      Bit, false),
+  plane!("caps_2",      Bit, false),
+  plane!("caps_3",      Bit, false),
+  plane!("ko",          Bit, false),
+];
+
+static ALPHA_PATTERN_V3_PLANES: [FeaturePlane; 8] = [
+  plane!("empty",       Bit, false),
+  plane!("friend",      Bit, true),
+  plane!("enemy",       Bit, true),
+  plane!("turns_1",     Bit, false),
+  plane!("libs_1",      Bit, false),
+  plane!("libs_2",      Bit, false),
+  plane!("libs_3",      Bit, false),
+  plane!("ko",          Bit, false),
+];
+
+static ALPHA_PATTERN_NOKO_V3_PLANES: [FeaturePlane; 7] = [
+  plane!("empty",       Bit, false),
+  plane!("friend",      Bit, true),
+  plane!("enemy",       Bit, true),
+  plane!("libs_1",      Bit, false),
+  plane!("libs_2",      Bit, false),
+  plane!("libs_3",      Bit, false),
+  plane!("turns_1",     Bit, false),
+];
+
+static FEATURES_PLANES: [FeaturePlane; 4] = [
+  plane!("friend",             Byte, true),
+  plane!("enemy",              Byte, true),
+  plane!("prev_friend",        Byte, true),
+  plane!("prev_enemy",         Byte, true),
+];
+
+static LIB_FEATURES_PLANES: [FeaturePlane; 16] = [
+  plane!("friend",             Byte, true),
+  plane!("friend_atari",       Byte, true),
+  plane!("friend_live_2",      Byte, true),
+  plane!("friend_live_3",      Byte, true),
+  plane!("enemy",              Byte, true),
+  plane!("enemy_atari",        Byte, true),
+  plane!("enemy_live_2",       Byte, true),
+  plane!("enemy_live_3",       Byte, true),
+  plane!("prev_friend",        Byte, true),
+  plane!("prev_friend_atari",  Byte, true),
+  plane!("prev_friend_live_2", Byte, true),
+  plane!("prev_friend_live_3", Byte, true),
+  plane!("prev_enemy",         Byte, true),
+  plane!("prev_enemy_atari",   Byte, true),
+  plane!("prev_enemy_live_2",  Byte, true),
+  plane!("prev_enemy_live_3",  Byte, true),
+];
+
+static EXT_LIB_FEATURES_PLANES: [FeaturePlane; 28] = [
+  plane!("friend",             Byte, true),
+  plane!("friend_atari",       Byte, true),
+  plane!("friend_air_2",       Byte, true),
+  plane!("friend_air_3",       Byte, true),
+  plane!("friend_air_4",       Byte, true),
+  plane!("friend_ko",          Byte, true),
+  plane!("friend_rank_dan_1_2", Byte, true),
+  plane!("friend_rank_dan_3_4", Byte, true),
+  plane!("friend_rank_dan_5_6", Byte, true),
+  plane!("friend_rank_pro_1_3", Byte, true),
+  plane!("friend_rank_pro_4_6", Byte, true),
+  plane!("friend_rank_pro_7_9", Byte, true),
+  plane!("enemy",              Byte, true),
+  plane!("enemy_atari",        Byte, true),
+  plane!("enemy_air_2",        Byte, true),
+  plane!("enemy_air_3",        Byte, true),
+  plane!("enemy_air_4",        Byte, true),
+  plane!("enemy_ko",           Byte, true),
+  plane!("enemy_rank_dan_1_2", Byte, true),
+  plane!("enemy_rank_dan_3_4", Byte, true),
+  plane!("enemy_rank_dan_5_6", Byte, true),
+  plane!("enemy_rank_pro_1_3", Byte, true),
+  plane!("enemy_rank_pro_4_6", Byte, true),
+  plane!("enemy_rank_pro_7_9", Byte, true),
+  plane!("prev_1",             Byte, false),
+  plane!("prev_2",             Byte, false),
+  plane!("prev_3",             Byte, false),
+  plane!("baseline",           Byte, false),
+];
+
+static ALPHA_V1_PLANES: [FeaturePlane; 37] = [
+  plane!("baseline",           Bit, false),
+  plane!("empty",              Bit, false),
+  plane!("friend",             Bit, true),
+  plane!("enemy",              Bit, true),
+  plane!("ko",                 Bit, false),
+  plane!("turns_1",            Bit, false),
+  plane!("turns_2",            Bit, false),
+  plane!("turns_3",            Bit, false),
+  plane!("turns_4",            Bit, false),
+  plane!("turns_5",            Bit, false),
+  plane!("turns_6",            Bit, false),
+  plane!("turns_7",            Bit, false),
+  plane!("turns_8",            Bit, false),
+  plane!("libs_1",             Bit, false),
+  plane!("libs_2",             Bit, false),
+  plane!("libs_3",             Bit, false),
+  plane!("libs_4",             Bit, false),
+  plane!("libs_5",             Bit, false),
+  plane!("libs_6",             Bit, false),
+  plane!("libs_7",             Bit, false),
+  plane!("libs_8",             Bit, false),
+  plane!("csuis_1",            Bit, false),
+  plane!("csuis_2",            Bit, false),
+  plane!("csuis_3",            Bit, false),
+  plane!("csuis_4",            Bit, false),
+  plane!("csuis_5",            Bit, false),
+  plane!("csuis_6",            Bit, false),
+  plane!("csuis_7",            Bit, false),
+  plane!("csuis_8",            Bit, false),
+  plane!("ccaps_1",            Bit, false),
+  plane!("ccaps_2",            Bit, false),
+  plane!("ccaps_3",            Bit, false),
+  plane!("ccaps_4",            Bit, false),
+  plane!("ccaps_5",            Bit, false),
+  plane!("ccaps_6",            Bit, false),
+  plane!("ccaps_7",            Bit, false),
+  plane!("ccaps_8",            Bit, false),
+];
+
+static ALPHA_V2_PLANES: [FeaturePlane; 44] = [
+  plane!("baseline",           Bit, false),
+  plane!("empty",              Bit, false),
+  plane!("friend",             Bit, true),
+  plane!("friend_caps_1",      Bit, true),
+  plane!("friend_caps_2",      Bit, true),
+  plane!("friend_caps_3",      Bit, true),
+  plane!("friend_caps_4",      Bit, true),
+  plane!("friend_caps_5",      Bit, true),
+  plane!("friend_caps_6",      Bit, true),
+  plane!("friend_caps_7",      Bit, true),
+  plane!("friend_caps_8",      Bit, true),
+  plane!("enemy",              Bit, true),
+  plane!("enemy_caps_1",       Bit, true),
+  plane!("enemy_caps_2",       Bit, true),
+  plane!("enemy_caps_3",       Bit, true),
+  plane!("enemy_caps_4",       Bit, true),
+  plane!("enemy_caps_5",       Bit, true),
+  plane!("enemy_caps_6",       Bit, true),
+  plane!("enemy_caps_7",       Bit, true),
+  plane!("enemy_caps_8",       Bit, true),
+  plane!("enemy_rank_1a_2a",   Bit, true),
+  plane!("enemy_rank_3a_4a",   Bit, true),
+  plane!("enemy_rank_5a_7a",   Bit, true),
+  plane!("enemy_rank_1p_3p",   Bit, true),
+  plane!("enemy_rank_4p_6p",   Bit, true),
+  plane!("enemy_rank_7p_9p",   Bit, true),
+  plane!("turns_1",            Bit, false),
+  plane!("turns_2",            Bit, false),
+  plane!("turns_3",            Bit, false),
+  plane!("turns_4",            Bit, false),
+  plane!("turns_5",            Bit, false),
+  plane!("turns_6",            Bit, false),
+  plane!("turns_7",            Bit, false),
+  plane!("turns_8",            Bit, false),
+  plane!("libs_1",             Bit, false),
+  plane!("libs_2",             Bit, false),
+  plane!("libs_3",             Bit, false),
+  plane!("libs_4",             Bit, false),
+  plane!("libs_5",             Bit, false),
+  plane!("libs_6",             Bit, false),
+  plane!("libs_7",             Bit, false),
+  plane!("libs_8",             Bit, false),
+  plane!("ko",                 Bit, false),
+  plane!("center",             Byte, false),
+];
+
+/// Extracted by `TxnStateFeaturesData`: stones at the current and previous
+/// turns.
+pub static FEATURES: FeatureSet = FeatureSet{
+  name:     "features",
+  version:  1,
+  planes:   &FEATURES_PLANES,
+};
+
+/// Extracted by `TxnStateLibFeaturesData`.
+pub static LIB_FEATURES: FeatureSet = FeatureSet{
+  name:     "lib_features",
+  version:  2,
+  planes:   &LIB_FEATURES_PLANES,
+};
+
+/// Extracted by `TxnStateExtLibFeatsData`.
+pub static EXT_LIB_FEATURES: FeatureSet = FeatureSet{
+  name:     "ext_lib_features",
+  version:  3,
+  planes:   &EXT_LIB_FEATURES_PLANES,
+};
+
+pub static ALPHA_V1: FeatureSet = FeatureSet{
+  name:     "alpha_v1",
+  version:  1,
+  planes:   &ALPHA_V1_PLANES,
+};
+
+pub static ALPHA_V2: FeatureSet = FeatureSet{
+  name:     "alpha_v2",
+  version:  2,
+  planes:   &ALPHA_V2_PLANES,
+};
+
+pub static ALPHA_V3: FeatureSet = FeatureSet{
+  name:     "alpha_v3",
+  version:  3,
+  planes:   &ALPHA_V3_PLANES,
+};
+
+pub static ALPHA_MINI_V3: FeatureSet = FeatureSet{
+  name:     "alpha_mini_v3",
+  version:  3,
+  planes:   &ALPHA_MINI_V3_PLANES,
+};
+
+pub static ALPHA_PATTERN_V3: FeatureSet = FeatureSet{
+  name:     "alpha_pattern_v3",
+  version:  3,
+  planes:   &ALPHA_PATTERN_V3_PLANES,
+};
+
+pub static ALPHA_PATTERN_NOKO_V3: FeatureSet = FeatureSet{
+  name:     "alpha_pattern_noko_v3",
+  version:  3,
+  planes:   &ALPHA_PATTERN_NOKO_V3_PLANES,
+};
+
+static SHAPE_V1_PLANES: [FeaturePlane; 3] = [
+  plane!("shape_diamond",   Byte, true),
+  plane!("shape_5x5",       Byte, true),
+  plane!("shape_corner",    Byte, true),
+];
+
+/// Extracted by `shapes::TxnStateShapeData`. Extraction also needs a shape
+/// dictionary, so it has no registered builder; see
+/// `build_shape_feature_extractor`.
+pub static SHAPE_V1: FeatureSet = FeatureSet{
+  name:     "shape_v1",
+  version:  1,
+  planes:   &SHAPE_V1_PLANES,
+};
+
+struct FeatureSetEntry {
+  feature_set:  &'static FeatureSet,
+  build:        Option<fn(TxnStateConfig) -> Box<FeatureExtractor>>,
+}
+
+static FEATURE_SETS: [FeatureSetEntry; 10] = [
+  FeatureSetEntry{feature_set: &FEATURES,         build: Some(build_extractor::<TxnStateFeaturesData>)},
+  FeatureSetEntry{feature_set: &LIB_FEATURES,     build: Some(build_extractor::<TxnStateLibFeaturesData>)},
+  FeatureSetEntry{feature_set: &EXT_LIB_FEATURES, build: Some(build_extractor::<TxnStateExtLibFeatsData>)},
+  FeatureSetEntry{feature_set: &ALPHA_V1,         build: Some(build_extractor::<TxnStateAlphaFeatsV1Data>)},
+  FeatureSetEntry{feature_set: &ALPHA_V2,         build: Some(build_extractor::<TxnStateAlphaFeatsV2Data>)},
+  FeatureSetEntry{feature_set: &ALPHA_V3,         build: Some(build_extractor::<TxnStateAlphaV3FeatsData>)},
+  FeatureSetEntry{feature_set: &ALPHA_MINI_V3,    build: Some(build_extractor::<TxnStateAlphaMiniV3FeatsData>)},
+  FeatureSetEntry{feature_set: &ALPHA_PATTERN_V3, build: Some(build_extractor::<TxnStateAlphaPatternV3FeatsData>)},
+  FeatureSetEntry{feature_set: &ALPHA_PATTERN_NOKO_V3,  build: Some(build_extractor::<TxnStateAlphaPatternNoKoV3FeatsData>)},
+  FeatureSetEntry{feature_set: &SHAPE_V1,         build: None},
+];
+
+pub fn registered_feature_sets() -> Vec<&'static FeatureSet> {
+  FEATURE_SETS.iter().map(|entry| entry.feature_set).collect()
+}
+
+pub fn lookup_feature_set(name: &str) -> Option<&'static FeatureSet> {
+  FEATURE_SETS.iter().find(|entry| entry.feature_set.name == name).map(|entry| entry.feature_set)
+}
+
+/// Plays moves and extracts frames for a feature set chosen at runtime.
+pub trait FeatureExtractor {
+  fn feature_set(&self) -> &'static FeatureSet;
+  fn reset(&mut self);
+  fn current_turn(&self) -> Stone;
+  fn unsafe_set_current_turn(&mut self, turn: Stone);
+  fn place_setup_stones(&mut self, stones: &[(Stone, Point)]) -> Result<(), ()>;
+  fn try_action(&mut self, turn: Stone, action: Action) -> Result<(), ()>;
+  fn extract_relative_planes(&self, turn: Stone) -> Vec<u8>;
+
+  fn extract_relative_serial_frame(&self, turn: Stone) -> Vec<u8> {
+    self.feature_set().serialize_frame(&self.extract_relative_planes(turn))
+  }
+}
+
+pub struct TxnStateFeatureExtractor<D> where D: TxnStateSerialFeatsData {
+  state:    TxnState<D>,
+}
+
+impl<D> TxnStateFeatureExtractor<D> where D: TxnStateSerialFeatsData {
+  pub fn new(state_cfg: TxnStateConfig, data: D) -> TxnStateFeatureExtractor<D> {
+    let mut state = TxnState::new(state_cfg, data);
+    state.reset();
+    TxnStateFeatureExtractor{
+      state:    state,
+    }
+  }
+
+  pub fn state(&self) -> &TxnState<D> {
+    &self.state
+  }
+}
+
+impl<D> FeatureExtractor for TxnStateFeatureExtractor<D> where D: TxnStateSerialFeatsData {
+  fn feature_set(&self) -> &'static FeatureSet {
+    D::feature_set()
+  }
+
+  fn reset(&mut self) {
+    self.state.reset();
+  }
+
+  fn current_turn(&self) -> Stone {
+    self.state.current_turn()
+  }
+
+  fn unsafe_set_current_turn(&mut self, turn: Stone) {
+    self.state.unsafe_set_current_turn(turn);
+  }
+
+  fn place_setup_stones(&mut self, stones: &[(Stone, Point)]) -> Result<(), ()> {
+    self.state.place_setup_stones(stones)
+  }
+
+  fn try_action(&mut self, turn: Stone, action: Action) -> Result<(), ()> {
+    match self.state.try_action(turn, action) {
+      Ok(_) => {
+        self.state.commit();
+        Ok(())
+      }
+      Err(_) => {
+        self.state.undo();
+        Err(())
+      }
+    }
+  }
+
+  fn extract_relative_planes(&self, turn: Stone) -> Vec<u8> {
+    let mut buf: Vec<u8> = repeat(0).take(D::feature_set().num_planes() * Board::SIZE).collect();
+    self.state.get_data().extract_relative_features(turn, &mut buf);
+    buf
+  }
+}
+
+pub fn build_shape_feature_extractor(state_cfg: TxnStateConfig, dict: Arc<ShapeDictionary>) -> Box<FeatureExtractor> {
+  Box::new(TxnStateFeatureExtractor::new(state_cfg, TxnStateShapeData::new(Some(dict))))
+}
+
+fn build_extractor<D>(state_cfg: TxnStateConfig) -> Box<FeatureExtractor>
+where D: 'static + TxnStateSerialFeatsData + Default {
+  Box::new(TxnStateFeatureExtractor::new(state_cfg, D::default()))
+}
+
+/// Whether `build_feature_extractor` can build the named feature set.
+pub fn has_feature_extractor(name: &str) -> bool {
+  FEATURE_SETS.iter().any(|entry| entry.feature_set.name == name && entry.build.is_some())
+}
+
+/// Builds an extractor for a registered feature set. Returns `None` for
+/// unknown sets and for sets which need more than a state config (`shape_v1`).
+pub fn build_feature_extractor(name: &str, state_cfg: TxnStateConfig) -> Option<Box<FeatureExtractor>> {
+  FEATURE_SETS.iter()
+    .find(|entry| entry.feature_set.name == name)
+    .and_then(|entry| entry.build)
+    .map(|build| build(state_cfg))
+}
@@ -7,6 +7,11 @@ use txnstate::{
   for_each_adjacent, for_each_diagonal, check_good_move_fast,
 };
 use txnstate::extras::{TxnStateLegalityData, TxnStateNodeData};
+use txnstate::feature_sets::{
+  FeatureSet,
+  FEATURES, LIB_FEATURES, EXT_LIB_FEATURES, ALPHA_V1, ALPHA_V2,
+  ALPHA_V3, ALPHA_MINI_V3, ALPHA_PATTERN_V3, ALPHA_PATTERN_NOKO_V3,
+};
 use util::{slice_twice_mut};
 
 use array_new::{NdArraySerialize, Array3d, BitArray3d};
@@ -21,6 +26,39 @@ pub trait TxnStateFeatures: TxnStateData {
   fn extract_relative_features(&self, turn: Stone, dst_buf: &mut [u8]);
 }
 
+/// Feature data which can be written to an episode database; see
+/// `txnstate::feature_sets` for the registered feature sets.
+pub trait TxnStateSerialFeatsData: TxnStateFeatures {
+  fn feature_set() -> &'static FeatureSet;
+
+  fn feature_set_name() -> &'static str {
+    Self::feature_set().name
+  }
+
+  fn feature_version() -> u32 {
+    Self::feature_set().version
+  }
+
+  fn num_bit_planes() -> usize {
+    Self::feature_set().num_bit_planes()
+  }
+
+  fn num_byte_planes() -> usize {
+    Self::feature_set().num_byte_planes()
+  }
+
+  fn serial_frame_size() -> usize {
+    Self::feature_set().serial_frame_size()
+  }
+
+  fn extract_relative_serial_frame(&self, turn: Stone) -> Vec<u8> {
+    let feature_set = Self::feature_set();
+    let mut buf: Vec<u8> = repeat(0).take(feature_set.num_planes() * Board::SIZE).collect();
+    self.extract_relative_features(turn, &mut buf);
+    feature_set.serialize_frame(&buf)
+  }
+}
+
 #[derive(Clone, RustcDecodable, RustcEncodable)]
 pub struct TxnStateFeaturesData {
   //compute_diff:       bool,
@@ -98,6 +136,24 @@ impl TxnStateFeaturesData {
   }
 }
 
+impl TxnStateFeatures for TxnStateFeaturesData {
+  fn extract_relative_features(&self, turn: Stone, dst_buf: &mut [u8]) {
+    TxnStateFeaturesData::extract_relative_features(self, turn, dst_buf);
+  }
+}
+
+impl TxnStateSerialFeatsData for TxnStateFeaturesData {
+  fn feature_set() -> &'static FeatureSet {
+    &FEATURES
+  }
+}
+
+impl Default for TxnStateFeaturesData {
+  fn default() -> TxnStateFeaturesData {
+    TxnStateFeaturesData::new()
+  }
+}
+
 impl TxnStateData for TxnStateFeaturesData {
   fn reset(&mut self) {
     self.time_step = 0;
@@ -314,6 +370,24 @@ impl TxnStateLibFeaturesData {
   }
 }
 
+impl TxnStateFeatures for TxnStateLibFeaturesData {
+  fn extract_relative_features(&self, turn: Stone, dst_buf: &mut [u8]) {
+    TxnStateLibFeaturesData::extract_relative_features(self, turn, dst_buf);
+  }
+}
+
+impl TxnStateSerialFeatsData for TxnStateLibFeaturesData {
+  fn feature_set() -> &'static FeatureSet {
+    &LIB_FEATURES
+  }
+}
+
+impl Default for TxnStateLibFeaturesData {
+  fn default() -> TxnStateLibFeaturesData {
+    TxnStateLibFeaturesData::new()
+  }
+}
+
 impl TxnStateData for TxnStateLibFeaturesData {
   fn reset(&mut self) {
     self.time_step = 0;
@@ -644,6 +718,24 @@ impl TxnStateExtLibFeatsData {
   }
 }
 
+impl TxnStateFeatures for TxnStateExtLibFeatsData {
+  fn extract_relative_features(&self, turn: Stone, dst_buf: &mut [u8]) {
+    TxnStateExtLibFeatsData::extract_relative_features(self, turn, dst_buf);
+  }
+}
+
+impl TxnStateSerialFeatsData for TxnStateExtLibFeatsData {
+  fn feature_set() -> &'static FeatureSet {
+    &EXT_LIB_FEATURES
+  }
+}
+
+impl Default for TxnStateExtLibFeatsData {
+  fn default() -> TxnStateExtLibFeatsData {
+    TxnStateExtLibFeatsData::new()
+  }
+}
+
 impl TxnStateData for TxnStateExtLibFeatsData {
   fn reset(&mut self) {
     for p in 0 .. Self::GRAY {
@@ -1272,6 +1364,24 @@ impl TxnStateAlphaFeatsV1Data {
   }
 }
 
+impl TxnStateFeatures for TxnStateAlphaFeatsV1Data {
+  fn extract_relative_features(&self, turn: Stone, dst_buf: &mut [u8]) {
+    TxnStateAlphaFeatsV1Data::extract_relative_features(self, turn, dst_buf);
+  }
+}
+
+impl TxnStateSerialFeatsData for TxnStateAlphaFeatsV1Data {
+  fn feature_set() -> &'static FeatureSet {
+    &ALPHA_V1
+  }
+}
+
+impl Default for TxnStateAlphaFeatsV1Data {
+  fn default() -> TxnStateAlphaFeatsV1Data {
+    TxnStateAlphaFeatsV1Data::new()
+  }
+}
+
 impl TxnStateData for TxnStateAlphaFeatsV1Data {
   fn reset(&mut self) {
     for p in 0 .. Self::BLACK_PLANE {
@@ -2180,6 +2290,24 @@ impl TxnStateAlphaFeatsV2Data {
   }
 }
 
+impl TxnStateFeatures for TxnStateAlphaFeatsV2Data {
+  fn extract_relative_features(&self, turn: Stone, dst_buf: &mut [u8]) {
+    TxnStateAlphaFeatsV2Data::extract_relative_features(self, turn, dst_buf);
+  }
+}
+
+impl TxnStateSerialFeatsData for TxnStateAlphaFeatsV2Data {
+  fn feature_set() -> &'static FeatureSet {
+    &ALPHA_V2
+  }
+}
+
+impl Default for TxnStateAlphaFeatsV2Data {
+  fn default() -> TxnStateAlphaFeatsV2Data {
+    TxnStateAlphaFeatsV2Data::new()
+  }
+}
+
 impl TxnStateData for TxnStateAlphaFeatsV2Data {
   fn reset(&mut self) {
     // FIXME(20160202): technically, should have ranks when the board is empty,
@@ -2881,6 +3009,24 @@ impl TxnStateAlphaV3FeatsData {
   }
 }
 
+impl TxnStateFeatures for TxnStateAlphaV3FeatsData {
+  fn extract_relative_features(&self, turn: Stone, dst_buf: &mut [u8]) {
+    TxnStateAlphaV3FeatsData::extract_relative_features(self, turn, dst_buf);
+  }
+}
+
+impl TxnStateSerialFeatsData for TxnStateAlphaV3FeatsData {
+  fn feature_set() -> &'static FeatureSet {
+    &ALPHA_V3
+  }
+}
+
+impl Default for TxnStateAlphaV3FeatsData {
+  fn default() -> TxnStateAlphaV3FeatsData {
+    TxnStateAlphaV3FeatsData::new()
+  }
+}
+
 impl TxnStateData for TxnStateAlphaV3FeatsData {
   fn reset(&mut self) {
     for p in 0 .. Self::BLACK_PLANE {
@@ -3303,6 +3449,24 @@ impl TxnStateAlphaMiniV3FeatsData {
   }
 }
 
+impl TxnStateFeatures for TxnStateAlphaMiniV3FeatsData {
+  fn extract_relative_features(&self, turn: Stone, dst_buf: &mut [u8]) {
+    TxnStateAlphaMiniV3FeatsData::extract_relative_features(self, turn, dst_buf);
+  }
+}
+
+impl TxnStateSerialFeatsData for TxnStateAlphaMiniV3FeatsData {
+  fn feature_set() -> &'static FeatureSet {
+    &ALPHA_MINI_V3
+  }
+}
+
+impl Default for TxnStateAlphaMiniV3FeatsData {
+  fn default() -> TxnStateAlphaMiniV3FeatsData {
+    TxnStateAlphaMiniV3FeatsData::new()
+  }
+}
+
 impl TxnStateData for TxnStateAlphaMiniV3FeatsData {
   fn reset(&mut self) {
     for p in 0 .. Self::BLACK_PLANE {
@@ -3615,6 +3779,24 @@ impl TxnStateAlphaPatternV3FeatsData {
   }
 }
 
+impl TxnStateFeatures for TxnStateAlphaPatternV3FeatsData {
+  fn extract_relative_features(&self, turn: Stone, dst_buf: &mut [u8]) {
+    TxnStateAlphaPatternV3FeatsData::extract_relative_features(self, turn, dst_buf);
+  }
+}
+
+impl TxnStateSerialFeatsData for TxnStateAlphaPatternV3FeatsData {
+  fn feature_set() -> &'static FeatureSet {
+    &ALPHA_PATTERN_V3
+  }
+}
+
+impl Default for TxnStateAlphaPatternV3FeatsData {
+  fn default() -> TxnStateAlphaPatternV3FeatsData {
+    TxnStateAlphaPatternV3FeatsData::new()
+  }
+}
+
 impl TxnStateData for TxnStateAlphaPatternV3FeatsData {
   fn reset(&mut self) {
     for p in 0 .. Self::BLACK_PLANE {
@@ -3897,6 +4079,24 @@ impl TxnStateAlphaPatternNoKoV3FeatsData {
   }
 }
 
+impl TxnStateFeatures for TxnStateAlphaPatternNoKoV3FeatsData {
+  fn extract_relative_features(&self, turn: Stone, dst_buf: &mut [u8]) {
+    TxnStateAlphaPatternNoKoV3FeatsData::extract_relative_features(self, turn, dst_buf);
+  }
+}
+
+impl TxnStateSerialFeatsData for TxnStateAlphaPatternNoKoV3FeatsData {
+  fn feature_set() -> &'static FeatureSet {
+    &ALPHA_PATTERN_NOKO_V3
+  }
+}
+
+impl Default for TxnStateAlphaPatternNoKoV3FeatsData {
+  fn default() -> TxnStateAlphaPatternNoKoV3FeatsData {
+    TxnStateAlphaPatternNoKoV3FeatsData::new()
+  }
+}
+
 impl TxnStateData for TxnStateAlphaPatternNoKoV3FeatsData {
   fn reset(&mut self) {
     for p in 0 .. Self::BLACK_PLANE {
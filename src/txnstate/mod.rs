@@ -1,18 +1,31 @@
-use board::{Board, Rules, RuleSet, Coord, PlayerRank, Stone, Point, Action};
+use board::{Board, Rules, RuleSet, HandicapKomi, KoRule, SuicideRule, Coord, PlayerRank, Stone, Point, Action};
 use gtp_board::{dump_xcoord};
 use pattern::{Pattern3x3, LibPattern3x3};
 
 use rustc_serialize::{Decodable, Encodable};
 use std::cmp::{max};
-use std::collections::{BTreeSet};
+use std::collections::{BTreeSet, HashSet};
 use std::iter::{repeat};
+use std::mem::{replace, size_of};
+use std::sync::{Arc};
 use vec_map::{VecMap};
 
 pub mod extras;
+pub mod feature_sets;
 pub mod features;
 
 pub const TOMBSTONE:  Point = Point(-1);
 
+/// Zobrist key of a stone at a point, for positional hashing. The keys are
+/// derived from a fixed seed (splitmix64) so hashes are stable across runs.
+pub fn zobrist_key(stone: Stone, point: Point) -> u64 {
+  let mut z = (1 + stone.offset() * Board::SIZE + point.idx()) as u64;
+  z = z.wrapping_mul(0x9e3779b97f4a7c15);
+  z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+  z ^ (z >> 31)
+}
+
 pub fn for_each_adjacent<F>(point: Point, mut f: F) where F: FnMut(Point) {
   let (x, y) = (point.0 % Board::DIM_PT.0, point.0 / Board::DIM_PT.0);
   let upper = Board::DIM_PT.0 - 1;
@@ -200,6 +213,7 @@ pub enum IllegalReason {
   NotEmpty,
   Suicide,
   Ko,
+  Superko,
 }
 
 pub trait TxnStateData: Encodable + Decodable {
@@ -224,6 +238,7 @@ pub struct TxnPosition {
   num_stones:   [usize; 2],
   stones:       Vec<Stone>,
   stone_epochs: Vec<i16>,
+  hash:         u64,
 
   // State that depends on the previously placed point.
   // FIXME(20151120)
@@ -256,6 +271,7 @@ pub struct TxnPositionProposal {
   // For reverting stateful data structures.
   place:        Option<(Stone, Point)>,
   prev_place:   (Stone, Point),
+  prev_hash:    u64,
 }
 
 #[derive(Clone, RustcDecodable, RustcEncodable, Debug)]
@@ -843,6 +859,10 @@ pub struct TxnStateConfig {
   pub rules:    Rules,
   pub ranks:    [PlayerRank; 2],
   pub komi:     f32,
+  /// Keep the committed actions for `history`, `undo_plies`, and `seek_ply`.
+  /// Off by default: search node and rollout states are cloned all the time,
+  /// and their clones should stay O(board) rather than O(game).
+  pub history:  bool,
 }
 
 impl Default for TxnStateConfig {
@@ -854,14 +874,76 @@ impl Default for TxnStateConfig {
         PlayerRank::Dan(9),
       ],
       komi:     6.5,
+      history:  false,
+    }
+  }
+}
+
+/// A block of position hashes which no longer changes, shared by every clone
+/// of the state which froze it.
+#[derive(RustcDecodable, RustcEncodable)]
+struct FrozenPositionHashes {
+  hashes: HashSet<u64>,
+  parent: Option<Arc<FrozenPositionHashes>>,
+}
+
+/// The hashes of every committed position, for superko. The older hashes are
+/// frozen in shared blocks, so that cloning a state (e.g. for a search node or
+/// a rollout) only copies the few recent ones.
+#[derive(Clone, RustcDecodable, RustcEncodable)]
+struct PositionHashes {
+  frozen: Option<Arc<FrozenPositionHashes>>,
+  recent: Vec<u64>,
+}
+
+impl PositionHashes {
+  const RECENT_LEN: usize = 32;
+
+  fn new() -> PositionHashes {
+    let mut hashes = PositionHashes{
+      frozen: None,
+      recent: Vec::with_capacity(PositionHashes::RECENT_LEN),
+    };
+    hashes.insert(0);
+    hashes
+  }
+
+  fn clear(&mut self) {
+    self.frozen = None;
+    self.recent.clear();
+  }
+
+  fn insert(&mut self, hash: u64) {
+    self.recent.push(hash);
+    if self.recent.len() >= PositionHashes::RECENT_LEN {
+      let hashes = self.recent.drain(..).collect();
+      self.frozen = Some(Arc::new(FrozenPositionHashes{
+        hashes: hashes,
+        parent: self.frozen.take(),
+      }));
+    }
+  }
+
+  fn contains(&self, hash: u64) -> bool {
+    if self.recent.contains(&hash) {
+      return true;
+    }
+    let mut block = self.frozen.as_ref();
+    while let Some(frozen) = block {
+      if frozen.hashes.contains(&hash) {
+        return true;
+      }
+      block = frozen.parent.as_ref();
     }
+    false
   }
 }
 
 /// Transactional board state.
 ///
 /// Some various properties we would like TxnState to have:
-/// - support 1-step undo
+/// - support 1-step undo of the pending txn, and multi-step undo of committed
+///   actions by replaying the game history
 /// - the board position and chains list should be in a consistent state before
 ///   entering a txn and after committing a txn
 /// - the TxnState holds auxiliary extra data (e.g., useful for search nodes vs.
@@ -884,6 +966,23 @@ pub struct TxnState<Data=()> where Data: TxnStateData + Clone {
   // Position data. This is managed by the TxnState.
   pub position: TxnPosition,
   proposal:     TxnPositionProposal,
+  // Hashes of every committed position, only kept under superko rules.
+  // Committed positions are never taken back one at a time (`seek_ply`
+  // rebuilds from the setup stones), so a set suffices.
+  position_hashes:  PositionHashes,
+
+  // Setup stones placed before the first action (e.g. handicap stones), and
+  // the turn to move after them.
+  setup_stones: Vec<(Stone, Point)>,
+  setup_turn:   Stone,
+  // Number of committed actions since the last reset, not counting setup
+  // stones; kept whether or not the history is.
+  ply:          usize,
+  // Committed actions since the last reset, and actions which were undone
+  // via `undo_plies` (most recently undone last). Only kept if
+  // `config.history` is set.
+  history:      Vec<(Stone, Action)>,
+  redo_history: Vec<(Stone, Action)>,
 
   // Chains data. This is managed separately.
   pub chains:   TxnChainsList,
@@ -907,6 +1006,12 @@ impl TxnState<()> {
     self.num_captures = other.num_captures.clone();
     self.position     = other.position.clone();
     self.proposal     = other.proposal.clone();
+    self.position_hashes.clone_from(&other.position_hashes);
+    self.setup_stones.clone_from(&other.setup_stones);
+    self.setup_turn   = other.setup_turn;
+    self.ply          = other.ply;
+    self.history.clone_from(&other.history);
+    self.redo_history.clone_from(&other.redo_history);
     self.chains       = other.chains.clone();
   }
 }
@@ -931,6 +1036,7 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
         num_stones:   [0, 0],
         stones:       stones,
         stone_epochs: stone_epochs,
+        hash:         0,
         prev_play:    None,
         ko:           None,
         prev_ko:      None,
@@ -957,13 +1063,119 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
         ko_candidate: None,
         place:        None,
         prev_place:   (Stone::Empty, TOMBSTONE),
+        prev_hash:    0,
       },
+      position_hashes:  PositionHashes::new(),
+      setup_stones: vec![],
+      setup_turn:   Stone::Black,
+      ply:          0,
+      history:      vec![],
+      redo_history: vec![],
       chains: TxnChainsList::new(),
       data: data,
     }
   }
 
   pub fn reset(&mut self) {
+    self.reset_position();
+    self.setup_stones.clear();
+    self.setup_turn = Stone::Black;
+    self.history.clear();
+    self.redo_history.clear();
+  }
+
+  /// Places setup stones (e.g. SGF `AB`/`AW` handicap stones) before the
+  /// first action. Setup stones are not part of the history; they are kept
+  /// by `seek_ply` and cleared by `reset`. The current turn is unchanged.
+  /// If any stone cannot be placed, none of them are.
+  pub fn place_setup_stones(&mut self, stones: &[(Stone, Point)]) -> Result<(), ()> {
+    assert!(!self.in_txn && !self.in_soft_txn);
+    if self.ply > 0 {
+      return Err(());
+    }
+    let turn = self.position.turn;
+    let num_prev_stones = self.setup_stones.len();
+    for &(stone, point) in stones {
+      match self.try_place(stone, point) {
+        Ok(_) => {
+          self.commit();
+          self.uncount_setup_ply();
+        }
+        Err(_) => {
+          // Rebuild the position from the setup stones placed before this
+          // call rather than leave some of `stones` on the board.
+          self.undo();
+          self.setup_stones.truncate(num_prev_stones);
+          self.reset_position();
+          self.replay_setup_stones();
+          self.position.turn = turn;
+          return Err(());
+        }
+      }
+      self.setup_stones.push((stone, point));
+    }
+    self.position.turn = turn;
+    self.position.ko = None;
+    self.setup_turn = turn;
+    self.redo_history.clear();
+    Ok(())
+  }
+
+  fn replay_setup_stones(&mut self) {
+    for &(stone, point) in self.setup_stones.clone().iter() {
+      self.replay_action(stone, Action::Place{point: point});
+      self.uncount_setup_ply();
+    }
+    self.position.turn = self.setup_turn;
+    self.position.ko = None;
+  }
+
+  pub fn setup_stones(&self) -> &[(Stone, Point)] {
+    &self.setup_stones
+  }
+
+  /// The number of handicap stones: the black setup stones, if there are at
+  /// least two of them and no white setup stones.
+  pub fn num_handicap_stones(&self) -> usize {
+    let mut num_black = 0;
+    for &(stone, _) in self.setup_stones.iter() {
+      match stone {
+        Stone::Black => num_black += 1,
+        _ => return 0,
+      }
+    }
+    if num_black >= 2 {
+      num_black
+    } else {
+      0
+    }
+  }
+
+  /// Places black handicap stones as setup stones on the empty board, after
+  /// which white is to move.
+  pub fn place_handicap_stones(&mut self, points: &[Point]) -> Result<(), ()> {
+    if self.ply > 0 || !self.setup_stones.is_empty() || points.len() < 2 {
+      return Err(());
+    }
+    let stones: Vec<_> = points.iter().map(|&pt| (Stone::Black, pt)).collect();
+    try!(self.place_setup_stones(&stones));
+    self.position.turn = Stone::White;
+    self.setup_turn = Stone::White;
+    Ok(())
+  }
+
+  /// The points white receives for the handicap stones under the rules, on
+  /// top of the komi; the scoring functions add them in.
+  pub fn handicap_komi(&self) -> f32 {
+    let num_stones = self.num_handicap_stones();
+    match self.rules.handicap_komi {
+      HandicapKomi::Zero => 0.0,
+      HandicapKomi::One => num_stones as f32,
+      HandicapKomi::OneExceptFirst => num_stones.saturating_sub(1) as f32,
+    }
+  }
+
+  fn reset_position(&mut self) {
     // TODO(20151105)
     self.in_soft_txn = false;
     self.in_txn = false;
@@ -984,8 +1196,12 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
     self.position.prev_play = None;
     self.position.ko = None;
     self.position.prev_ko = None;
+    self.position.hash = 0;
+    self.ply = 0;
     self.position.prev_self_atari = false;
     self.position.prev_oppo_atari = false;
+    self.position_hashes.clear();
+    self.position_hashes.insert(0);
     self.chains.reset();
     self.data.reset();
   }
@@ -1003,6 +1219,12 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
     self.num_captures = other.num_captures.clone();
     self.position     = other.position.clone();
     self.proposal     = other.proposal.clone();
+    self.position_hashes.clone_from(&other.position_hashes);
+    self.setup_stones.clone_from(&other.setup_stones);
+    self.setup_turn   = other.setup_turn;
+    self.ply          = other.ply;
+    self.history.clone_from(&other.history);
+    self.redo_history.clone_from(&other.redo_history);
     self.chains       = other.chains.clone();
     self.data         = data;
   }
@@ -1020,6 +1242,12 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
       num_captures: self.num_captures.clone(),
       position:     self.position.clone(),
       proposal:     self.proposal.clone(),
+      position_hashes:  self.position_hashes.clone(),
+      setup_stones: self.setup_stones.clone(),
+      setup_turn:   self.setup_turn,
+      ply:          self.ply,
+      history:      self.history.clone(),
+      redo_history: self.redo_history.clone(),
       chains:       self.chains.clone(),
       data:         (),
     }
@@ -1035,6 +1263,111 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
     &self.data
   }
 
+  /// The actions committed since the last reset, oldest first; empty unless
+  /// `config.history` is set.
+  pub fn history(&self) -> &[(Stone, Action)] {
+    &self.history
+  }
+
+  /// The estimated heap size of the history and of the recent superko
+  /// position hashes; the older hashes are shared between clones.
+  pub fn approx_history_bytes(&self) -> usize {
+    (self.history.capacity() + self.redo_history.capacity()) * size_of::<(Stone, Action)>()
+        + self.position_hashes.recent.capacity() * size_of::<u64>()
+  }
+
+  /// Stops keeping the history and frees it, e.g. for the root state of a
+  /// search whose nodes clone it.
+  pub fn disable_history(&mut self) {
+    self.config.history = false;
+    self.history = vec![];
+    self.redo_history = vec![];
+  }
+
+  pub fn current_ply(&self) -> usize {
+    self.ply
+  }
+
+  /// The point played by the last committed action; None after a pass or
+  /// before the first action.
+  pub fn last_play(&self) -> Option<(Stone, Point)> {
+    if self.ply > 0 {
+      self.position.prev_play
+    } else {
+      None
+    }
+  }
+
+  fn uncount_setup_ply(&mut self) {
+    self.ply -= 1;
+    if self.config.history {
+      self.history.pop();
+    }
+  }
+
+  /// Takes back the last `num_plies` committed actions. Unlike `undo`, this
+  /// works across commits; see `seek_ply`.
+  pub fn undo_plies(&mut self, num_plies: usize) -> Result<(), ()> {
+    if num_plies > self.ply {
+      return Err(());
+    }
+    let ply = self.ply - num_plies;
+    self.seek_ply(ply)
+  }
+
+  /// Replays the next `num_plies` actions which were taken back by
+  /// `undo_plies` or `seek_ply`.
+  pub fn redo_plies(&mut self, num_plies: usize) -> Result<(), ()> {
+    if num_plies > self.redo_history.len() {
+      return Err(());
+    }
+    let ply = self.ply + num_plies;
+    self.seek_ply(ply)
+  }
+
+  /// Moves to the position after `ply` committed actions. Going back rebuilds
+  /// the position, chains, and data by replaying the remaining history from
+  /// the setup stones; going forward replays the redo history. Without
+  /// `config.history` only the current ply can be sought.
+  ///
+  /// FIXME(20160420): a turn set by `unsafe_set_current_turn` after the last
+  /// committed action is not restored.
+  pub fn seek_ply(&mut self, ply: usize) -> Result<(), ()> {
+    assert!(!self.in_txn && !self.in_soft_txn);
+    if !self.config.history {
+      return if ply == self.ply { Ok(()) } else { Err(()) };
+    }
+    if ply > self.history.len() + self.redo_history.len() {
+      return Err(());
+    }
+    if ply < self.history.len() {
+      let mut history = replace(&mut self.history, Vec::with_capacity(ply));
+      let mut redo_history = replace(&mut self.redo_history, vec![]);
+      for i in (ply .. history.len()).rev() {
+        redo_history.push(history[i]);
+      }
+      history.truncate(ply);
+      self.reset_position();
+      self.replay_setup_stones();
+      for &(turn, action) in history.iter() {
+        self.replay_action(turn, action);
+      }
+      self.redo_history = redo_history;
+    }
+    while self.history.len() < ply {
+      let (turn, action) = *self.redo_history.last().unwrap();
+      self.replay_action(turn, action);
+    }
+    Ok(())
+  }
+
+  fn replay_action(&mut self, turn: Stone, action: Action) {
+    match self.try_action(turn, action) {
+      Ok(_) => self.commit(),
+      Err(e) => panic!("TxnState: failed to replay history: {:?} {:?} {:?}", turn, action, e),
+    }
+  }
+
   pub fn is_terminal(&self) -> bool {
     // FIXME(20151106): some rulesets require white to pass first?
     self.resigned.is_some() || (self.passed[0] && self.passed[1])
@@ -1095,6 +1428,16 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
     }
   }
 
+  /// Zobrist hash of the stones currently on the board.
+  pub fn current_hash(&self) -> u64 {
+    self.position.hash
+  }
+
+  /// The number of prisoners taken by `stone`.
+  pub fn current_captures(&self, stone: Stone) -> usize {
+    self.num_captures[stone.offset()]
+  }
+
   pub fn current_ko(&self) -> Option<(Stone, Point)> {
     self.position.ko
   }
@@ -1114,8 +1457,7 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
     if self.rules.score_territory {
       // FIXME(20151107)
     }
-    // TODO(20151107): handicap komi (for non-Japanese rulesets).
-    //w_score - b_score + komi
+    //w_score - b_score + komi + self.handicap_komi()
     unimplemented!();
   }
 
@@ -1181,7 +1523,7 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
     score[0] += territory_count[0] as f32;
     score[1] += territory_count[1] as f32;
 
-    score[1] - score[0] + komi
+    score[1] - score[0] + komi + self.handicap_komi()
   }
 
   pub fn current_score_rollout(&self, komi: f32) -> f32 {
@@ -1201,7 +1543,7 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
         _ => {}
       }
     }
-    w_score - b_score + komi
+    w_score - b_score + komi + self.handicap_komi()
   }
 
   pub fn current_score_rollout_exact(&self, komi: f32) -> f32 {
@@ -1223,7 +1565,7 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
       }
     }
 
-    w_score - b_score + komi
+    w_score - b_score + komi + self.handicap_komi()
   }
 
   pub fn count_unconditionally_alive(&self, turn: Stone, scratch: &mut BensonScratch) -> (usize, usize) {
@@ -1280,7 +1622,8 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
           break;
         }
       }
-      scratch.small_enc[idx] = is_small_enc;
+      scratch.small_enc.push(is_small_enc);
+      scratch.small_enc_extchs.push(vec![]);
     }
 
     // Find X chains adjacent to the small enclosed regions.
@@ -1342,6 +1685,17 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
       }
     }
 
+    // Start from every chain bordering a small enclosed region and every small
+    // enclosed region, then prune both down to a fixed point.
+    for chain_head_idx in scratch.chain_small_encs.keys() {
+      scratch.vital_chains.insert(chain_head_idx, ());
+    }
+    for (region_idx, &is_small_enc) in scratch.small_enc.iter().enumerate() {
+      if is_small_enc {
+        scratch.vital_regions.insert(region_idx, ());
+      }
+    }
+
     let mut prev_vital_chains_count = None;
     loop {
       scratch.queue.clear();
@@ -1406,11 +1760,27 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
     (live_chain_count, live_region_count)
   }
 
+  /// The stones of `turn` which Benson's algorithm finds unconditionally
+  /// alive.
+  pub fn unconditionally_alive_stones(&self, turn: Stone, scratch: &mut BensonScratch) -> Vec<Point> {
+    self.count_unconditionally_alive(turn, scratch);
+    let mut stones = vec![];
+    for chain_head_idx in scratch.vital_chains.keys() {
+      self.chains.iter_chain(Point::from_idx(chain_head_idx), |pt| {
+        stones.push(pt);
+      });
+    }
+    stones.sort();
+    stones
+  }
+
   pub fn iter_legal_moves_accurate<F>(&mut self, turn: Stone, /*scratch: &mut TxnStateScratch,*/ mut f: F) where F: FnMut(Point) {
     for p in 0 .. Board::SIZE as i16 {
       let point = Point(p);
-      if check_illegal_move_simple(&self.position, &self.chains, turn, point).is_some() {
-        continue;
+      match check_illegal_move_simple(&self.position, &self.chains, turn, point) {
+        None => {}
+        Some(IllegalReason::Suicide) if self.allows_suicide() => {}
+        Some(_) => continue,
       }
       match self.try_place(turn, point) {
         Ok(_) => {
@@ -1422,6 +1792,13 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
     }
   }
 
+  fn allows_suicide(&self) -> bool {
+    match self.rules.suicide_rule {
+      SuicideRule::Allowed => true,
+      SuicideRule::Illegal => false,
+    }
+  }
+
   pub fn is_capture(&self, stone: Stone, point: Point) -> bool {
     let mut capture = false;
     for_each_adjacent(point, |adj_point| {
@@ -1550,6 +1927,7 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
       chains.iter_chain_mut(adj_head, |chains, ch_point| {
         //let cap_stone = position.stones[ch_point.idx()];
         position.stones[ch_point.idx()] = Stone::Empty;
+        position.hash ^= zobrist_key(opponent, ch_point);
         position.last_killed[opp_off].push(ch_point);
         num_cap_stones += 1;
         for_each_adjacent(ch_point, |adj_ch_point| {
@@ -1615,9 +1993,12 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
     }
     // TODO(20151105): Allow placements out of turn, but somehow warn about it?
     let place_p = place_point.idx();
-    // Do not allow simple illegal moves.
-    if let Some(reason) = check_illegal_move_simple(&self.position, &self.chains, turn, place_point) {
-      return Err(TxnStatus::Illegal(reason));
+    // Do not allow simple illegal moves. Placing in the opponent's eye is
+    // a (single stone) suicide, which some rules allow.
+    match check_illegal_move_simple(&self.position, &self.chains, turn, place_point) {
+      None => {}
+      Some(IllegalReason::Suicide) if self.allows_suicide() => {}
+      Some(reason) => return Err(TxnStatus::Illegal(reason)),
     }
 
     // Then, enable mutating state (and full undos).
@@ -1633,9 +2014,11 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
     self.proposal.ko_candidate = None;
     self.proposal.place = Some((turn, place_point));
     self.proposal.prev_place = (self.position.stones[place_p], place_point);
+    self.proposal.prev_hash = self.position.hash;
     self.position.turn = turn;
     self.position.num_stones[turn.offset()] += 1;
     self.position.stones[place_p] = turn;
+    self.position.hash ^= zobrist_key(turn, place_point);
     self.position.last_move = Some((turn, Action::Place{point: place_point}));
     self.position.last_placed = Some((turn, place_point));
     self.position.last_ko = self.position.ko;
@@ -1650,7 +2033,14 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
     self.capture_adjacent_chains(turn, opp_turn, place_point, true);
 
     // 3. Suicide own chains.
-    let num_suicided_stones = self.capture_adjacent_chains(opp_turn, turn, place_point, false);
+    let mut num_suicided_stones = self.capture_adjacent_chains(opp_turn, turn, place_point, false);
+    if num_suicided_stones == 0 && self.allows_suicide() {
+      // A lone placed stone is not adjacent to its own chain, so check it
+      // directly.
+      let place_head = self.chains.find_chain(place_point);
+      num_suicided_stones = self.capture_chain(opp_turn, turn, place_head, false);
+      self.position.num_stones[turn.offset()] -= num_suicided_stones;
+    }
 
     // Determine local atari flags (used by local features).
     {
@@ -1680,12 +2070,18 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
       }
     }
 
-    // Currently, always disallow suicide.
-    if num_suicided_stones > 0 {
-      Err(TxnStatus::Illegal(IllegalReason::Suicide))
-    } else {
-      Ok(())
+    if num_suicided_stones > 0 && !self.allows_suicide() {
+      return Err(TxnStatus::Illegal(IllegalReason::Suicide));
     }
+
+    // Positional superko: the resulting position must be new.
+    if let KoRule::Superko = self.rules.ko_rule {
+      if self.position_hashes.contains(self.position.hash) {
+        return Err(TxnStatus::Illegal(IllegalReason::Superko));
+      }
+    }
+
+    Ok(())
   }
 
   pub fn commit(&mut self) {
@@ -1703,6 +2099,18 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
 
     let (update_turn, update_action) = self.position.last_move.unwrap();
 
+    // Record the action. Committing the next undone action keeps the rest of
+    // the redo history; anything else starts a new line of play.
+    self.ply += 1;
+    if self.config.history {
+      self.history.push((update_turn, update_action));
+      if self.redo_history.last() == Some(&(update_turn, update_action)) {
+        self.redo_history.pop();
+      } else {
+        self.redo_history.clear();
+      }
+    }
+
     if self.in_soft_txn {
       self.resigned = self.proposal.resigned;
       self.position.prev_self_atari = false;
@@ -1731,6 +2139,10 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
 
       // Commit changes to the chains list. This performs checkpointing per chain.
       self.chains.commit();
+
+      if let KoRule::Superko = self.rules.ko_rule {
+        self.position_hashes.insert(self.position.hash);
+      }
     }
 
     // Run changes on the extra data.
@@ -1779,6 +2191,7 @@ impl<Data> TxnState<Data> where Data: TxnStateData + Clone {
         let (prev_place_stone, prev_place_point) = self.proposal.prev_place;
         self.position.num_stones[last_turn.offset()] -= 1;
         self.position.stones[prev_place_point.idx()] = prev_place_stone;
+        self.position.hash = self.proposal.prev_hash;
 
         self.position.turn = self.proposal.prev_turn;
         //self.position.ko = self.proposal.prev_ko;
@@ -0,0 +1,96 @@
+extern crate holmes;
+extern crate rand;
+
+use holmes::board::{Stone, Point, Action};
+use holmes::book::{OpeningBook, OpeningBookBuilder, canonical_move_key};
+use holmes::gtp_board::{Coord};
+use holmes::sgf::{Sgf, SgfRankStyle};
+use holmes::txnstate::{TxnStateConfig, TxnState};
+
+use rand::{thread_rng};
+use std::env;
+
+fn point(code: &str) -> Point {
+  Point::from_coord(Coord::parse_code_str(code).unwrap())
+}
+
+fn build_book() -> OpeningBook {
+  let mut builder = OpeningBookBuilder::new(2);
+  for text in [
+    "(;GM[1]SZ[19]RE[B+R];B[pd];W[dd];B[pp])",
+    "(;GM[1]SZ[19]RE[W+R];B[pd];W[dp];B[pp])",
+    // The first game mirrored left to right.
+    "(;GM[1]SZ[19]RE[B+R];B[dd];W[pd];B[dp])",
+  ].iter() {
+    let sgf = Sgf::from_text(text.as_bytes());
+    builder.add_sgf(&sgf, SgfRankStyle::Server).unwrap();
+  }
+  assert_eq!(3, builder.num_games());
+  builder.build(1)
+}
+
+#[test]
+fn test_book_lookup() {
+  let book = build_book();
+  let mut state = TxnState::new(TxnStateConfig::default(), ());
+  state.reset();
+
+  // All first moves are the same corner up to symmetry.
+  let moves = book.lookup(&state, Stone::Black);
+  assert_eq!(1, moves.len());
+  assert_eq!(3, moves[0].count);
+  assert_eq!(2, moves[0].wins);
+
+  // After a transformed first move, the replies are transformed back. A
+  // corner stone is symmetric about the diagonal, so the adjacent corner
+  // reply may come back as either adjacent corner.
+  state.try_action(Stone::Black, Action::Place{point: point("D16")}).unwrap();
+  state.commit();
+  let moves = book.lookup(&state, Stone::White);
+  assert_eq!(2, moves.len());
+  assert!(moves[0].point == point("Q16") || moves[0].point == point("D4"));
+  assert_eq!((2, 0), (moves[0].count, moves[0].wins));
+  assert_eq!(point("Q4"), moves[1].point);
+  assert_eq!((1, 1), (moves[1].count, moves[1].wins));
+  assert!(book.lookup(&state, Stone::Black).is_empty());
+
+  let mov = book.choose(&state, Stone::White, 1, 0.0, &mut thread_rng()).unwrap();
+  assert_eq!(moves[0], mov);
+  assert!(book.choose(&state, Stone::White, 3, 0.0, &mut thread_rng()).is_none());
+  let mov = book.choose(&state, Stone::White, 1, 1.0, &mut thread_rng()).unwrap();
+  assert!(mov == moves[0] || mov == moves[1]);
+}
+
+#[test]
+fn test_book_save_open() {
+  let book = build_book();
+  let path = env::temp_dir().join("holmes-book-test.bin");
+  book.save(&path);
+  let saved_book = OpeningBook::open(&path).unwrap();
+  assert_eq!(book.max_ply, saved_book.max_ply);
+  assert_eq!(book.entries.len(), saved_book.entries.len());
+  for (entry, saved_entry) in book.entries.iter().zip(saved_book.entries.iter()) {
+    assert_eq!(entry.key, saved_entry.key);
+    assert_eq!(entry.moves, saved_entry.moves);
+  }
+}
+
+#[test]
+fn test_canonical_move_key() {
+  let mut state = TxnState::new(TxnStateConfig::default(), ());
+  state.reset();
+
+  // The empty board is symmetric, so the corners are all equivalent.
+  let (key, corner) = canonical_move_key(&state, Stone::Black, point("Q16"));
+  for code in ["D4", "D16", "Q4"].iter() {
+    assert_eq!((key, corner), canonical_move_key(&state, Stone::Black, point(code)), "{}", code);
+  }
+  assert!(canonical_move_key(&state, Stone::Black, point("C16")).1 != corner);
+
+  // A corner stone is symmetric about the diagonal only.
+  state.try_action(Stone::Black, Action::Place{point: point("Q16")}).unwrap();
+  state.commit();
+  let (_, adjacent) = canonical_move_key(&state, Stone::White, point("D16"));
+  assert_eq!(adjacent, canonical_move_key(&state, Stone::White, point("Q4")).1);
+  assert!(adjacent != canonical_move_key(&state, Stone::White, point("D4")).1);
+}
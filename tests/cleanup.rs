@@ -0,0 +1,65 @@
+extern crate holmes;
+
+use holmes::board::{Stone, Point, Action};
+use holmes::gtp_board::{Coord};
+use holmes::search::parallel_policies::{PriorPolicy};
+use holmes::search::parallel_policies::uniform::{UniformPriorPolicy};
+use holmes::search::parallel_tree::{
+  TreePolicyConfig,
+  CleanupPriorConfig, SharedTree,
+};
+use holmes::txnstate::{TxnStateConfig, TxnState};
+use holmes::txnstate::extras::{TxnStateNodeData};
+
+use std::sync::{Arc};
+
+fn point(code: &str) -> Point {
+  Point::from_coord(Coord::parse_code_str(code).unwrap())
+}
+
+fn prior_of(prior_values: &[(Point, f32)], code: &str) -> f32 {
+  prior_values.iter().find(|&&(pt, _)| pt == point(code)).unwrap().1
+}
+
+#[test]
+fn test_cleanup_prior() {
+  // White C3 is in atari; white Q16 is dead but not in atari.
+  let mut state = TxnState::new(TxnStateConfig::default(), TxnStateNodeData::new());
+  state.reset();
+  for &(turn, action) in [
+    (Stone::Black, Action::Place{point: point("C2")}),
+    (Stone::White, Action::Place{point: point("C3")}),
+    (Stone::Black, Action::Place{point: point("B3")}),
+    (Stone::White, Action::Place{point: point("Q16")}),
+    (Stone::Black, Action::Place{point: point("D3")}),
+    (Stone::White, Action::Pass),
+  ].iter() {
+    state.try_action(turn, action).unwrap();
+    state.commit();
+  }
+  let valid_moves = [point("C4"), point("Q17"), point("K10")];
+  let tree = SharedTree::new(TreePolicyConfig::test_default());
+  tree.set_cleanup_prior(CleanupPriorConfig{
+    turn:         Stone::Black,
+    dead_stones:  Arc::new(vec![point("Q16")]),
+    weight:       1.0,
+  });
+
+  let mut prior_values = vec![];
+  tree.with_tree_priors(&mut UniformPriorPolicy, |prior_policy| {
+    prior_policy.fill_prior_values(&state, &valid_moves, &mut prior_values);
+  });
+  let sum = prior_values.iter().fold(0.0, |acc, &(_, p)| acc + p);
+  assert!((sum - 1.0).abs() < 1.0e-5);
+  // Captures come first, then moves next to dead stones.
+  assert!(prior_of(&prior_values, "C4") > prior_of(&prior_values, "Q17"));
+  assert!(prior_of(&prior_values, "Q17") > prior_of(&prior_values, "K10"));
+
+  // The bias only applies to the cleaning up player.
+  state.try_action(Stone::Black, Action::Pass).unwrap();
+  state.commit();
+  tree.with_tree_priors(&mut UniformPriorPolicy, |prior_policy| {
+    prior_policy.fill_prior_values(&state, &valid_moves, &mut prior_values);
+  });
+  assert_eq!(prior_of(&prior_values, "C4"), prior_of(&prior_values, "K10"));
+}
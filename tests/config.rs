@@ -0,0 +1,159 @@
+extern crate holmes;
+
+use holmes::config::{EngineConfig};
+use holmes::search::parallel_tree::{HorizonConfig, SearchParallelism, SearchWorkerBatchConfig};
+
+use std::env;
+use std::fs::{File};
+use std::io::{Write};
+use std::path::{PathBuf};
+
+fn write_config(name: &str, text: &str) -> PathBuf {
+  let path = env::temp_dir().join(format!("holmes-config-{}.toml", name));
+  let mut file = File::create(&path).unwrap();
+  file.write_all(text.as_bytes()).unwrap();
+  path
+}
+
+fn overrides(strs: &[&str]) -> Vec<String> {
+  strs.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn test_defaults() {
+  let path = write_config("empty", "");
+  let cfg = EngineConfig::load(Some(&path), &[]).unwrap();
+  assert_eq!(1024, cfg.search.num_rollouts);
+  assert_eq!(6.5, cfg.state_cfg().komi);
+  assert_eq!(0.9, cfg.hyperparam.ucb_c);
+  assert_eq!(4, cfg.num_workers(4));
+  assert!(cfg.tree_memory_cfg().is_none());
+  assert!(cfg.report_dir().is_none());
+  assert!(cfg.validate().is_ok());
+
+  // The shipped sample config only spells out the defaults.
+  let sample = EngineConfig::load(Some(&PathBuf::from("holmes.toml")), &[]).unwrap();
+  assert_eq!(cfg.to_toml_string(), sample.to_toml_string());
+}
+
+#[test]
+fn test_layering() {
+  let path = write_config("layering",
+      "[search]\nbatch_size = 32\nnum_workers = 2\n[tree]\nhorizon = \"pwide\"\n[rules]\nkomi = 7\n");
+  let cfg = EngineConfig::load(Some(&path), &overrides(&[
+    "search.num_workers=3",
+    "rules.ruleset = chinese",
+    "tree.pwide_mu=2.5",
+  ])).unwrap();
+  assert_eq!(32, cfg.search.batch_size);
+  assert_eq!(3, cfg.num_workers(4));
+  // Integers are promoted to floats.
+  assert_eq!(7.0, cfg.rules.komi);
+  assert_eq!("chinese", cfg.rules.ruleset);
+  match cfg.tree_policy_cfg().horizon_cfg {
+    HorizonConfig::Pwide{mu} => assert_eq!(2.5, mu),
+    x => panic!("unexpected horizon: {:?}", x),
+  }
+}
+
+#[test]
+fn test_tree_memory_budget() {
+  let path = write_config("tree-memory-budget", "[tree]
+memory_budget_mb = 64
+");
+  let cfg = EngineConfig::load(Some(&path), &[]).unwrap();
+  let memory_cfg = cfg.tree_memory_cfg().unwrap();
+  assert_eq!(64 * 1024 * 1024, memory_cfg.budget_bytes);
+  assert!(memory_cfg.compact_states);
+  let cfg = EngineConfig::load(Some(&path), &overrides(&["tree.compact_states=false"])).unwrap();
+  assert!(!cfg.tree_memory_cfg().unwrap().compact_states);
+  let cfg = EngineConfig::load(Some(&path), &overrides(&["tree.memory_budget_mb=0"])).unwrap();
+  assert!(cfg.tree_memory_cfg().is_none());
+}
+
+#[test]
+fn test_nngs_config() {
+  let cfg = EngineConfig::load(Some(&PathBuf::from("conf/holmes-nngs.toml")), &[]).unwrap();
+  assert_eq!("time", cfg.search.limit);
+  assert_eq!(256, cfg.search_cfg().batch_size);
+  let tree_cfg = cfg.tree_policy_cfg();
+  match tree_cfg.horizon_cfg {
+    HorizonConfig::Fixed{max_horizon} => assert_eq!(30, max_horizon),
+    x => panic!("unexpected horizon: {:?}", x),
+  }
+  assert_eq!(24, tree_cfg.visit_thresh);
+  assert_eq!(0.0625, tree_cfg.mc_scale);
+
+  // The legacy hyperparameters share the prior and RAVE keys of [tree].
+  let hyper = cfg.hyperparam_cfg();
+  assert_eq!(tree_cfg.prior_equiv, hyper.prior_equiv);
+  assert_eq!(tree_cfg.rave, hyper.rave);
+  assert!(!hyper.pwide);
+}
+
+#[test]
+fn test_errors() {
+  let path = write_config("unknown-key", "[search]\nnum_rollout = 32\n");
+  assert!(EngineConfig::load(Some(&path), &[]).is_err());
+  let path = write_config("bad-type", "[tree]\nrave = 1\n");
+  assert!(EngineConfig::load(Some(&path), &[]).is_err());
+  let path = write_config("bad-section", "komi = 7.5\n");
+  assert!(EngineConfig::load(Some(&path), &[]).is_err());
+  assert!(EngineConfig::load(Some(&PathBuf::from("no-such-config.toml")), &[]).is_err());
+
+  let path = write_config("ok", "");
+  assert!(EngineConfig::load(Some(&path), &overrides(&["search.batch_size"])).is_err());
+  assert!(EngineConfig::load(Some(&path), &overrides(&["batch_size=4"])).is_err());
+  assert!(EngineConfig::load(Some(&path), &overrides(&["search.batch_size=four"])).is_err());
+  assert!(EngineConfig::load(Some(&path), &overrides(&["search.batch_size=-4"])).is_err());
+
+  // Validation.
+  assert!(EngineConfig::load(Some(&path), &overrides(&["search.batch_size=0"])).is_err());
+  assert!(EngineConfig::load(Some(&path), &overrides(&["tree.horizon=none"])).is_err());
+  assert!(EngineConfig::load(Some(&path), &overrides(&["rules.ruleset=tromp"])).is_err());
+  assert!(EngineConfig::load(Some(&path), &overrides(&["time.min_remaining_s=-1"])).is_err());
+  assert!(EngineConfig::load(Some(&path), &overrides(&["search.limit=playouts"])).is_err());
+  assert!(EngineConfig::load(Some(&path), &overrides(&["search.limit=time", "search.time_budget_ms=0"])).is_err());
+}
+
+#[test]
+fn test_search_limit() {
+  let path = write_config("search-limit", "");
+  let cfg = EngineConfig::load(Some(&path), &[]).unwrap();
+  match cfg.search_batch_cfg() {
+    SearchWorkerBatchConfig::Fixed{num_batches} => assert_eq!(1024 / 16, num_batches),
+    x => panic!("unexpected search limit: {:?}", x),
+  }
+  let cfg = EngineConfig::load(Some(&path), &overrides(&["search.limit=time", "search.time_budget_ms=2500"])).unwrap();
+  match cfg.search_batch_cfg() {
+    SearchWorkerBatchConfig::TimeLimit{budget_ms, ..} => assert_eq!(2500, budget_ms),
+    x => panic!("unexpected search limit: {:?}", x),
+  }
+  let cfg = EngineConfig::load(Some(&path), &overrides(&["search.limit=nodes", "search.max_nodes=100"])).unwrap();
+  match cfg.search_batch_cfg() {
+    SearchWorkerBatchConfig::NodeLimit{max_nodes} => assert_eq!(100, max_nodes),
+    x => panic!("unexpected search limit: {:?}", x),
+  }
+}
+
+#[test]
+fn test_search_parallelism() {
+  let path = write_config("search-parallelism", "[search]\nparallelism = \"root\"\nroot_trees = 3\n");
+  let cfg = EngineConfig::load(Some(&path), &[]).unwrap();
+  assert_eq!(SearchParallelism::Root{num_trees: 3}, cfg.search_parallelism());
+  let cfg = EngineConfig::load(Some(&path), &overrides(&["search.parallelism=tree"])).unwrap();
+  assert_eq!(SearchParallelism::Tree, cfg.search_parallelism());
+  assert!(EngineConfig::load(Some(&path), &overrides(&["search.root_trees=0"])).is_err());
+  assert!(EngineConfig::load(Some(&path), &overrides(&["search.num_workers=2"])).is_err());
+  assert!(EngineConfig::load(Some(&path), &overrides(&["search.parallelism=leaf"])).is_err());
+}
+
+#[test]
+fn test_report() {
+  let path = write_config("report", "[report]\ndir = \"reports\"\nmin_visits = 100\n");
+  let cfg = EngineConfig::load(Some(&path), &[]).unwrap();
+  assert_eq!(Some(PathBuf::from("reports")), cfg.report_dir());
+  assert_eq!(100, cfg.tree_export_cfg().min_visits);
+  assert_eq!(3, cfg.tree_export_cfg().max_depth);
+  assert!(EngineConfig::load(Some(&path), &overrides(&["report.max_depth=0"])).is_err());
+}
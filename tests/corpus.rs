@@ -0,0 +1,108 @@
+extern crate holmes;
+
+use holmes::board::{Stone};
+use holmes::corpus::{CorpusEntry, CorpusIndex, CorpusQuery, CorpusSplit};
+use holmes::sgf::{SgfRankStyle};
+
+fn entry(path: &str, text: &str) -> CorpusEntry {
+  CorpusEntry::from_text(path, text.as_bytes())
+}
+
+#[test]
+fn test_entry_metadata() {
+  let e = entry("kgs/2015/a.sgf",
+      "(;GM[1]SZ[19]RU[Japanese]KM[6.50]DT[2015-03-01]PB[b]BR[3d]PW[w]WR[1k]RE[W+Resign];B[pd];W[dd];B[pp])");
+  assert!(e.errors.is_empty(), "{:?}", e.errors);
+  assert_eq!(3, e.num_moves);
+  assert_eq!(0, e.handicap);
+  assert_eq!(Some(Stone::White), e.outcome());
+  assert_eq!(Some(2015), e.year());
+  assert_eq!("a.sgf", e.split_key());
+
+  // The second black move repeats the turn.
+  let e = entry("b.sgf", "(;GM[1]SZ[19]RE[Void];B[pd];B[dd])");
+  assert_eq!(1, e.errors.len());
+  assert_eq!(None, e.outcome());
+
+  // A malformed record is reported rather than aborting the scan.
+  let e = entry("c.sgf", "(;GM[1]SZ[nineteen];B[pd])");
+  assert_eq!(1, e.errors.len());
+  assert!(e.errors[0].starts_with("failed to parse: "), "{:?}", e.errors);
+  let e = entry("d.sgf", "x(;GM[1]SZ[19];B[pd])");
+  assert_eq!(1, e.errors.len());
+}
+
+#[test]
+fn test_query() {
+  let even = entry("even.sgf",
+      "(;GM[1]SZ[19]RU[Japanese]KM[6.5]PB[b]BR[3d]PW[w]WR[5d]RE[B+2.5];B[pd];W[dd])");
+  let handicap = entry("handicap.sgf",
+      "(;GM[1]SZ[19]RU[Chinese]HA[2]KM[0.5]PB[b]BR[2k]PW[w]WR[2d]RE[W+R]AB[dp][pd];W[dd])");
+
+  let query = CorpusQuery::parse("size = 19 and handicap = 0, komi >= 5.5 && komi <= 7.5", SgfRankStyle::Server).unwrap();
+  assert!(query.matches(&even));
+  assert!(!query.matches(&handicap));
+
+  let query = CorpusQuery::parse("rank >= 1d and completed and ok", SgfRankStyle::Server).unwrap();
+  assert!(query.matches(&even));
+  assert!(!query.matches(&handicap));
+
+  let query = CorpusQuery::parse("rules != japanese", SgfRankStyle::Server).unwrap();
+  assert!(!query.matches(&even));
+  assert!(query.matches(&handicap));
+
+  assert!(CorpusQuery::parse("", SgfRankStyle::Server).unwrap().matches(&handicap));
+  assert!(CorpusQuery::parse("colour = black", SgfRankStyle::Server).is_err());
+  assert!(CorpusQuery::parse("rank >= 3x", SgfRankStyle::Server).is_err());
+  assert!(CorpusQuery::parse("rules > chinese", SgfRankStyle::Server).is_err());
+}
+
+#[test]
+fn test_split_assign() {
+  let mut counts = [0; 3];
+  for i in 0 .. 10000 {
+    let key = format!("{}.sgf", i);
+    let split = CorpusSplit::assign(&key, 0.1, 0.2);
+    assert_eq!(split, CorpusSplit::assign(&key, 0.1, 0.2));
+    match split {
+      CorpusSplit::Train => counts[0] += 1,
+      CorpusSplit::Valid => counts[1] += 1,
+      CorpusSplit::Test  => counts[2] += 1,
+    }
+  }
+  assert!(counts[0] > 6500 && counts[0] < 7500, "{:?}", counts);
+  assert!(counts[1] > 700 && counts[1] < 1300, "{:?}", counts);
+  assert!(counts[2] > 1700 && counts[2] < 2300, "{:?}", counts);
+  assert_eq!(CorpusSplit::Train, CorpusSplit::assign("a.sgf", 0.0, 0.0));
+}
+
+#[test]
+fn test_validate() {
+  let index = CorpusIndex{entries: vec![
+    entry("a.sgf", "(;GM[1]SZ[19]PB[x]PW[y]RE[B+R];B[pd];W[dd];B[pp])"),
+    // Same moves, different metadata.
+    entry("b.sgf", "(;GM[1]SZ[19]PB[z]PW[w]RE[W+3.5];B[pd];W[dd];B[pp])"),
+    // Mirrored left to right.
+    entry("c.sgf", "(;GM[1]SZ[19]RE[B+T];B[dd];W[pd];B[dp])"),
+    // The third move is on an occupied point.
+    entry("d.sgf", "(;GM[1]SZ[19]RE[B+R];B[pd];W[dd];B[dd])"),
+    entry("e.sgf", "(;GM[1]SZ[19]RE[Black wins];B[qd];W[dd];B[pp])"),
+    entry("f.sgf", "(;GM[1]SZ[19]RE[B+R];B[qd])"),
+  ]};
+  assert_eq!(index.entries[0].moves_hash, index.entries[1].moves_hash);
+  assert!(index.entries[0].moves_hash != index.entries[2].moves_hash);
+  assert_eq!(index.entries[0].canonical_hash, index.entries[2].canonical_hash);
+
+  let (report, clean_idxs) = index.validate(2);
+  assert_eq!(vec![0], clean_idxs);
+  assert_eq!((6, 1), (report.num_games, report.num_clean));
+  let kinds: Vec<_> = report.issues.iter().map(|issue| (&issue.path as &str, &issue.kind as &str)).collect();
+  assert_eq!(vec![
+    ("b.sgf", "duplicate"),
+    ("c.sgf", "symmetric duplicate"),
+    ("d.sgf", "illegal move"),
+    ("e.sgf", "bad result"),
+    ("f.sgf", "truncated"),
+  ], kinds);
+  assert_eq!("a.sgf", report.issues[0].detail);
+}
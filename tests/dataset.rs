@@ -0,0 +1,41 @@
+extern crate holmes;
+
+use holmes::dataset::{DatasetHeader, DatasetLabel, DatasetProvenance};
+use holmes::txnstate::features::{
+  TxnStateAlphaV3FeatsData,
+  TxnStateAlphaPatternNoKoV3FeatsData,
+};
+
+use std::env;
+
+#[test]
+fn test_header_round_trip() {
+  let header = DatasetHeader::new::<TxnStateAlphaV3FeatsData>(
+      &[DatasetLabel::Action, DatasetLabel::Value],
+      DatasetProvenance::new("test", vec!["index".to_string()]),
+  );
+  let prefix = env::temp_dir().join("holmes-dataset-test");
+  let path = DatasetHeader::split_path(&prefix, "train");
+  header.save(&path);
+  let loaded = DatasetHeader::open_split(&prefix, "train").unwrap();
+  assert_eq!(header, loaded);
+  assert_eq!((19, 19, 32), loaded.input_dims());
+  assert!(loaded.check_features::<TxnStateAlphaV3FeatsData>().is_ok());
+  assert!(loaded.check_features::<TxnStateAlphaPatternNoKoV3FeatsData>().is_err());
+}
+
+#[test]
+fn test_header_refuses_mismatched_network() {
+  let header = DatasetHeader::new::<TxnStateAlphaV3FeatsData>(
+      &[DatasetLabel::Action],
+      DatasetProvenance::new("test", vec![]),
+  );
+  assert!(header.check_network((19, 19, 32), 1, DatasetLabel::Action).is_ok());
+  assert!(header.check_network((19, 19, 44), 1, DatasetLabel::Action).is_err());
+  assert!(header.check_network((19, 19, 32), 0, DatasetLabel::Action).is_err());
+  assert!(header.check_network((19, 19, 32), 1, DatasetLabel::Value).is_err());
+
+  let mut header = header;
+  header.format_version += 1;
+  assert!(header.check_network((19, 19, 32), 1, DatasetLabel::Action).is_err());
+}
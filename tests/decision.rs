@@ -0,0 +1,132 @@
+extern crate holmes;
+
+use holmes::board::{Board, RuleSet, Stone, Point, Action};
+use holmes::gtp_board::{Coord};
+use holmes::search::decision::{DecisionConfig, DecisionPolicy, estimate_final_score};
+use holmes::search::parallel_tree::{MonteCarloSearchResult};
+use holmes::txnstate::{TxnStateConfig, TxnState};
+
+fn point(code: &str) -> Point {
+  Point::from_coord(Coord::parse_code_str(code).unwrap())
+}
+
+fn new_state(rules: RuleSet) -> TxnState {
+  let mut state = TxnState::new(TxnStateConfig{
+    rules:  rules.rules(),
+    .. TxnStateConfig::default()
+  }, ());
+  state.reset();
+  state
+}
+
+/// A result on the empty board in which black owns the first `num_black`
+/// points, of which the first `num_live` are counted as live stones, and
+/// white owns the next `num_white` points.
+fn owned_result(value: f32, num_black: usize, num_live: usize, num_white: usize) -> MonteCarloSearchResult {
+  let points: Vec<_> = (0 .. Board::SIZE).map(|p| Point::from_idx(p)).collect();
+  MonteCarloSearchResult{
+    turn:             Stone::Black,
+    action:           Action::Place{point: point("K10")},
+    expected_score:   0.0,
+    expected_value:   value,
+    b_mc_alive:       num_black,
+    w_mc_alive:       num_white,
+    top_prior_values: vec![],
+    pv:               vec![],
+    dead_stones:      vec![vec![], vec![]],
+    live_stones:      vec![points[ .. num_live].to_vec(), vec![]],
+    territory:        vec![points[num_live .. num_black].to_vec(), points[num_black .. num_black + num_white].to_vec()],
+    outcome:          None,
+    root_visits:      vec![],
+    komi:             6.5,
+  }
+}
+
+#[test]
+fn test_decision_estimate_score() {
+  let result = owned_result(0.5, 180, 10, 181);
+  assert_eq!(181.0 - 180.0 + 6.5, estimate_final_score(&new_state(RuleSet::KgsChinese), &result));
+  // Live stones are not points under territory scoring.
+  assert_eq!(181.0 - 170.0 + 6.5, estimate_final_score(&new_state(RuleSet::KgsJapanese), &result));
+}
+
+#[test]
+fn test_decision_pass() {
+  let state = new_state(RuleSet::KgsChinese);
+  let policy = DecisionPolicy::new(DecisionConfig::default());
+  let result = owned_result(0.5, 180, 0, 181);
+  assert!(policy.should_pass(&state, Stone::White, &result));
+  assert!(!policy.should_pass(&state, Stone::Black, &result));
+  // Black would win by passing, but too much is unsettled.
+  let result = owned_result(0.5, 175, 0, 160);
+  assert!(!policy.should_pass(&state, Stone::Black, &result));
+}
+
+#[test]
+fn test_decision_resign() {
+  let cfg = DecisionConfig{
+    resign_min_ply: 0,
+    .. DecisionConfig::default()
+  };
+  let mut policy = DecisionPolicy::new(cfg);
+  let state = new_state(RuleSet::KgsJapanese);
+  let low = owned_result(0.05, 100, 0, 100);
+  let high = owned_result(0.5, 100, 0, 100);
+  let place = Action::Place{point: point("K10")};
+  assert_eq!(place, policy.decide(&state, Stone::Black, &low));
+  assert_eq!(place, policy.decide(&state, Stone::Black, &low));
+  assert_eq!(place, policy.decide(&state, Stone::Black, &high));
+  assert_eq!(place, policy.decide(&state, Stone::Black, &low));
+  assert_eq!(place, policy.decide(&state, Stone::Black, &low));
+  assert_eq!(Action::Resign, policy.decide(&state, Stone::Black, &low));
+
+  // No resigning in handicap games.
+  let mut policy = DecisionPolicy::new(cfg);
+  let mut state = new_state(RuleSet::KgsJapanese);
+  state.place_setup_stones(&[(Stone::Black, point("D4")), (Stone::Black, point("Q16"))]).unwrap();
+  for _ in 0 .. 5 {
+    assert_eq!(place, policy.decide(&state, Stone::White, &low));
+  }
+}
+
+#[test]
+fn test_decision_low_value() {
+  let policy = DecisionPolicy::new(DecisionConfig{
+    resign_min_ply: 2,
+    .. DecisionConfig::default()
+  });
+  let mut state = new_state(RuleSet::KgsJapanese);
+  assert!(!policy.is_low_value(&state, 0.05));
+  for &(turn, code) in [(Stone::Black, "D4"), (Stone::White, "Q16")].iter() {
+    state.try_action(turn, Action::Place{point: point(code)}).unwrap();
+    state.commit();
+  }
+  assert!(policy.is_low_value(&state, 0.05));
+  assert!(!policy.is_low_value(&state, 0.5));
+}
+
+#[test]
+fn test_decision_unsearched() {
+  let mut policy = DecisionPolicy::new(DecisionConfig::default());
+  let mut state = new_state(RuleSet::KgsChinese);
+  let place = Action::Place{point: point("K10")};
+  // No pass before the opponent passes.
+  assert_eq!(place, policy.decide_unsearched(&state, Stone::Black, place));
+  state.try_action(Stone::Black, Action::Place{point: point("D4")}).unwrap();
+  state.commit();
+  assert_eq!(place, policy.decide_unsearched(&state, Stone::White, place));
+  state.try_action(Stone::White, Action::Pass).unwrap();
+  state.commit();
+  // Black owns the whole board after white passes.
+  assert_eq!(Action::Pass, policy.decide_unsearched(&state, Stone::Black, place));
+
+  // With every empty point neutral, black would lose on komi by passing back.
+  let mut state = new_state(RuleSet::KgsChinese);
+  for &(turn, code) in [(Stone::Black, "D4"), (Stone::White, "K10"), (Stone::Black, "Q16")].iter() {
+    state.try_action(turn, Action::Place{point: point(code)}).unwrap();
+    state.commit();
+  }
+  state.try_action(Stone::White, Action::Pass).unwrap();
+  state.commit();
+  assert_eq!(place, policy.decide_unsearched(&state, Stone::Black, place));
+}
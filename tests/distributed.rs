@@ -0,0 +1,263 @@
+extern crate bincode;
+extern crate holmes;
+extern crate rng;
+
+use holmes::search::distributed::{
+  DistributedSearchConfig,
+  DistributedSearchMaster,
+  DistributedSearchWorker,
+  WorkerMessage, MasterMessage,
+};
+use holmes::board::{Point};
+use holmes::search::parallel_policies::{
+  SearchPolicyWorkerBuilder, SearchPolicyWorker,
+  PriorPolicy, DiffPriorPolicy, TreePolicy, RolloutPolicy,
+};
+use holmes::search::parallel_policies::uniform::{UniformPolicyWorkerBuilder, UniformPolicyWorker};
+use holmes::search::parallel_tree::{
+  TreePolicyConfig,
+  Node, SharedTree,
+};
+use holmes::txnstate::{TxnStateConfig, TxnState};
+use holmes::txnstate::extras::{TxnStateNodeData};
+
+use bincode::{SizeLimit};
+use bincode::rustc_serialize::{encode_into, decode_from};
+use rng::xorshift::{Xorshiftplus128Rng};
+use std::env;
+use std::net::{TcpStream};
+use std::path::{PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{Ordering};
+use std::sync::mpsc::{channel};
+use std::thread;
+
+fn init_state() -> TxnState<TxnStateNodeData> {
+  let mut state = TxnState::new(TxnStateConfig::default(), TxnStateNodeData::new());
+  state.reset();
+  state
+}
+
+/// Priors increasing with the point index, unlike the uniform priors which
+/// keep the legal moves in increasing order.
+struct IndexPriorPolicy;
+
+impl PriorPolicy for IndexPriorPolicy {
+  fn fill_prior_values(&mut self, _state: &TxnState<TxnStateNodeData>, valid_moves: &[Point], prior_values: &mut Vec<(Point, f32)>) {
+    prior_values.clear();
+    let total = valid_moves.iter().fold(0.0, |acc, &pt| acc + (pt.idx() + 1) as f32);
+    for &point in valid_moves.iter() {
+      prior_values.push((point, (point.idx() + 1) as f32 / total));
+    }
+  }
+}
+
+#[derive(Clone)]
+struct IndexPriorWorkerBuilder {
+  tree_cfg: TreePolicyConfig,
+}
+
+impl SearchPolicyWorkerBuilder for IndexPriorWorkerBuilder {
+  type Worker = IndexPriorWorker;
+
+  fn into_worker(self, tid: usize, worker_tree_batch_capacity: usize, worker_rollout_batch_capacity: usize) -> IndexPriorWorker {
+    IndexPriorWorker{
+      prior_policy: IndexPriorPolicy,
+      inner:        UniformPolicyWorkerBuilder::new(self.tree_cfg)
+        .into_worker(tid, worker_tree_batch_capacity, worker_rollout_batch_capacity),
+    }
+  }
+}
+
+struct IndexPriorWorker {
+  prior_policy: IndexPriorPolicy,
+  inner:        UniformPolicyWorker,
+}
+
+impl SearchPolicyWorker for IndexPriorWorker {
+  fn prior_policy(&mut self) -> &mut PriorPolicy {
+    &mut self.prior_policy
+  }
+
+  fn diff_prior_policy(&mut self) -> &mut DiffPriorPolicy {
+    self.inner.diff_prior_policy()
+  }
+
+  fn tree_policy(&mut self) -> &mut TreePolicy<R=Xorshiftplus128Rng> {
+    self.inner.tree_policy()
+  }
+
+  fn exploration_policies(&mut self) -> (&mut PriorPolicy, &mut TreePolicy<R=Xorshiftplus128Rng>) {
+    (&mut self.prior_policy, self.inner.tree_policy())
+  }
+
+  fn rollout_policy(&mut self) -> &mut RolloutPolicy<R=Xorshiftplus128Rng> {
+    self.inner.rollout_policy()
+  }
+}
+
+/// Whether the legal moves of every node below the root are in decreasing
+/// order, i.e. sorted by the index priors.
+fn all_index_priors(node: &Arc<RwLock<Node>>, is_root: bool) -> bool {
+  let node = node.read().unwrap();
+  if !is_root && !node.valid_moves.windows(2).all(|w| w[0].idx() > w[1].idx()) {
+    return false;
+  }
+  node.child_nodes.iter().all(|child| match *child {
+    Some(ref child) => all_index_priors(child, false),
+    None => true,
+  })
+}
+
+/// Cargo builds the tools next to the `deps` directory of the tests.
+fn distributed_exe() -> PathBuf {
+  let mut path = env::current_exe().unwrap();
+  path.pop();
+  if path.ends_with("deps") {
+    path.pop();
+  }
+  path.join("holmes-distributed")
+}
+
+fn root_counts(tree: &SharedTree) -> (usize, usize) {
+  let root_node = tree.root_node();
+  let root_node = root_node.read().unwrap();
+  let trials = root_node.values.num_trials.iter().fold(0, |acc, n| acc + n.load(Ordering::Acquire));
+  let losses = root_node.values.virtual_losses.iter().fold(0, |acc, n| acc + n.load(Ordering::Acquire));
+  (trials, losses)
+}
+
+#[test]
+fn test_distributed_search() {
+  let mut master = DistributedSearchMaster::bind("127.0.0.1:0", UniformPolicyWorkerBuilder::new(TreePolicyConfig::test_default())).unwrap();
+  let addr = master.local_addr();
+  let workers: Vec<_> = (0 .. 2).map(|_| thread::spawn(move || {
+    let mut worker = DistributedSearchWorker::connect(addr, 8, UniformPolicyWorkerBuilder::new(TreePolicyConfig::test_default())).unwrap();
+    worker.run()
+  })).collect();
+  master.wait_for_workers(2);
+
+  let cfg = DistributedSearchConfig{
+    num_rollouts:       128,
+    max_batch_size:     8,
+    result_timeout_ms:  10000,
+    remote_priors:      false,
+  };
+  let tree = SharedTree::new(TreePolicyConfig::test_default());
+  let (_, stats) = master.search(cfg, &init_state(), tree.clone());
+  assert_eq!(128, stats.num_rollouts);
+  assert_eq!((128, 0), root_counts(&tree));
+
+  master.shutdown();
+  let num_rollouts = workers.into_iter().fold(0, |acc, w| acc + w.join().unwrap());
+  assert_eq!(128, num_rollouts);
+}
+
+#[test]
+fn test_distributed_search_dropped_worker() {
+  let mut master = DistributedSearchMaster::bind("127.0.0.1:0", UniformPolicyWorkerBuilder::new(TreePolicyConfig::test_default())).unwrap();
+  let addr = master.local_addr();
+
+  // Takes the first batch and disconnects without sending back any results.
+  // The real worker only connects after that, so it cannot take the whole
+  // search first.
+  let mut stream = TcpStream::connect(addr).unwrap();
+  encode_into(&WorkerMessage::RequestBatch{max_batch_size: 8}, &mut stream, SizeLimit::Infinite).unwrap();
+  let (dropped_tx, dropped_rx) = channel();
+  let dropper = thread::spawn(move || {
+    match decode_from(&mut stream, SizeLimit::Infinite).unwrap() {
+      MasterMessage::Batch{leaf_states, ..} => assert_eq!(8, leaf_states.len()),
+      MasterMessage::Quit => panic!("unexpected quit"),
+    }
+    drop(stream);
+    dropped_tx.send(()).unwrap();
+  });
+  master.wait_for_workers(1);
+  let worker = thread::spawn(move || {
+    dropped_rx.recv().unwrap();
+    let mut worker = DistributedSearchWorker::connect(addr, 8, UniformPolicyWorkerBuilder::new(TreePolicyConfig::test_default())).unwrap();
+    worker.run()
+  });
+
+  let cfg = DistributedSearchConfig{
+    num_rollouts:       64,
+    max_batch_size:     8,
+    result_timeout_ms:  10000,
+    remote_priors:      false,
+  };
+  let tree = SharedTree::new(TreePolicyConfig::test_default());
+  let (_, stats) = master.search(cfg, &init_state(), tree.clone());
+  dropper.join().unwrap();
+  // The dropped leafs are handed out again, and no virtual loss is left over.
+  assert_eq!(64, stats.num_rollouts);
+  assert_eq!((64, 0), root_counts(&tree));
+
+  master.shutdown();
+  assert_eq!(64, worker.join().unwrap());
+}
+
+#[test]
+fn test_distributed_search_remote_priors() {
+  let mut master = DistributedSearchMaster::bind("127.0.0.1:0", UniformPolicyWorkerBuilder::new(TreePolicyConfig::test_default())).unwrap();
+  let addr = master.local_addr();
+  // One worker with batches of one leaf gets every result back before the
+  // next traversal, so no remote prior arrives too late.
+  let worker = thread::spawn(move || {
+    let mut worker = DistributedSearchWorker::connect(addr, 1, IndexPriorWorkerBuilder{tree_cfg: TreePolicyConfig::test_default()}).unwrap();
+    worker.run()
+  });
+  master.wait_for_workers(1);
+
+  for &remote_priors in [false, true].iter() {
+    let cfg = DistributedSearchConfig{
+      num_rollouts:       64,
+      max_batch_size:     1,
+      result_timeout_ms:  10000,
+      remote_priors:      remote_priors,
+    };
+    let tree = SharedTree::new(TreePolicyConfig::test_default());
+    let (_, stats) = master.search(cfg, &init_state(), tree.clone());
+    assert_eq!(64, stats.num_rollouts);
+    assert_eq!((64, 0), root_counts(&tree));
+    let root_node = tree.root_node();
+    assert!(root_node.read().unwrap().child_nodes.iter().any(|child| child.is_some()));
+    assert_eq!(remote_priors, all_index_priors(&root_node, true));
+  }
+
+  master.shutdown();
+  worker.join().unwrap();
+}
+
+#[test]
+fn test_distributed_search_worker_processes() {
+  let mut master = DistributedSearchMaster::bind("127.0.0.1:0", UniformPolicyWorkerBuilder::new(TreePolicyConfig::test_default())).unwrap();
+  let addr = master.local_addr();
+  let mut children: Vec<_> = (0 .. 3).map(|_| {
+    Command::new(distributed_exe())
+      .arg("--worker").arg(&format!("{}", addr))
+      .arg("--batch-size").arg("8")
+      .stdout(Stdio::null())
+      .spawn()
+      .unwrap()
+  }).collect();
+  master.wait_for_workers(3);
+
+  let cfg = DistributedSearchConfig{
+    num_rollouts:       256,
+    max_batch_size:     8,
+    result_timeout_ms:  10000,
+    remote_priors:      false,
+  };
+  for _ in 0 .. 2 {
+    let tree = SharedTree::new(TreePolicyConfig::test_default());
+    let (_, stats) = master.search(cfg, &init_state(), tree.clone());
+    assert_eq!(256, stats.num_rollouts);
+    assert_eq!((256, 0), root_counts(&tree));
+  }
+
+  master.shutdown();
+  for child in children.iter_mut() {
+    assert!(child.wait().unwrap().success());
+  }
+}
@@ -0,0 +1,104 @@
+extern crate holmes;
+
+use holmes::board::{Board, RuleSet, PlayerRank, Stone, Point, Action};
+use holmes::gtp_board::{Coord};
+use holmes::txnstate::{TxnStateConfig, TxnState};
+use holmes::txnstate::feature_sets::{
+  FeaturePlaneKind,
+  lookup_feature_set, registered_feature_sets, has_feature_extractor, build_feature_extractor,
+};
+use holmes::txnstate::features::{
+  TxnStateSerialFeatsData,
+  TxnStateAlphaV3FeatsData,
+  TxnStateAlphaMiniV3FeatsData,
+  TxnStateAlphaPatternV3FeatsData,
+  TxnStateAlphaPatternNoKoV3FeatsData,
+};
+
+fn state_cfg() -> TxnStateConfig {
+  TxnStateConfig{
+    rules:  RuleSet::KgsJapanese.rules(),
+    ranks:  [PlayerRank::Dan(9), PlayerRank::Dan(9)],
+    komi:   6.5,
+    history: false,
+  }
+}
+
+fn moves() -> Vec<(Stone, Action)> {
+  ["D4", "Q16", "C3", "D3", "C4", "R4", "pass", "E4"].iter().enumerate().map(|(t, code)| {
+    let turn = if t % 2 == 0 { Stone::Black } else { Stone::White };
+    let action = match *code {
+      "pass" => Action::Pass,
+      code => Action::Place{point: Point::from_coord(Coord::parse_code_str(code).unwrap())},
+    };
+    (turn, action)
+  }).collect()
+}
+
+fn check_legacy_frames<D, F, G>(name: &str, new_state: F, legacy_blob: G)
+where D: TxnStateSerialFeatsData, F: Fn() -> TxnState<D>, G: Fn(&TxnState<D>, Stone) -> Vec<u8> {
+  let mut legacy_state = new_state();
+  legacy_state.reset();
+  let mut extractor = build_feature_extractor(name, state_cfg()).unwrap();
+  assert_eq!(name, extractor.feature_set().name);
+  for (turn, action) in moves() {
+    let frame = extractor.extract_relative_serial_frame(turn);
+    assert_eq!(legacy_blob(&legacy_state, turn), frame, "{}", name);
+    assert_eq!(D::serial_frame_size(), frame.len());
+    extractor.try_action(turn, action).unwrap();
+    legacy_state.try_action(turn, action).unwrap();
+    legacy_state.commit();
+  }
+}
+
+#[test]
+fn test_registry_lookup() {
+  assert_eq!(10, registered_feature_sets().len());
+  for feature_set in registered_feature_sets().iter() {
+    assert!(lookup_feature_set(feature_set.name).is_some());
+    assert_eq!(feature_set.num_planes(), feature_set.num_bit_planes() + feature_set.num_byte_planes());
+    // The shape features also need a dictionary.
+    if !has_feature_extractor(feature_set.name) {
+      assert_eq!("shape_v1", feature_set.name);
+      assert!(build_feature_extractor(feature_set.name, state_cfg()).is_none());
+      continue;
+    }
+    let mut extractor = build_feature_extractor(feature_set.name, state_cfg()).unwrap();
+    assert_eq!(feature_set.name, extractor.feature_set().name);
+    for (turn, action) in moves() {
+      let planes = extractor.extract_relative_planes(turn);
+      assert_eq!(feature_set.num_planes() * Board::SIZE, planes.len());
+      assert_eq!(feature_set.serial_frame_size(), extractor.extract_relative_serial_frame(turn).len());
+      extractor.try_action(turn, action).unwrap();
+    }
+  }
+  assert!(lookup_feature_set("alpha_v4").is_none());
+  assert!(build_feature_extractor("alpha_v4", state_cfg()).is_none());
+
+  let alpha_v3 = lookup_feature_set("alpha_v3").unwrap();
+  assert_eq!((19, 19, 32), alpha_v3.input_dims());
+  assert_eq!((31, 1), (alpha_v3.num_bit_planes(), alpha_v3.num_byte_planes()));
+  assert_eq!(FeaturePlaneKind::Byte, alpha_v3.planes[alpha_v3.plane_index("center").unwrap()].kind);
+  assert!(alpha_v3.planes[alpha_v3.plane_index("friend").unwrap()].relative);
+}
+
+#[test]
+fn test_generic_frames_match_legacy_blobs() {
+  assert_eq!(TxnStateAlphaV3FeatsData::serial_size(), TxnStateAlphaV3FeatsData::serial_frame_size());
+  assert_eq!(TxnStateAlphaMiniV3FeatsData::serial_size(), TxnStateAlphaMiniV3FeatsData::serial_frame_size());
+  assert_eq!(TxnStateAlphaPatternV3FeatsData::serial_size(), TxnStateAlphaPatternV3FeatsData::serial_frame_size());
+  assert_eq!(TxnStateAlphaPatternNoKoV3FeatsData::serial_size(), TxnStateAlphaPatternNoKoV3FeatsData::serial_frame_size());
+
+  check_legacy_frames("alpha_v3",
+      || TxnState::new(state_cfg(), TxnStateAlphaV3FeatsData::new()),
+      |state, turn| state.get_data().extract_relative_serial_blob(turn));
+  check_legacy_frames("alpha_mini_v3",
+      || TxnState::new(state_cfg(), TxnStateAlphaMiniV3FeatsData::new()),
+      |state, turn| state.get_data().extract_relative_serial_blob(turn));
+  check_legacy_frames("alpha_pattern_v3",
+      || TxnState::new(state_cfg(), TxnStateAlphaPatternV3FeatsData::new()),
+      |state, turn| state.get_data().extract_relative_serial_blob(turn));
+  check_legacy_frames("alpha_pattern_noko_v3",
+      || TxnState::new(state_cfg(), TxnStateAlphaPatternNoKoV3FeatsData::new()),
+      |state, turn| state.get_data().extract_relative_serial_blob(turn));
+}
@@ -0,0 +1,96 @@
+extern crate holmes;
+
+use holmes::board::{Stone, Point, Action};
+use holmes::gtp_board::{Coord};
+use holmes::search::komi::{DynamicKomiMode, DynamicKomiConfig, DynamicKomi};
+use holmes::txnstate::{TxnStateConfig, TxnState};
+
+fn point(code: &str) -> Point {
+  Point::from_coord(Coord::parse_code_str(code).unwrap())
+}
+
+fn handicap_state(num_stones: usize) -> TxnState {
+  let mut state = TxnState::new(TxnStateConfig::default(), ());
+  state.reset();
+  let stones: Vec<_> = ["D4", "Q16", "D16", "Q4"].iter().take(num_stones)
+    .map(|&code| (Stone::Black, point(code)))
+    .collect();
+  state.place_setup_stones(&stones).unwrap();
+  state
+}
+
+#[test]
+fn test_komi_handicap() {
+  let cfg = DynamicKomiConfig{
+    mode:       DynamicKomiMode::Handicap,
+    fade_ply:   4,
+    .. DynamicKomiConfig::default()
+  };
+  let mut komi = DynamicKomi::new(cfg);
+  let mut state = handicap_state(4);
+  // White is behind by about 4 stones and demands less of itself.
+  assert_eq!(0.5 + 28.0, komi.effective_komi(&state, Stone::White, 0.5));
+  assert_eq!(0.5 + 28.0, komi.effective_komi(&state, Stone::Black, 0.5));
+  for &(turn, code) in [(Stone::White, "K10"), (Stone::Black, "C3")].iter() {
+    state.try_action(turn, Action::Place{point: point(code)}).unwrap();
+    state.commit();
+  }
+  assert_eq!(0.5 + 14.0, komi.effective_komi(&state, Stone::White, 0.5));
+  for &(turn, code) in [(Stone::White, "K11"), (Stone::Black, "C4")].iter() {
+    state.try_action(turn, Action::Place{point: point(code)}).unwrap();
+    state.commit();
+  }
+  assert_eq!(0.5, komi.effective_komi(&state, Stone::White, 0.5));
+
+  // No shift in even games.
+  let state = handicap_state(0);
+  assert_eq!(6.5, komi.effective_komi(&state, Stone::White, 6.5));
+
+  // The shift fades in whole steps, so the komi holds between steps.
+  let mut komi = DynamicKomi::new(DynamicKomiConfig{
+    fade_ply:   3,
+    step:       5.0,
+    .. cfg
+  });
+  let mut state = handicap_state(4);
+  assert_eq!(0.5 + 25.0, komi.effective_komi(&state, Stone::White, 0.5));
+  state.try_action(Stone::White, Action::Place{point: point("K10")}).unwrap();
+  state.commit();
+  assert_eq!(0.5 + 15.0, komi.effective_komi(&state, Stone::Black, 0.5));
+}
+
+#[test]
+fn test_komi_situational_ratchet() {
+  let state = handicap_state(0);
+  let cfg = DynamicKomiConfig{
+    mode:   DynamicKomiMode::Situational,
+    .. DynamicKomiConfig::default()
+  };
+  let mut komi = DynamicKomi::new(cfg);
+  assert_eq!(6.5, komi.effective_komi(&state, Stone::Black, 6.5));
+  komi.update(0.9);
+  komi.update(0.9);
+  assert_eq!(8.5, komi.effective_komi(&state, Stone::Black, 6.5));
+  komi.update(0.6);
+  assert_eq!(2.0, komi.shift());
+  komi.update(0.1);
+  komi.update(0.9);
+  assert_eq!(2.0, komi.shift());
+
+  let mut komi = DynamicKomi::new(DynamicKomiConfig{
+    mode:   DynamicKomiMode::Ratchet,
+    .. cfg
+  });
+  assert_eq!(6.5, komi.effective_komi(&state, Stone::White, 6.5));
+  komi.update(0.9);
+  komi.update(0.9);
+  assert_eq!(4.5, komi.effective_komi(&state, Stone::White, 6.5));
+  komi.update(0.1);
+  assert_eq!(1.0, komi.shift());
+  // The shift never rises again above the level where white fell behind.
+  komi.update(0.9);
+  komi.update(0.9);
+  assert_eq!(1.0, komi.shift());
+  komi.reset();
+  assert_eq!(0.0, komi.shift());
+}
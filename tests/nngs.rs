@@ -0,0 +1,439 @@
+extern crate holmes;
+
+use holmes::board::{Stone, Point, Action, PlayerRank};
+use holmes::client::agent::{AgentMsg};
+use holmes::client::agent::parallel_search::{ParallelSearchAsyncAgent};
+use holmes::client::mock_nngs::{MockNngsConfig, MockNngsServer, ScriptedAsyncAgent};
+use holmes::client::nngs::{
+  NngsMsg, NngsPrompt, NngsAction, NngsPhase, NngsSession,
+  NngsClient, NngsOneShotClient,
+  NngsServerConfig, NngsClientConfig, NngsAutomatchConfig, NngsMatchConfig,
+  parse_nngs_line,
+};
+use holmes::config::{EngineConfig};
+use holmes::gtp_board::{Coord};
+
+use std::env;
+use std::fs::{File};
+use std::io::{Write};
+use std::path::{PathBuf};
+
+fn place(code: &str) -> Action {
+  Action::Place{point: Point::from_coord(Coord::parse_code_str(code).unwrap())}
+}
+
+fn server_cfg(server: &MockNngsServer) -> NngsServerConfig {
+  NngsServerConfig{
+    host:     server.host(),
+    port:     server.port(),
+    login:    "holmes".to_string(),
+    password: None,
+  }
+}
+
+fn script_match_cfg(name: &str, moves: &[&str]) -> NngsMatchConfig {
+  let path = env::temp_dir().join(format!("holmes-nngs-{}.txt", name));
+  let mut file = File::create(&path).unwrap();
+  for code in moves.iter() {
+    writeln!(file, "{}", code).unwrap();
+  }
+  NngsMatchConfig{
+    load_save_path:   Some(path.to_str().unwrap().to_string()),
+    skip_as_black:    None,
+    automatch:    true,
+    our_stone:    Stone::Black,
+    opponent:     "mockbot".to_string(),
+    board_size:   19,
+    main_time:    10,
+    byoyomi_time: 0,
+  }
+}
+
+fn writes(actions: &[NngsAction]) -> Vec<String> {
+  actions.iter().filter_map(|action| match action {
+    &NngsAction::Write(ref cmd) => Some(String::from_utf8_lossy(cmd).into_owned()),
+    _ => None,
+  }).collect()
+}
+
+#[test]
+fn test_parse_nngs_lines() {
+  assert_eq!(NngsMsg::Prompt(NngsPrompt::Game), parse_nngs_line(b"1 6\r\n"));
+  match parse_nngs_line(b"9 Use <match mockbot B 19 10 0> or <decline mockbot> to respond.\n") {
+    NngsMsg::MatchRequest{ref opponent, our_stone, board_size, main_time_mins, byoyomi_time_mins} => {
+      assert_eq!("mockbot", opponent);
+      assert_eq!(Stone::Black, our_stone);
+      assert_eq!((19, 10, 0), (board_size, main_time_mins, byoyomi_time_mins));
+    }
+    msg => panic!("unexpected: {:?}", msg),
+  }
+  match parse_nngs_line(b"15 Game 3 I: mockbot (0 599 -1) vs holmes (2 587 -1)\n") {
+    NngsMsg::GameHeader{game_id, ref white, ref black, black_clock, ..} => {
+      assert_eq!(3, game_id);
+      assert_eq!(("mockbot", "holmes"), (white as &str, black as &str));
+      assert_eq!((2, 587), (black_clock.captures, black_clock.time_left_s));
+    }
+    msg => panic!("unexpected: {:?}", msg),
+  }
+  assert_eq!(
+      NngsMsg::Move{move_number: 12, turn: Stone::White, action: place("Q16")},
+      parse_nngs_line(b"15  12(W): Q16\n"));
+  assert_eq!(
+      NngsMsg::Move{move_number: 15, turn: Stone::Black, action: Action::Pass},
+      parse_nngs_line(b"15  15(B): Pass\n"));
+  assert_eq!(
+      NngsMsg::Resigned{player: "mockbot".to_string()},
+      parse_nngs_line(b"9 mockbot has resigned the game.\n"));
+  match parse_nngs_line(b"21 {Game 3: mockbot vs holmes : W+Resign}\n") {
+    NngsMsg::GameResult{game_id, ref result, ..} => {
+      assert_eq!(3, game_id);
+      assert_eq!("W+Resign", result);
+    }
+    msg => panic!("unexpected: {:?}", msg),
+  }
+  assert_eq!(
+      NngsMsg::StatsRating{rank: Some(PlayerRank::Kyu(5))},
+      parse_nngs_line(b"9 Rating:      5k*  1234\n"));
+  assert_eq!(
+      NngsMsg::TimeLeft{player: "holmes".to_string(), time_left_s: 581},
+      parse_nngs_line(b"9 Black(holmes) : 9:41\n"));
+  match parse_nngs_line(b"some telnet banner\n") {
+    NngsMsg::Line{..} => {}
+    msg => panic!("unexpected: {:?}", msg),
+  }
+}
+
+#[test]
+fn test_session_automatch_filters() {
+  let mut client_cfg = NngsClientConfig::default();
+  client_cfg.automatch = Some(NngsAutomatchConfig{
+    speed:  Some("blitz".to_string()),
+    rank:   Some("1k".to_string()),
+  });
+  let mut session = NngsSession::new("holmes".to_string(), client_cfg, None);
+  let mut actions = vec![];
+  session.handle_agent(AgentMsg::Ready, &mut actions);
+  assert!(actions.is_empty());
+
+  // Too slow for blitz.
+  session.handle_server(parse_nngs_line(b"9 Use <match slowbot W 19 30 0> or <decline slowbot> to respond."), &mut actions);
+  assert_eq!(vec!["decline slowbot".to_string()], writes(&actions));
+
+  // Fast enough, but we must look up the rank first; 5k is too weak.
+  actions.clear();
+  session.handle_server(parse_nngs_line(b"9 Use <match mockbot W 19 5 0> or <decline mockbot> to respond."), &mut actions);
+  assert_eq!(vec!["stats mockbot".to_string()], writes(&actions));
+  actions.clear();
+  session.handle_server(parse_nngs_line(b"9 Player:      mockbot"), &mut actions);
+  session.handle_server(parse_nngs_line(b"9 Rating:      5k"), &mut actions);
+  assert_eq!(vec!["decline mockbot".to_string()], writes(&actions));
+  assert_eq!(NngsPhase::Idle, session.phase());
+}
+
+#[test]
+fn test_automatch_config_validation() {
+  let mut automatch = NngsAutomatchConfig{
+    speed:  Some("blitz".to_string()),
+    rank:   Some("1k".to_string()),
+  };
+  assert!(automatch.validate().is_ok());
+  assert_eq!(Some(5), automatch.max_main_time_mins());
+  automatch.speed = Some("lightning".to_string());
+  assert!(automatch.validate().is_err());
+  automatch.speed = None;
+  automatch.rank = Some("99x".to_string());
+  assert!(automatch.validate().is_err());
+
+  let mut client_cfg = NngsClientConfig::default();
+  assert!(client_cfg.validate().is_ok());
+  client_cfg.automatch = Some(automatch);
+  assert!(client_cfg.validate().is_err());
+  client_cfg.automatch = None;
+  client_cfg.min_backoff_ms = 2 * client_cfg.max_backoff_ms;
+  assert!(client_cfg.validate().is_err());
+}
+
+fn start_game(session: &mut NngsSession, our_stone: Stone, actions: &mut Vec<NngsAction>) {
+  session.handle_agent(AgentMsg::Ready, actions);
+  session.handle_agent(AgentMsg::AcceptMatch{
+    passive: false, opponent: "mockbot".to_string(), our_stone: our_stone,
+    board_size: 19, main_time_secs: 600, byoyomi_time_secs: 0,
+  }, actions);
+  session.handle_server(parse_nngs_line(b"15 Game 1 I: mockbot (0 600 -1) vs holmes (0 600 -1)"), actions);
+  assert_eq!(NngsPhase::Playing, session.phase());
+  actions.clear();
+}
+
+fn submit(session: &mut NngsSession, turn: Stone, action: Action, dead_stones: Vec<Vec<Point>>, actions: &mut Vec<NngsAction>) {
+  session.handle_agent(AgentMsg::SubmitAction{
+    turn:   turn,
+    action: action,
+    set_dead_stones:  !dead_stones.is_empty(),
+    dead_stones:  dead_stones,
+    live_stones:  vec![vec![], vec![]],
+    territory:    vec![vec![], vec![]],
+    outcome:      Some(turn),
+  }, actions);
+}
+
+#[test]
+fn test_session_defers_match_until_ready() {
+  let mut session = NngsSession::new("holmes".to_string(), NngsClientConfig::default(), None);
+  let mut actions = vec![];
+  session.handle_server(parse_nngs_line(b"9 Use <match mockbot W 19 10 0> or <decline mockbot> to respond."), &mut actions);
+  assert!(actions.is_empty());
+  session.handle_agent(AgentMsg::Ready, &mut actions);
+  assert_eq!(1, actions.len());
+  match actions[0] {
+    NngsAction::Agent(AgentMsg::RequestMatch{passive, ref opponent, our_stone, ..}) => {
+      assert!(passive);
+      assert_eq!("mockbot", opponent);
+      assert_eq!(Stone::White, our_stone);
+    }
+    _ => panic!("unexpected action"),
+  }
+}
+
+#[test]
+fn test_session_resends_unconfirmed_move_on_reconnect() {
+  let mut session = NngsSession::new("holmes".to_string(), NngsClientConfig::default(), None);
+  let mut actions = vec![];
+  start_game(&mut session, Stone::Black, &mut actions);
+  submit(&mut session, Stone::Black, place("D4"), vec![], &mut actions);
+  assert_eq!(vec!["D4".to_string()], writes(&actions));
+
+  // The connection drops before the server echoes our move.
+  actions.clear();
+  session.disconnect();
+  session.connect(None, &mut actions);
+  assert_eq!(Some(&"D4".to_string()), writes(&actions).last());
+  assert_eq!(NngsPhase::Playing, session.phase());
+
+  // Once echoed, the move is not sent again.
+  session.handle_server(parse_nngs_line(b"15   0(B): D4"), &mut actions);
+  actions.clear();
+  session.disconnect();
+  session.connect(None, &mut actions);
+  assert_eq!(Some(&"set verbose FALSE".to_string()), writes(&actions).last());
+}
+
+#[test]
+fn test_session_scoring_says_done_once() {
+  let mut client_cfg = NngsClientConfig::default();
+  client_cfg.max_games = Some(1);
+  let mut session = NngsSession::new("holmes".to_string(), client_cfg, None);
+  let mut actions = vec![];
+  start_game(&mut session, Stone::White, &mut actions);
+  let dead_pt = Point::from_coord(Coord::parse_code_str("C3").unwrap());
+  submit(&mut session, Stone::White, Action::Pass, vec![vec![dead_pt], vec![]], &mut actions);
+  assert_eq!(vec!["pass".to_string()], writes(&actions));
+
+  actions.clear();
+  session.handle_server(NngsMsg::Prompt(NngsPrompt::Scoring), &mut actions);
+  assert_eq!(NngsPhase::Scoring, session.phase());
+  assert_eq!(vec!["C3".to_string(), "done".to_string()], writes(&actions));
+  // The server prompts again after each removal.
+  actions.clear();
+  session.handle_server(NngsMsg::Prompt(NngsPrompt::Scoring), &mut actions);
+  assert!(actions.is_empty());
+
+  // Back at the server prompt, the game is over, and so is the session.
+  session.handle_server(NngsMsg::Prompt(NngsPrompt::Server), &mut actions);
+  assert_eq!(NngsPhase::Finished, session.phase());
+  assert_eq!(1, session.games_played());
+  assert_eq!(vec!["quit".to_string()], writes(&actions));
+}
+
+fn rechallenge_delays(actions: &[NngsAction]) -> Vec<u32> {
+  actions.iter().filter_map(|action| match action {
+    &NngsAction::Rechallenge{delay_ms} => Some(delay_ms),
+    _ => None,
+  }).collect()
+}
+
+#[test]
+fn test_session_rechallenges_after_decline() {
+  let mut client_cfg = NngsClientConfig::default();
+  client_cfg.min_backoff_ms = 1000;
+  client_cfg.max_backoff_ms = 3000;
+  let match_cfg = NngsMatchConfig{
+    load_save_path:   None,
+    .. script_match_cfg("rechallenge", &[])
+  };
+  let mut session = NngsSession::new("holmes".to_string(), client_cfg, Some(match_cfg));
+  let mut actions = vec![];
+  session.handle_agent(AgentMsg::Ready, &mut actions);
+  assert_eq!(vec!["match mockbot B 19 10 0".to_string()], writes(&actions));
+  assert_eq!(NngsPhase::Challenging, session.phase());
+
+  // Each decline schedules another challenge, backing off up to the maximum.
+  for &delay_ms in [1000, 2000, 3000, 3000].iter() {
+    actions.clear();
+    session.handle_server(parse_nngs_line(b"9 mockbot declines your request for a match."), &mut actions);
+    assert_eq!(NngsPhase::Idle, session.phase());
+    assert!(writes(&actions).is_empty());
+    assert_eq!(vec![delay_ms], rechallenge_delays(&actions));
+    actions.clear();
+    session.rechallenge(&mut actions);
+    assert_eq!(vec!["match mockbot B 19 10 0".to_string()], writes(&actions));
+    assert_eq!(NngsPhase::Challenging, session.phase());
+  }
+
+  // An accepted match resets the backoff, and a late retry does nothing
+  // once a game has started.
+  actions.clear();
+  session.handle_server(parse_nngs_line(b"9 Match [19x19] with mockbot in 10 accepted."), &mut actions);
+  session.handle_server(parse_nngs_line(b"9 mockbot declines your request for a match."), &mut actions);
+  session.handle_agent(AgentMsg::AcceptMatch{
+    passive: false, opponent: "mockbot".to_string(), our_stone: Stone::Black,
+    board_size: 19, main_time_secs: 600, byoyomi_time_secs: 0,
+  }, &mut actions);
+  assert_eq!(vec![1000], rechallenge_delays(&actions));
+  actions.clear();
+  session.rechallenge(&mut actions);
+  assert!(actions.is_empty());
+  assert_eq!(NngsPhase::Playing, session.phase());
+}
+
+#[test]
+fn test_session_ignores_resigns_in_other_games() {
+  let mut session = NngsSession::new("holmes".to_string(), NngsClientConfig::default(), None);
+  let mut actions = vec![];
+  start_game(&mut session, Stone::Black, &mut actions);
+  session.handle_server(parse_nngs_line(b"9 someone has resigned the game."), &mut actions);
+  assert_eq!(NngsPhase::Playing, session.phase());
+  assert_eq!(0, session.games_played());
+  session.handle_server(parse_nngs_line(b"9 mockbot has resigned the game."), &mut actions);
+  assert_eq!(1, session.games_played());
+}
+
+#[test]
+fn test_session_skips_replayed_moves() {
+  let mut session = NngsSession::new("holmes".to_string(), NngsClientConfig::default(), None);
+  let mut actions = vec![];
+  session.handle_agent(AgentMsg::Ready, &mut actions);
+  session.handle_agent(AgentMsg::AcceptMatch{
+    passive: true, opponent: "mockbot".to_string(), our_stone: Stone::White,
+    board_size: 19, main_time_secs: 600, byoyomi_time_secs: 0,
+  }, &mut actions);
+  assert_eq!(NngsPhase::Playing, session.phase());
+  actions.clear();
+  for _ in 0 .. 2 {
+    session.handle_server(parse_nngs_line(b"15 Game 1 I: holmes (0 600 -1) vs mockbot (0 600 -1)"), &mut actions);
+    session.handle_server(parse_nngs_line(b"15   0(B): D4"), &mut actions);
+  }
+  let num_recv = actions.iter().filter(|action| match *action {
+    &NngsAction::Agent(AgentMsg::RecvAction{..}) => true,
+    _ => false,
+  }).count();
+  assert_eq!(1, num_recv);
+}
+
+#[test]
+fn test_client_plays_scripted_game_to_scoring() {
+  let server = MockNngsServer::spawn(MockNngsConfig{
+    challenge:    false,
+    moves:    vec![place("Q16"), place("D16")],
+    ..MockNngsConfig::default()
+  });
+  let mut client_cfg = NngsClientConfig::default();
+  client_cfg.max_games = Some(1);
+  let match_cfg = script_match_cfg("scoring", &["D4", "Q4", "C3"]);
+  let mut client = NngsClient::<ScriptedAsyncAgent>::new(server_cfg(&server), client_cfg, Some(match_cfg), ());
+  client.run_loop();
+
+  let transcript = server.join();
+  assert_eq!(1, transcript.connections);
+  assert_eq!(1, transcript.games.len());
+  let game = &transcript.games[0];
+  assert_eq!(Stone::Black, game.client_stone);
+  assert_eq!(vec![
+    (Stone::Black, place("D4")),
+    (Stone::White, place("Q16")),
+    (Stone::Black, place("Q4")),
+    (Stone::White, place("D16")),
+    (Stone::Black, place("C3")),
+    (Stone::White, Action::Pass),
+    (Stone::Black, Action::Pass),
+  ], game.moves);
+  assert!(transcript.commands.contains(&"done".to_string()));
+  assert_eq!(Some(&"quit".to_string()), transcript.commands.last());
+}
+
+#[test]
+fn test_client_reconnects_mid_game() {
+  let server = MockNngsServer::spawn(MockNngsConfig{
+    challenge:    false,
+    moves:    vec![place("Q16"), place("D16"), Action::Resign],
+    drop_after_moves: Some(3),
+    ..MockNngsConfig::default()
+  });
+  let mut client_cfg = NngsClientConfig::default();
+  client_cfg.min_backoff_ms = 10;
+  client_cfg.max_games = Some(1);
+  let match_cfg = script_match_cfg("reconnect", &["D4", "Q4", "C3"]);
+  let mut client = NngsClient::<ScriptedAsyncAgent>::new(server_cfg(&server), client_cfg, Some(match_cfg), ());
+  client.run_loop();
+
+  let transcript = server.join();
+  assert_eq!(2, transcript.connections);
+  assert_eq!(1, transcript.games.len());
+  let game = &transcript.games[0];
+  assert_eq!("B+Resign", game.result);
+  assert_eq!(5, game.moves.len());
+  assert_eq!((Stone::Black, place("C3")), game.moves[4]);
+}
+
+#[test]
+fn test_client_plays_consecutive_games() {
+  let server = MockNngsServer::spawn(MockNngsConfig{
+    moves:    vec![Action::Resign],
+    num_games:    3,
+    ..MockNngsConfig::default()
+  });
+  let mut client_cfg = NngsClientConfig::default();
+  client_cfg.max_games = Some(3);
+  let mut client = NngsClient::<ScriptedAsyncAgent>::new(server_cfg(&server), client_cfg, None, ());
+  client.run_loop();
+
+  let transcript = server.join();
+  assert_eq!(3, transcript.games.len());
+  for game in transcript.games.iter() {
+    assert_eq!("B+Resign", game.result);
+  }
+}
+
+#[test]
+fn test_one_shot_client_finishes_on_resign() {
+  let server = MockNngsServer::spawn(MockNngsConfig{
+    moves:    vec![Action::Resign],
+    ..MockNngsConfig::default()
+  });
+  let mut client = NngsOneShotClient::<ScriptedAsyncAgent>::new(server_cfg(&server), None, ());
+  client.run_loop();
+
+  let transcript = server.join();
+  assert_eq!(1, transcript.games.len());
+  assert_eq!(vec![(Stone::Black, Action::Pass)], transcript.games[0].moves);
+  assert_eq!(Some(&"quit".to_string()), transcript.commands.last());
+}
+
+#[test]
+#[ignore] // Requires a CUDA device and the convnet policy weights.
+fn test_parallel_search_agent_vs_mock() {
+  let server = MockNngsServer::spawn(MockNngsConfig{
+    mock_stone:   Stone::Black,
+    moves:    vec![place("D4"), place("Q16"), Action::Resign],
+    ..MockNngsConfig::default()
+  });
+  let mut client_cfg = NngsClientConfig::default();
+  client_cfg.max_games = Some(1);
+  let engine_cfg = EngineConfig::load(Some(&PathBuf::from("conf/holmes-nngs.toml")), &[]).unwrap();
+  let mut client = NngsClient::<ParallelSearchAsyncAgent>::new(server_cfg(&server), client_cfg, None, engine_cfg);
+  client.run_loop();
+
+  let transcript = server.join();
+  assert_eq!(1, transcript.games.len());
+  assert_eq!("W+Resign", transcript.games[0].result);
+  assert_eq!(4, transcript.games[0].moves.len());
+}
@@ -0,0 +1,162 @@
+extern crate holmes;
+
+use holmes::board::{Stone, Point, Action};
+use holmes::gtp_board::{Coord};
+use holmes::mm::{MmTrainer};
+use holmes::pattern::{
+  MAX_PREV_DISTANCE,
+  MoveFeature, MoveFeatureGroup, PatternGammaDatabase,
+  extract_move_features, prev_distance,
+};
+use holmes::search::parallel_policies::pattern::{PatternPolicyWorkerBuilder};
+use holmes::search::parallel_tree::{TreePolicyConfig};
+use holmes::sgf::{Sgf, SgfRankStyle};
+use holmes::txnstate::{TxnStateConfig, TxnState};
+
+use std::env;
+use std::fs::{File};
+use std::io::{Write};
+
+fn point(code: &str) -> Point {
+  Point::from_coord(Coord::parse_code_str(code).unwrap())
+}
+
+fn has_group(features: &[MoveFeature], group: MoveFeatureGroup) -> bool {
+  features.iter().any(|f| f.group() == Some(group))
+}
+
+#[test]
+fn test_move_features() {
+  let mut state = TxnState::new(TxnStateConfig::default(), ());
+  state.reset();
+  for &(turn, code) in [
+    (Stone::Black, "D4"), (Stone::White, "D5"),
+    (Stone::Black, "C5"), (Stone::White, "Q16"),
+  ].iter() {
+    state.try_action(turn, Action::Place{point: point(code)}).unwrap();
+    state.commit();
+  }
+  let mut features = vec![];
+
+  // E5 puts the white stone at D5 in atari.
+  extract_move_features(&state, Stone::Black, point("E5"), &mut features);
+  assert!(has_group(&features, MoveFeatureGroup::Atari));
+  assert!(!has_group(&features, MoveFeatureGroup::Capture));
+  assert!(!has_group(&features, MoveFeatureGroup::PrevDistance));
+
+  // Near the previous move at Q16.
+  extract_move_features(&state, Stone::Black, point("R17"), &mut features);
+  assert!(features.contains(&MoveFeature::new(MoveFeatureGroup::PrevDistance, 3)));
+
+  // After E5, black D6 captures; after B1, white A1 is a self-atari.
+  for &(turn, code) in [
+    (Stone::Black, "E5"), (Stone::White, "Q17"),
+    (Stone::Black, "B1"), (Stone::White, "Q18"),
+  ].iter() {
+    state.try_action(turn, Action::Place{point: point(code)}).unwrap();
+    state.commit();
+  }
+  extract_move_features(&state, Stone::Black, point("D6"), &mut features);
+  assert!(has_group(&features, MoveFeatureGroup::Capture));
+  assert!(!has_group(&features, MoveFeatureGroup::SelfAtari));
+  extract_move_features(&state, Stone::White, point("A1"), &mut features);
+  assert!(has_group(&features, MoveFeatureGroup::SelfAtari));
+
+  // Patterns are relative to the player to move.
+  let mut mirror = TxnState::new(TxnStateConfig::default(), ());
+  mirror.reset();
+  for &(turn, code) in [(Stone::White, "D4"), (Stone::Black, "D5")].iter() {
+    mirror.try_action(turn, Action::Place{point: point(code)}).unwrap();
+    mirror.commit();
+  }
+  let mut mirror_features = vec![];
+  extract_move_features(&mirror, Stone::White, point("E4"), &mut mirror_features);
+  let mut orig = TxnState::new(TxnStateConfig::default(), ());
+  orig.reset();
+  for &(turn, code) in [(Stone::Black, "D4"), (Stone::White, "D5")].iter() {
+    orig.try_action(turn, Action::Place{point: point(code)}).unwrap();
+    orig.commit();
+  }
+  extract_move_features(&orig, Stone::Black, point("E4"), &mut features);
+  assert_eq!(features[0], mirror_features[0]);
+}
+
+#[test]
+fn test_mm_trainer() {
+  let mut trainer = MmTrainer::new();
+  for text in [
+    "(;GM[1]SZ[19];B[pd];W[dd];B[pq];W[dp];B[qk];W[cj];B[ec];W[ce])",
+    "(;GM[1]SZ[19];B[dp];W[pp];B[dc];W[pd];B[jd];W[jp];B[cm];W[qn])",
+  ].iter() {
+    let sgf = Sgf::from_text(text.as_bytes());
+    assert_eq!(8, trainer.add_sgf(&sgf, SgfRankStyle::Server, false).unwrap());
+  }
+  let sgf = Sgf::from_text(b"(;GM[1]SZ[19];B[pd];W[dp];B[pp];W[dd])");
+  assert_eq!(4, trainer.add_sgf(&sgf, SgfRankStyle::Server, true).unwrap());
+  assert_eq!(16, trainer.num_train_positions());
+  assert_eq!(4, trainer.num_valid_positions());
+
+  let init_eval = trainer.evaluate_train();
+  for _ in 0 .. 5 {
+    trainer.iterate();
+  }
+  let eval = trainer.evaluate_train();
+  assert!(eval.log_likelihood > init_eval.log_likelihood);
+
+  let db = trainer.to_database();
+  let path = env::temp_dir().join("holmes-gammas-test.db");
+  db.save(&path);
+  let saved_db = PatternGammaDatabase::open(&path).unwrap();
+  assert_eq!(db.gammas, saved_db.gammas);
+}
+
+#[test]
+fn test_local_move_gamma() {
+  let mut db = PatternGammaDatabase::new();
+  db.gammas.insert(MoveFeature::new(MoveFeatureGroup::Atari, 0), 4.0);
+  db.gammas.insert(MoveFeature::new(MoveFeatureGroup::PrevDistance, 2), 3.0);
+  db.gammas.insert(MoveFeature::new(MoveFeatureGroup::PrevDistance, 3), 0.5);
+  let prev_gammas = db.prev_distance_gammas();
+  assert_eq!(MAX_PREV_DISTANCE as usize + 1, prev_gammas.len());
+
+  let mut state = TxnState::new(TxnStateConfig::default(), ());
+  state.reset();
+  for &(turn, code) in [
+    (Stone::Black, "D4"), (Stone::White, "D5"),
+    (Stone::Black, "C5"), (Stone::White, "Q16"),
+  ].iter() {
+    state.try_action(turn, Action::Place{point: point(code)}).unwrap();
+    state.commit();
+  }
+  // The rollout policy caches the local gammas and weighs in the distance to
+  // the previous move; that must agree with the full gamma.
+  let mut features = vec![];
+  let (_, prev_point) = state.last_play().unwrap();
+  for &code in ["E5", "D6", "E6", "R17", "Q17", "K10"].iter() {
+    let p = point(code);
+    let mut gamma = db.local_move_gamma(&state, Stone::Black, p, &mut features);
+    let dist = prev_distance(p, prev_point);
+    if dist <= MAX_PREV_DISTANCE {
+      gamma *= prev_gammas[dist as usize];
+    }
+    assert_eq!(db.move_gamma(&state, Stone::Black, p, &mut features), gamma);
+  }
+  assert_eq!(4.0, db.move_gamma(&state, Stone::Black, point("E5"), &mut features));
+  assert_eq!(0.5, db.move_gamma(&state, Stone::Black, point("R17"), &mut features));
+}
+
+#[test]
+fn test_pattern_policy_gamma_db_errors() {
+  let tree_cfg = TreePolicyConfig::test_default();
+  let missing_path = env::temp_dir().join("holmes-gammas-missing.db");
+  assert!(PatternPolicyWorkerBuilder::new(tree_cfg, &missing_path).is_err());
+
+  // An old format file is an error, not a panic.
+  let old_path = env::temp_dir().join("holmes-gammas-old.db");
+  File::create(&old_path).unwrap().write_all(b"pattern\t1.0\n").unwrap();
+  assert!(PatternPolicyWorkerBuilder::new(tree_cfg, &old_path).is_err());
+
+  let path = env::temp_dir().join("holmes-gammas-policy.db");
+  PatternGammaDatabase::new().save(&path);
+  assert!(PatternPolicyWorkerBuilder::new(tree_cfg, &path).is_ok());
+}
@@ -0,0 +1,67 @@
+extern crate holmes;
+extern crate rustc_serialize;
+
+use holmes::search::distributed::{
+  DistributedSearchConfig,
+  DistributedSearchMaster,
+  DistributedSearchWorker,
+};
+use holmes::search::parallel_policies::uniform::{UniformPolicyWorkerBuilder};
+use holmes::search::parallel_tree::{
+  TreePolicyConfig,
+  SharedTree,
+};
+use holmes::search::report::{SearchReport, TreeExportConfig, tree_to_dot, tree_to_sgf};
+use holmes::txnstate::{TxnStateConfig, TxnState};
+use holmes::txnstate::extras::{TxnStateNodeData};
+
+use rustc_serialize::json::{Json};
+use std::thread;
+
+#[test]
+fn test_search_report() {
+  let mut master = DistributedSearchMaster::bind("127.0.0.1:0", UniformPolicyWorkerBuilder::new(TreePolicyConfig::test_default())).unwrap();
+  let addr = master.local_addr();
+  let worker = thread::spawn(move || {
+    let mut worker = DistributedSearchWorker::connect(addr, 16, UniformPolicyWorkerBuilder::new(TreePolicyConfig::test_default())).unwrap();
+    worker.run()
+  });
+  master.wait_for_workers(1);
+  let mut init_state = TxnState::new(TxnStateConfig::default(), TxnStateNodeData::new());
+  init_state.reset();
+  let cfg = DistributedSearchConfig{
+    num_rollouts:       256,
+    max_batch_size:     16,
+    result_timeout_ms:  10000,
+    remote_priors:      false,
+  };
+  let tree = SharedTree::new(TreePolicyConfig::test_default());
+  let (result, stats) = master.search(cfg, &init_state, tree.clone());
+  master.shutdown();
+  worker.join().unwrap();
+
+  let report = SearchReport::new(&tree, &stats, 5);
+  assert_eq!(256, report.root_visits);
+  assert_eq!(256, report.children.iter().fold(0, |acc, c| acc + c.visits));
+  assert!(report.children.windows(2).all(|w| w[0].visits >= w[1].visits));
+  assert_eq!(1, report.depth_histogram[0]);
+  assert!(!report.pv.is_empty());
+  assert_eq!(report.children[0].point, report.pv[0].action);
+  // The search result and the report share the same variation.
+  assert_eq!(result.pv[0].action, report.pv[0].action);
+  assert_eq!(result.pv[0].visits, report.pv[0].visits);
+
+  let json = Json::from_str(&report.to_json()).unwrap();
+  assert_eq!(Some(256), json.find("root_visits").and_then(|v| v.as_u64()));
+  assert!(json.find("children").and_then(|v| v.as_array()).is_some());
+
+  let export_cfg = TreeExportConfig{max_depth: 2, min_visits: 4};
+  let dot = tree_to_dot(&tree, export_cfg);
+  assert!(dot.starts_with("digraph search {\n"));
+  assert!(dot.ends_with("}\n"));
+  let sgf = tree_to_sgf(&tree, export_cfg);
+  assert!(sgf.starts_with("(;GM[1]FF[4]\n"));
+  let opens = sgf.chars().filter(|&c| c == '(').count();
+  let closes = sgf.chars().filter(|&c| c == ')').count();
+  assert_eq!(opens, closes);
+}
@@ -0,0 +1,423 @@
+extern crate holmes;
+
+use holmes::agents::{PreGame};
+use holmes::board::{Board, RuleSet, PlayerRank, Stone, Point, Action};
+use holmes::gtp_board::{Coord};
+use holmes::sgf::{Sgf};
+use holmes::txnstate::{
+  TxnStateConfig, TxnState, TxnStatus, IllegalReason, BensonScratch,
+  for_each_adjacent,
+};
+
+use std::fs::{File};
+use std::io::{Read};
+use std::path::{PathBuf};
+
+fn point(code: &str) -> Point {
+  Point::from_coord(Coord::parse_code_str(code).unwrap())
+}
+
+fn new_state(ruleset: RuleSet) -> TxnState {
+  TxnState::new(
+      TxnStateConfig{
+        rules:  ruleset.rules(),
+        ranks:  [PlayerRank::Dan(9), PlayerRank::Dan(9)],
+        komi:   6.5,
+        history: true,
+      },
+      (),
+  )
+}
+
+fn stones_of(state: &TxnState) -> Vec<Stone> {
+  (0 .. Board::SIZE).map(|p| state.current_stone(Point::from_idx(p))).collect()
+}
+
+/// Plays out a fixture from `tests/fixtures/rules`, returning the final state
+/// and the position before the last move (for ko checks).
+fn load_fixture(name: &str, ruleset: RuleSet) -> (TxnState, Vec<Stone>) {
+  let path = PathBuf::from("tests/fixtures/rules").join(&format!("{}.sgf", name));
+  let mut file = File::open(&path).unwrap();
+  let mut text = vec![];
+  file.read_to_end(&mut text).unwrap();
+  let sgf = Sgf::from_text(&text);
+  let mut state = new_state(ruleset);
+  let mut prev_stones = stones_of(&state);
+  for &(ref turn_code, ref move_code) in sgf.moves.iter() {
+    let turn = Stone::from_code_str(turn_code);
+    let action = Action::from_code_str(move_code);
+    prev_stones = stones_of(&state);
+    assert_eq!(Ok(()), state.try_action(turn, action), "{}: {:?} {:?}", name, turn, action);
+    state.commit();
+  }
+  (state, prev_stones)
+}
+
+/// Checks a window of the board in the lower left corner; `rows` are listed
+/// top to bottom, and the last row is row 1.
+fn assert_window(state: &TxnState, rows: &[&str]) {
+  for (i, row) in rows.iter().enumerate() {
+    let y = rows.len() - 1 - i;
+    for (x, c) in row.split_whitespace().enumerate() {
+      let expected = match c {
+        "X" => Stone::Black,
+        "O" => Stone::White,
+        "." => Stone::Empty,
+        _ => unreachable!(),
+      };
+      let pt = Point::from_coord(Coord::new(x as u8, y as u8));
+      assert_eq!(expected, state.current_stone(pt), "at {}", pt.to_coord().to_string());
+    }
+  }
+}
+
+fn naive_group(stones: &[Stone], start: usize) -> (Vec<usize>, usize) {
+  let color = stones[start];
+  let mut group = vec![start];
+  let mut libs = vec![];
+  let mut head = 0;
+  while head < group.len() {
+    let p = group[head];
+    for_each_adjacent(Point::from_idx(p), |adj_pt| {
+      let q = adj_pt.idx();
+      if stones[q] == color && !group.contains(&q) {
+        group.push(q);
+      } else if stones[q] == Stone::Empty && !libs.contains(&q) {
+        libs.push(q);
+      }
+    });
+    head += 1;
+  }
+  (group, libs.len())
+}
+
+/// A from-scratch reference for a placement under simple ko and no suicide.
+fn naive_play(stones: &[Stone], turn: Stone, p: usize) -> Option<Vec<Stone>> {
+  if stones[p] != Stone::Empty {
+    return None;
+  }
+  let mut next = stones.to_vec();
+  next[p] = turn;
+  let mut adj = vec![];
+  for_each_adjacent(Point::from_idx(p), |adj_pt| adj.push(adj_pt.idx()));
+  for &q in adj.iter() {
+    if next[q] == turn.opponent() {
+      let (group, libs) = naive_group(&next, q);
+      if libs == 0 {
+        for &g in group.iter() {
+          next[g] = Stone::Empty;
+        }
+      }
+    }
+  }
+  if naive_group(&next, p).1 == 0 {
+    return None;
+  }
+  Some(next)
+}
+
+fn naive_legal_points(stones: &[Stone], turn: Stone, prev_stones: &[Stone]) -> Vec<Point> {
+  (0 .. Board::SIZE).filter(|&p| {
+    match naive_play(stones, turn, p) {
+      Some(next) => &next as &[Stone] != prev_stones,
+      None => false,
+    }
+  }).map(|p| Point::from_idx(p)).collect()
+}
+
+/// Counts leaf nodes of the legal move tree to `depth`, optionally checking
+/// the legal moves at every node against the naive reference.
+fn perft(state: &TxnState, turn: Stone, depth: usize, prev_stones: &[Stone], check: bool) -> usize {
+  let mut legal_points = vec![];
+  let mut scratch_state = state.clone();
+  scratch_state.iter_legal_moves_accurate(turn, |pt| legal_points.push(pt));
+  let stones = stones_of(state);
+  if check {
+    assert_eq!(naive_legal_points(&stones, turn, prev_stones), legal_points);
+  }
+  if depth <= 1 {
+    return legal_points.len();
+  }
+  let mut count = 0;
+  for &pt in legal_points.iter() {
+    let mut child = state.clone();
+    assert_eq!(Ok(()), child.try_place(turn, pt));
+    child.commit();
+    count += perft(&child, turn.opponent(), depth - 1, &stones, check);
+  }
+  count
+}
+
+/// Counts the same leaf nodes as `perft`, but walks the tree on one state:
+/// every move is tried and undone in place, which exercises the incremental
+/// pseudo-liberty updates and their undo, and the subtree below a legal move
+/// is walked after committing it and taking it back with `undo_plies`.
+fn perft_in_place(state: &mut TxnState, turn: Stone, depth: usize) -> usize {
+  let stones = stones_of(state);
+  let hash = state.current_hash();
+  let mut count = 0;
+  for p in 0 .. Board::SIZE {
+    match state.try_action(turn, Action::Place{point: Point::from_idx(p)}) {
+      Ok(_) => {
+        if depth <= 1 {
+          state.undo();
+          count += 1;
+        } else {
+          state.commit();
+          count += perft_in_place(state, turn.opponent(), depth - 1);
+          assert_eq!(Ok(()), state.undo_plies(1));
+        }
+      }
+      Err(_) => {
+        state.undo();
+      }
+    }
+    assert_eq!(hash, state.current_hash());
+    assert!(stones == stones_of(state));
+  }
+  count
+}
+
+/// Checks `perft_in_place` against the clone based `perft`.
+fn assert_perft(state: &TxnState, turn: Stone, depth: usize, prev_stones: &[Stone], check: bool) -> usize {
+  let count = perft(state, turn, depth, prev_stones, check);
+  let mut walk_state = state.clone();
+  assert_eq!(count, perft_in_place(&mut walk_state, turn, depth));
+  count
+}
+
+#[test]
+fn test_capture_single_stone() {
+  let (state, _) = load_fixture("capture_corner", RuleSet::KgsJapanese);
+  assert_window(&state, &[
+    ". . .",
+    "X . .",
+    ". X .",
+  ]);
+  assert_eq!(Stone::White, state.current_turn());
+}
+
+#[test]
+fn test_capture_group() {
+  let (state, _) = load_fixture("capture_group", RuleSet::KgsJapanese);
+  assert_window(&state, &[
+    "X X .",
+    ". . X",
+  ]);
+}
+
+#[test]
+fn test_ko() {
+  let (mut state, _) = load_fixture("ko", RuleSet::KgsJapanese);
+  assert_window(&state, &[
+    ". . X O .",
+    ". X . X O",
+  ]);
+  assert_eq!(Some((Stone::White, point("C1"))), state.current_ko());
+
+  // The immediate retake is illegal.
+  assert_eq!(Err(TxnStatus::Illegal(IllegalReason::Ko)), state.try_place(Stone::White, point("C1")));
+  state.undo();
+
+  // After an exchange elsewhere, it is legal again.
+  state.try_place(Stone::White, point("D16")).unwrap();
+  state.commit();
+  state.try_place(Stone::Black, point("Q4")).unwrap();
+  state.commit();
+  assert_eq!(Ok(()), state.try_place(Stone::White, point("C1")));
+  state.commit();
+  assert_eq!(Stone::Empty, state.current_stone(point("D1")));
+}
+
+#[test]
+fn test_superko() {
+  // Passes clear the simple ko, so under Japanese rules White may retake.
+  let (mut state, _) = load_fixture("superko", RuleSet::KgsJapanese);
+  assert_eq!(None, state.current_ko());
+  assert_eq!(Ok(()), state.try_place(Stone::White, point("C1")));
+  state.undo();
+
+  // Under positional superko the retake repeats the position before Black's
+  // capture.
+  let (mut state, _) = load_fixture("superko", RuleSet::KgsChinese);
+  assert_eq!(Err(TxnStatus::Illegal(IllegalReason::Superko)), state.try_place(Stone::White, point("C1")));
+  state.undo();
+  assert_eq!(Stone::Black, state.current_stone(point("D1")));
+  assert_eq!(Stone::Empty, state.current_stone(point("C1")));
+}
+
+#[test]
+fn test_suicide_illegal() {
+  let (mut state, _) = load_fixture("suicide", RuleSet::KgsJapanese);
+  assert_eq!(Err(TxnStatus::Illegal(IllegalReason::Suicide)), state.try_place(Stone::Black, point("B1")));
+  state.undo();
+  assert_eq!(Stone::Black, state.current_stone(point("A1")));
+  assert_eq!(Stone::Empty, state.current_stone(point("B1")));
+}
+
+#[test]
+fn test_suicide_allowed() {
+  let (mut state, _) = load_fixture("suicide", RuleSet::KgsNewZealand);
+  let hash_before = state.current_hash();
+
+  // Multi-stone suicide is legal and removes the suicided chain.
+  assert_eq!(Ok(()), state.try_place(Stone::Black, point("B1")));
+  state.undo();
+  assert_eq!(hash_before, state.current_hash());
+  assert_eq!(Stone::Black, state.current_stone(point("A1")));
+  assert_eq!(Ok(()), state.try_place(Stone::Black, point("B1")));
+  state.commit();
+  assert_window(&state, &[
+    "O O .",
+    ". . O",
+  ]);
+
+  // Single stone suicide only repeats the position, which superko forbids.
+  state.try_place(Stone::White, point("B1")).unwrap();
+  state.commit();
+  assert_eq!(Err(TxnStatus::Illegal(IllegalReason::Superko)), state.try_place(Stone::Black, point("A1")));
+  state.undo();
+  assert_eq!(Stone::Empty, state.current_stone(point("A1")));
+}
+
+#[test]
+fn test_benson() {
+  let mut scratch = BensonScratch::new();
+  let (state, _) = load_fixture("benson_two_eyes", RuleSet::KgsJapanese);
+  assert_eq!((6, 2), state.count_unconditionally_alive(Stone::Black, &mut scratch));
+  assert_eq!((0, 0), state.count_unconditionally_alive(Stone::White, &mut scratch));
+
+  let (state, _) = load_fixture("benson_one_eye", RuleSet::KgsJapanese);
+  assert_eq!((0, 0), state.count_unconditionally_alive(Stone::Black, &mut scratch));
+}
+
+#[test]
+fn test_cleanup_alive_stones() {
+  // The cleanup phase never treats these stones as dead.
+  let mut scratch = BensonScratch::new();
+  let (state, _) = load_fixture("benson_two_eyes", RuleSet::KgsJapanese);
+  let alive = state.unconditionally_alive_stones(Stone::Black, &mut scratch);
+  assert_eq!(6, alive.len());
+  for &pt in alive.iter() {
+    assert_eq!(Stone::Black, state.current_stone(pt));
+  }
+  assert!(state.unconditionally_alive_stones(Stone::White, &mut scratch).is_empty());
+
+  let (state, _) = load_fixture("benson_one_eye", RuleSet::KgsJapanese);
+  assert!(state.unconditionally_alive_stones(Stone::Black, &mut scratch).is_empty());
+}
+
+#[test]
+fn test_handicap_stones() {
+  let handicap = [point("D4"), point("Q16"), point("D16"), point("Q4")];
+  let mut territory = vec![0; Board::SIZE];
+  let mut scores = vec![];
+  for &(ruleset, handicap_komi) in [
+      (RuleSet::KgsJapanese, 0.0),
+      (RuleSet::KgsChinese, 4.0),
+      (RuleSet::KgsAga, 3.0)].iter()
+  {
+    let mut state = new_state(ruleset);
+    assert_eq!(Err(()), state.place_handicap_stones(&handicap[.. 1]));
+    assert_eq!(Ok(()), state.place_handicap_stones(&handicap));
+    assert_eq!(Stone::White, state.current_turn());
+    assert_eq!(0, state.current_ply());
+    assert_eq!(4, state.num_handicap_stones());
+    assert_eq!(handicap_komi, state.handicap_komi());
+    assert_eq!(Err(()), state.place_handicap_stones(&handicap));
+    scores.push(state.current_score_tromp_taylor_undead(6.5, &mut territory));
+  }
+  assert_eq!(scores[0] + 4.0, scores[1]);
+  assert_eq!(scores[0] + 3.0, scores[2]);
+}
+
+#[test]
+fn test_handicap_stones_undo() {
+  let handicap = [point("D4"), point("Q16")];
+  let mut state = new_state(RuleSet::KgsJapanese);
+  state.place_handicap_stones(&handicap).unwrap();
+  state.try_action(Stone::White, Action::Place{point: point("Q4")}).unwrap();
+  state.commit();
+  assert_eq!(Stone::Black, state.current_turn());
+
+  state.undo_plies(1).unwrap();
+  assert_eq!(0, state.current_ply());
+  assert_eq!(Stone::White, state.current_turn());
+  assert_eq!(Stone::Empty, state.current_stone(point("Q4")));
+  assert_eq!(Stone::Black, state.current_stone(point("D4")));
+  assert_eq!(Stone::Black, state.current_stone(point("Q16")));
+  state.redo_plies(1).unwrap();
+  assert_eq!(Stone::Black, state.current_turn());
+  assert_eq!(Stone::White, state.current_stone(point("Q4")));
+}
+
+#[test]
+fn test_setup_stones_all_or_nothing() {
+  let mut state = new_state(RuleSet::KgsJapanese);
+  state.place_setup_stones(&[(Stone::Black, point("D4"))]).unwrap();
+  let hash = state.current_hash();
+  // The second stone lands on an occupied point.
+  let stones = [(Stone::White, point("Q16")), (Stone::White, point("D4"))];
+  assert_eq!(Err(()), state.place_setup_stones(&stones));
+  assert_eq!(Stone::Empty, state.current_stone(point("Q16")));
+  assert_eq!(Stone::Black, state.current_stone(point("D4")));
+  assert_eq!(vec![(Stone::Black, point("D4"))], state.setup_stones().to_vec());
+  assert_eq!(Stone::Black, state.current_turn());
+  assert_eq!(hash, state.current_hash());
+}
+
+#[test]
+fn test_free_handicap() {
+  let pregame = PreGame;
+  // Not on the first two lines.
+  for &(code, ok) in [("A1", false), ("B2", false), ("C3", true), ("R17", true), ("S18", false), ("K2", false)].iter() {
+    assert_eq!(ok, pregame.is_free_handicap_candidate(&[], point(code)), "{}", code);
+  }
+  // Not within two lines of another handicap stone.
+  let placed = [point("D4")];
+  for &(code, ok) in [("F6", false), ("D6", false), ("G4", true), ("D7", true)].iter() {
+    assert_eq!(ok, pregame.is_free_handicap_candidate(&placed, point(code)), "{}", code);
+  }
+
+  // The best ranked candidate is placed after each stone.
+  let mut num_calls = 0;
+  let chosen = pregame.choose_free_handicap(3, |placed| {
+    assert_eq!(num_calls, placed.len());
+    num_calls += 1;
+    ["A1", "D4", "E5", "Q16", "K10"].iter().map(|&code| point(code)).collect()
+  });
+  assert_eq!(3, num_calls);
+  assert_eq!(vec![point("D4"), point("Q16"), point("K10")], chosen);
+
+  // With no candidate left, all of the stones go on the fixed positions.
+  let chosen = pregame.choose_free_handicap(2, |_| vec![point("D4"), point("E4")]);
+  assert_eq!(pregame.fixed_handicap_positions(2), chosen);
+  assert_eq!(vec![point("D4"), point("Q16")], chosen);
+}
+
+#[test]
+fn test_perft_empty_board() {
+  let state = new_state(RuleSet::KgsJapanese);
+  let empty = stones_of(&state);
+  assert_eq!(361, assert_perft(&state, Stone::Black, 1, &empty, true));
+  assert_eq!(129960, assert_perft(&state, Stone::Black, 2, &empty, false));
+}
+
+#[test]
+fn test_perft_fixtures() {
+  // White may not retake the ko; no other captures or suicides are possible
+  // in two plies.
+  let (state, prev) = load_fixture("ko", RuleSet::KgsJapanese);
+  assert_eq!(354, assert_perft(&state, Stone::White, 1, &prev, true));
+  assert_eq!(354 * 354, assert_perft(&state, Stone::White, 2, &prev, true));
+
+  let (state, prev) = load_fixture("superko", RuleSet::KgsJapanese);
+  assert_eq!(355, assert_perft(&state, Stone::White, 1, &prev, true));
+  let (state, prev) = load_fixture("superko", RuleSet::KgsChinese);
+  assert_eq!(354, assert_perft(&state, Stone::White, 1, &prev, false));
+
+  let (state, prev) = load_fixture("suicide", RuleSet::KgsJapanese);
+  assert_eq!(354, assert_perft(&state, Stone::Black, 1, &prev, true));
+  let (state, prev) = load_fixture("suicide", RuleSet::KgsNewZealand);
+  assert_eq!(355, assert_perft(&state, Stone::Black, 1, &prev, false));
+}
@@ -0,0 +1,264 @@
+extern crate holmes;
+extern crate rand;
+extern crate rng;
+
+use holmes::board::{Stone, Action};
+use holmes::search::parallel_policies::uniform::{UniformPolicyWorkerBuilder, UniformPolicyWorker};
+use holmes::search::parallel_tree::{
+  TreePolicyConfig, HorizonConfig,
+  NodeValues, AtomicNodeValues,
+  Node, SharedTree, TreeMemoryConfig,
+  SearchWorkerConfig, SearchWorkerBatchConfig,
+  ParallelMonteCarloSearchServer,
+  ParallelMonteCarloSearch,
+  RootParallelMonteCarloSearch,
+};
+use holmes::txnstate::{TxnStateConfig, TxnState};
+use holmes::txnstate::extras::{TxnStateNodeData};
+
+use rand::{thread_rng};
+use rng::xorshift::{Xorshiftplus128Rng};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{Ordering};
+use std::thread::{sleep_ms};
+
+fn node_values(trials: &[usize]) -> AtomicNodeValues {
+  let values = AtomicNodeValues::new(trials.len(), HorizonConfig::All);
+  for (j, &n) in trials.iter().enumerate() {
+    values.num_trials[j].store(n, Ordering::Release);
+  }
+  values
+}
+
+/// A search server with `num_workers` uniform policy workers, the empty
+/// board to search from, and an RNG.
+fn search_setup(tree_cfg: TreePolicyConfig, num_workers: usize) -> (ParallelMonteCarloSearchServer<UniformPolicyWorker>, TxnState<TxnStateNodeData>, Xorshiftplus128Rng) {
+  let state_cfg = TxnStateConfig::default();
+  let server = ParallelMonteCarloSearchServer::new(
+      state_cfg,
+      num_workers, 1, 8,
+      UniformPolicyWorkerBuilder::new(tree_cfg),
+  );
+  let mut init_state = TxnState::new(state_cfg, TxnStateNodeData::new());
+  init_state.reset();
+  let rng = Xorshiftplus128Rng::new(&mut thread_rng());
+  (server, init_state, rng)
+}
+
+fn fixed_worker_cfg(num_batches: usize, rollout_batch_size: usize, early_stop: bool) -> SearchWorkerConfig {
+  SearchWorkerConfig{
+    batch_cfg:    SearchWorkerBatchConfig::Fixed{num_batches: num_batches},
+    tree_batch_size:      None,
+    rollout_batch_size:   rollout_batch_size,
+    early_stop:   early_stop,
+  }
+}
+
+fn sum_virtual_losses(node: &Arc<RwLock<Node>>) -> usize {
+  let node = node.read().unwrap();
+  let mut sum = node.values.virtual_losses.iter()
+    .fold(0, |acc, v| acc + v.load(Ordering::Acquire));
+  for child in node.child_nodes.iter() {
+    if let Some(ref child) = *child {
+      sum += sum_virtual_losses(child);
+    }
+  }
+  sum
+}
+
+#[test]
+fn test_can_overtake_best() {
+  // A tie can still go either way.
+  assert!(node_values(&[0, 0, 0]).can_overtake_best(0));
+  let values = node_values(&[4, 10, 0]);
+  assert!(!values.can_overtake_best(5));
+  assert!(values.can_overtake_best(6));
+  // A lone arm is overtaken by an unvisited one.
+  let values = node_values(&[7]);
+  assert!(!values.can_overtake_best(6));
+  assert!(values.can_overtake_best(7));
+}
+
+#[test]
+fn test_search_early_stop() {
+  // With a horizon of one, every rollout goes through the same root arm, so
+  // the best move is decided halfway through the budget.
+  let tree_cfg = TreePolicyConfig{
+    horizon_cfg:    HorizonConfig::Fixed{max_horizon: 1},
+    .. TreePolicyConfig::test_default()
+  };
+  let (server, init_state, mut rng) = search_setup(tree_cfg, 2);
+  let num_batches = 64;
+  let budget = num_batches * 16;
+
+  for &early_stop in [false, true].iter() {
+    let worker_cfg = fixed_worker_cfg(num_batches, 16, early_stop);
+    let search = ParallelMonteCarloSearch::new();
+    let (_, stats) = search.join(worker_cfg, &server, Stone::Black, &init_state, SharedTree::new(tree_cfg), &mut rng);
+    if early_stop {
+      assert!(stats.stopped_early);
+      assert!(stats.saved_rollouts > 0);
+      assert!(stats.num_rollouts < budget);
+      assert_eq!(budget, stats.num_rollouts + stats.saved_rollouts);
+    } else {
+      assert!(!stats.stopped_early);
+      assert_eq!(0, stats.saved_rollouts);
+      assert_eq!(budget, stats.num_rollouts);
+    }
+  }
+}
+
+#[test]
+fn test_search_memory_budget() {
+  let (server, init_state, mut rng) = search_setup(TreePolicyConfig::test_default(), 2);
+  let num_batches = 64;
+  let budget = num_batches * 16;
+  let worker_cfg = fixed_worker_cfg(num_batches, 16, false);
+
+  // A budget of `memory_budget_mb = 1` holds a few dozen node states, far
+  // fewer than the search expands.
+  for &compact_states in [false, true].iter() {
+    let tree = SharedTree::new(TreePolicyConfig::test_default());
+    tree.set_memory_cfg(TreeMemoryConfig{
+      budget_bytes:   1024 * 1024,
+      compact_states: compact_states,
+    });
+    let search = ParallelMonteCarloSearch::new();
+    let (_, stats) = search.join(worker_cfg, &server, Stone::Black, &init_state, tree.clone(), &mut rng);
+    assert_eq!(budget, stats.num_rollouts);
+    assert!(stats.num_pruned > 0 || stats.num_compacted > 0);
+    if !compact_states {
+      assert_eq!(0, stats.num_compacted);
+    }
+    // The last batch may grow the tree again after the last collection.
+    assert!(stats.tree_bytes < 2 * 1024 * 1024);
+    assert_eq!(stats.tree_bytes, tree.memory_bytes());
+  }
+}
+
+#[test]
+fn test_search_virtual_losses_balance() {
+  let tree_cfg = TreePolicyConfig{
+    virtual_loss:   2.0,
+    .. TreePolicyConfig::test_default()
+  };
+  let (server, init_state, mut rng) = search_setup(tree_cfg, 4);
+  let worker_cfg = fixed_worker_cfg(16, 32, false);
+  let tree = SharedTree::new(tree_cfg);
+  let search = ParallelMonteCarloSearch::new();
+  let (_, stats) = search.join(worker_cfg, &server, Stone::Black, &init_state, tree.clone(), &mut rng);
+  assert_eq!(16 * 32, stats.num_rollouts);
+  // Every virtual loss added on the way down is reverted by the backup.
+  let root_node = tree.root_node();
+  assert!(root_node.read().unwrap().values.total_trials.load(Ordering::Acquire) > 0);
+  assert_eq!(0, sum_virtual_losses(&root_node));
+}
+
+#[test]
+fn test_root_parallel_search_join() {
+  let (server, init_state, mut rng) = search_setup(TreePolicyConfig::test_default(), 4);
+  let worker_cfg = fixed_worker_cfg(16, 16, false);
+  // Two trees of two workers each, with 4 rollouts per worker and batch.
+  let trees = vec![SharedTree::new(TreePolicyConfig::test_default()), SharedTree::new(TreePolicyConfig::test_default())];
+  let search = RootParallelMonteCarloSearch::new();
+  let (result, stats) = search.join(worker_cfg, &server, Stone::Black, &init_state, &trees, &mut rng);
+  assert_eq!(2 * 16 * 8, stats.num_rollouts);
+
+  // The merged visits are the sums of the root visits of both trees.
+  let mut tree_visits = vec![0; result.root_visits.len()];
+  for tree in trees.iter() {
+    let root_node = tree.root_node();
+    let root_node = root_node.read().unwrap();
+    assert_eq!(16 * 8, root_node.values.total_trials.load(Ordering::Acquire));
+    for j in 0 .. root_node.valid_moves.len() {
+      let n_j = root_node.values.num_trials[j].load(Ordering::Acquire);
+      if n_j == 0 {
+        continue;
+      }
+      let i = result.root_visits.iter().position(|&(pt, _)| pt == root_node.valid_moves[j]).unwrap();
+      tree_visits[i] += n_j;
+    }
+  }
+  let merged_visits: Vec<_> = result.root_visits.iter().map(|&(_, n)| n).collect();
+  assert_eq!(tree_visits, merged_visits);
+  assert_eq!(stats.num_rollouts, merged_visits.iter().fold(0, |acc, &n| acc + n));
+
+  // The merged vote picks the most visited move.
+  let &(best_point, best_visits) = result.root_visits.iter()
+    .max_by_key(|&&(_, n)| n).unwrap();
+  assert_eq!(best_visits, stats.argmax_ntrials);
+  match result.action {
+    Action::Place{point} => {
+      assert_eq!(best_visits, result.root_visits.iter().find(|&&(pt, _)| pt == point).unwrap().1);
+      if best_point != point {
+        // A tie between the two.
+        assert!(result.root_visits.iter().filter(|&&(_, n)| n == best_visits).count() > 1);
+      }
+    }
+    x => panic!("unexpected action: {:?}", x),
+  }
+}
+
+#[test]
+fn test_root_wins_with_komi() {
+  let (server, init_state, mut rng) = search_setup(TreePolicyConfig::test_default(), 2);
+  let worker_cfg = fixed_worker_cfg(16, 16, false);
+  let tree = SharedTree::new(TreePolicyConfig::test_default());
+  let search = ParallelMonteCarloSearch::new();
+  let (_, stats) = search.join(worker_cfg, &server, Stone::Black, &init_state, tree.clone(), &mut rng);
+  assert_eq!(16 * 16, stats.num_rollouts);
+
+  // Under the search komi the rescored wins are the root arm successes.
+  let root_node = tree.root_node();
+  let num_succs = root_node.read().unwrap().values.num_succs.iter()
+    .fold(0, |acc, n| acc + n.load(Ordering::Acquire));
+  assert_eq!((num_succs, stats.num_rollouts), tree.root_wins_with_komi(Stone::Black, init_state.config.komi));
+  // No rollout outscores a komi larger than the board.
+  assert_eq!((0, stats.num_rollouts), tree.root_wins_with_komi(Stone::Black, 1000.5));
+  assert_eq!((stats.num_rollouts, stats.num_rollouts), tree.root_wins_with_komi(Stone::Black, -1000.5));
+  let (white_wins, _) = tree.root_wins_with_komi(Stone::White, init_state.config.komi);
+  assert_eq!(stats.num_rollouts, num_succs + white_wins);
+}
+
+#[test]
+fn test_advance_frees_tree_bytes() {
+  let (server, init_state, mut rng) = search_setup(TreePolicyConfig::test_default(), 2);
+  let worker_cfg = fixed_worker_cfg(16, 16, false);
+  let tree = SharedTree::new(TreePolicyConfig::test_default());
+  let search = ParallelMonteCarloSearch::new();
+  let (result, stats) = search.join(worker_cfg, &server, Stone::Black, &init_state, tree.clone(), &mut rng);
+  assert_eq!(stats.tree_bytes, tree.memory_bytes());
+
+  // The size of the subtrees off the move path is gone once `try_advance`
+  // returns, and does not change while they are dropped.
+  assert!(tree.try_advance(Stone::Black, result.action));
+  let advanced_bytes = tree.memory_bytes();
+  assert!(advanced_bytes < stats.tree_bytes);
+  sleep_ms(100);
+  assert_eq!(advanced_bytes, tree.memory_bytes());
+}
+
+#[test]
+fn test_root_parallel_search_early_stop() {
+  // As in `test_search_early_stop`, every tree puts all of its rollouts
+  // through the same root arm, so the merged vote is decided halfway.
+  let tree_cfg = TreePolicyConfig{
+    horizon_cfg:    HorizonConfig::Fixed{max_horizon: 1},
+    .. TreePolicyConfig::test_default()
+  };
+  let (server, init_state, mut rng) = search_setup(tree_cfg, 4);
+  let num_batches = 64;
+  let tree_budget = num_batches * 8;
+  let worker_cfg = fixed_worker_cfg(num_batches, 16, true);
+  let trees = vec![SharedTree::new(tree_cfg), SharedTree::new(tree_cfg)];
+  let search = RootParallelMonteCarloSearch::new();
+  let (_, stats) = search.join(worker_cfg, &server, Stone::Black, &init_state, &trees, &mut rng);
+  assert!(stats.stopped_early);
+  assert!(stats.saved_rollouts > 0);
+  assert!(stats.num_rollouts < 2 * tree_budget);
+  // Both groups stop on the merged decision, not just the one which made it.
+  for tree in trees.iter() {
+    let root_node = tree.root_node();
+    assert!(root_node.read().unwrap().values.total_trials.load(Ordering::Acquire) < tree_budget);
+  }
+}
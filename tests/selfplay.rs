@@ -0,0 +1,58 @@
+extern crate holmes;
+extern crate rand;
+extern crate rng;
+
+use holmes::board::{Stone, Action};
+use holmes::search::parallel_policies::uniform::{UniformPolicyWorkerBuilder};
+use holmes::search::parallel_tree::{
+  TreePolicyConfig,
+  ParallelMonteCarloSearchServer,
+};
+use holmes::selfplay::{SelfPlayConfig, SelfPlayDriver};
+use holmes::txnstate::{TxnStateConfig, TxnState};
+
+use rand::{thread_rng};
+use rng::xorshift::{Xorshiftplus128Rng};
+
+#[test]
+fn test_selfplay_short_game() {
+  let cfg = SelfPlayConfig{
+    num_games:        1,
+    num_rollouts:     32,
+    batch_size:       8,
+    dirichlet_alpha:  0.03,
+    noise_frac:       0.25,
+    temperature:      1.0,
+    temperature_plies:    4,
+    max_plies:        8,
+    komi:             6.5,
+  };
+  let state_cfg = TxnStateConfig::default();
+  let server = ParallelMonteCarloSearchServer::new(
+      state_cfg,
+      2, 1, cfg.batch_size,
+      UniformPolicyWorkerBuilder::new(TreePolicyConfig::test_default()),
+  );
+  let rng = Xorshiftplus128Rng::new(&mut thread_rng());
+  let mut driver = SelfPlayDriver::new(cfg, TreePolicyConfig::test_default(), state_cfg, server, rng);
+  let episode = driver.play_episode();
+
+  assert!(!episode.positions.is_empty());
+  assert!(episode.positions.len() <= cfg.max_plies);
+
+  // The recorded moves replay legally, alternating turns, and each search
+  // spent its rollouts on the root.
+  let mut state = TxnState::new(state_cfg, ());
+  state.reset();
+  for (ply, position) in episode.positions.iter().enumerate() {
+    assert_eq!(if ply % 2 == 0 { Stone::Black } else { Stone::White }, position.turn);
+    let total_visits = position.root_visits.iter().fold(0, |acc, &(_, n)| acc + n);
+    assert!(total_visits > 0 && total_visits <= cfg.num_rollouts);
+    if position.action == Action::Resign {
+      assert_eq!(ply + 1, episode.positions.len());
+      break;
+    }
+    state.try_action(position.turn, position.action).unwrap();
+    state.commit();
+  }
+}
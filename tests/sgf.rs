@@ -0,0 +1,69 @@
+extern crate holmes;
+
+use holmes::board::{RuleSet, PlayerRank, Stone, Point, Action};
+use holmes::gtp_board::{Coord};
+use holmes::sgf::{Sgf, SgfRankStyle};
+use holmes::txnstate::{TxnStateConfig, TxnState};
+
+fn point(code: &str) -> Point {
+  Point::from_coord(Coord::parse_code_str(code).unwrap())
+}
+
+static HANDICAP_SGF: &'static [u8] =
+    b"(;GM[1]SZ[19]RU[Chinese]HA[2]KM[0.5]PB[b]BR[3d]PW[w]WR[5k]RE[W+R]AB[dp][pd];W[dd];B[pp])";
+
+#[test]
+fn test_infer_state_cfg() {
+  let sgf = Sgf::from_text(HANDICAP_SGF);
+  assert_eq!(Some(2), sgf.handicap);
+  assert_eq!(2, sgf.num_handicap());
+
+  let cfg = sgf.infer_state_cfg(SgfRankStyle::Server);
+  assert_eq!([PlayerRank::Ama(3), PlayerRank::Kyu(5)], cfg.ranks);
+  assert_eq!(0.5, cfg.komi);
+  assert!(cfg.rules.score_stones);
+  assert!(!cfg.rules.score_captures);
+
+  let cfg = sgf.infer_state_cfg(SgfRankStyle::Gogod);
+  assert_eq!([PlayerRank::Dan(3), PlayerRank::Kyu(5)], cfg.ranks);
+
+  let sgf = Sgf::from_text(b"(;GM[1]SZ[19]HA[3]PB[b]BR[?]PW[w]RE[B+2.5];B[pd])");
+  let cfg = sgf.infer_state_cfg(SgfRankStyle::Server);
+  assert_eq!(TxnStateConfig::default().ranks, cfg.ranks);
+  assert_eq!(0.5, cfg.komi);
+  assert!(cfg.rules.score_captures);
+  assert_eq!(Some(RuleSet::KgsNewZealand), RuleSet::parse_sgf_str("NZ"));
+  assert_eq!(None, RuleSet::parse_sgf_str("Tibetan"));
+}
+
+#[test]
+fn test_setup_stones() {
+  let sgf = Sgf::from_text(HANDICAP_SGF);
+  let setup = sgf.setup_stones();
+  // Note that `Coord::from_sgf` does not flip the y axis.
+  assert_eq!(vec![(Stone::Black, point("D16")), (Stone::Black, point("Q4"))], setup);
+
+  let state_cfg = TxnStateConfig{history: true, .. sgf.infer_state_cfg(SgfRankStyle::Server)};
+  let mut state = TxnState::new(state_cfg, ());
+  state.reset();
+  state.place_setup_stones(&setup).unwrap();
+  assert_eq!(Stone::Black, state.current_turn());
+  assert_eq!(0, state.current_ply());
+  assert_eq!(Stone::Black, state.current_stone(point("D16")));
+  assert_eq!(Stone::Black, state.current_stone(point("Q4")));
+
+  state.unsafe_set_current_turn(Stone::White);
+  state.try_action(Stone::White, Action::Place{point: point("D4")}).unwrap();
+  state.commit();
+  assert_eq!(1, state.current_ply());
+  assert!(state.place_setup_stones(&[(Stone::Black, point("Q16"))]).is_err());
+
+  state.seek_ply(0).unwrap();
+  assert_eq!(Stone::Empty, state.current_stone(point("D4")));
+  assert_eq!(Stone::Black, state.current_stone(point("D16")));
+  assert_eq!(setup, state.setup_stones().to_vec());
+
+  state.reset();
+  assert!(state.setup_stones().is_empty());
+  assert_eq!(Stone::Empty, state.current_stone(point("D16")));
+}
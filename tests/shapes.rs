@@ -0,0 +1,100 @@
+extern crate holmes;
+
+use holmes::board::{Board, Stone, Point, Action};
+use holmes::gtp_board::{Coord};
+use holmes::sgf::{Sgf, SgfRankStyle};
+use holmes::shapes::{
+  ShapeKind, SHAPE_KINDS, ShapeDictionary, TxnStateShapeData,
+  compute_shape_hash,
+};
+use holmes::txnstate::{TxnStateConfig, TxnState};
+use holmes::txnstate::extras::{TxnStateNodeData};
+
+use std::env;
+
+fn point(code: &str) -> Point {
+  Point::from_coord(Coord::parse_code_str(code).unwrap())
+}
+
+fn play_moves(moves: &[(Stone, &str)]) -> TxnState<TxnStateShapeData> {
+  let mut state = TxnState::new(TxnStateConfig::default(), TxnStateShapeData::new(None));
+  state.reset();
+  for &(turn, code) in moves.iter() {
+    state.try_action(turn, Action::Place{point: point(code)}).unwrap();
+    state.commit();
+  }
+  state
+}
+
+#[test]
+fn test_shapes_incremental() {
+  // Includes a capture at the edge: white A2 is taken by black A3.
+  let state = play_moves(&[
+    (Stone::Black, "C3"), (Stone::White, "D4"), (Stone::Black, "B1"),
+    (Stone::White, "A2"), (Stone::Black, "B2"), (Stone::White, "E5"),
+    (Stone::Black, "A1"), (Stone::White, "Q16"), (Stone::Black, "A3"),
+    (Stone::White, "R17"),
+  ]);
+  assert_eq!(Stone::Empty, state.current_stone(point("A2")));
+  for p in 0 .. Board::SIZE {
+    let point = Point::from_idx(p);
+    for &kind in SHAPE_KINDS.iter() {
+      for &turn in [Stone::Black, Stone::White].iter() {
+        assert_eq!(compute_shape_hash(&state, kind, turn, point),
+            state.get_data().shape_hash(kind, turn, point));
+      }
+    }
+  }
+}
+
+#[test]
+fn test_shapes_symmetry() {
+  let state = play_moves(&[(Stone::Black, "D4"), (Stone::White, "C6")]);
+  // The same moves mirrored about the diagonal, with colors reversed.
+  let mirror = play_moves(&[(Stone::White, "D4"), (Stone::Black, "F3")]);
+  for &kind in [ShapeKind::Diamond, ShapeKind::Square5x5, ShapeKind::Corner].iter() {
+    assert_eq!(compute_shape_hash(&state, kind, Stone::Black, point("C5")),
+        compute_shape_hash(&mirror, kind, Stone::White, point("E3")));
+  }
+  assert!(compute_shape_hash(&state, ShapeKind::Diamond, Stone::Black, point("C5"))
+      != compute_shape_hash(&state, ShapeKind::Diamond, Stone::White, point("C5")));
+  assert!(compute_shape_hash(&state, ShapeKind::Corner, Stone::Black, point("K10")).is_none());
+}
+
+#[test]
+fn test_shapes_mine_save_open() {
+  let mut dict = ShapeDictionary::new();
+  for text in [
+    "(;GM[1]SZ[19];B[pd];W[dd];B[qf])",
+    "(;GM[1]SZ[19];B[dp];W[pp];B[cn])",
+  ].iter() {
+    let sgf = Sgf::from_text(text.as_bytes());
+    dict.add_sgf(&sgf, SgfRankStyle::Server, 0).unwrap();
+  }
+  // The first move of both games is a corner 4-4 point on the empty board.
+  let state = play_moves(&[]);
+  let hash = compute_shape_hash(&state, ShapeKind::Corner, Stone::Black, point("Q16")).unwrap();
+  let stats = dict.lookup(hash).unwrap();
+  assert_eq!(2, stats.played);
+  assert!(stats.seen >= stats.played);
+  assert!(dict.match_bonus(&state, Stone::Black, point("Q16")) > 0.0);
+
+  // Search states carry the same hashes incrementally.
+  let mut node_state = TxnState::new(TxnStateConfig::default(), TxnStateNodeData::with_shapes());
+  node_state.reset();
+  for &(turn, code) in [(Stone::Black, "Q16"), (Stone::White, "D4"), (Stone::Black, "R14")].iter() {
+    for p in 0 .. Board::SIZE {
+      let point = Point::from_idx(p);
+      let shapes = node_state.get_data().shapes.as_ref().unwrap();
+      assert_eq!(dict.match_bonus(&node_state, turn, point), dict.shape_bonus(shapes, turn, point));
+    }
+    node_state.try_action(turn, Action::Place{point: point(code)}).unwrap();
+    node_state.commit();
+  }
+
+  dict.prune(1);
+  let path = env::temp_dir().join("holmes-shapes-test.bin");
+  dict.save(&path);
+  let saved_dict = ShapeDictionary::open(&path).unwrap();
+  assert_eq!(dict.entries, saved_dict.entries);
+}
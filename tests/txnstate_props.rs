@@ -0,0 +1,506 @@
+extern crate holmes;
+extern crate rand;
+extern crate rustc_serialize;
+
+use holmes::board::{Board, RuleSet, PlayerRank, Stone, Point, Action};
+use holmes::random::{XorShift128PlusRng};
+use holmes::shapes::{SHAPE_KINDS, TxnStateShapeData, compute_shape_hash};
+use holmes::txnstate::{
+  TOMBSTONE, TxnStateData, TxnStateConfig, TxnState, for_each_adjacent,
+};
+use holmes::txnstate::extras::{
+  TxnStateLegalityData, TxnStateNodeData, TxnStateRolloutData, TxnStateRolloutLegalityData,
+};
+use holmes::txnstate::features::{
+  TxnStateFeaturesData,
+  TxnStateLibFeaturesData,
+  TxnStateExtLibFeatsData,
+  TxnStateAlphaFeatsV1Data,
+  TxnStateAlphaFeatsV2Data,
+  TxnStateAlphaV3FeatsData,
+  TxnStateAlphaMiniV3FeatsData,
+  TxnStateAlphaPatternV3FeatsData,
+  TxnStateAlphaPatternNoKoV3FeatsData,
+  TxnStatePattern3x3FeatsData,
+};
+use rustc_serialize::json;
+
+use rand::{Rng, SeedableRng, thread_rng};
+use std::cmp::{min};
+use std::env;
+
+const NUM_TRIALS:   usize = 20;
+const NUM_STEPS:    usize = 300;
+
+/// One randomized step: the player to move attempts `action`, and a legal
+/// attempt is either committed or undone.
+#[derive(Clone, Copy, Debug)]
+struct Step {
+  action: Action,
+  commit: bool,
+}
+
+fn gen_steps<R>(rng: &mut R, num_steps: usize) -> Vec<Step> where R: Rng {
+  (0 .. num_steps).map(|_| {
+    let action = if rng.gen_range(0, 40) == 0 {
+      Action::Pass
+    } else {
+      // Bias towards the lower left corner so that captures, ko, and
+      // suicide happen often.
+      let dim = if rng.gen_range(0, 2) == 0 { 5 } else { Board::DIM };
+      let x = rng.gen_range(0, dim);
+      let y = rng.gen_range(0, dim);
+      Action::Place{point: Point::from_idx(x + y * Board::DIM)}
+    };
+    Step{action: action, commit: rng.gen_range(0, 5) != 0}
+  }).collect()
+}
+
+fn naive_group(stones: &[Stone], start: usize) -> (Vec<usize>, usize) {
+  let color = stones[start];
+  let mut group = vec![start];
+  let mut libs = vec![];
+  let mut head = 0;
+  while head < group.len() {
+    let p = group[head];
+    for_each_adjacent(Point::from_idx(p), |adj_pt| {
+      let q = adj_pt.idx();
+      if stones[q] == color && !group.contains(&q) {
+        group.push(q);
+      } else if stones[q] == Stone::Empty && !libs.contains(&q) {
+        libs.push(q);
+      }
+    });
+    head += 1;
+  }
+  (group, libs.len())
+}
+
+/// From-scratch placement under simple ko and no suicide (Japanese rules).
+fn naive_play(stones: &[Stone], prev_stones: &[Stone], turn: Stone, p: usize) -> Option<Vec<Stone>> {
+  if stones[p] != Stone::Empty {
+    return None;
+  }
+  let mut next = stones.to_vec();
+  next[p] = turn;
+  let mut adj = vec![];
+  for_each_adjacent(Point::from_idx(p), |adj_pt| adj.push(adj_pt.idx()));
+  for &q in adj.iter() {
+    if next[q] == turn.opponent() {
+      let (group, libs) = naive_group(&next, q);
+      if libs == 0 {
+        for &g in group.iter() {
+          next[g] = Stone::Empty;
+        }
+      }
+    }
+  }
+  if naive_group(&next, p).1 == 0 || &next as &[Stone] == prev_stones {
+    return None;
+  }
+  Some(next)
+}
+
+fn stones_of<Data>(state: &TxnState<Data>) -> Vec<Stone> where Data: TxnStateData + Clone {
+  (0 .. Board::SIZE).map(|p| state.current_stone(Point::from_idx(p))).collect()
+}
+
+fn check_chains<Data>(state: &TxnState<Data>, stones: &[Stone]) -> Result<(), String> where Data: TxnStateData + Clone {
+  for p in 0 .. Board::SIZE {
+    let point = Point::from_idx(p);
+    let head = state.chains.find_chain(point);
+    if stones[p] == Stone::Empty {
+      if head != TOMBSTONE {
+        return Err(format!("empty point {} has a chain", point.to_coord().to_string()));
+      }
+      continue;
+    }
+    let chain = match state.chains.get_chain(head) {
+      Some(chain) => chain,
+      None => return Err(format!("stone at {} has no chain", point.to_coord().to_string())),
+    };
+    let (group, libs) = naive_group(stones, p);
+    if group.iter().any(|&q| state.chains.find_chain(Point::from_idx(q)) != head) {
+      return Err(format!("chain at {} is split", point.to_coord().to_string()));
+    }
+    if chain.count_length() != group.len() {
+      return Err(format!("chain at {}: size {} != {}",
+          point.to_coord().to_string(), chain.count_length(), group.len()));
+    }
+    if chain.count_libs_up_to_8() != min(libs, 8) {
+      return Err(format!("chain at {}: libs {} != {}",
+          point.to_coord().to_string(), chain.count_libs_up_to_8(), libs));
+    }
+  }
+  Ok(())
+}
+
+/// Checks cached legal points of the player to move against brute force.
+fn check_legality(state: &TxnState<TxnStateLegalityData>) -> Result<(), String> {
+  let turn = state.current_turn();
+  let mut cached = vec![];
+  state.get_data().fill_legal_points(turn, &mut cached);
+  let mut shadow = state.shrink_clone();
+  let mut expected = vec![];
+  for p in 0 .. Board::SIZE {
+    let point = Point::from_idx(p);
+    if shadow.try_place(turn, point).is_ok() {
+      expected.push(point);
+    }
+    shadow.undo();
+  }
+  cached.sort();
+  if cached != expected {
+    return Err(format!("cached legal points differ: {:?} vs {:?}", cached, expected));
+  }
+  Ok(())
+}
+
+/// Where a feature data keeps its board planes. Liberty and chain size planes
+/// are cumulative ("at least n") and are zero on empty points.
+struct PlaneLayout {
+  set:    u8,
+  empty:  usize,
+  black:  usize,
+  white:  usize,
+  libs:   &'static [usize],
+  caps:   &'static [usize],
+  ko:     Option<usize>,
+}
+
+/// Checks the stone, liberty, chain size, and ko planes against the board.
+fn check_planes<Data>(state: &TxnState<Data>, feats: &[u8], layout: &PlaneLayout) -> Result<(), String>
+where Data: TxnStateData + Clone {
+  let set = layout.set;
+  let stones = stones_of(state);
+  let ko_point = state.current_ko().map(|(_, point)| point.idx());
+  for p in 0 .. Board::SIZE {
+    let (planes, libs, size) = match stones[p] {
+      Stone::Empty => ([set, 0, 0], 0, 0),
+      stone => {
+        let (group, libs) = naive_group(&stones, p);
+        let planes = if stone == Stone::Black { [0, set, 0] } else { [0, 0, set] };
+        (planes, libs, group.len())
+      }
+    };
+    let mut expected = vec![
+      (layout.empty, planes[0]),
+      (layout.black, planes[1]),
+      (layout.white, planes[2]),
+    ];
+    for (n, &plane) in layout.libs.iter().enumerate() {
+      expected.push((plane, if libs >= n + 1 { set } else { 0 }));
+    }
+    for (n, &plane) in layout.caps.iter().enumerate() {
+      expected.push((plane, if size >= n + 1 { set } else { 0 }));
+    }
+    if let Some(plane) = layout.ko {
+      expected.push((plane, if ko_point == Some(p) { set } else { 0 }));
+    }
+    for &(plane, value) in expected.iter() {
+      if feats[plane + p] != value {
+        return Err(format!("feature plane {} at {}: {} != {}",
+            plane / Board::SIZE, Point::from_idx(p).to_coord().to_string(), feats[plane + p], value));
+      }
+    }
+  }
+  Ok(())
+}
+
+fn check_v3(state: &TxnState<TxnStateAlphaV3FeatsData>) -> Result<(), String> {
+  type Feats = TxnStateAlphaV3FeatsData;
+  check_planes(state, &state.get_data().features, &PlaneLayout{
+    set:    Feats::SET,
+    empty:  Feats::EMPTY_PLANE,
+    black:  Feats::BLACK_PLANE,
+    white:  Feats::WHITE_PLANE,
+    libs:   &[
+      Feats::LIBS_1_PLANE, Feats::LIBS_2_PLANE, Feats::LIBS_3_PLANE, Feats::LIBS_4_PLANE,
+      Feats::LIBS_5_PLANE, Feats::LIBS_6_PLANE, Feats::LIBS_7_PLANE, Feats::LIBS_8_PLANE,
+    ],
+    caps:   &[
+      Feats::CAPS_1_PLANE, Feats::CAPS_2_PLANE, Feats::CAPS_3_PLANE, Feats::CAPS_4_PLANE,
+      Feats::CAPS_5_PLANE, Feats::CAPS_6_PLANE, Feats::CAPS_7_PLANE, Feats::CAPS_8_PLANE,
+    ],
+    ko:     Some(Feats::KO_PLANE),
+  })
+}
+
+fn check_mini_v3(state: &TxnState<TxnStateAlphaMiniV3FeatsData>) -> Result<(), String> {
+  type Feats = TxnStateAlphaMiniV3FeatsData;
+  check_planes(state, &state.get_data().features, &PlaneLayout{
+    set:    Feats::SET,
+    empty:  Feats::EMPTY_PLANE,
+    black:  Feats::BLACK_PLANE,
+    white:  Feats::WHITE_PLANE,
+    libs:   &[Feats::LIBS_1_PLANE, Feats::LIBS_2_PLANE, Feats::LIBS_3_PLANE, Feats::LIBS_4_PLANE],
+    caps:   &[Feats::CAPS_1_PLANE, Feats::CAPS_2_PLANE, Feats::CAPS_3_PLANE],
+    ko:     Some(Feats::KO_PLANE),
+  })
+}
+
+fn check_pattern_v3(state: &TxnState<TxnStateAlphaPatternV3FeatsData>) -> Result<(), String> {
+  type Feats = TxnStateAlphaPatternV3FeatsData;
+  check_planes(state, &state.get_data().features, &PlaneLayout{
+    set:    Feats::SET,
+    empty:  Feats::EMPTY_PLANE,
+    black:  Feats::BLACK_PLANE,
+    white:  Feats::WHITE_PLANE,
+    libs:   &[Feats::LIBS_1_PLANE, Feats::LIBS_2_PLANE, Feats::LIBS_3_PLANE],
+    caps:   &[],
+    ko:     Some(Feats::KO_PLANE),
+  })
+}
+
+fn check_pattern_noko_v3(state: &TxnState<TxnStateAlphaPatternNoKoV3FeatsData>) -> Result<(), String> {
+  type Feats = TxnStateAlphaPatternNoKoV3FeatsData;
+  check_planes(state, &state.get_data().features, &PlaneLayout{
+    set:    Feats::SET,
+    empty:  Feats::EMPTY_PLANE,
+    black:  Feats::BLACK_PLANE,
+    white:  Feats::WHITE_PLANE,
+    libs:   &[Feats::LIBS_1_PLANE, Feats::LIBS_2_PLANE, Feats::LIBS_3_PLANE],
+    caps:   &[],
+    ko:     None,
+  })
+}
+
+/// Checks incremental shape hashes against hashes computed from the stones.
+/// Only a slice of the board (which moves with the position) is checked per
+/// step to keep the test fast.
+fn check_shapes(state: &TxnState<TxnStateShapeData>) -> Result<(), String> {
+  let turn = state.current_turn();
+  let offset = (state.current_hash() % 8) as usize;
+  for p in 0 .. Board::SIZE {
+    if (p + offset) % 8 != 0 {
+      continue;
+    }
+    let point = Point::from_idx(p);
+    for &kind in SHAPE_KINDS.iter() {
+      let cached = state.get_data().shape_hash(kind, turn, point);
+      let expected = compute_shape_hash(state, kind, turn, point);
+      if cached != expected {
+        return Err(format!("shape {:?} at {}: {:?} != {:?}",
+            kind, point.to_coord().to_string(), cached, expected));
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Runs `steps` against a TxnState, checking after every step that:
+/// - an undone txn leaves the position exactly as before;
+/// - the position matches a from-scratch replay of the committed actions;
+/// - chain sizes and liberties match a naive flood fill;
+/// - the data matches `check_data` and is identical to the data of a clean
+///   replay which never saw an undone txn.
+fn run_steps<Data, F>(steps: &[Step], new_data: &F, check_data: fn(&TxnState<Data>) -> Result<(), String>) -> Result<(), String>
+where Data: TxnStateData + Clone, F: Fn() -> Data {
+  let config = TxnStateConfig{
+    rules:  RuleSet::KgsJapanese.rules(),
+    ranks:  [PlayerRank::Dan(9), PlayerRank::Dan(9)],
+    komi:   6.5,
+    history: false,
+  };
+  let mut state = TxnState::new(config, new_data());
+  let mut clean = TxnState::new(config, new_data());
+  state.reset();
+  clean.reset();
+  let mut stones = stones_of(&state);
+  let mut prev_stones = stones.clone();
+
+  for (i, step) in steps.iter().enumerate() {
+    let turn = state.current_turn();
+    let before_hash = state.current_hash();
+    let before_ko = state.current_ko();
+    let expected = match step.action {
+      Action::Place{point} => naive_play(&stones, &prev_stones, turn, point.idx()),
+      _ => Some(stones.clone()),
+    };
+    let res = state.try_action(turn, step.action);
+    if res.is_ok() != expected.is_some() {
+      return Err(format!("step {}: {:?} {:?} returned {:?}", i, turn, step.action, res));
+    }
+    if res.is_ok() && step.commit {
+      state.commit();
+      if let Err(e) = clean.try_action(turn, step.action) {
+        return Err(format!("step {}: clean replay rejected {:?}: {:?}", i, step.action, e));
+      }
+      clean.commit();
+      prev_stones = stones;
+      stones = expected.unwrap();
+    } else {
+      state.undo();
+      if state.current_turn() != turn || state.current_hash() != before_hash || state.current_ko() != before_ko {
+        return Err(format!("step {}: undo did not restore turn, hash, or ko", i));
+      }
+    }
+
+    if stones_of(&state) != stones {
+      return Err(format!("step {}: board differs from rebuild", i));
+    }
+    if stones_of(&clean) != stones || clean.current_hash() != state.current_hash() {
+      return Err(format!("step {}: clean replay differs", i));
+    }
+    try!(check_chains(&state, &stones).map_err(|e| format!("step {}: {}", i, e)));
+    try!(check_data(&state).map_err(|e| format!("step {}: {}", i, e)));
+    if json::encode(state.get_data()).unwrap() != json::encode(clean.get_data()).unwrap() {
+      return Err(format!("step {}: data differs from clean replay", i));
+    }
+  }
+  Ok(())
+}
+
+/// Greedily removes chunks and then single steps while the failure persists.
+fn shrink<F>(mut steps: Vec<Step>, fails: F) -> Vec<Step> where F: Fn(&[Step]) -> bool {
+  let mut chunk = steps.len() / 2;
+  while chunk >= 1 {
+    let mut start = 0;
+    while start < steps.len() {
+      let end = min(start + chunk, steps.len());
+      let mut candidate = steps[ .. start].to_vec();
+      candidate.extend_from_slice(&steps[end .. ]);
+      if fails(&candidate) {
+        steps = candidate;
+      } else {
+        start += chunk;
+      }
+    }
+    chunk /= 2;
+  }
+  steps
+}
+
+/// Set HOLMES_TEST_SEED to replay a failing trial.
+fn test_seed(name: &str) -> (u64, XorShift128PlusRng) {
+  let seed = match env::var("HOLMES_TEST_SEED").ok().and_then(|s| s.parse().ok()) {
+    Some(seed) => seed,
+    None => thread_rng().next_u64(),
+  };
+  println!("DEBUG: {}: seed {}", name, seed);
+  (seed, XorShift128PlusRng::from_seed([seed, seed ^ 0x9e37_79b9_7f4a_7c15]))
+}
+
+fn check_random_trials<Data, F>(name: &str, new_data: F, check_data: fn(&TxnState<Data>) -> Result<(), String>)
+where Data: TxnStateData + Clone, F: Fn() -> Data {
+  let (seed, mut rng) = test_seed(name);
+  for trial in 0 .. NUM_TRIALS {
+    let steps = gen_steps(&mut rng, NUM_STEPS);
+    if let Err(e) = run_steps(&steps, &new_data, check_data) {
+      let min_steps = shrink(steps, |steps| run_steps(steps, &new_data, check_data).is_err());
+      let min_err = run_steps(&min_steps, &new_data, check_data).unwrap_err();
+      panic!("{}: seed {} trial {}: {}\nshrunk to {} steps: {}\n{:?}",
+          name, seed, trial, e, min_steps.len(), min_err, min_steps);
+    }
+  }
+}
+
+/// For data without an oracle; `run_steps` still compares it to a clean
+/// replay.
+fn check_nothing<Data>(_state: &TxnState<Data>) -> Result<(), String> where Data: TxnStateData + Clone {
+  Ok(())
+}
+
+#[test]
+fn test_txnstate_random_undo_commit() {
+  check_random_trials("()", || (), check_nothing);
+}
+
+#[test]
+fn test_txnstate_random_legality_data() {
+  check_random_trials("legality", || TxnStateLegalityData::new(true), check_legality);
+}
+
+#[test]
+fn test_txnstate_random_mini_v3_features() {
+  check_random_trials("alpha_mini_v3", || TxnStateAlphaMiniV3FeatsData::new(), check_mini_v3);
+}
+
+#[test]
+fn test_txnstate_random_v3_features() {
+  check_random_trials("alpha_v3", || TxnStateAlphaV3FeatsData::new(), check_v3);
+}
+
+#[test]
+fn test_txnstate_random_pattern_v3_features() {
+  check_random_trials("alpha_pattern_v3", || TxnStateAlphaPatternV3FeatsData::new(), check_pattern_v3);
+}
+
+#[test]
+fn test_txnstate_random_pattern_noko_v3_features() {
+  check_random_trials("alpha_pattern_noko_v3", || TxnStateAlphaPatternNoKoV3FeatsData::new(), check_pattern_noko_v3);
+}
+
+#[test]
+fn test_txnstate_random_shape_data() {
+  check_random_trials("shapes", || TxnStateShapeData::new(None), check_shapes);
+}
+
+#[test]
+fn test_txnstate_random_older_features() {
+  check_random_trials("features", || TxnStateFeaturesData::new(), check_nothing);
+  check_random_trials("lib_features", || TxnStateLibFeaturesData::new(), check_nothing);
+  check_random_trials("ext_lib_features", || TxnStateExtLibFeatsData::new(), check_nothing);
+  check_random_trials("alpha_v1", || TxnStateAlphaFeatsV1Data::new(), check_nothing);
+  check_random_trials("alpha_v2", || TxnStateAlphaFeatsV2Data::new(), check_nothing);
+  check_random_trials("pattern_3x3", || TxnStatePattern3x3FeatsData::new(), check_nothing);
+}
+
+#[test]
+fn test_txnstate_random_search_data() {
+  check_random_trials("node", || TxnStateNodeData::new(), check_nothing);
+  check_random_trials("rollout", || TxnStateRolloutData::new(), check_nothing);
+  check_random_trials("rollout_legality", || TxnStateRolloutLegalityData::new(), check_nothing);
+}
+
+#[test]
+fn test_shrink_finds_minimal_steps() {
+  let steps: Vec<Step> = (0 .. 50).map(|p| Step{action: Action::Place{point: Point::from_idx(p)}, commit: true}).collect();
+  let bad = Point::from_idx(17);
+  let min_steps = shrink(steps, |steps| steps.iter().any(|s| s.action == Action::Place{point: bad}));
+  assert_eq!(1, min_steps.len());
+  assert_eq!(Action::Place{point: bad}, min_steps[0].action);
+}
+
+#[test]
+fn test_txnstate_seek_ply_restores_data() {
+  let (_, mut rng) = test_seed("seek_ply_restores_data");
+  let config = TxnStateConfig{history: true, .. TxnStateConfig::default()};
+  let mut state = TxnState::new(config, TxnStateAlphaMiniV3FeatsData::new());
+  state.reset();
+  let mut snapshots = vec![(stones_of(&state), json::encode(state.get_data()).unwrap())];
+  for step in gen_steps(&mut rng, NUM_STEPS).iter() {
+    let turn = state.current_turn();
+    if state.try_action(turn, step.action).is_ok() {
+      state.commit();
+      snapshots.push((stones_of(&state), json::encode(state.get_data()).unwrap()));
+    } else {
+      state.undo();
+    }
+  }
+  let num_plies = state.current_ply();
+  assert_eq!(snapshots.len(), num_plies + 1);
+  assert!(state.redo_plies(1).is_err());
+
+  for _ in 0 .. 20 {
+    let ply = rng.gen_range(0, num_plies + 1);
+    state.seek_ply(ply).unwrap();
+    assert_eq!(ply, state.current_ply());
+    assert_eq!(snapshots[ply].0, stones_of(&state));
+    assert_eq!(snapshots[ply].1, json::encode(state.get_data()).unwrap());
+  }
+
+  // Undo all the way back, then redo to the end of the game.
+  state.seek_ply(num_plies).unwrap();
+  assert!(state.undo_plies(num_plies + 1).is_err());
+  state.undo_plies(num_plies).unwrap();
+  assert_eq!(snapshots[0].0, stones_of(&state));
+  state.redo_plies(num_plies).unwrap();
+  assert_eq!(snapshots[num_plies].1, json::encode(state.get_data()).unwrap());
+
+  // A new action after undo discards the redo history.
+  state.undo_plies(1).unwrap();
+  state.try_action(state.current_turn(), Action::Pass).unwrap();
+  state.commit();
+  assert!(state.redo_plies(1).is_err());
+}
@@ -0,0 +1,143 @@
+extern crate getopts;
+extern crate holmes;
+extern crate rand;
+extern crate rng;
+extern crate time;
+
+use holmes::board::{Stone};
+use holmes::search::parallel_policies::uniform::{UniformPolicyWorkerBuilder};
+use holmes::search::parallel_tree::{
+  TreePolicyConfig,
+  SharedTree,
+  SearchWorkerConfig, SearchWorkerBatchConfig,
+  SearchParallelism,
+  ParallelMonteCarloSearchServer,
+  ParallelMonteCarloSearch,
+  RootParallelMonteCarloSearch,
+};
+use holmes::txnstate::{TxnStateConfig, TxnState};
+use holmes::txnstate::extras::{TxnStateNodeData};
+
+use getopts::{Options};
+use rand::{thread_rng};
+use rng::xorshift::{Xorshiftplus128Rng};
+use std::cmp::{max, min};
+use std::env;
+use time::{get_time};
+
+/// Measures the search throughput of the GPU-free uniform policy worker for
+/// doubling numbers of worker threads, each with the same batch size, so
+/// that the speedup over one thread shows how the shared tree scales. With
+/// `--mode root` the threads are split among independent root parallel
+/// trees instead; the share of visits of the chosen move and the number of
+/// distinct moves chosen over the trials compare the decisions.
+fn main() {
+  let args: Vec<_> = env::args().collect();
+  let mut opts = Options::new();
+  opts.optopt("t", "max-threads", "largest number of worker threads (default 32)", "n");
+  opts.optopt("b", "batch-size", "rollouts per thread per batch (default 16)", "n");
+  opts.optopt("n", "num-batches", "batches per search (default 64)", "n");
+  opts.optopt("", "virtual-loss", "virtual loss weight (default 1)", "w");
+  opts.optopt("", "trials", "timed searches per thread count (default 3)", "n");
+  opts.optopt("", "mode", "tree or root parallelism (default tree)", "mode");
+  opts.optopt("", "root-trees", "most root parallel trees (default 4)", "n");
+  let matches = match opts.parse(&args[1 ..]) {
+    Ok(m) => m,
+    Err(e) => panic!("failed to parse args: {:?}", e),
+  };
+  let max_threads: usize = matches.opt_str("t").unwrap_or("32".to_string())
+    .parse().ok().expect("FATAL: bench: max threads should be an integer");
+  let batch_size: usize = matches.opt_str("b").unwrap_or("16".to_string())
+    .parse().ok().expect("FATAL: bench: batch size should be an integer");
+  let num_batches: usize = matches.opt_str("n").unwrap_or("64".to_string())
+    .parse().ok().expect("FATAL: bench: num batches should be an integer");
+  let virtual_loss: f32 = matches.opt_str("virtual-loss").unwrap_or("1".to_string())
+    .parse().ok().expect("FATAL: bench: virtual loss should be a number");
+  let num_trials: usize = matches.opt_str("trials").unwrap_or("3".to_string())
+    .parse().ok().expect("FATAL: bench: trials should be an integer");
+  let root_trees: usize = matches.opt_str("root-trees").unwrap_or("4".to_string())
+    .parse().ok().expect("FATAL: bench: root trees should be an integer");
+  let root_mode = match &matches.opt_str("mode").unwrap_or("tree".to_string()) as &str {
+    "tree" => false,
+    "root" => true,
+    x => panic!("FATAL: bench: unknown mode: {}", x),
+  };
+
+  let state_cfg = TxnStateConfig::default();
+  let tree_cfg = TreePolicyConfig{
+    virtual_loss:   virtual_loss,
+    .. TreePolicyConfig::test_default()
+  };
+  let mut init_state = TxnState::new(state_cfg, TxnStateNodeData::new());
+  init_state.reset();
+  let mut rng = Xorshiftplus128Rng::new(&mut thread_rng());
+
+  println!("threads  mode       rollouts/s  speedup  nodes/search  best share  moves");
+  let mut base_rate = None;
+  let mut num_threads = 1;
+  while num_threads <= max_threads {
+    let server = ParallelMonteCarloSearchServer::new(
+        state_cfg,
+        num_threads, 1, batch_size,
+        UniformPolicyWorkerBuilder::new(tree_cfg),
+    );
+    let worker_cfg = SearchWorkerConfig{
+      batch_cfg:    SearchWorkerBatchConfig::Fixed{num_batches: num_batches},
+      tree_batch_size:      None,
+      rollout_batch_size:   num_threads * batch_size,
+      early_stop:   false,
+    };
+    let parallelism = if root_mode {
+      SearchParallelism::Root{num_trees: min(root_trees, num_threads)}
+    } else {
+      SearchParallelism::Tree
+    };
+    let mut run_search = || {
+      match parallelism {
+        SearchParallelism::Tree => {
+          let search = ParallelMonteCarloSearch::new();
+          search.join(worker_cfg, &server, Stone::Black, &init_state, SharedTree::new(tree_cfg), &mut rng)
+        }
+        SearchParallelism::Root{num_trees} => {
+          let search = RootParallelMonteCarloSearch::new();
+          let trees: Vec<_> = (0 .. num_trees).map(|_| SharedTree::new(tree_cfg)).collect();
+          search.join(worker_cfg, &server, Stone::Black, &init_state, &trees, &mut rng)
+        }
+      }
+    };
+    // The first search warms up the workers and is not timed.
+    run_search();
+    let mut total_ms = 0;
+    let mut total_rollouts = 0;
+    let mut total_nodes = 0;
+    let mut total_best = 0;
+    let mut actions = vec![];
+    for _ in 0 .. num_trials {
+      let start_time = get_time();
+      let (result, stats) = run_search();
+      total_ms += (get_time() - start_time).num_milliseconds() as usize;
+      total_rollouts += stats.num_rollouts;
+      total_nodes += stats.num_expanded;
+      total_best += stats.argmax_ntrials;
+      if !actions.contains(&result.action) {
+        actions.push(result.action);
+      }
+    }
+    let rate = total_rollouts as f32 / (total_ms as f32 * 0.001).max(0.001);
+    let base = match base_rate {
+      Some(base) => base,
+      None => {
+        base_rate = Some(rate);
+        rate
+      }
+    };
+    let mode = match parallelism {
+      SearchParallelism::Tree => "tree".to_string(),
+      SearchParallelism::Root{num_trees} => format!("root/{}", num_trees),
+    };
+    println!("{:7}  {:9}  {:10.0}  {:7.2}  {:12}  {:10.3}  {:5}",
+        num_threads, mode, rate, rate / base, total_nodes / max(1, num_trials),
+        total_best as f32 / max(1, total_rollouts) as f32, actions.len());
+    num_threads *= 2;
+  }
+}
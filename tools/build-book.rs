@@ -0,0 +1,67 @@
+extern crate getopts;
+extern crate holmes;
+
+use holmes::book::{OpeningBookBuilder};
+use holmes::sgf::{Sgf, SgfRankStyle};
+
+use getopts::{Options};
+use std::env;
+use std::fs::{File};
+use std::io::{Read, BufRead, BufReader};
+use std::path::{PathBuf};
+
+fn main() {
+  let args: Vec<_> = env::args().collect();
+  let mut opts = Options::new();
+  opts.optmulti("i", "index", "index of sgf paths, one per line (e.g. from sgf-corpus)", "index");
+  opts.optopt("o", "output", "output opening book path", "book");
+  opts.optopt("", "max-ply", "number of opening moves to record per game (default 30)", "n");
+  opts.optopt("", "min-count", "drop moves played fewer times (default 2)", "n");
+  opts.optflag("", "gogod", "parse ranks as in GoGoD (\"9d\" is pro, \"4a\" is amateur)");
+  let matches = match opts.parse(&args[1 ..]) {
+    Ok(m) => m,
+    Err(e) => panic!("failed to parse args: {:?}", e),
+  };
+  let index_paths = matches.opt_strs("i");
+  if index_paths.is_empty() {
+    panic!("FATAL: book: at least one index required");
+  }
+  let book_path = PathBuf::from(matches.opt_str("o").expect("FATAL: book: output path required"));
+  let max_ply: usize = matches.opt_str("max-ply").unwrap_or("30".to_string())
+    .parse().ok().expect("FATAL: book: max ply should be an integer");
+  let min_count: u32 = matches.opt_str("min-count").unwrap_or("2".to_string())
+    .parse().ok().expect("FATAL: book: min count should be an integer");
+  let rank_style = if matches.opt_present("gogod") {
+    SgfRankStyle::Gogod
+  } else {
+    SgfRankStyle::Server
+  };
+
+  let mut builder = OpeningBookBuilder::new(max_ply);
+  let mut num_skipped = 0;
+  for index_path in index_paths.iter() {
+    let index_file = File::open(&PathBuf::from(index_path))
+      .ok().expect("FATAL: book: failed to open index");
+    for line in BufReader::new(index_file).lines() {
+      let sgf_path = PathBuf::from(line.unwrap().trim());
+      let mut text = vec![];
+      match File::open(&sgf_path) {
+        Ok(mut file) => { file.read_to_end(&mut text).unwrap(); }
+        Err(e) => {
+          println!("WARNING: book: failed to open {:?}: {:?}", sgf_path, e);
+          num_skipped += 1;
+          continue;
+        }
+      }
+      let sgf = Sgf::from_text(&text);
+      if builder.add_sgf(&sgf, rank_style).is_err() {
+        println!("WARNING: book: skipping {:?}", sgf_path);
+        num_skipped += 1;
+      }
+    }
+  }
+  let book = builder.build(min_count);
+  book.save(&book_path);
+  println!("games: {} skipped: {}", builder.num_games(), num_skipped);
+  println!("book: positions: {} moves: {}", book.entries.len(), book.num_moves());
+}
@@ -116,6 +116,7 @@ fn main() {
           rules:  RuleSet::KgsJapanese.rules(),
           ranks:  [PlayerRank::Dan(9), PlayerRank::Dan(9)],
           komi:   6.5,
+          history: false,
         },
         TxnStateLibFeaturesData::new(),
     );
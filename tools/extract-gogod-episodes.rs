@@ -5,19 +5,11 @@ extern crate episodb;
 extern crate holmes;
 extern crate rustc_serialize;
 
-use holmes::board::{RuleSet, Coord, PlayerRank, Stone, Point, Action};
-use holmes::sgf::{Sgf, parse_raw_sgf};
-use holmes::txnstate::{TxnStateConfig, TxnState};
-use holmes::txnstate::features::{
-  /*TxnStateFeaturesData,
-  TxnStateLibFeaturesData,
-  TxnStateExtLibFeatsData,
-  TxnStateAlphaFeatsV1Data,
-  TxnStateAlphaFeatsV2Data,*/
-  TxnStateAlphaV3FeatsData,
-  TxnStateAlphaMiniV3FeatsData,
-  TxnStateAlphaPatternV3FeatsData,
-  TxnStateAlphaPatternNoKoV3FeatsData,
+use holmes::board::{Coord, PlayerRank, Stone, Point, Action};
+use holmes::dataset::{DatasetHeader, DatasetLabel, DatasetProvenance};
+use holmes::sgf::{Sgf, SgfRankStyle, parse_raw_sgf};
+use holmes::txnstate::feature_sets::{
+  lookup_feature_set, registered_feature_sets, has_feature_extractor, build_feature_extractor,
 };
 
 //use array::{NdArrayFormat, ArrayDeserialize, ArraySerialize, Array3d};
@@ -36,10 +28,25 @@ fn main() {
   let args: Vec<_> = env::args().collect();
   let prefix = args[1].clone();
   let suffix = args[2].clone();
+  // XXX(20160420): The feature set is selected by name, e.g. "alpha_v3".
+  let feature_set_name = args.get(3).map(|s| s.clone()).unwrap_or("alpha_pattern_noko_v3".to_string());
+  let feature_set = match lookup_feature_set(&feature_set_name) {
+    Some(feature_set) => feature_set,
+    None => {
+      let names: Vec<_> = registered_feature_sets().iter().map(|fs| fs.name).collect();
+      panic!("FATAL: extract: unknown feature set: \"{}\" (known: {:?})", feature_set_name, names);
+    }
+  };
+  if !has_feature_extractor(feature_set.name) {
+    panic!("FATAL: extract: feature set needs more than a state config: \"{}\"", feature_set.name);
+  }
+  println!("DEBUG: extract: feature set: {} v{} planes: {}",
+      feature_set.name, feature_set.version, feature_set.num_planes());
   let index_path = PathBuf::from(&format!("{}_index", prefix));
   let frames_db_path = PathBuf::from(&format!("{}_frames_{}.episodb", prefix, suffix));
   let action_labels_db_path = PathBuf::from(&format!("{}_labels_action_{}.episodb", prefix, suffix));
   let value_labels_db_path = PathBuf::from(&format!("{}_labels_value_{}.episodb", prefix, suffix));
+  let header_path = DatasetHeader::split_path(&PathBuf::from(&prefix), &suffix);
 
   let index_file = BufReader::new(File::open(&index_path).unwrap());
   let mut index_lines = Vec::new();
@@ -70,12 +77,14 @@ fn main() {
   //let expected_frame_sz = BitArray3d::serial_size((19, 19, 31)) + Array3d::<u8>::serial_size((19, 19, 1));
   //let expected_frame_sz = BitArray3d::serial_size(expected_dims);
 
-  //type FeatsData = TxnStateAlphaV3FeatsData;
-  //type FeatsData = TxnStateAlphaMiniV3FeatsData;
-  //type FeatsData = TxnStateAlphaPatternV3FeatsData;
-  type FeatsData = TxnStateAlphaPatternNoKoV3FeatsData;
+  let expected_frame_sz = feature_set.serial_frame_size();
 
-  let expected_frame_sz = FeatsData::serial_size();
+  let header = DatasetHeader::with_feature_set(feature_set,
+      &[DatasetLabel::Action, DatasetLabel::Value],
+      DatasetProvenance::new("extract-gogod-episodes", vec![index_path.to_str().unwrap().to_string()]),
+  );
+  assert_eq!(expected_frame_sz, header.frame_size);
+  header.save(&header_path);
 
   let n = sgf_paths.len();
   let est_ep_len = 216;
@@ -345,15 +354,16 @@ fn main() {
     }
 
     let mut history = vec![];
-    let mut state = TxnState::new(
-        TxnStateConfig{
-          rules:  RuleSet::KgsJapanese.rules(),
-          ranks:  [b_rank, w_rank],
-          komi:   6.5,
-        },
-        FeatsData::new(),
-    );
-    state.reset();
+    let mut state_cfg = sgf.infer_state_cfg(SgfRankStyle::Gogod);
+    state_cfg.ranks = [b_rank, w_rank];
+    let mut state = build_feature_extractor(feature_set.name, state_cfg).unwrap();
+    if state.place_setup_stones(&sgf.setup_stones()).is_err() {
+      println!("WARNING: extract: found illegal setup stones: sgf path: '{:?}'", sgf_path);
+      continue;
+    }
+    if sgf.num_handicap() > 0 {
+      state.unsafe_set_current_turn(Stone::White);
+    }
     for (t, &(ref player, ref mov)) in sgf.moves.iter().enumerate() {
       let turn = match player as &str {
         "B" => Stone::Black,
@@ -375,11 +385,9 @@ fn main() {
         "Resign"  => Action::Resign,
         x         => Action::Place{point: Point::from_coord(Coord::from_code_str(x))},
       };
-      history.push((turn, action, state.clone(), outcome));
+      history.push((turn, action, state.extract_relative_serial_frame(turn), outcome));
       match state.try_action(turn, action) {
-        Ok(_) => {
-          state.commit();
-        }
+        Ok(_) => {}
         Err(_) => {
           println!("WARNING: extract: found an illegal action: sgf path: '{:?}'", sgf_path);
           history.clear();
@@ -396,7 +404,7 @@ fn main() {
     action_labels_db.append_episode();
     value_labels_db.append_episode();
 
-    for (t, &(turn, action, ref state, outcome)) in history.iter().enumerate() {
+    for (t, &(turn, action, ref serial_frame, outcome)) in history.iter().enumerate() {
       if t >= 1 {
         // XXX(20151209): if 2 turns in a row, skip the rest of this game.
         if history[t-1].0 == turn {
@@ -460,10 +468,9 @@ fn main() {
       bytes_arr.serialize(&mut serial_frame).unwrap();*/
 
       // XXX(20160223): Option 3: Let the feature data return a mystery blob.
-      let serial_frame = state.get_data().extract_relative_serial_blob(turn);
-
+      // XXX(20160420): The blob is now serialized by the feature set registry.
       assert_eq!(serial_frame.len(), expected_frame_sz);
-      frames_db.append_frame(&serial_frame);
+      frames_db.append_frame(serial_frame);
 
       let mut action_label_cursor = Cursor::new(Vec::with_capacity(4));
       action_label_cursor.write_i32::<LittleEndian>(action_label).unwrap();
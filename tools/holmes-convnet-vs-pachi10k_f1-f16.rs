@@ -13,7 +13,7 @@ fn main() {
       (Resource::CudaGpu, 1),
     ],
     assets:     vec![
-      //Asset::Copy{src: PathBuf::from("default.hyperparam")},
+      //Asset::Copy{src: PathBuf::from("holmes.toml")},
       Asset::Symlink{src: PathBuf::from("patterns.prob")},
       Asset::Symlink{src: PathBuf::from("patterns.spat")},
       Asset::SymlinkAs{
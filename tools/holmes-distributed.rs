@@ -0,0 +1,110 @@
+extern crate getopts;
+extern crate holmes;
+
+use holmes::search::distributed::{
+  DistributedSearchConfig,
+  DistributedSearchMaster,
+  DistributedSearchWorker,
+};
+use holmes::search::parallel_policies::uniform::{UniformPolicyWorkerBuilder};
+use holmes::search::parallel_tree::{
+  TreePolicyConfig,
+  SharedTree,
+};
+use holmes::txnstate::{TxnStateConfig, TxnState};
+use holmes::txnstate::extras::{TxnStateNodeData};
+
+use getopts::{Options};
+use std::env;
+use std::process::{Command, Child};
+
+/// Runs a distributed search with the GPU-free uniform policies. The master
+/// (`--master`) owns the tree and waits for `--workers` workers, which either
+/// run elsewhere (`--worker`) or are spawned on localhost
+/// (`--spawn-workers`), then runs `--trials` searches from the empty board.
+fn main() {
+  let args: Vec<_> = env::args().collect();
+  let mut opts = Options::new();
+  opts.optopt("", "master", "run the master on this address", "host:port");
+  opts.optopt("", "worker", "run a worker for the master at this address", "host:port");
+  opts.optopt("", "workers", "number of workers to wait for (default 2)", "n");
+  opts.optflag("", "spawn-workers", "spawn the workers as local processes");
+  opts.optopt("r", "rollouts", "rollouts per search (default 1024)", "n");
+  opts.optopt("b", "batch-size", "most leafs per worker batch (default 16)", "n");
+  opts.optopt("", "virtual-loss", "virtual loss weight (default 1)", "w");
+  opts.optopt("", "timeout-ms", "result timeout (default 5000)", "ms");
+  opts.optflag("", "remote-priors", "have the workers evaluate the priors of new leafs");
+  opts.optopt("", "trials", "number of searches (default 3)", "n");
+  let matches = match opts.parse(&args[1 ..]) {
+    Ok(m) => m,
+    Err(e) => panic!("failed to parse args: {:?}", e),
+  };
+  let batch_size: usize = matches.opt_str("b").unwrap_or("16".to_string())
+    .parse().ok().expect("FATAL: distributed: batch size should be an integer");
+  let virtual_loss: f32 = matches.opt_str("virtual-loss").unwrap_or("1".to_string())
+    .parse().ok().expect("FATAL: distributed: virtual loss should be a number");
+  let tree_cfg = TreePolicyConfig{
+    virtual_loss:   virtual_loss,
+    .. TreePolicyConfig::test_default()
+  };
+
+  if let Some(addr) = matches.opt_str("worker") {
+    let mut worker = match DistributedSearchWorker::connect(&addr as &str, batch_size, UniformPolicyWorkerBuilder::new(tree_cfg)) {
+      Ok(worker) => worker,
+      Err(e) => panic!("FATAL: distributed: failed to connect to {}: {:?}", addr, e),
+    };
+    let num_rollouts = worker.run();
+    println!("DEBUG: distributed worker: done, rollouts: {}", num_rollouts);
+    return;
+  }
+
+  let addr = matches.opt_str("master").unwrap_or("127.0.0.1:9200".to_string());
+  let num_workers: usize = matches.opt_str("workers").unwrap_or("2".to_string())
+    .parse().ok().expect("FATAL: distributed: workers should be an integer");
+  let num_rollouts: usize = matches.opt_str("r").unwrap_or("1024".to_string())
+    .parse().ok().expect("FATAL: distributed: rollouts should be an integer");
+  let timeout_ms: usize = matches.opt_str("timeout-ms").unwrap_or("5000".to_string())
+    .parse().ok().expect("FATAL: distributed: timeout should be an integer");
+  let num_trials: usize = matches.opt_str("trials").unwrap_or("3".to_string())
+    .parse().ok().expect("FATAL: distributed: trials should be an integer");
+
+  let mut master = match DistributedSearchMaster::bind(&addr as &str, UniformPolicyWorkerBuilder::new(tree_cfg)) {
+    Ok(master) => master,
+    Err(e) => panic!("FATAL: distributed: failed to bind {}: {:?}", addr, e),
+  };
+  let local_addr = master.local_addr();
+  println!("DEBUG: distributed master: listening on {}", local_addr);
+  let mut children: Vec<Child> = vec![];
+  if matches.opt_present("spawn-workers") {
+    let exe = env::current_exe().unwrap();
+    for _ in 0 .. num_workers {
+      let child = Command::new(&exe)
+        .arg("--worker").arg(&format!("{}", local_addr))
+        .arg("--batch-size").arg(&format!("{}", batch_size))
+        .arg("--virtual-loss").arg(&format!("{}", virtual_loss))
+        .spawn()
+        .ok().expect("FATAL: distributed: failed to spawn a worker");
+      children.push(child);
+    }
+  }
+  master.wait_for_workers(num_workers);
+
+  let mut init_state = TxnState::new(TxnStateConfig::default(), TxnStateNodeData::new());
+  init_state.reset();
+  let cfg = DistributedSearchConfig{
+    num_rollouts:       num_rollouts,
+    max_batch_size:     batch_size,
+    result_timeout_ms:  timeout_ms,
+    remote_priors:      matches.opt_present("remote-priors"),
+  };
+  for trial in 0 .. num_trials {
+    let (result, stats) = master.search(cfg, &init_state, SharedTree::new(tree_cfg));
+    println!("DEBUG: distributed master: search {}: action: {:?} value: {:.3} rollouts: {} nodes: {} elapsed: {} ms",
+        trial, result.action, result.expected_value, stats.num_rollouts, stats.num_expanded, stats.elapsed_ms);
+  }
+
+  master.shutdown();
+  for mut child in children.into_iter() {
+    let _ = child.wait();
+  }
+}
@@ -3,6 +3,7 @@ extern crate holmes;
 
 use holmes::agents::parallel_search::{ParallelMonteCarloSearchAgent};
 use holmes::agents::search::{SearchAgent};
+use holmes::config::{EngineConfig};
 use holmes::gtp::{GtpEngine};
 use holmes::gtp_board::{Player};
 use holmes::gtp_client::{Client};
@@ -34,7 +35,12 @@ fn main() {
     _ => panic!("FATAL: holmes: invalid player: {}", player_str),
   };*/
   //let agent = SearchAgent::new();
-  let agent = ParallelMonteCarloSearchAgent::new(None);
+  let cfg = match EngineConfig::load(None, &[]) {
+    Ok(cfg) => cfg,
+    Err(e) => panic!("FATAL: holmes: bad config: {}", e),
+  };
+  cfg.log();
+  let agent = ParallelMonteCarloSearchAgent::new(&cfg);
   let client = Client::new(agent, host, port, None);
   GtpEngine::new(client).runloop();
 }
@@ -1,9 +1,15 @@
+extern crate getopts;
 extern crate holmes;
 
 //use holmes::client::nngs::{NngsClient, NngsConfig};
 //use holmes::client::agent::{HelloAsyncAgent};
 use holmes::client::agent::parallel_search::{ParallelSearchAsyncAgent};
-use holmes::client::nngs::{NngsOneShotClient, NngsServerConfig, NngsMatchConfig};
+use holmes::client::nngs::{NngsClient, NngsClientConfig, NngsServerConfig, NngsMatchConfig};
+use holmes::config::{EngineConfig};
+
+use getopts::{Options};
+use std::env;
+use std::path::{Path, PathBuf};
 
 fn main() {
   /*let config = NngsConfig{
@@ -13,6 +19,24 @@ fn main() {
   let mut client = NngsClient::new("jsb.cs.uec.ac.jp".to_string(), 9696, config);
   client.run_loop();*/
 
+  let args: Vec<_> = env::args().collect();
+  let mut opts = Options::new();
+  opts.optopt("c", "config", "engine config file (default: $HOLMES_CONFIG, else holmes.toml)", "config");
+  opts.optmulti("", "set", "override a config value, e.g. --set tree.max_horizon=30", "section.key=value");
+  let matches = match opts.parse(&args[1 ..]) {
+    Ok(m) => m,
+    Err(e) => panic!("failed to parse args: {:?}", e),
+  };
+  let cfg_path = matches.opt_str("c").map(|path| PathBuf::from(&path));
+  let engine_cfg = match EngineConfig::load(cfg_path.as_ref().map(|path| path as &Path), &matches.opt_strs("set")) {
+    Ok(cfg) => cfg,
+    Err(e) => panic!("FATAL: holmes-nngs: bad engine config: {}", e),
+  };
+  engine_cfg.log();
+  if let Err(e) = engine_cfg.check_network_paths() {
+    panic!("FATAL: holmes-nngs: {}", e);
+  }
+
   let server_cfg = NngsServerConfig{
     host:     "jsb.cs.uec.ac.jp".to_string(),
     port:     9696,
@@ -24,6 +48,12 @@ fn main() {
   client.run_loop();*/
 
   //let mut client = NngsOneShotClient::<HelloAsyncAgent>::new(server_cfg, None);
-  let mut client = NngsOneShotClient::<ParallelSearchAsyncAgent>::new(server_cfg, None);
+  //let mut client = NngsOneShotClient::<ParallelSearchAsyncAgent>::new(server_cfg, None);
+  let client_cfg = match NngsClientConfig::open() {
+    Ok(cfg) => cfg,
+    Err(e) => panic!("FATAL: holmes-nngs: bad client config: {}", e),
+  };
+  let match_cfg = NngsMatchConfig::open().ok();
+  let mut client = NngsClient::<ParallelSearchAsyncAgent>::new(server_cfg, client_cfg, match_cfg, engine_cfg);
   client.run_loop();
 }
@@ -2,18 +2,24 @@ extern crate getopts;
 extern crate holmes;
 
 use holmes::agents::parallel_search::{ParallelMonteCarloSearchAgent};
+use holmes::config::{EngineConfig};
 use holmes::gtp::{GtpEngine};
 use holmes::gtp_client::{Client};
-use holmes::search::parallel_tree::{MonteCarloSearchConfig, TreePolicyConfig, HorizonConfig};
 
 use getopts::{Options};
 use std::env;
+use std::path::{Path, PathBuf};
 
 fn main() {
   let args: Vec<_> = env::args().collect();
   let mut opts = Options::new();
   opts.optopt("h", "host", "host address for GTP", "host");
   opts.optopt("p", "port", "port for GTP", "port");
+  opts.optopt("c", "config", "engine config file (default: $HOLMES_CONFIG, else holmes.toml)", "config");
+  opts.optmulti("", "set", "override a config value, e.g. --set search.num_rollouts=4096", "section.key=value");
+  opts.optopt("", "visits", "search each move with this many rollouts", "n");
+  opts.optopt("", "time-ms", "search each move for this many milliseconds", "ms");
+  opts.optopt("", "nodes", "search each move until this many new nodes are expanded", "n");
   let matches = match opts.parse(&args[1 ..]) {
     Ok(m) => m,
     Err(e) => panic!("failed to parse args: {:?}", e),
@@ -23,23 +29,28 @@ fn main() {
     .parse().ok().expect("FATAL: holmes: port should be an integer");
   println!("DEBUG: holmes: host: {}", host);
   println!("DEBUG: holmes: port: {}", port);
-  let mc_cfg = MonteCarloSearchConfig{
-    num_rollouts:   1024,
-    batch_size:     16,
-    //num_rollouts:   4096,
-    //batch_size:     128,
+  let cfg_path = matches.opt_str("c").map(|path| PathBuf::from(&path));
+  // The search limit flags are shorthands for config overrides.
+  let mut overrides = matches.opt_strs("set");
+  for &(flag, limit, key) in [
+    ("visits",  "visits", "num_rollouts"),
+    ("time-ms", "time",   "time_budget_ms"),
+    ("nodes",   "nodes",  "max_nodes"),
+  ].iter() {
+    if let Some(value) = matches.opt_str(flag) {
+      overrides.push(format!("search.limit={}", limit));
+      overrides.push(format!("search.{}={}", key, value));
+    }
+  }
+  let cfg = match EngineConfig::load(cfg_path.as_ref().map(|path| path as &Path), &overrides) {
+    Ok(cfg) => cfg,
+    Err(e) => panic!("FATAL: holmes: bad config: {}", e),
   };
-  let tree_cfg = TreePolicyConfig{
-    horizon_cfg:    HorizonConfig::Fixed{max_horizon: 20},
-    visit_thresh:   1,
-    mc_scale:       1.0,
-    //mc_scale:       0.125,
-    prior_equiv:    16.0,
-    rave:           false,
-    rave_equiv:     0.0,
-    virtual_loss:   false,
-  };
-  let agent = ParallelMonteCarloSearchAgent::new(mc_cfg, tree_cfg, None);
+  cfg.log();
+  if let Err(e) = cfg.check_network_paths() {
+    panic!("FATAL: holmes: {}", e);
+  }
+  let agent = ParallelMonteCarloSearchAgent::new(&cfg);
   let client = Client::new(agent, host, port, None);
   GtpEngine::new(client).runloop();
 }
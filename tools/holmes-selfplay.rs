@@ -0,0 +1,106 @@
+extern crate getopts;
+extern crate holmes;
+extern crate rand;
+extern crate rng;
+
+use holmes::board::{RuleSet, PlayerRank};
+use holmes::book::{OpeningBookBuilder};
+use holmes::search::parallel_policies::{SearchPolicyWorkerBuilder};
+use holmes::search::parallel_policies::convnet::{ConvnetPolicyWorkerBuilder};
+use holmes::search::parallel_policies::uniform::{UniformPolicyWorkerBuilder};
+use holmes::search::parallel_tree::{
+  TreePolicyConfig, HorizonConfig,
+  ParallelMonteCarloSearchServer,
+};
+use holmes::selfplay::{SelfPlayConfig, SelfPlayDriver, SelfPlayEpisodeWriter};
+use holmes::txnstate::{TxnStateConfig};
+
+use getopts::{Options};
+use rand::{thread_rng};
+use rng::xorshift::{Xorshiftplus128Rng};
+use std::env;
+use std::path::{PathBuf};
+
+fn run_selfplay<B>(cfg: SelfPlayConfig, tree_cfg: TreePolicyConfig, num_workers: usize, worker_batch_capacity: usize, builder: B, prefix: PathBuf, suffix: String, feature_set: String, book_path: Option<PathBuf>)
+where B: 'static + SearchPolicyWorkerBuilder {
+  let state_cfg = TxnStateConfig{
+    rules:  RuleSet::KgsJapanese.rules(),
+    ranks:  [PlayerRank::Dan(9), PlayerRank::Dan(9)],
+    komi:   cfg.komi,
+    history: false,
+  };
+  let server = ParallelMonteCarloSearchServer::new(
+      state_cfg,
+      num_workers, 1, worker_batch_capacity,
+      builder,
+  );
+  let rng = Xorshiftplus128Rng::new(&mut thread_rng());
+  let mut driver = SelfPlayDriver::new(cfg, tree_cfg, state_cfg, server, rng);
+  let mut writer = SelfPlayEpisodeWriter::create(&prefix, &suffix, &feature_set, cfg.num_games, cfg.max_plies);
+  let mut book_builder = OpeningBookBuilder::new(30);
+  let mut num_written = 0;
+  for i in 0 .. cfg.num_games {
+    let episode = driver.play_episode();
+    if writer.append_episode(&episode) {
+      num_written += 1;
+    }
+    if book_path.is_some() && book_builder.add_selfplay_episode(&episode).is_err() {
+      println!("WARNING: self-play: failed to add episode to the opening book");
+    }
+    println!("DEBUG: self-play: {} / {}: written: {}", i + 1, cfg.num_games, num_written);
+  }
+  if let Some(book_path) = book_path {
+    let book = book_builder.build(1);
+    book.save(&book_path);
+    println!("DEBUG: self-play: opening book: {} positions, {} moves",
+        book.entries.len(), book.num_moves());
+  }
+}
+
+fn main() {
+  let args: Vec<_> = env::args().collect();
+  let mut opts = Options::new();
+  opts.optopt("o", "prefix", "output path prefix of the episode dbs", "prefix");
+  opts.optopt("s", "suffix", "output suffix of the episode dbs", "suffix");
+  opts.optopt("n", "num-workers", "number of search workers", "n");
+  opts.optopt("f", "features", "name of the feature set to extract", "features");
+  opts.optflag("", "convnet", "use the convnet policy workers (requires CUDA)");
+  opts.optopt("", "book", "also build an opening book of the self-play openings", "book");
+  let matches = match opts.parse(&args[1 ..]) {
+    Ok(m) => m,
+    Err(e) => panic!("failed to parse args: {:?}", e),
+  };
+  let prefix = PathBuf::from(matches.opt_str("o").expect("FATAL: selfplay: prefix required"));
+  let suffix = matches.opt_str("s").unwrap_or("selfplay".to_string());
+  let num_workers: usize = matches.opt_str("n").unwrap_or("1".to_string())
+    .parse().ok().expect("FATAL: selfplay: num workers should be an integer");
+  let book_path = matches.opt_str("book").map(|path| PathBuf::from(&path));
+  let feature_set = matches.opt_str("f").unwrap_or("alpha_pattern_noko_v3".to_string());
+
+  let cfg = match SelfPlayConfig::open() {
+    Ok(cfg) => cfg,
+    Err(_) => {
+      println!("WARNING: selfplay: failed to open selfplay.config, using defaults");
+      SelfPlayConfig::default()
+    }
+  };
+  println!("DEBUG: selfplay: config: {:?}", cfg);
+  let tree_cfg = TreePolicyConfig{
+    horizon_cfg:    HorizonConfig::All,
+    visit_thresh:   1,
+    mc_scale:       1.0,
+    prior_equiv:    16.0,
+    rave:           false,
+    rave_equiv:     0.0,
+    virtual_loss:   0.0,
+  };
+
+  let worker_batch_capacity = cfg.batch_size;
+  if matches.opt_present("convnet") {
+    let builder = ConvnetPolicyWorkerBuilder::new(tree_cfg, num_workers, 1, worker_batch_capacity);
+    run_selfplay(cfg, tree_cfg, num_workers, worker_batch_capacity, builder, prefix, suffix, feature_set, book_path);
+  } else {
+    let builder = UniformPolicyWorkerBuilder::new(tree_cfg);
+    run_selfplay(cfg, tree_cfg, num_workers, worker_batch_capacity, builder, prefix, suffix, feature_set, book_path);
+  }
+}
@@ -13,7 +13,7 @@ fn main() {
       (Resource::CudaGpu, 1),
     ],
     assets:     vec![
-      Asset::Copy{src: PathBuf::from("default.hyperparam")},
+      Asset::Copy{src: PathBuf::from("holmes.toml")},
       Asset::Symlink{src: PathBuf::from("patterns.prob")},
       Asset::Symlink{src: PathBuf::from("patterns.spat")},
       Asset::SymlinkAs{
@@ -63,7 +63,7 @@ fn main() {
         vec![
           ("LD_LIBRARY_PATH".to_string(), "/nscratch/phj/local/cuda/lib64:/usr/local/cuda-7.0/lib64".to_string()),
           ("RUST_BACKTRACE".to_string(), "1".to_string()),
-          ("HYPERPARAM_PATH".to_string(), "default.hyperparam".to_string()),
+          ("HOLMES_CONFIG".to_string(), "holmes.toml".to_string()),
         ]),
       (PathBuf::from("bin/pachi-11.ivybridge"),
         vec![
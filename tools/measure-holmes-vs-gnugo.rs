@@ -96,7 +96,7 @@ fn main() {
       let mut child = Command::new("./gtpctl-holmes-vs-gnugo.sh")
         .env("CUDA_VISIBLE_DEVICES", &format!("{}", device_num))
         //.env("HYPERPARAM_INSTANCE_PATH", "experiments/hyperparam/ikeda_rave.instance.json")
-        .env("HYPERPARAM_PATH", "default.hyperparam")
+        .env("HOLMES_CONFIG", "holmes.toml")
         .arg(&format!("{}", b_port))
         .arg(&format!("{}", w_port))
         .arg(&format!("{}", seed))  // XXX: Need to set seed, otherwise gnugo
@@ -80,7 +80,7 @@ fn main() {
       let (b_port, w_port, r_port) = (6060 + 3*job_idx, 6060 + 3*job_idx + 1, 6060 + 3*job_idx + 2);
       let mut child = Command::new("./gtp_ref_ctrl-holmes-vs-pachi10k-with-gnugo.sh")
         .env("CUDA_VISIBLE_DEVICES", &format!("{}", device_num))
-        .env("HYPERPARAM_PATH", "default.hyperparam")
+        .env("HOLMES_CONFIG", "holmes.toml")
         .arg(&format!("{}", b_port))
         .arg(&format!("{}", w_port))
         .arg(&format!("{}", r_port))
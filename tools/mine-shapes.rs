@@ -0,0 +1,74 @@
+extern crate getopts;
+extern crate holmes;
+
+use holmes::sgf::{Sgf, SgfRankStyle};
+use holmes::shapes::{ShapeDictionary};
+
+use getopts::{Options};
+use std::env;
+use std::fs::{File};
+use std::io::{Read, BufRead, BufReader};
+use std::path::{PathBuf};
+
+fn main() {
+  let args: Vec<_> = env::args().collect();
+  let mut opts = Options::new();
+  opts.optmulti("i", "index", "index of sgf paths, one per line (e.g. from sgf-corpus)", "index");
+  opts.optopt("o", "output", "output shape dictionary path", "dict");
+  opts.optopt("", "max-ply", "number of moves to mine per game, 0 for all (default 0)", "n");
+  opts.optopt("", "min-seen", "drop shapes seen fewer times (default 20)", "n");
+  opts.optflag("", "gogod", "parse ranks as in GoGoD (\"9d\" is pro, \"4a\" is amateur)");
+  let matches = match opts.parse(&args[1 ..]) {
+    Ok(m) => m,
+    Err(e) => panic!("failed to parse args: {:?}", e),
+  };
+  let index_paths = matches.opt_strs("i");
+  if index_paths.is_empty() {
+    panic!("FATAL: shapes: at least one index required");
+  }
+  let dict_path = PathBuf::from(matches.opt_str("o").expect("FATAL: shapes: output path required"));
+  let max_ply: usize = matches.opt_str("max-ply").unwrap_or("0".to_string())
+    .parse().ok().expect("FATAL: shapes: max ply should be an integer");
+  let min_seen: u32 = matches.opt_str("min-seen").unwrap_or("20".to_string())
+    .parse().ok().expect("FATAL: shapes: min seen should be an integer");
+  let rank_style = if matches.opt_present("gogod") {
+    SgfRankStyle::Gogod
+  } else {
+    SgfRankStyle::Server
+  };
+
+  let mut dict = ShapeDictionary::new();
+  let mut num_games = 0;
+  let mut num_skipped = 0;
+  for index_path in index_paths.iter() {
+    let index_file = File::open(&PathBuf::from(index_path))
+      .ok().expect("FATAL: shapes: failed to open index");
+    for line in BufReader::new(index_file).lines() {
+      let sgf_path = PathBuf::from(line.unwrap().trim());
+      let mut text = vec![];
+      match File::open(&sgf_path) {
+        Ok(mut file) => { file.read_to_end(&mut text).unwrap(); }
+        Err(e) => {
+          println!("WARNING: shapes: failed to open {:?}: {:?}", sgf_path, e);
+          num_skipped += 1;
+          continue;
+        }
+      }
+      let sgf = Sgf::from_text(&text);
+      if dict.add_sgf(&sgf, rank_style, max_ply).is_err() {
+        println!("WARNING: shapes: skipping {:?}", sgf_path);
+        num_skipped += 1;
+        continue;
+      }
+      num_games += 1;
+      if num_games % 1000 == 0 {
+        println!("DEBUG: shapes: games: {} shapes: {}", num_games, dict.entries.len());
+      }
+    }
+  }
+  let num_mined = dict.entries.len();
+  dict.prune(min_seen);
+  dict.save(&dict_path);
+  println!("games: {} skipped: {}", num_games, num_skipped);
+  println!("shapes: mined: {} kept: {}", num_mined, dict.entries.len());
+}
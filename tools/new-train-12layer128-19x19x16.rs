@@ -1,4 +1,5 @@
 extern crate array_cuda;
+extern crate holmes;
 extern crate rembrandt;
 
 #[macro_use]
@@ -9,6 +10,7 @@ extern crate scoped_threadpool;
 
 use array_cuda::device::{DeviceContext};
 use array_cuda::device::comm::{for_all_devices};
+use holmes::dataset::{DatasetHeader, DatasetLabel};
 use rembrandt::arch_new::{
   AtomicData, ArchWorker,
   PipelineArchConfig, PipelineArchSharedData, PipelineArchWorker,
@@ -74,6 +76,17 @@ fn train() {
     save_iters:     3000,
   };
   let datum_cfg = SampleDatumConfig::Bytes3d;
+
+  // XXX(20160420): Refuse to train on a dataset whose features do not match
+  // the network inputs.
+  let dataset_prefix = PathBuf::from("data/gogodb_19x19x16_episode.v2");
+  for split in ["train", "valid"].iter() {
+    let header = DatasetHeader::open_split(&dataset_prefix, split)
+      .ok().expect("FATAL: failed to open dataset header");
+    if let Err(e) = header.check_network((19, 19, input_channels), input_channels, DatasetLabel::Action) {
+      panic!("FATAL: {} split: {}", split, e);
+    }
+  }
   //let datum_cfg = SampleDatumConfig::Bits3d;
   let label_cfg = SampleLabelConfig::Category{num_categories: 361};
 
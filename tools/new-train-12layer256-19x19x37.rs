@@ -11,6 +11,7 @@ extern crate scoped_threadpool;
 use array_cuda::device::{DeviceContext};
 use array_cuda::device::comm::{for_all_devices};
 use holmes::data::{SymmetryAugment};
+use holmes::dataset::{DatasetHeader, DatasetLabel};
 use rembrandt::arch_new::{
   AtomicData, ArchWorker,
   PipelineArchConfig, PipelineArchSharedData, PipelineArchWorker,
@@ -83,6 +84,17 @@ fn train() {
   };
   //let datum_cfg = SampleDatumConfig::Bytes3d;
   let datum_cfg = SampleDatumConfig::Bits3d{scale: 1};
+
+  // XXX(20160420): Refuse to train on a dataset whose features do not match
+  // the network inputs.
+  let dataset_prefix = PathBuf::from("data/gogodb_19x19x37_episode.v3");
+  for split in ["train", "valid"].iter() {
+    let header = DatasetHeader::open_split(&dataset_prefix, split)
+      .ok().expect("FATAL: failed to open dataset header");
+    if let Err(e) = header.check_network((19, 19, input_channels), 0, DatasetLabel::Action) {
+      panic!("FATAL: {} split: {}", split, e);
+    }
+  }
   let label_cfg = SampleLabelConfig::Category{num_categories: 361};
 
   info!("sgd cfg: {:?}", sgd_opt_cfg);
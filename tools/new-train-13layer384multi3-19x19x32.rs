@@ -11,6 +11,7 @@ extern crate scoped_threadpool;
 use array_cuda::device::{DeviceContext};
 use array_cuda::device::comm::{for_all_devices};
 use holmes::data::{SymmetryAugment};
+use holmes::dataset::{DatasetHeader, DatasetLabel};
 use rembrandt::arch_new::{
   PipelineArchConfig, PipelineArchSharedData, PipelineArchWorker,
 };
@@ -80,6 +81,17 @@ fn train() {
     save_iters:     3000,
   };
   let datum_cfg = SampleDatumConfig::BitsThenBytes3d{scale: 255};
+
+  // XXX(20160420): Refuse to train on a dataset whose features do not match
+  // the network inputs.
+  let dataset_prefix = PathBuf::from("data/gogodb_w2015_alphav3m_19x19x32_episode");
+  for split in ["train", "valid"].iter() {
+    let header = DatasetHeader::open_split(&dataset_prefix, split)
+      .ok().expect("FATAL: failed to open dataset header");
+    if let Err(e) = header.check_network((19, 19, input_channels), 1, DatasetLabel::Action) {
+      panic!("FATAL: {} split: {}", split, e);
+    }
+  }
   let train_label_cfg = SampleLabelConfig::LookaheadCategories{
     num_categories: 361,
     lookahead:      lookahead,
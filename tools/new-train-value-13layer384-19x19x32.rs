@@ -11,6 +11,7 @@ extern crate scoped_threadpool;
 use array_cuda::device::{DeviceContext};
 use array_cuda::device::comm::{for_all_devices};
 use holmes::data::{SymmetryAugment};
+use holmes::dataset::{DatasetHeader, DatasetLabel};
 use rembrandt::arch_new::{
   PipelineArchConfig, PipelineArchSharedData, PipelineArchWorker,
 };
@@ -75,6 +76,17 @@ fn train() {
     save_iters:     3000,
   };
   let datum_cfg = SampleDatumConfig::BitsThenBytes3d{scale: 255};
+
+  // XXX(20160420): Refuse to train on a dataset whose features do not match
+  // the network inputs.
+  let dataset_prefix = PathBuf::from("datasets/gogodb_w2015-preproc-alphav3m_19x19x32");
+  for split in ["train", "valid"].iter() {
+    let header = DatasetHeader::open_split(&dataset_prefix, split)
+      .ok().expect("FATAL: failed to open dataset header");
+    if let Err(e) = header.check_network((19, 19, input_channels), 1, DatasetLabel::Value) {
+      panic!("FATAL: {} split: {}", split, e);
+    }
+  }
   /*let train_label_cfg = SampleLabelConfig::LookaheadCategories{
     num_categories: 361,
     lookahead:      3,
@@ -148,6 +160,7 @@ fn train() {
       let arch_cfg = arch_cfg.clone();
       let arch_shared = arch_shared.clone();
       let atomic_data = atomic_data.clone();
+      let dataset_prefix = dataset_prefix.clone();
       scope.execute(move || {
         let context = DeviceContext::new(tid);
         let ctx = context.as_ref();
@@ -162,7 +175,7 @@ fn train() {
             &ctx,
         );
 
-        let dataset_cfg = DatasetConfig::open(&PathBuf::from("datasets/gogodb_w2015-preproc-alphav3m_19x19x32.data"));
+        let dataset_cfg = DatasetConfig::open(&dataset_prefix.with_extension("data"));
 
         let mut train_data =
             //RandomEpisodeIterator::new(
@@ -0,0 +1,110 @@
+extern crate getopts;
+extern crate holmes;
+
+use holmes::corpus::{CorpusIndex, CorpusQuery, CorpusSplit};
+use holmes::sgf::{SgfRankStyle};
+
+use getopts::{Options};
+use std::collections::{BTreeMap};
+use std::env;
+use std::fs::{File};
+use std::io::{Write};
+use std::path::{PathBuf};
+
+fn main() {
+  let args: Vec<_> = env::args().collect();
+  let mut opts = Options::new();
+  opts.optopt("d", "dir", "directory of sgf files to scan (recursively)", "dir");
+  opts.optopt("i", "index", "previously saved corpus index to filter", "index");
+  opts.optopt("o", "prefix", "output path prefix", "prefix");
+  opts.optopt("q", "query", "filter query, e.g. \"size = 19 and handicap = 0 and rank >= 1d and completed and ok\"", "query");
+  opts.optopt("", "valid-frac", "fraction of games in the valid split (default 0.05)", "frac");
+  opts.optopt("", "test-frac", "fraction of games in the test split (default 0.05)", "frac");
+  opts.optflag("", "validate", "write a validation report and a cleaned index, and split only the clean games");
+  opts.optopt("", "min-moves", "games with fewer moves are reported as truncated (default 20)", "n");
+  opts.optflag("", "gogod", "parse ranks as in GoGoD (\"9d\" is pro, \"4a\" is amateur)");
+  let matches = match opts.parse(&args[1 ..]) {
+    Ok(m) => m,
+    Err(e) => panic!("failed to parse args: {:?}", e),
+  };
+  let prefix = PathBuf::from(matches.opt_str("o").expect("FATAL: corpus: output prefix required"));
+  let rank_style = if matches.opt_present("gogod") {
+    SgfRankStyle::Gogod
+  } else {
+    SgfRankStyle::Server
+  };
+  let query_str = matches.opt_str("q").unwrap_or(String::new());
+  let query = match CorpusQuery::parse(&query_str, rank_style) {
+    Ok(query) => query,
+    Err(e) => panic!("FATAL: corpus: bad query: {}", e),
+  };
+  let valid_frac: f64 = matches.opt_str("valid-frac").unwrap_or("0.05".to_string())
+    .parse().ok().expect("FATAL: corpus: valid frac should be a number");
+  let test_frac: f64 = matches.opt_str("test-frac").unwrap_or("0.05".to_string())
+    .parse().ok().expect("FATAL: corpus: test frac should be a number");
+  assert!(valid_frac >= 0.0 && test_frac >= 0.0 && valid_frac + test_frac <= 1.0);
+
+  let index = match (matches.opt_str("d"), matches.opt_str("i")) {
+    (Some(dir), None) => {
+      let index = CorpusIndex::scan_dir(&PathBuf::from(dir));
+      let index_path = PathBuf::from(&format!("{}_corpus.toml", prefix.to_str().unwrap()));
+      index.save(&index_path);
+      println!("DEBUG: corpus: saved index: {:?}", index_path);
+      index
+    }
+    (None, Some(index_path)) => {
+      CorpusIndex::open(&PathBuf::from(&index_path))
+        .ok().expect("FATAL: corpus: failed to open index")
+    }
+    _ => panic!("FATAL: corpus: exactly one of --dir or --index is required"),
+  };
+
+  let mut is_clean: Vec<_> = index.entries.iter().map(|_| true).collect();
+  if matches.opt_present("validate") {
+    let min_moves: usize = matches.opt_str("min-moves").unwrap_or("20".to_string())
+      .parse().ok().expect("FATAL: corpus: min moves should be an integer");
+    let (report, clean_idxs) = index.validate(min_moves);
+    let report_path = PathBuf::from(&format!("{}_report.toml", prefix.to_str().unwrap()));
+    report.save(&report_path);
+    let mut clean_index = File::create(&PathBuf::from(&format!("{}_clean_index", prefix.to_str().unwrap()))).unwrap();
+    for flag in is_clean.iter_mut() {
+      *flag = false;
+    }
+    for &idx in clean_idxs.iter() {
+      writeln!(clean_index, "{}", index.entries[idx].path).unwrap();
+      is_clean[idx] = true;
+    }
+    println!("validated: clean: {} / {} issues: {:?}",
+        report.num_clean, report.num_games, report.count_by_kind());
+  }
+
+  let splits = [CorpusSplit::Train, CorpusSplit::Valid, CorpusSplit::Test];
+  let mut split_files: Vec<_> = splits.iter()
+    .map(|split| File::create(&split.index_path(&prefix)).unwrap())
+    .collect();
+  let mut split_counts = [0; 3];
+  let mut error_counts = BTreeMap::new();
+  let mut num_errors = 0;
+  for (idx, entry) in index.entries.iter().enumerate() {
+    if !entry.errors.is_empty() {
+      num_errors += 1;
+      for error in entry.errors.iter() {
+        // Count errors by kind, i.e. up to the first ':'.
+        let kind = error.split(':').next().unwrap().to_string();
+        *error_counts.entry(kind).or_insert(0) += 1;
+      }
+    }
+    if !is_clean[idx] || !query.matches(entry) {
+      continue;
+    }
+    let split = CorpusSplit::assign(entry.split_key(), valid_frac, test_frac);
+    let k = splits.iter().position(|&s| s == split).unwrap();
+    writeln!(split_files[k], "{}", entry.path).unwrap();
+    split_counts[k] += 1;
+  }
+  println!("games: {}", index.entries.len());
+  println!("games with errors: {} {:?}", num_errors, error_counts);
+  println!("query: {:?}", query.terms);
+  println!("filtered: train: {} valid: {} test: {}",
+      split_counts[0], split_counts[1], split_counts[2]);
+}
@@ -1,196 +1,117 @@
+extern crate getopts;
 extern crate holmes;
-extern crate rembrandt;
-
-#[macro_use]
-extern crate log;
-extern crate env_logger;
-extern crate rand;
-//extern crate scoped_threadpool;
 extern crate time;
-extern crate vec_map;
 
-use holmes::array_util::{array_argmax};
-use holmes::board::{Board, Point};
-use holmes::data::{SymmetryAugment};
-use holmes::pattern::{LibPattern3x3, InvariantLibPattern3x3};
-use holmes::txnstate::{for_each_x8};
-use rembrandt::data_new::{
-  SampleDatumConfig, SampleLabelConfig,
-  DatasetConfig,
-  DataIterator, SampleIterator, RandomEpisodeIterator,
-  AugmentDataSource, PartitionDataSource,
-  SampleDatum, SampleLabel,
-};
+use holmes::mm::{MmTrainer, MmEval};
+use holmes::pattern::{MOVE_FEATURE_GROUPS};
+use holmes::sgf::{Sgf, SgfRankStyle};
 
-use rand::{Rng, thread_rng};
-use std::cmp::{max};
-use std::collections::{HashMap};
+use getopts::{Options};
+use std::env;
+use std::fs::{File};
+use std::io::{Read, BufRead, BufReader};
 use std::path::{PathBuf};
-use std::sync::{Arc};
 use time::{get_time};
-use vec_map::{VecMap};
-
-fn main() {
-  let datum_cfg = SampleDatumConfig::Bits3d{scale: 255};
-  let label_cfg = SampleLabelConfig::Category{
-    num_categories: 361,
-  };
-  let dataset_cfg = DatasetConfig::open(&PathBuf::from("datasets/gogodb_w2015-preproc-alphapatternnokov3m_19x19x7.data"));
-  let mut train_data =
-      RandomEpisodeIterator::new(
-          //dataset_cfg.build_with_cfg(datum_cfg, label_cfg, "train"),
-          Box::new(AugmentDataSource::new(SymmetryAugment::new(&mut thread_rng()), dataset_cfg.build_with_cfg(datum_cfg, label_cfg, "train"))),
-      );
-  let mut valid_data =
-      SampleIterator::new(
-          //Box::new(PartitionDataSource::new(tid, num_workers, dataset_cfg.build_with_cfg(datum_cfg, label_cfg, "valid")))
-          dataset_cfg.build_with_cfg(datum_cfg, label_cfg, "valid")
-      );
-
-  let step_size = 0.001;
-  //let epoch_size = train_data.max_num_samples();
-
-  let mut prev_move_gamma: f32 = 0.0;
-  let mut all_gammas: HashMap<LibPattern3x3, f32> = HashMap::with_capacity(32768);
-  let mut invariant_gammas: HashMap<InvariantLibPattern3x3, f32> = HashMap::with_capacity(32768);
-
-  let mut matched_prev_adj = VecMap::with_capacity(361);
-  let mut matched_pats = VecMap::with_capacity(361);
-  //let mut matched_factors = VecMap::with_capacity(361);
 
-  loop {
-    let mut start_time = get_time();
-
-    let mut interval_counter = 0;
-    let mut interval_acc = 0;
-    let mut interval_loss = 0.0;
-
-    let mut epoch = 0;
-    let mut local_idx = 0;
-
-    train_data.each_sample(datum_cfg, label_cfg, &mut |epoch_idx, datum, maybe_label| {
-      let repr_bytes = match datum {
-        &SampleDatum::WHCBytes(ref repr_bytes) => {
-          repr_bytes.as_slice()
-        }
-      };
-      let category = match (label_cfg, maybe_label) {
-        (SampleLabelConfig::Category{num_categories}, Some(&SampleLabel::Category{category})) => {
-          assert!(category >= 0);
-          assert!(category < num_categories);
-          category
-        }
-        _ => panic!("unsupported label"),
-      };
-
-      let target_p = category as usize;
-
-      matched_prev_adj.clear();
-      matched_pats.clear();
-      //matched_factors.clear();
-      let mut prev_move_p = None;
-      for prev_p in 0 .. Board::SIZE {
-        if repr_bytes[prev_p + 6 * Board::SIZE] != 0 {
-          prev_move_p = Some(prev_p);
-          break;
-        }
-      }
-      //assert!(prev_move_p.is_some());
-      if let Some(prev_move_p) = prev_move_p {
-        for_each_x8(Point::from_idx(prev_move_p), |_, adj_pt| {
-          matched_prev_adj.insert(adj_pt.idx(), true);
-        });
-      }
-      for p in 0 .. Board::SIZE {
-        if repr_bytes[p] == 0 {
-          continue;
-        }
-        let pat = LibPattern3x3::from_repr_bytes(repr_bytes, p);
-        let invariant_pat = pat.to_invariant();
-        if !all_gammas.contains_key(&pat) {
-          all_gammas.insert(pat, 0.0);
-        }
-        if !invariant_gammas.contains_key(&invariant_pat) {
-          invariant_gammas.insert(invariant_pat, 0.0);
-        }
-        //matched_pats.insert(p, pat);
-        matched_pats.insert(p, invariant_pat);
+fn load_index(trainer: &mut MmTrainer, index_path: &str, rank_style: SgfRankStyle, max_games: usize, valid: bool) -> (usize, usize) {
+  let index_file = File::open(&PathBuf::from(index_path))
+    .ok().expect("FATAL: train: failed to open index");
+  let mut num_games = 0;
+  let mut num_skipped = 0;
+  for line in BufReader::new(index_file).lines() {
+    if max_games > 0 && num_games >= max_games {
+      break;
+    }
+    let sgf_path = PathBuf::from(line.unwrap().trim());
+    let mut text = vec![];
+    match File::open(&sgf_path) {
+      Ok(mut file) => { file.read_to_end(&mut text).unwrap(); }
+      Err(e) => {
+        println!("WARNING: train: failed to open {:?}: {:?}", sgf_path, e);
+        num_skipped += 1;
+        continue;
       }
+    }
+    let sgf = Sgf::from_text(&text);
+    if trainer.add_sgf(&sgf, rank_style, valid).is_err() {
+      println!("WARNING: train: skipping {:?}", sgf_path);
+      num_skipped += 1;
+      continue;
+    }
+    num_games += 1;
+    if num_games % 1000 == 0 {
+      println!("DEBUG: train: loaded {} games from {}", num_games, index_path);
+    }
+  }
+  (num_games, num_skipped)
+}
 
-      let mut max_gamma = Some(prev_move_gamma);
-      for (p, pat) in matched_pats.iter() {
-        //let p_gamma = all_gammas[pat];
-        let p_gamma = invariant_gammas[pat];
-        match max_gamma {
-          None => max_gamma = Some(p_gamma),
-          Some(g) => max_gamma = Some(g.max(p_gamma)),
-        }
-      }
+fn print_eval(label: &str, iter: usize, eval: MmEval) {
+  println!("DEBUG: train: iter: {} {}: positions: {} acc: {:.4} log likelihood: {:.4}",
+      iter, label, eval.num_positions, eval.accuracy, eval.log_likelihood);
+}
 
-      let mut sum_factors = 0.0;
-      sum_factors += (prev_move_gamma - max_gamma.unwrap()).exp();
-      for (p, pat) in matched_pats.iter() {
-        //let p_gamma = all_gammas[pat];
-        let p_gamma = invariant_gammas[pat];
-        let p_stab_gamma = p_gamma - max_gamma.unwrap();
-        let p_factor = p_stab_gamma.exp();
-        //matched_factors.insert(p, p_factor);
-        sum_factors += p_factor;
-      }
+fn main() {
+  let args: Vec<_> = env::args().collect();
+  let mut opts = Options::new();
+  opts.optmulti("t", "train", "index of training sgf paths (e.g. a split index from sgf-corpus)", "index");
+  opts.optmulti("v", "valid", "index of validation sgf paths", "index");
+  opts.optopt("o", "output", "output gamma database path", "db");
+  opts.optopt("", "iters", "number of MM iterations (default 20)", "n");
+  opts.optopt("", "max-games", "games to load per index, 0 for all (default 0)", "n");
+  opts.optflag("", "gogod", "parse ranks as in GoGoD (\"9d\" is pro, \"4a\" is amateur)");
+  let matches = match opts.parse(&args[1 ..]) {
+    Ok(m) => m,
+    Err(e) => panic!("failed to parse args: {:?}", e),
+  };
+  let train_paths = matches.opt_strs("t");
+  if train_paths.is_empty() {
+    panic!("FATAL: train: at least one training index required");
+  }
+  let valid_paths = matches.opt_strs("v");
+  let db_path = PathBuf::from(matches.opt_str("o").expect("FATAL: train: output path required"));
+  let num_iters: usize = matches.opt_str("iters").unwrap_or("20".to_string())
+    .parse().ok().expect("FATAL: train: iters should be an integer");
+  let max_games: usize = matches.opt_str("max-games").unwrap_or("0".to_string())
+    .parse().ok().expect("FATAL: train: max games should be an integer");
+  let rank_style = if matches.opt_present("gogod") {
+    SgfRankStyle::Gogod
+  } else {
+    SgfRankStyle::Server
+  };
 
-      let mut out_indexes = vec![];
-      let mut out_probs = vec![];
-      for (p, pat) in matched_pats.iter() {
-        //let p_gamma = all_gammas[pat];
-        let p_gamma = invariant_gammas[pat];
-        let p_stab_gamma = p_gamma - max_gamma.unwrap();
-        let p_factor = if matched_prev_adj.contains_key(p) {
-          p_stab_gamma.exp() + prev_move_gamma.exp()
-        } else {
-          p_stab_gamma.exp()
-        };
-        let p_prob = p_factor / sum_factors;
-        out_indexes.push(p);
-        out_probs.push(p_prob);
-        let gradient = if p == target_p {
-          interval_loss += -(p_prob.ln());
-          p_prob - 1.0
-        } else {
-          p_prob
-        };
-        if matched_prev_adj.contains_key(p) {
-          prev_move_gamma -= step_size * gradient;
-        }
-        //*all_gammas.get_mut(&pat).unwrap() = p_gamma - step_size * gradient;
-        *invariant_gammas.get_mut(&pat).unwrap() = p_gamma - step_size * gradient;
-      }
-      //println!("DEBUG: train: probs: {:?}", probs);
-      let argmax_rank = array_argmax(&out_probs).unwrap();
-      let argmax_p = out_indexes[argmax_rank];
-      if argmax_p == target_p {
-        interval_acc += 1;
-      }
+  let mut trainer = MmTrainer::new();
+  for &(paths, valid) in [(&train_paths, false), (&valid_paths, true)].iter() {
+    for index_path in paths.iter() {
+      let (num_games, num_skipped) = load_index(&mut trainer, index_path, rank_style, max_games, valid);
+      println!("DEBUG: train: index: {} games: {} skipped: {}", index_path, num_games, num_skipped);
+    }
+  }
+  println!("DEBUG: train: positions: train: {} valid: {} features: {}",
+      trainer.num_train_positions(), trainer.num_valid_positions(), trainer.num_features());
+
+  for iter in 0 .. num_iters {
+    let start_time = get_time();
+    trainer.iterate();
+    let elapsed_ms = (get_time() - start_time).num_milliseconds();
+    println!("DEBUG: train: iter: {} elapsed: {:.3} s", iter, elapsed_ms as f32 * 0.001);
+    print_eval("train", iter, trainer.evaluate_train());
+    if trainer.num_valid_positions() > 0 {
+      print_eval("valid", iter, trainer.evaluate_valid());
+    }
+  }
 
-      interval_counter += 1;
-      local_idx += 1;
-      if local_idx % 10000 == 0 {
-        let lap_time = get_time();
-        let elapsed_ms = (lap_time - start_time).num_milliseconds();
-        interval_loss /= interval_counter as f32;
-        println!("DEBUG: train: epoch: {}, samples: {}, patterns found: {}/{} acc: {:.3} loss: {:.6} elapsed: {:.3} s",
-            epoch, local_idx,
-            all_gammas.len(), invariant_gammas.len(),
-            interval_acc as f32 / interval_counter as f32,
-            interval_loss,
-            elapsed_ms as f32 * 0.001,
-        );
-        start_time = lap_time;
-        interval_counter = 0;
-        interval_acc = 0;
-        interval_loss = 0.0;
+  for &group in MOVE_FEATURE_GROUPS.iter() {
+    let gammas = trainer.group_gammas(group);
+    if gammas.len() <= 20 {
+      for &(feature, gamma) in gammas.iter() {
+        println!("{} {}: {:.4}", group.to_str(), feature.level(), gamma);
       }
-    });
-    epoch += 1;
-    //break; // FIXME(20160411)
+    } else {
+      println!("{}: {} features", group.to_str(), gammas.len());
+    }
   }
+  let db = trainer.to_database();
+  db.save(&db_path);
+  println!("gammas: {}", db.gammas.len());
 }